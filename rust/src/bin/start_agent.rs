@@ -1,10 +1,20 @@
 //! start-agent CLI command
 //! Start an agent with specified configuration
 
+use agent_commander::cli_parser::{
+    parse_bwrap_options, parse_docker_exec_options, parse_docker_options, parse_duration_seconds,
+    parse_worktree_options,
+};
 use agent_commander::{
     agent, parse_start_agent_args, show_start_agent_help, validate_start_agent_options,
-    AgentOptions, AgentStartOptions,
+    AgentOptions, AgentStartOptions, AsyncPermissionHandler, PermissionHandler, ProcessSignal,
+    TerminationSchedule,
 };
+use std::io::IsTerminal;
+use std::time::Duration;
+
+/// How long an approver command may take per request by default
+const DEFAULT_APPROVE_TIMEOUT: Duration = Duration::from_mins(1);
 
 fn parse_tool_env(entries: Vec<String>) -> Result<Vec<(String, String)>, String> {
     entries
@@ -40,6 +50,12 @@ async fn main() {
         std::process::exit(1);
     }
 
+    // Validated above
+    let docker = parse_docker_options(&options).unwrap_or_default();
+    let docker_exec = parse_docker_exec_options(&options).unwrap_or_default();
+    let bwrap = parse_bwrap_options(&options).unwrap_or_default();
+    let worktree = parse_worktree_options(&options).unwrap_or_default();
+
     let extra_env = match parse_tool_env(options.tool_env) {
         Ok(extra_env) => extra_env,
         Err(error) => {
@@ -48,6 +64,35 @@ async fn main() {
         }
     };
 
+    // Values were validated above
+    let mut termination_schedule = TerminationSchedule::default();
+    if let Some(grace) = options.interrupt_grace.as_deref() {
+        termination_schedule.interrupt_grace =
+            parse_duration_seconds(grace).unwrap_or(termination_schedule.interrupt_grace);
+    }
+    if let Some(grace) = options.terminate_grace.as_deref() {
+        termination_schedule.terminate_grace =
+            parse_duration_seconds(grace).unwrap_or(termination_schedule.terminate_grace);
+    }
+
+    // Without an approver, a person at the terminal decides each request;
+    // when nobody can be asked, requests are rejected
+    let on_permission_request = if options.approve_each
+        && options.approve_command.is_none()
+        && !options.dry_run
+        && std::io::stdin().is_terminal()
+    {
+        match PermissionHandler::terminal() {
+            Ok(handler) => Some(handler),
+            Err(error) => {
+                eprintln!("Warning: {}; rejecting every permission request", error);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Create agent controller
     let agent_options = AgentOptions {
         tool: options.tool.unwrap_or_default(),
@@ -66,13 +111,33 @@ async fn main() {
         read_only: options.read_only,
         plan_only: options.plan_only,
         approve_each: options.approve_each,
+        on_permission_request,
+        on_permission_request_async: options
+            .approve_command
+            .as_deref()
+            .map(AsyncPermissionHandler::command),
+        permission_timeout: options.approve_command.as_ref().map(|_| {
+            options
+                .approve_timeout
+                .as_deref()
+                .and_then(parse_duration_seconds)
+                .unwrap_or(DEFAULT_APPROVE_TIMEOUT)
+        }),
         executable: options.tool_executable,
         extra_args: options.tool_args,
         extra_env,
         skip_default_safety_flags: options.skip_default_safety_flags,
         isolation: options.isolation,
         screen_name: options.screen_name,
+        tmux_session: options.tmux_session,
         container_name: options.container_name,
+        docker,
+        docker_exec,
+        bwrap,
+        worktree,
+        timeout: options.timeout.as_deref().and_then(parse_duration_seconds),
+        termination_schedule,
+        state_dir: options.state_dir,
         ..Default::default()
     };
 
@@ -98,8 +163,44 @@ async fn main() {
 
     // If not detached, wait for completion
     if !options.detached && !options.dry_run {
-        match controller.stop(Default::default()).await {
+        // The agent runs in its own process group, so forward Ctrl+C to it:
+        // the first one interrupts, the next one kills
+        let process_group = controller.process_group();
+        let stop = controller.stop(Default::default());
+        tokio::pin!(stop);
+        let mut interrupts = 0;
+        let outcome = loop {
+            tokio::select! {
+                outcome = &mut stop => break outcome,
+                _ = tokio::signal::ctrl_c() => {
+                    interrupts += 1;
+                    let signal = if interrupts == 1 {
+                        ProcessSignal::Interrupt
+                    } else {
+                        ProcessSignal::Kill
+                    };
+                    if let Some(ref group) = process_group {
+                        let _ = group.signal(signal).await;
+                    }
+                }
+            }
+        };
+
+        match outcome {
             Ok(result) => {
+                if let Some(ref worktree) = result.worktree {
+                    eprintln!("Worktree: {}", worktree.path.display());
+                    eprintln!("Branch: {}", worktree.branch);
+                    eprintln!("Commits: {}", worktree.commit_range());
+                    if worktree.removed {
+                        eprintln!("Worktree removed");
+                    }
+                }
+                if result.metadata.timed_out {
+                    // Same exit status as coreutils `timeout`
+                    eprintln!("Error: agent run timed out and was terminated");
+                    std::process::exit(124);
+                }
                 std::process::exit(result.exit_code);
             }
             Err(e) => {
@@ -1,9 +1,10 @@
 //! stop-agent CLI command
-//! Stop a detached agent
+//! Stop a detached agent (screen or tmux session, docker container or tracked run)
 
+use agent_commander::cli_parser::parse_stop_worktree_options;
 use agent_commander::{
     agent, parse_stop_agent_args, show_stop_agent_help, validate_stop_agent_options, AgentOptions,
-    AgentStopOptions,
+    AgentStopOptions, DockerOptions, DockerRemoval, RunRegistry,
 };
 
 #[tokio::main]
@@ -28,13 +29,52 @@ async fn main() {
         std::process::exit(1);
     }
 
+    // Validated above
+    let worktree = parse_stop_worktree_options(&options).unwrap_or_default();
+
+    // A --pid is resolved to its tracked run through the registry
+    let run_id = match options.pid.as_deref() {
+        Some(pid) => {
+            let registry = options
+                .state_dir
+                .as_ref()
+                .map_or_else(RunRegistry::default, RunRegistry::new);
+            // Validated above
+            let pid = pid.parse().unwrap_or_default();
+            match registry.find_by_pid(pid).await {
+                Ok(record) => Some(record.run_id),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => options.run_id,
+    };
+
+    // Validated above
+    let removal = options
+        .docker_rm
+        .as_deref()
+        .and_then(|value| DockerRemoval::parse(value).ok())
+        .unwrap_or_default();
+
     // Create agent controller (minimal config needed for stop)
     let agent_options = AgentOptions {
-        tool: "dummy".to_string(),             // Not used for stop
+        // Only used to parse the collected output
+        tool: options.tool.unwrap_or_else(|| "dummy".to_string()),
         working_directory: "/tmp".to_string(), // Not used for stop
-        isolation: options.isolation.unwrap_or_default(),
+        isolation: options.isolation.unwrap_or_else(|| "none".to_string()),
         screen_name: options.screen_name,
+        tmux_session: options.tmux_session,
         container_name: options.container_name,
+        docker: DockerOptions {
+            removal,
+            ..Default::default()
+        },
+        run_id,
+        state_dir: options.state_dir,
+        worktree,
         ..Default::default()
     };
 
@@ -53,6 +93,17 @@ async fn main() {
 
     match controller.stop(stop_options).await {
         Ok(result) => {
+            if !result.plain_output.is_empty() {
+                println!("{}", result.plain_output.trim_end());
+            }
+            if let Some(ref worktree) = result.worktree {
+                println!("Worktree: {}", worktree.path.display());
+                println!("Branch: {}", worktree.branch);
+                println!("Commits: {}", worktree.commit_range());
+                if worktree.removed {
+                    println!("Worktree removed");
+                }
+            }
             println!("Agent stopped successfully");
             std::process::exit(result.exit_code);
         }
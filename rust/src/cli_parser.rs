@@ -1,10 +1,21 @@
 //! Parse CLI arguments
 //! Simple argument parser without external dependencies
 
+use crate::isolation::{
+    BwrapOptions, DockerExecOptions, DockerOptions, DockerRemoval, DockerVolume, WorktreeOptions,
+};
 use std::collections::HashMap;
+use std::time::Duration;
 
 const VALUE_OPTION_KEYS: &[&str] = &["tool-arg"];
 
+/// Values accepted by `--isolation`
+const ISOLATION_MODES: &[&str] = &["none", "screen", "tmux", "docker", "docker-exec", "bwrap"];
+
+fn invalid_isolation_error() -> String {
+    format!("--isolation must be one of: {}", ISOLATION_MODES.join(", "))
+}
+
 /// Parsed command line arguments
 #[derive(Debug, Clone, Default)]
 pub struct ParsedArgs {
@@ -89,8 +100,12 @@ pub struct StartAgentOptions {
     pub replay_user_messages: bool,
     pub read_only: bool,
     pub plan_only: bool,
-    /// Approve each command (ask mode); relayable only for: claude, agent
+    /// Approve each command (ask mode); relayable only for: claude, agent, codex
     pub approve_each: bool,
+    /// Executable deciding each permission request (`--approve-command`)
+    pub approve_command: Option<String>,
+    /// Seconds the approver may take per request (`--approve-timeout`)
+    pub approve_timeout: Option<String>,
     pub resume: Option<String>,
     pub session_id: Option<String>,
     pub fork_session: bool,
@@ -100,7 +115,51 @@ pub struct StartAgentOptions {
     pub skip_default_safety_flags: bool,
     pub isolation: String,
     pub screen_name: Option<String>,
+    /// tmux session name (`--tmux-session`)
+    pub tmux_session: Option<String>,
     pub container_name: Option<String>,
+    /// Docker image (`--docker-image`)
+    pub docker_image: Option<String>,
+    /// Extra docker volumes, `SOURCE:TARGET[:ro|rw]` (`--docker-volume`, repeatable)
+    pub docker_volumes: Vec<String>,
+    /// Docker network mode (`--docker-network`)
+    pub docker_network: Option<String>,
+    /// Docker CPU limit (`--docker-cpus`)
+    pub docker_cpus: Option<String>,
+    /// Docker memory limit (`--docker-memory`)
+    pub docker_memory: Option<String>,
+    /// Docker user, `UID[:GID]`, name or `host` (`--docker-user`)
+    pub docker_user: Option<String>,
+    /// Host environment variables passed into the container (`--docker-env`, repeatable)
+    pub docker_env: Vec<String>,
+    /// Docker removal policy (`--docker-rm`)
+    pub docker_rm: Option<String>,
+    /// Working directory inside an existing container (`--exec-workdir`)
+    pub exec_workdir: Option<String>,
+    /// User inside an existing container (`--exec-user`)
+    pub exec_user: Option<String>,
+    /// Unshare the network inside the bwrap sandbox (`--bwrap-unshare-net`)
+    pub bwrap_unshare_net: bool,
+    /// Extra read-write paths inside the bwrap sandbox (`--bwrap-writable`, repeatable)
+    pub bwrap_writable: Vec<String>,
+    /// Run in a fresh git worktree on a new branch (`--worktree`)
+    pub worktree: bool,
+    /// Branch created for the worktree (`--worktree-branch`)
+    pub worktree_branch: Option<String>,
+    /// Commit-ish the worktree branch starts from (`--worktree-base`)
+    pub worktree_base: Option<String>,
+    /// Worktree directory (`--worktree-path`)
+    pub worktree_path: Option<String>,
+    /// Remove the worktree after the run if it is clean (`--worktree-cleanup`)
+    pub worktree_cleanup: bool,
+    /// Run timeout in seconds (`--timeout`)
+    pub timeout: Option<String>,
+    /// Seconds between SIGINT and SIGTERM once the timeout fires
+    pub interrupt_grace: Option<String>,
+    /// Seconds between SIGTERM and SIGKILL once the timeout fires
+    pub terminate_grace: Option<String>,
+    /// Run registry directory for detached runs without isolation
+    pub state_dir: Option<String>,
     pub dry_run: bool,
     pub detached: bool,
     pub attached: bool,
@@ -110,9 +169,29 @@ pub struct StartAgentOptions {
 /// Stop agent options
 #[derive(Debug, Clone, Default)]
 pub struct StopAgentOptions {
+    /// Tool the agent was started with, used to parse its output (`--tool`)
+    pub tool: Option<String>,
     pub isolation: Option<String>,
     pub screen_name: Option<String>,
+    /// tmux session name (`--tmux-session`)
+    pub tmux_session: Option<String>,
     pub container_name: Option<String>,
+    /// PID of a tracked detached run (`--pid`)
+    pub pid: Option<String>,
+    /// ID of a tracked detached run (`--run-id`)
+    pub run_id: Option<String>,
+    /// Run registry directory (`--state-dir`)
+    pub state_dir: Option<String>,
+    /// Docker removal policy the container was started with (`--docker-rm`)
+    pub docker_rm: Option<String>,
+    /// Report the run's git worktree (`--worktree`)
+    pub worktree: bool,
+    /// Branch the worktree was created on (`--worktree-branch`)
+    pub worktree_branch: Option<String>,
+    /// Worktree directory (`--worktree-path`)
+    pub worktree_path: Option<String>,
+    /// Remove the worktree if it is clean (`--worktree-cleanup`)
+    pub worktree_cleanup: bool,
     pub dry_run: bool,
     pub help: bool,
 }
@@ -151,6 +230,8 @@ pub fn parse_start_agent_args(args: &[String]) -> StartAgentOptions {
                 .get("permission-mode")
                 .map(|m| m == "ask")
                 .unwrap_or(false),
+        approve_command: parsed.get("approve-command").cloned(),
+        approve_timeout: parsed.get("approve-timeout").cloned(),
         resume: parsed.get("resume").cloned(),
         session_id: parsed.get("session-id").cloned(),
         fork_session: parsed.get_bool("fork-session"),
@@ -160,7 +241,29 @@ pub fn parse_start_agent_args(args: &[String]) -> StartAgentOptions {
         skip_default_safety_flags: parsed.get_bool("skip-default-safety-flags"),
         isolation,
         screen_name: parsed.get("screen-name").cloned(),
+        tmux_session: parsed.get("tmux-session").cloned(),
         container_name: parsed.get("container-name").cloned(),
+        docker_image: parsed.get("docker-image").cloned(),
+        docker_volumes: parsed.get_all("docker-volume"),
+        docker_network: parsed.get("docker-network").cloned(),
+        docker_cpus: parsed.get("docker-cpus").cloned(),
+        docker_memory: parsed.get("docker-memory").cloned(),
+        docker_user: parsed.get("docker-user").cloned(),
+        docker_env: parsed.get_all("docker-env"),
+        docker_rm: parsed.get("docker-rm").cloned(),
+        exec_workdir: parsed.get("exec-workdir").cloned(),
+        exec_user: parsed.get("exec-user").cloned(),
+        bwrap_unshare_net: parsed.get_bool("bwrap-unshare-net"),
+        bwrap_writable: parsed.get_all("bwrap-writable"),
+        worktree: parsed.get_bool("worktree"),
+        worktree_branch: parsed.get("worktree-branch").cloned(),
+        worktree_base: parsed.get("worktree-base").cloned(),
+        worktree_path: parsed.get("worktree-path").cloned(),
+        worktree_cleanup: parsed.get_bool("worktree-cleanup"),
+        timeout: parsed.get("timeout").cloned(),
+        interrupt_grace: parsed.get("interrupt-grace").cloned(),
+        terminate_grace: parsed.get("terminate-grace").cloned(),
+        state_dir: parsed.get("state-dir").cloned(),
         dry_run: parsed.get_bool("dry-run"),
         detached,
         attached: !detached, // Default is attached unless detached is specified
@@ -179,14 +282,134 @@ pub fn parse_stop_agent_args(args: &[String]) -> StopAgentOptions {
     let parsed = parse_args(args);
 
     StopAgentOptions {
+        tool: parsed.get("tool").cloned(),
         isolation: parsed.get("isolation").cloned(),
         screen_name: parsed.get("screen-name").cloned(),
+        tmux_session: parsed.get("tmux-session").cloned(),
         container_name: parsed.get("container-name").cloned(),
+        pid: parsed.get("pid").cloned(),
+        run_id: parsed.get("run-id").cloned(),
+        state_dir: parsed.get("state-dir").cloned(),
+        docker_rm: parsed.get("docker-rm").cloned(),
+        worktree: parsed.get_bool("worktree"),
+        worktree_branch: parsed.get("worktree-branch").cloned(),
+        worktree_path: parsed.get("worktree-path").cloned(),
+        worktree_cleanup: parsed.get_bool("worktree-cleanup"),
         dry_run: parsed.get_bool("dry-run"),
         help: parsed.get_bool("help") || parsed.get_bool("h"),
     }
 }
 
+/// Build docker isolation settings from start-agent options
+///
+/// # Arguments
+/// * `options` - Parsed options
+///
+/// # Returns
+/// Validated docker settings or the first invalid flag
+pub fn parse_docker_options(options: &StartAgentOptions) -> Result<DockerOptions, String> {
+    let defaults = DockerOptions::default();
+    let docker = DockerOptions {
+        image: options.docker_image.clone().unwrap_or(defaults.image),
+        volumes: options
+            .docker_volumes
+            .iter()
+            .map(|volume| DockerVolume::parse(volume))
+            .collect::<Result<_, _>>()?,
+        network: options.docker_network.clone(),
+        cpus: options.docker_cpus.clone(),
+        memory: options.docker_memory.clone(),
+        user: options.docker_user.clone(),
+        env_passthrough: options.docker_env.clone(),
+        removal: options
+            .docker_rm
+            .as_deref()
+            .map(DockerRemoval::parse)
+            .transpose()?
+            .unwrap_or_default(),
+    };
+    docker.validate()?;
+    Ok(docker)
+}
+
+/// Build docker exec isolation settings from start-agent options
+///
+/// # Arguments
+/// * `options` - Parsed options
+///
+/// # Returns
+/// Validated container working directory and user
+pub fn parse_docker_exec_options(options: &StartAgentOptions) -> Result<DockerExecOptions, String> {
+    let exec = DockerExecOptions {
+        working_directory: options.exec_workdir.clone(),
+        user: options.exec_user.clone(),
+    };
+    exec.validate()?;
+    Ok(exec)
+}
+
+/// Build bwrap sandbox settings from start-agent options
+///
+/// # Arguments
+/// * `options` - Parsed options
+///
+/// # Returns
+/// Validated network and writable path settings
+pub fn parse_bwrap_options(options: &StartAgentOptions) -> Result<BwrapOptions, String> {
+    let bwrap = BwrapOptions {
+        unshare_net: options.bwrap_unshare_net,
+        writable_paths: options.bwrap_writable.clone(),
+    };
+    bwrap.validate()?;
+    Ok(bwrap)
+}
+
+/// Build git worktree settings from start-agent options
+///
+/// # Arguments
+/// * `options` - Parsed options
+///
+/// # Returns
+/// Validated worktree settings, or None without `--worktree`
+pub fn parse_worktree_options(
+    options: &StartAgentOptions,
+) -> Result<Option<WorktreeOptions>, String> {
+    if !options.worktree {
+        return Ok(None);
+    }
+    let worktree = WorktreeOptions {
+        branch: options.worktree_branch.clone(),
+        base: options.worktree_base.clone(),
+        path: options.worktree_path.clone(),
+        cleanup: options.worktree_cleanup,
+    };
+    worktree.validate()?;
+    Ok(Some(worktree))
+}
+
+/// Build git worktree settings from stop-agent options
+///
+/// # Arguments
+/// * `options` - Parsed options
+///
+/// # Returns
+/// Validated worktree settings, or None without `--worktree`
+pub fn parse_stop_worktree_options(
+    options: &StopAgentOptions,
+) -> Result<Option<WorktreeOptions>, String> {
+    if !options.worktree {
+        return Ok(None);
+    }
+    let worktree = WorktreeOptions {
+        branch: options.worktree_branch.clone(),
+        path: options.worktree_path.clone(),
+        cleanup: options.worktree_cleanup,
+        ..Default::default()
+    };
+    worktree.validate()?;
+    Ok(Some(worktree))
+}
+
 /// Show start-agent help message
 pub fn show_start_agent_help() {
     println!(
@@ -205,8 +428,12 @@ Options:
   --verbose                        Enable verbose mode
   --read-only                      Enforce native read-only mode (agent: --permission-mode readonly)
   --plan-only                      Enforce native planning mode (agent: --permission-mode plan)
-  --approve-each                   Approve each command (ask mode); relayable for: claude, agent
+  --approve-each                   Approve each command (ask mode); relayable for: claude, agent, codex
   --permission-mode ask            Alias for --approve-each
+  --approve-command <path>         Decide each request with this executable: it reads the request
+                                   as JSON on stdin and prints once, always or reject
+  --approve-timeout <seconds>      Reject a request the approver has not answered in time
+                                   (default: 60)
   --resume <sessionId>             Resume a previous session by ID
   --session-id <uuid>              Use a specific session ID (must be valid UUID)
   --fork-session                   Create new session ID when resuming
@@ -215,10 +442,39 @@ Options:
   --tool-env <KEY=VALUE>           Add an environment variable for the tool (repeatable)
   --tool-arg <arg>                 Append a raw argument to the tool command (repeatable)
   --skip-default-safety-flags      Do not add default autonomous safety bypass flags
-  --isolation <mode>               Isolation mode: none, screen, docker (default: none)
+  --isolation <mode>               Isolation mode: none, screen, tmux, docker, docker-exec,
+                                   bwrap (default: none)
   --screen-name <name>             Screen session name (required for screen isolation)
-  --container-name <name>          Container name (required for docker isolation)
-  --detached                       Run in detached mode
+  --tmux-session <name>            tmux session name (required for tmux isolation)
+  --container-name <name>          Container name (required for docker isolation), or the
+                                   existing container to run in (required for docker-exec)
+  --docker-image <image>           Docker image (default: node:18-slim)
+  --docker-volume <src:dst[:ro]>   Extra docker mount, read-write unless :ro (repeatable)
+  --docker-network <mode>          Docker network mode, e.g. none, host, bridge
+  --docker-cpus <n>                Docker CPU limit, e.g. 1.5
+  --docker-memory <size>           Docker memory limit, e.g. 512m or 2g
+  --docker-user <uid[:gid]|host>   Run the container as this user; host = owner of the working directory
+  --docker-env <NAME>              Pass a host environment variable into the container (repeatable)
+  --docker-rm <policy>             Container removal: on-stop (default), auto (--rm), keep
+  --exec-workdir <path>            Working directory inside the container (docker-exec;
+                                   default: --working-directory)
+  --exec-user <uid[:gid]>          User inside the container (docker-exec)
+  --bwrap-unshare-net              Run the bwrap sandbox without network access
+  --bwrap-writable <path>          Extra read-write path inside the bwrap sandbox (repeatable)
+  --worktree                       Run in a fresh git worktree of --working-directory on a new
+                                   branch (not with docker-exec)
+  --worktree-branch <name>         Branch for the worktree (default: generated agent-<millis>)
+  --worktree-base <ref>            Commit, branch or tag the branch starts from (default: HEAD)
+  --worktree-path <path>           Worktree directory (default: worktrees/<branch> under the
+                                   state directory)
+  --worktree-cleanup               Remove the worktree after the run unless it has uncommitted
+                                   changes (the branch is kept)
+  --timeout <seconds>              Terminate the run after this many seconds (no isolation, bwrap or docker-exec)
+  --interrupt-grace <seconds>      Wait after SIGINT before SIGTERM on timeout (default: 10)
+  --terminate-grace <seconds>      Wait after SIGTERM before SIGKILL on timeout (default: 5)
+  --detached                       Run in detached mode (tracked by run ID without isolation or
+                                   with bwrap)
+  --state-dir <path>               Run registry directory for detached runs without isolation
   --dry-run                        Show command without executing
   --help, -h                       Show this help message
 
@@ -242,10 +498,35 @@ Examples:
   start-agent --tool claude --working-directory "/tmp/dir" \
     --isolation screen --screen-name my-agent --detached
 
+  # With tmux isolation (detached)
+  start-agent --tool claude --working-directory "/tmp/dir" --prompt "Hello" \
+    --isolation tmux --tmux-session my-agent --detached
+
   # With docker isolation (attached)
   start-agent --tool claude --working-directory "/tmp/dir" \
     --isolation docker --container-name my-container
 
+  # Docker without network access, owned by the invoking user
+  start-agent --tool claude --working-directory "/tmp/dir" \
+    --isolation docker --container-name my-container --docker-image node:20 \
+    --docker-network none --docker-user host --docker-memory 2g --detached
+
+  # Kernel-enforced read-only workspace, no network (Linux)
+  start-agent --tool codex --working-directory "/tmp/dir" --prompt "Review" \
+    --isolation bwrap --read-only --bwrap-unshare-net
+
+  # In its own worktree and branch, removed afterwards if clean
+  start-agent --tool claude --working-directory "/repo" --prompt "Fix issue 42" \
+    --worktree --worktree-branch agent/issue-42 --worktree-cleanup
+
+  # Inside an existing dev container (prints a run ID for stop-agent)
+  start-agent --tool claude --working-directory "/tmp/dir" \
+    --isolation docker-exec --container-name devbox --exec-workdir /workspace --detached
+
+  # Hard upper bound of 30 minutes for a CI run
+  start-agent --tool claude --working-directory "/tmp/dir" \
+    --prompt "Fix the failing test" --timeout 1800
+
   # Dry run
   start-agent --tool claude --working-directory "/tmp/dir" --dry-run
 "#
@@ -259,9 +540,22 @@ pub fn show_stop_agent_help() {
 Usage: stop-agent [options]
 
 Options:
-  --isolation <mode>               Isolation mode: screen, docker [required]
+  --tool <name>                    Tool the agent was started with (parses session ID and usage)
+  --isolation <mode>               Isolation mode: none, screen, tmux, docker, docker-exec,
+                                   bwrap [required unless --pid or --run-id]
   --screen-name <name>             Screen session name (required for screen isolation)
-  --container-name <name>          Container name (required for docker isolation)
+  --tmux-session <name>            tmux session name (required for tmux isolation)
+  --container-name <name>          Container name (required for docker and docker-exec isolation)
+  --docker-rm <policy>             Removal policy the container was started with (default: on-stop)
+  --worktree                       Report the run's git worktree, branch and commit range
+  --worktree-branch <name>         Branch the worktree was created on
+  --worktree-path <path>           Worktree directory (default: from the branch or tracked run)
+  --worktree-cleanup               Remove the worktree unless it has uncommitted changes
+  --pid <pid>                      PID of a detached run without isolation or with bwrap
+  --run-id <id>                    Run ID of a detached run without isolation, with bwrap,
+                                   or in docker-exec
+  --state-dir <path>               Run registry directory (default: $AGENT_COMMANDER_STATE_DIR,
+                                   $XDG_STATE_HOME/agent-commander or ~/.local/state/agent-commander)
   --dry-run                        Show command without executing
   --help, -h                       Show this help message
 
@@ -269,9 +563,22 @@ Examples:
   # Stop screen session
   stop-agent --isolation screen --screen-name my-agent
 
+  # Stop tmux session
+  stop-agent --isolation tmux --tmux-session my-agent
+
   # Stop docker container
   stop-agent --isolation docker --container-name my-container
 
+  # Stop a detached run without isolation
+  stop-agent --run-id run-1760690000000000000-4242
+
+  # Stop a worktree run in tmux and remove its worktree
+  stop-agent --isolation tmux --tmux-session my-agent \
+    --worktree --worktree-branch agent/issue-42 --worktree-cleanup
+
+  # Stop the agent inside an existing container, leaving the container running
+  stop-agent --isolation docker-exec --container-name devbox --run-id agent-1760690000000
+
   # Dry run
   stop-agent --isolation screen --screen-name my-agent --dry-run
 "#
@@ -285,6 +592,21 @@ pub struct ValidationResult {
     pub errors: Vec<String>,
 }
 
+/// Parse a duration given in (possibly fractional) seconds
+///
+/// # Arguments
+/// * `value` - Seconds, e.g. `"90"` or `"0.5"`
+///
+/// # Returns
+/// Duration, or None if the value is not a non-negative finite number
+pub fn parse_duration_seconds(value: &str) -> Option<Duration> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+}
+
 /// Validate start-agent options
 ///
 /// # Arguments
@@ -307,422 +629,208 @@ pub fn validate_start_agent_options(options: &StartAgentOptions) -> ValidationRe
         errors.push("--screen-name is required for screen isolation".to_string());
     }
 
-    if options.isolation == "docker" && options.container_name.is_none() {
-        errors.push("--container-name is required for docker isolation".to_string());
-    }
-
-    if !["none", "screen", "docker"].contains(&options.isolation.as_str()) {
-        errors.push("--isolation must be one of: none, screen, docker".to_string());
-    }
-
-    ValidationResult {
-        valid: errors.is_empty(),
-        errors,
+    if options.isolation == "tmux" && options.tmux_session.is_none() {
+        errors.push("--tmux-session is required for tmux isolation".to_string());
     }
-}
-
-/// Validate stop-agent options
-///
-/// # Arguments
-/// * `options` - Parsed options
-///
-/// # Returns
-/// Validation result with valid flag and errors
-pub fn validate_stop_agent_options(options: &StopAgentOptions) -> ValidationResult {
-    let mut errors = Vec::new();
 
-    if options.isolation.is_none() {
-        errors.push("--isolation is required".to_string());
+    if options.isolation == "docker" && options.container_name.is_none() {
+        errors.push("--container-name is required for docker isolation".to_string());
     }
 
-    if let Some(ref isolation) = options.isolation {
-        if !["screen", "docker"].contains(&isolation.as_str()) {
-            errors.push("--isolation must be one of: screen, docker".to_string());
-        }
-
-        if isolation == "screen" && options.screen_name.is_none() {
-            errors.push("--screen-name is required for screen isolation".to_string());
+    if options.isolation == "docker-exec" {
+        if options.container_name.is_none() {
+            errors.push("--container-name is required for docker-exec isolation".to_string());
         }
-
-        if isolation == "docker" && options.container_name.is_none() {
-            errors.push("--container-name is required for docker isolation".to_string());
+        if let Err(error) = parse_docker_exec_options(options) {
+            errors.push(error);
         }
+    } else if options.exec_workdir.is_some() || options.exec_user.is_some() {
+        errors.push("--exec-workdir and --exec-user require --isolation docker-exec".to_string());
     }
 
-    ValidationResult {
-        valid: errors.is_empty(),
-        errors,
+    if !ISOLATION_MODES.contains(&options.isolation.as_str()) {
+        errors.push(invalid_isolation_error());
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_args_basic_flags() {
-        let args: Vec<String> = vec!["--foo".into(), "bar".into(), "--baz".into()];
-        let result = parse_args(&args);
 
-        assert_eq!(result.get("foo"), Some(&"bar".to_string()));
-        assert!(result.has_flag("baz"));
+    for (flag, value) in [
+        ("--timeout", &options.timeout),
+        ("--interrupt-grace", &options.interrupt_grace),
+        ("--terminate-grace", &options.terminate_grace),
+        ("--approve-timeout", &options.approve_timeout),
+    ] {
+        if let Some(value) = value {
+            if parse_duration_seconds(value).is_none() {
+                errors.push(format!("{} must be a number of seconds", flag));
+            }
+        }
     }
 
-    #[test]
-    fn test_parse_args_with_positional() {
-        let args: Vec<String> = vec!["--foo".into(), "bar".into(), "positional1".into()];
-        let result = parse_args(&args);
-
-        assert_eq!(result.get("foo"), Some(&"bar".to_string()));
-        assert_eq!(result.positional, vec!["positional1"]);
+    let local_process = matches!(options.isolation.as_str(), "none" | "bwrap");
+    let timeout_supported = local_process || options.isolation == "docker-exec";
+    if options.timeout.is_some() && (!timeout_supported || options.detached) {
+        errors.push(
+            "--timeout is only supported for attached runs without isolation, with bwrap, or with docker-exec"
+                .to_string(),
+        );
     }
 
-    #[test]
-    fn test_parse_start_agent_args_basic() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--prompt".into(),
-            "Hello".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert_eq!(result.tool, Some("claude".to_string()));
-        assert_eq!(result.working_directory, Some("/tmp/test".to_string()));
-        assert_eq!(result.prompt, Some("Hello".to_string()));
-        assert_eq!(result.isolation, "none");
+    if options.approve_each && (!local_process || options.detached) {
+        errors.push(
+            "--approve-each is only supported for attached runs without isolation or with bwrap"
+                .to_string(),
+        );
     }
 
-    #[test]
-    fn test_parse_start_agent_args_with_isolation() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--isolation".into(),
-            "screen".into(),
-            "--screen-name".into(),
-            "my-session".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert_eq!(result.isolation, "screen");
-        assert_eq!(result.screen_name, Some("my-session".to_string()));
+    if !options.approve_each
+        && (options.approve_command.is_some() || options.approve_timeout.is_some())
+    {
+        errors.push("--approve-command and --approve-timeout require --approve-each".to_string());
     }
 
-    #[test]
-    fn test_parse_start_agent_args_dry_run() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--dry-run".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(result.dry_run);
+    if options.isolation != "bwrap"
+        && (options.bwrap_unshare_net || !options.bwrap_writable.is_empty())
+    {
+        errors.push("--bwrap-* options require --isolation bwrap".to_string());
+    } else if let Err(error) = parse_bwrap_options(options) {
+        errors.push(error);
     }
 
-    #[test]
-    fn test_parse_start_agent_args_read_only() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--read-only".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(result.read_only);
+    if options.worktree {
+        if options.isolation == "docker-exec" {
+            errors.push("--worktree cannot be combined with docker-exec isolation".to_string());
+        }
+        if let Err(error) = parse_worktree_options(options) {
+            errors.push(error);
+        }
+    } else if options.worktree_branch.is_some()
+        || options.worktree_base.is_some()
+        || options.worktree_path.is_some()
+        || options.worktree_cleanup
+    {
+        errors.push("--worktree-* options require --worktree".to_string());
     }
 
-    #[test]
-    fn test_parse_start_agent_args_with_raw_passthrough_options() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--tool-executable".into(),
-            "/opt/claude".into(),
-            "--tool-env".into(),
-            "MCP_TIMEOUT=10000".into(),
-            "--tool-env".into(),
-            "CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC=1".into(),
-            "--tool-arg".into(),
-            "--mcp-config".into(),
-            "--tool-arg".into(),
-            "/tmp/mcp.json".into(),
-            "--skip-default-safety-flags".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert_eq!(result.tool_executable, Some("/opt/claude".to_string()));
-        assert_eq!(
-            result.tool_env,
-            vec![
-                "MCP_TIMEOUT=10000".to_string(),
-                "CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC=1".to_string()
-            ]
-        );
-        assert_eq!(
-            result.tool_args,
-            vec!["--mcp-config".to_string(), "/tmp/mcp.json".to_string()]
-        );
-        assert!(result.skip_default_safety_flags);
+    if options.isolation == "docker" {
+        if let Err(error) = parse_docker_options(options) {
+            errors.push(error);
+        }
+    } else if options.docker_image.is_some()
+        || !options.docker_volumes.is_empty()
+        || options.docker_network.is_some()
+        || options.docker_cpus.is_some()
+        || options.docker_memory.is_some()
+        || options.docker_user.is_some()
+        || !options.docker_env.is_empty()
+        || options.docker_rm.is_some()
+    {
+        errors.push("--docker-* options require --isolation docker".to_string());
     }
 
-    #[test]
-    fn test_parse_start_agent_args_approve_each() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--approve-each".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(result.approve_each);
+    ValidationResult {
+        valid: errors.is_empty(),
+        errors,
     }
+}
 
-    #[test]
-    fn test_parse_start_agent_args_permission_mode_ask_alias() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--permission-mode".into(),
-            "ask".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(result.approve_each);
-    }
+/// Validate stop-agent options
+///
+/// # Arguments
+/// * `options` - Parsed options
+///
+/// # Returns
+/// Validation result with valid flag and errors
+pub fn validate_stop_agent_options(options: &StopAgentOptions) -> ValidationResult {
+    let mut errors = Vec::new();
+    let tracked = options.pid.is_some() || options.run_id.is_some();
 
-    #[test]
-    fn test_parse_start_agent_args_permission_mode_other_is_not_approve_each() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--permission-mode".into(),
-            "default".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(!result.approve_each);
+    if options.isolation.is_none() && !tracked {
+        errors.push("--isolation is required".to_string());
     }
 
-    #[test]
-    fn test_parse_start_agent_args_plan_only_alias() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--plan-only".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(result.read_only);
+    if options.pid.is_some() && options.run_id.is_some() {
+        errors.push("--pid and --run-id cannot be used together".to_string());
     }
 
-    #[test]
-    fn test_parse_start_agent_args_with_model_and_fallback_model() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--model".into(),
-            "opus".into(),
-            "--fallback-model".into(),
-            "sonnet".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert_eq!(result.model, Some("opus".to_string()));
-        assert_eq!(result.fallback_model, Some("sonnet".to_string()));
+    if let Some(ref pid) = options.pid {
+        if pid.parse::<u32>().is_err() {
+            errors.push("--pid must be a process ID".to_string());
+        }
     }
 
-    #[test]
-    fn test_parse_start_agent_args_with_session_management_options() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--resume".into(),
-            "abc123".into(),
-            "--session-id".into(),
-            "123e4567-e89b-12d3-a456-426614174000".into(),
-            "--fork-session".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert_eq!(result.resume, Some("abc123".to_string()));
-        assert_eq!(
-            result.session_id,
-            Some("123e4567-e89b-12d3-a456-426614174000".to_string())
-        );
-        assert!(result.fork_session);
-    }
+    if let Some(ref isolation) = options.isolation {
+        if !ISOLATION_MODES.contains(&isolation.as_str()) {
+            errors.push(invalid_isolation_error());
+        }
 
-    #[test]
-    fn test_parse_start_agent_args_with_append_system_prompt() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--system-prompt".into(),
-            "You are helpful".into(),
-            "--append-system-prompt".into(),
-            "Extra instructions".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert_eq!(result.system_prompt, Some("You are helpful".to_string()));
-        assert_eq!(
-            result.append_system_prompt,
-            Some("Extra instructions".to_string())
-        );
-    }
+        let local_process = matches!(isolation.as_str(), "none" | "bwrap");
+        if local_process && !tracked {
+            errors.push(format!(
+                "--pid or --run-id is required for isolation {}",
+                isolation
+            ));
+        }
 
-    #[test]
-    fn test_parse_start_agent_args_with_prompt_file() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "codex".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--prompt-file".into(),
-            "/tmp/prompt.txt".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert_eq!(result.prompt_file, Some("/tmp/prompt.txt".to_string()));
-    }
+        if isolation != "docker" && options.docker_rm.is_some() {
+            errors.push("--docker-rm requires --isolation docker".to_string());
+        }
 
-    #[test]
-    fn test_parse_start_agent_args_with_verbose_and_replay_user_messages() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-            "--verbose".into(),
-            "--replay-user-messages".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(result.verbose);
-        assert!(result.replay_user_messages);
-    }
+        if let Some(Err(error)) = options.docker_rm.as_deref().map(DockerRemoval::parse) {
+            errors.push(error);
+        }
 
-    #[test]
-    fn test_parse_start_agent_args_defaults_for_new_options() {
-        let args: Vec<String> = vec![
-            "--tool".into(),
-            "claude".into(),
-            "--working-directory".into(),
-            "/tmp/test".into(),
-        ];
-        let result = parse_start_agent_args(&args);
-
-        assert!(!result.verbose);
-        assert!(!result.replay_user_messages);
-        assert!(!result.fork_session);
-        assert!(result.model.is_none());
-        assert!(result.fallback_model.is_none());
-        assert!(result.resume.is_none());
-        assert!(result.session_id.is_none());
-        assert!(result.append_system_prompt.is_none());
-        assert!(result.prompt_file.is_none());
-    }
+        if isolation == "docker-exec" {
+            if options.run_id.is_none() {
+                errors.push("--run-id is required for docker-exec isolation".to_string());
+            }
+            if options.container_name.is_none() {
+                errors.push("--container-name is required for docker-exec isolation".to_string());
+            }
+            if options.pid.is_some() {
+                errors.push("--pid is only supported for isolation none or bwrap".to_string());
+            }
+        } else if !local_process && tracked {
+            errors.push(
+                "--pid and --run-id are only supported for isolation none or bwrap".to_string(),
+            );
+        }
 
-    #[test]
-    fn test_parse_stop_agent_args_screen() {
-        let args: Vec<String> = vec![
-            "--isolation".into(),
-            "screen".into(),
-            "--screen-name".into(),
-            "my-session".into(),
-        ];
-        let result = parse_stop_agent_args(&args);
-
-        assert_eq!(result.isolation, Some("screen".to_string()));
-        assert_eq!(result.screen_name, Some("my-session".to_string()));
-    }
+        if isolation == "screen" && options.screen_name.is_none() {
+            errors.push("--screen-name is required for screen isolation".to_string());
+        }
 
-    #[test]
-    fn test_validate_start_agent_options_valid() {
-        let options = StartAgentOptions {
-            tool: Some("claude".to_string()),
-            working_directory: Some("/tmp/test".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-        let result = validate_start_agent_options(&options);
-
-        assert!(result.valid);
-        assert!(result.errors.is_empty());
-    }
+        if isolation == "tmux" && options.tmux_session.is_none() {
+            errors.push("--tmux-session is required for tmux isolation".to_string());
+        }
 
-    #[test]
-    fn test_validate_start_agent_options_missing_tool() {
-        let options = StartAgentOptions {
-            working_directory: Some("/tmp/test".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-        let result = validate_start_agent_options(&options);
-
-        assert!(!result.valid);
-        assert!(result.errors.iter().any(|e| e.contains("tool")));
-    }
+        if isolation == "docker" && options.container_name.is_none() {
+            errors.push("--container-name is required for docker isolation".to_string());
+        }
 
-    #[test]
-    fn test_validate_start_agent_options_screen_without_name() {
-        let options = StartAgentOptions {
-            tool: Some("claude".to_string()),
-            working_directory: Some("/tmp/test".to_string()),
-            isolation: "screen".to_string(),
-            ..Default::default()
-        };
-        let result = validate_start_agent_options(&options);
-
-        assert!(!result.valid);
-        assert!(result.errors.iter().any(|e| e.contains("screen-name")));
+        if isolation == "docker-exec" && options.worktree {
+            errors.push("--worktree cannot be combined with docker-exec isolation".to_string());
+        }
     }
 
-    #[test]
-    fn test_validate_stop_agent_options_valid() {
-        let options = StopAgentOptions {
-            isolation: Some("screen".to_string()),
-            screen_name: Some("my-session".to_string()),
-            ..Default::default()
-        };
-        let result = validate_stop_agent_options(&options);
-
-        assert!(result.valid);
-        assert!(result.errors.is_empty());
+    if options.worktree {
+        if options.worktree_branch.is_none() && options.worktree_path.is_none() && !tracked {
+            errors.push(
+                "--worktree-branch or --worktree-path is required for --worktree".to_string(),
+            );
+        }
+        if let Err(error) = parse_stop_worktree_options(options) {
+            errors.push(error);
+        }
+    } else if options.worktree_branch.is_some()
+        || options.worktree_path.is_some()
+        || options.worktree_cleanup
+    {
+        errors.push("--worktree-* options require --worktree".to_string());
     }
 
-    #[test]
-    fn test_validate_stop_agent_options_missing_isolation() {
-        let options = StopAgentOptions::default();
-        let result = validate_stop_agent_options(&options);
-
-        assert!(!result.valid);
-        assert!(result.errors.iter().any(|e| e.contains("isolation")));
+    ValidationResult {
+        valid: errors.is_empty(),
+        errors,
     }
 }
+
+// Tests are in rust/tests/cli_parser_tests.rs
@@ -1,5 +1,11 @@
-//! Build command strings for different agent tools
+//! Build launch specs and command strings for different agent tools
 
+use crate::isolation::{
+    build_bwrap_launch_spec, build_docker_exec_launch_spec, build_docker_launch_spec,
+    build_screen_launch_spec, build_tmux_launch_spec, BwrapOptions, CaptureFiles,
+    DockerExecOptions, DockerOptions,
+};
+use crate::launch::{quote_shell_arg, LaunchSpec};
 use crate::tools::{
     agent::{self, AgentBuildOptions},
     claude::{self, ClaudeBuildOptions},
@@ -9,6 +15,53 @@ use crate::tools::{
     opencode::{self, OpencodeBuildOptions},
     qwen::{self, QwenBuildOptions},
 };
+use crate::AgentOptions;
+
+/// Whether a tool can read its prompt from a file on stdin
+fn supports_prompt_file_input(tool: &str) -> bool {
+    matches!(
+        tool,
+        "claude" | "codex" | "opencode" | "agent" | "qwen" | "gemini"
+    )
+}
+
+/// Whether `start` writes the prompt to a temporary file the tool reads on
+/// stdin
+pub(crate) fn should_create_prompt_file(options: &AgentOptions, dry_run: bool) -> bool {
+    // Ask mode sends the prompt as the first stream-json frame instead
+    if dry_run
+        || options.prompt_file.is_some()
+        || options.approve_each
+        || !supports_prompt_file_input(&options.tool)
+    {
+        return false;
+    }
+
+    if options.tool == "claude" {
+        return options.prompt.is_some();
+    }
+
+    options.prompt.is_some() || options.system_prompt.is_some()
+}
+
+/// Content of a generated prompt file; Claude gets its system prompt as a
+/// flag, the other tools read it ahead of the prompt
+pub(crate) fn build_prompt_file_content(
+    tool: &str,
+    prompt: Option<&str>,
+    system_prompt: Option<&str>,
+) -> String {
+    if tool == "claude" {
+        return prompt.unwrap_or_default().to_string();
+    }
+
+    match (system_prompt, prompt) {
+        (Some(system_prompt), Some(prompt)) => format!("{}\n\n{}", system_prompt, prompt),
+        (Some(system_prompt), None) => system_prompt.to_string(),
+        (None, Some(prompt)) => prompt.to_string(),
+        (None, None) => String::new(),
+    }
+}
 
 /// Agent command build options
 #[derive(Debug, Clone, Default)]
@@ -31,13 +84,27 @@ pub struct AgentCommandOptions {
     pub plan_only: bool,
     /// Approve each mutating command (`--permission-mode ask` / `--approve-each`)
     pub approve_each: bool,
+    /// OpenCode-compatible JSON permission policy (`agent --permission`)
+    pub permission: Option<String>,
     pub executable: Option<String>,
     pub extra_args: Vec<String>,
     pub extra_env: Vec<(String, String)>,
     pub skip_default_safety_flags: bool,
     pub isolation: String,
     pub screen_name: Option<String>,
+    pub tmux_session: Option<String>,
     pub container_name: Option<String>,
+    /// Container settings (docker isolation)
+    pub docker: DockerOptions,
+    /// Directory for the session transcript and exit code (screen and tmux
+    /// isolation, and docker with `auto` removal)
+    pub capture_dir: Option<String>,
+    /// Container working directory and user (docker-exec isolation)
+    pub docker_exec: DockerExecOptions,
+    /// Detached run directory name inside the container (docker-exec isolation)
+    pub run_id: Option<String>,
+    /// Network and extra writable paths (bwrap isolation)
+    pub bwrap: BwrapOptions,
     pub detached: bool,
 }
 
@@ -57,108 +124,54 @@ pub fn read_only_unsupported_error(tool: &str) -> String {
     )
 }
 
-/// Escape quotes in strings for shell commands (single quotes)
-fn escape_quotes(s: &str) -> String {
-    s.replace('\'', "'\\''")
-}
-
-/// Escape strings for use inside bash -c "..."
-fn escape_for_bash_c(s: &str) -> String {
-    s.replace('\\', "\\\\")
-        .replace('"', "\\\"")
-        .replace('$', "\\$")
-        .replace('`', "\\`")
-}
-
-/// Build the base tool command (generic)
-fn build_tool_command(tool: &str, prompt: Option<&str>, system_prompt: Option<&str>) -> String {
-    let mut command = tool.to_string();
+/// Build the base tool launch spec (generic)
+fn build_tool_launch_spec(
+    tool: &str,
+    prompt: Option<&str>,
+    system_prompt: Option<&str>,
+) -> LaunchSpec {
+    let mut spec = LaunchSpec::new(tool);
 
     if let Some(p) = prompt {
-        command.push_str(&format!(" --prompt \"{}\"", escape_quotes(p)));
+        spec.args.push("--prompt".to_string());
+        spec.args.push(p.to_string());
     }
 
     if let Some(sp) = system_prompt {
-        command.push_str(&format!(" --system-prompt \"{}\"", escape_quotes(sp)));
+        spec.args.push("--system-prompt".to_string());
+        spec.args.push(sp.to_string());
     }
 
-    command
-}
-
-/// Build screen isolation command
-fn build_screen_command(base_command: &str, screen_name: Option<&str>, detached: bool) -> String {
-    let session_name = screen_name.map(|s| s.to_string()).unwrap_or_else(|| {
-        format!(
-            "agent-{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        )
-    });
-
-    if detached {
-        // Start detached screen session
-        format!(
-            "screen -dmS \"{}\" bash -c '{}'",
-            session_name,
-            escape_quotes(base_command)
-        )
-    } else {
-        // Start attached screen session
-        format!(
-            "screen -S \"{}\" bash -c '{}'",
-            session_name,
-            escape_quotes(base_command)
-        )
-    }
-}
-
-/// Build docker isolation command
-fn build_docker_command(
-    base_command: &str,
-    container_name: Option<&str>,
-    working_directory: &str,
-    detached: bool,
-) -> String {
-    let name = container_name.map(|s| s.to_string()).unwrap_or_else(|| {
-        format!(
-            "agent-{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-        )
-    });
-
-    let mut command = "docker run".to_string();
-
-    if detached {
-        command.push_str(" -d");
-    } else {
-        command.push_str(" -it");
-    }
-
-    command.push_str(&format!(" --name \"{}\"", name));
-    command.push_str(&format!(
-        " -v \"{}:{}\"",
-        working_directory, working_directory
-    ));
-    command.push_str(&format!(" -w \"{}\"", working_directory));
-    command.push_str(" node:18-slim");
-    command.push_str(&format!(" bash -c '{}'", escape_quotes(base_command)));
-
-    command
+    spec
 }
 
 /// Build the command for executing an agent
 ///
+/// The string is only meant for display (dry runs); the agent itself is
+/// started from [`build_agent_launch_spec`] without a shell.
+///
 /// # Arguments
 /// * `options` - Command options
 ///
 /// # Returns
 /// The command string
 pub fn build_agent_command(options: &AgentCommandOptions) -> String {
+    build_agent_launch_spec(options).to_shell_command()
+}
+
+/// Build the shell-free launch spec for executing an agent
+///
+/// Without isolation the spec runs the tool directly in the working
+/// directory. With `screen`, `tmux`, `docker` or `docker-exec` isolation it
+/// runs the wrapper, which gets the tool's rendered shell command as its
+/// inner command; `bwrap` wraps the tool's argv instead.
+///
+/// # Arguments
+/// * `options` - Command options
+///
+/// # Returns
+/// Program, argv, cwd, environment and stdin source
+pub fn build_agent_launch_spec(options: &AgentCommandOptions) -> LaunchSpec {
     // A planning request implies a read-only restriction for tools that do not
     // distinguish the two modes.
     let read_only_requested = options.read_only || options.plan_only;
@@ -170,32 +183,37 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
     );
 
     // Per-command approval ("ask" mode) is only enforceable on tools that expose
-    // a drivable JSON permission request/response protocol (agent, claude). Fail
-    // clearly for the rest, mirroring the --read-only gate above.
+    // a drivable JSON permission request/response protocol (agent, claude, and
+    // codex through its app-server). Fail clearly for the rest, mirroring the
+    // --read-only gate above.
     assert!(
         !(options.approve_each && !crate::permissions::supports_ask(&options.tool)),
         "{}",
         crate::permissions::ask_unsupported_error(&options.tool)
     );
 
-    // Build base command using tool-specific builder if available
-    let base_command = if is_tool_supported(&options.tool) {
+    // Build base spec using tool-specific builder if available
+    let mut spec = if is_tool_supported(&options.tool) {
         match options.tool.as_str() {
-            "claude" => claude::build_command(&ClaudeBuildOptions {
+            // Ask mode runs a headless stream-json session in both directions:
+            // permission requests arrive on stdout and the prompt and decisions
+            // are written to stdin by the controller's relay (codex speaks
+            // JSON-RPC as `codex app-server` instead)
+            "claude" => claude::build_launch_spec(&ClaudeBuildOptions {
                 prompt: options.prompt.clone(),
                 prompt_file: options.prompt_file.clone(),
                 system_prompt: options.system_prompt.clone(),
                 append_system_prompt: options.append_system_prompt.clone(),
                 model: options.model.clone(),
                 fallback_model: options.fallback_model.clone(),
-                json: options.json,
-                json_input: false,
-                verbose: options.verbose,
+                json: options.json || options.approve_each,
+                json_input: options.approve_each,
+                verbose: options.verbose || options.approve_each,
                 replay_user_messages: options.replay_user_messages,
                 resume: options.resume.clone(),
                 session_id: options.session_id.clone(),
                 fork_session: options.fork_session,
-                print: false,
+                print: options.approve_each,
                 read_only: read_only_requested,
                 approve_each: options.approve_each,
                 executable: options.executable.clone(),
@@ -203,9 +221,9 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
                 extra_args: options.extra_args.clone(),
                 skip_default_safety_flags: options.skip_default_safety_flags,
                 permission_mode: None,
-                stream_input: false,
+                stream_input: options.approve_each,
             }),
-            "codex" => codex::build_command(&CodexBuildOptions {
+            "codex" => codex::build_launch_spec(&CodexBuildOptions {
                 prompt: options.prompt.clone(),
                 prompt_file: options.prompt_file.clone(),
                 system_prompt: options.system_prompt.clone(),
@@ -219,8 +237,9 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
                 skip_default_safety_flags: options.skip_default_safety_flags,
                 sandbox_mode: None,
                 approval_mode: None,
+                app_server: options.approve_each,
             }),
-            "opencode" => opencode::build_command(&OpencodeBuildOptions {
+            "opencode" => opencode::build_launch_spec(&OpencodeBuildOptions {
                 prompt: options.prompt.clone(),
                 prompt_file: options.prompt_file.clone(),
                 system_prompt: options.system_prompt.clone(),
@@ -232,7 +251,7 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
                 extra_env: options.extra_env.clone(),
                 extra_args: options.extra_args.clone(),
             }),
-            "agent" => agent::build_command(&AgentBuildOptions {
+            "agent" => agent::build_launch_spec(&AgentBuildOptions {
                 prompt: options.prompt.clone(),
                 prompt_file: options.prompt_file.clone(),
                 system_prompt: options.system_prompt.clone(),
@@ -243,8 +262,8 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
                 plan_only: options.plan_only,
                 approve_each: options.approve_each,
                 permission_mode: None,
-                permission: None,
-                stream_input: false,
+                permission: options.permission.clone(),
+                stream_input: options.approve_each,
                 executable: options.executable.clone(),
                 extra_env: options.extra_env.clone(),
                 extra_args: options.extra_args.clone(),
@@ -263,7 +282,7 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
                     skip_default_safety_flags: options.skip_default_safety_flags,
                     ..GeminiBuildOptions::new()
                 };
-                gemini::build_command(&options)
+                gemini::build_launch_spec(&options)
             }
             "qwen" => {
                 let options = QwenBuildOptions {
@@ -280,9 +299,9 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
                     skip_default_safety_flags: options.skip_default_safety_flags,
                     ..QwenBuildOptions::new()
                 };
-                qwen::build_command(&options)
+                qwen::build_launch_spec(&options)
             }
-            _ => build_tool_command(
+            _ => build_tool_launch_spec(
                 &options.tool,
                 options.prompt.as_deref(),
                 options.system_prompt.as_deref(),
@@ -290,66 +309,64 @@ pub fn build_agent_command(options: &AgentCommandOptions) -> String {
         }
     } else {
         // Unknown tool, use generic command builder
-        build_tool_command(
+        build_tool_launch_spec(
             &options.tool,
             options.prompt.as_deref(),
             options.system_prompt.as_deref(),
         )
     };
 
-    // Wrap in bash -c with working directory change
-    let mut full_command = format!(
-        "bash -c \"cd {} && {}\"",
-        escape_for_bash_c(&options.working_directory),
-        escape_for_bash_c(&base_command)
-    );
+    spec.cwd = Some(options.working_directory.clone().into());
 
     // Apply isolation wrapper
     match options.isolation.as_str() {
-        "screen" => {
-            full_command = build_screen_command(
-                &full_command,
-                options.screen_name.as_deref(),
-                options.detached,
-            );
-        }
-        "docker" => {
-            full_command = build_docker_command(
-                &full_command,
-                options.container_name.as_deref(),
-                &options.working_directory,
-                options.detached,
-            );
-        }
-        _ => {}
+        "screen" => build_screen_launch_spec(
+            &spec.to_shell_command(),
+            options.screen_name.as_deref(),
+            options.detached,
+            options
+                .capture_dir
+                .as_ref()
+                .map(CaptureFiles::in_dir)
+                .as_ref(),
+        ),
+        "tmux" => build_tmux_launch_spec(
+            &spec.to_shell_command(),
+            options.tmux_session.as_deref(),
+            options.detached,
+            options
+                .capture_dir
+                .as_ref()
+                .map(CaptureFiles::in_dir)
+                .as_ref(),
+        ),
+        "docker" => build_docker_launch_spec(
+            &spec.to_shell_command(),
+            options.container_name.as_deref(),
+            &options.working_directory,
+            options.detached,
+            &options.docker,
+            options
+                .capture_dir
+                .as_ref()
+                .map(CaptureFiles::in_dir)
+                .as_ref(),
+        ),
+        "bwrap" => build_bwrap_launch_spec(
+            &spec,
+            &options.working_directory,
+            read_only_requested,
+            &options.bwrap,
+        ),
+        "docker-exec" => build_docker_exec_launch_spec(
+            &spec,
+            options.container_name.as_deref().unwrap_or_default(),
+            options.run_id.as_deref().unwrap_or_default(),
+            options.detached,
+            &options.docker_exec,
+        ),
+        _ => spec,
     }
-
-    full_command
-}
-
-/// Build stop command for screen sessions
-///
-/// # Arguments
-/// * `screen_name` - Screen session name
-///
-/// # Returns
-/// Stop command
-pub fn build_screen_stop_command(screen_name: &str) -> String {
-    format!("screen -S \"{}\" -X quit", screen_name)
-}
-
-/// Build stop command for docker containers
-///
-/// # Arguments
-/// * `container_name` - Container name
-///
-/// # Returns
-/// Stop command
-pub fn build_docker_stop_command(container_name: &str) -> String {
-    format!(
-        "docker stop \"{}\" && docker rm \"{}\"",
-        container_name, container_name
-    )
 }
 
 /// Build stdin piping command for tools that accept input via stdin
@@ -361,608 +378,7 @@ pub fn build_docker_stop_command(container_name: &str) -> String {
 /// # Returns
 /// Piped command
 pub fn build_piped_command(input: &str, command: &str) -> String {
-    let escaped_input = escape_quotes(input);
-    format!("printf '%s' '{}' | {}", escaped_input, command)
+    format!("printf '%s' {} | {}", quote_shell_arg(input), command)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_build_agent_command_basic_claude() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Hello".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("bash -c"));
-        assert!(command.contains("cd"));
-        assert!(command.contains("/tmp/test"));
-        assert!(command.contains("claude"));
-        assert!(command.contains("--prompt"));
-        assert!(command.contains("Hello"));
-    }
-
-    #[test]
-    fn test_build_agent_command_with_system_prompt() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Hello".to_string()),
-            system_prompt: Some("You are helpful".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--prompt"));
-        assert!(command.contains("--system-prompt"));
-        assert!(command.contains("You are helpful"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_with_fallback_model() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            model: Some("opus".to_string()),
-            fallback_model: Some("sonnet".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--model"));
-        assert!(command.contains("claude-opus-4-7"));
-        assert!(command.contains("--fallback-model"));
-        assert!(command.contains("claude-sonnet-4-6"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_with_append_system_prompt() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            append_system_prompt: Some("Extra instructions".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--append-system-prompt"));
-        assert!(command.contains("Extra instructions"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_with_session_management() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            resume: Some("abc123".to_string()),
-            session_id: Some("123e4567-e89b-12d3-a456-426614174000".to_string()),
-            fork_session: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--resume"));
-        assert!(command.contains("abc123"));
-        assert!(command.contains("--session-id"));
-        assert!(command.contains("123e4567-e89b-12d3-a456-426614174000"));
-        assert!(command.contains("--fork-session"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_with_verbose_streaming() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            verbose: true,
-            replay_user_messages: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--verbose"));
-        assert!(command.contains("--replay-user-messages"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_raw_passthrough() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Hello".to_string()),
-            executable: Some("/opt/Claude Code/bin/claude".to_string()),
-            extra_env: vec![
-                (
-                    "CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC".to_string(),
-                    "1".to_string(),
-                ),
-                ("MCP_TIMEOUT".to_string(), "10000".to_string()),
-            ],
-            extra_args: vec![
-                "--mcp-config".to_string(),
-                "/tmp/mcp config.json".to_string(),
-                "--permission-mode".to_string(),
-                "default".to_string(),
-            ],
-            skip_default_safety_flags: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("env"));
-        assert!(command.contains("CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC=1"));
-        assert!(command.contains("MCP_TIMEOUT=10000"));
-        assert!(command.contains("/opt/Claude Code/bin/claude"));
-        assert!(command.contains("--mcp-config"));
-        assert!(command.contains("/tmp/mcp config.json"));
-        assert!(command.contains("--permission-mode"));
-        assert!(command.contains("default"));
-        assert!(!command.contains("--dangerously-skip-permissions"));
-    }
-
-    #[test]
-    fn test_build_agent_command_qwen_raw_passthrough() {
-        let options = AgentCommandOptions {
-            tool: "qwen".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt_file: Some("/tmp/prompt.txt".to_string()),
-            executable: Some("/opt/qwen code/qwen".to_string()),
-            extra_env: vec![("QWEN_HOME".to_string(), "/tmp/qwen home".to_string())],
-            extra_args: vec![
-                "--checkpointing".to_string(),
-                "--approval-mode".to_string(),
-                "default".to_string(),
-            ],
-            skip_default_safety_flags: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("cat"));
-        assert!(command.contains("/tmp/prompt.txt"));
-        assert!(command.contains("| env QWEN_HOME="));
-        assert!(command.contains("/tmp/qwen home"));
-        assert!(command.contains("/opt/qwen code/qwen"));
-        assert!(command.contains("--checkpointing"));
-        assert!(command.contains("--approval-mode"));
-        assert!(command.contains("default"));
-        assert!(!command.contains("--yolo"));
-    }
-
-    #[test]
-    fn test_build_agent_command_gemini_raw_passthrough() {
-        let options = AgentCommandOptions {
-            tool: "gemini".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt_file: Some("/tmp/prompt.txt".to_string()),
-            executable: Some("/opt/gemini cli/gemini".to_string()),
-            extra_env: vec![("GEMINI_HOME".to_string(), "/tmp/gemini home".to_string())],
-            extra_args: vec!["--telemetry".to_string(), "false".to_string()],
-            skip_default_safety_flags: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("cat"));
-        assert!(command.contains("/tmp/prompt.txt"));
-        assert!(command.contains("| env GEMINI_HOME="));
-        assert!(command.contains("/tmp/gemini home"));
-        assert!(command.contains("/opt/gemini cli/gemini"));
-        assert!(command.contains("--telemetry"));
-        assert!(command.contains("false"));
-        assert!(!command.contains("--yolo"));
-    }
-
-    #[test]
-    fn test_build_agent_command_unknown_tool() {
-        let options = AgentCommandOptions {
-            tool: "unknown-tool".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Hello".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("bash -c"));
-        assert!(command.contains("unknown-tool"));
-        assert!(command.contains("--prompt"));
-    }
-
-    #[test]
-    fn test_build_agent_command_screen_isolation() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            isolation: "screen".to_string(),
-            screen_name: Some("my-session".to_string()),
-            detached: true,
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("screen"));
-        assert!(command.contains("-dmS"));
-        assert!(command.contains("my-session"));
-    }
-
-    #[test]
-    fn test_build_agent_command_docker_isolation() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            isolation: "docker".to_string(),
-            container_name: Some("my-container".to_string()),
-            detached: true,
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("docker run"));
-        assert!(command.contains("-d"));
-        assert!(command.contains("--name \"my-container\""));
-        assert!(command.contains("-v \"/tmp/test:/tmp/test\""));
-    }
-
-    #[test]
-    fn test_build_agent_command_with_model() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            model: Some("opus".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--model"));
-        assert!(command.contains("claude-opus-4-7"));
-    }
-
-    #[test]
-    fn test_build_agent_command_codex() {
-        let options = AgentCommandOptions {
-            tool: "codex".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Hello".to_string()),
-            json: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("codex"));
-        assert!(command.contains("exec"));
-        assert!(command.contains("--json"));
-    }
-
-    #[test]
-    fn test_build_agent_command_codex_prompt_file() {
-        let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
-        let options = AgentCommandOptions {
-            tool: "codex".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some(inline_prompt.to_string()),
-            system_prompt: Some("System instructions".to_string()),
-            prompt_file: Some("/tmp/agent prompt.txt".to_string()),
-            json: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("cat"));
-        assert!(command.contains("/tmp/agent prompt.txt"));
-        assert!(command.contains("codex"));
-        assert!(!command.contains(inline_prompt));
-        assert!(!command.contains("System instructions"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_prompt_file() {
-        let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some(inline_prompt.to_string()),
-            system_prompt: Some("You are helpful".to_string()),
-            prompt_file: Some("/tmp/agent prompt.txt".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("cat"));
-        assert!(command.contains("/tmp/agent prompt.txt"));
-        assert!(command.contains("claude"));
-        assert!(command.contains("--system-prompt"));
-        assert!(command.contains("You are helpful"));
-        assert!(!command.contains(inline_prompt));
-    }
-
-    #[test]
-    fn test_build_agent_command_qwen_prompt_file() {
-        let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
-        let options = AgentCommandOptions {
-            tool: "qwen".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some(inline_prompt.to_string()),
-            system_prompt: Some("System instructions".to_string()),
-            prompt_file: Some("/tmp/agent prompt.txt".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("cat"));
-        assert!(command.contains("/tmp/agent prompt.txt"));
-        assert!(command.contains("qwen"));
-        assert!(!command.contains(inline_prompt));
-        assert!(!command.contains("System instructions"));
-    }
-
-    #[test]
-    fn test_build_agent_command_gemini_prompt_file() {
-        let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
-        let options = AgentCommandOptions {
-            tool: "gemini".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some(inline_prompt.to_string()),
-            system_prompt: Some("System instructions".to_string()),
-            prompt_file: Some("/tmp/agent prompt.txt".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("cat"));
-        assert!(command.contains("/tmp/agent prompt.txt"));
-        assert!(command.contains("gemini"));
-        assert!(!command.contains(inline_prompt));
-        assert!(!command.contains("System instructions"));
-    }
-
-    #[test]
-    fn test_build_agent_command_opencode() {
-        let options = AgentCommandOptions {
-            tool: "opencode".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Hello".to_string()),
-            json: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("opencode"));
-        assert!(command.contains("run"));
-        assert!(command.contains("--format"));
-    }
-
-    #[test]
-    fn test_build_agent_command_agent_tool() {
-        let options = AgentCommandOptions {
-            tool: "agent".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            model: Some("grok".to_string()),
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("agent"));
-        assert!(command.contains("--model"));
-        assert!(command.contains("opencode/grok-code"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_read_only() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Plan only".to_string()),
-            read_only: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--permission-mode"));
-        assert!(command.contains("plan"));
-        assert!(!command.contains("--dangerously-skip-permissions"));
-    }
-
-    #[test]
-    fn test_build_agent_command_codex_read_only() {
-        let options = AgentCommandOptions {
-            tool: "codex".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Plan only".to_string()),
-            read_only: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("codex --ask-for-approval never exec"));
-        assert!(command.contains("--sandbox"));
-        assert!(command.contains("read-only"));
-        assert!(!command.contains("--dangerously-bypass-approvals-and-sandbox"));
-    }
-
-    #[test]
-    fn test_build_agent_command_opencode_read_only() {
-        let options = AgentCommandOptions {
-            tool: "opencode".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Plan only".to_string()),
-            read_only: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("OPENCODE_PERMISSION="));
-        assert!(command.contains("bash"));
-        assert!(command.contains("edit"));
-        assert!(command.contains("deny"));
-    }
-
-    #[test]
-    fn test_build_agent_command_agent_read_only_uses_readonly_mode() {
-        let options = AgentCommandOptions {
-            tool: "agent".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Inspect only".to_string()),
-            read_only: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--permission-mode"));
-        assert!(command.contains("readonly"));
-        assert!(!command.contains("plan"));
-    }
-
-    #[test]
-    fn test_build_agent_command_agent_plan_only_uses_plan_mode() {
-        let options = AgentCommandOptions {
-            tool: "agent".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Plan only".to_string()),
-            plan_only: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--permission-mode"));
-        assert!(command.contains("plan"));
-        assert!(!command.contains("readonly"));
-    }
-
-    #[test]
-    fn test_build_agent_command_agent_approve_each_uses_ask_mode() {
-        let options = AgentCommandOptions {
-            tool: "agent".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Do work".to_string()),
-            approve_each: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--permission-mode"));
-        assert!(command.contains("ask"));
-        // Ask mode requires streaming stdin so requests can be answered mid-turn.
-        assert!(command.contains("--input-format"));
-        assert!(command.contains("stream-json"));
-        assert!(!command.contains("readonly"));
-    }
-
-    #[test]
-    fn test_build_agent_command_claude_approve_each_uses_default_mode() {
-        let options = AgentCommandOptions {
-            tool: "claude".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            prompt: Some("Do work".to_string()),
-            approve_each: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let command = build_agent_command(&options);
-        assert!(command.contains("--permission-mode"));
-        assert!(command.contains("default"));
-        // Default mode keeps Claude's own per-tool prompting active instead of
-        // bypassing it.
-        assert!(!command.contains("--dangerously-skip-permissions"));
-        assert!(!command.contains("plan"));
-    }
-
-    #[test]
-    #[should_panic(expected = "does not support enforceable per-command approval")]
-    fn test_build_agent_command_approve_each_rejects_codex() {
-        let options = AgentCommandOptions {
-            tool: "codex".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            approve_each: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let _command = build_agent_command(&options);
-    }
-
-    #[test]
-    #[should_panic(expected = "does not support enforceable per-command approval")]
-    fn test_build_agent_command_approve_each_rejects_unknown_tool() {
-        let options = AgentCommandOptions {
-            tool: "unknown-tool".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            approve_each: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let _command = build_agent_command(&options);
-    }
-
-    #[test]
-    #[should_panic(expected = "does not support enforceable read-only mode")]
-    fn test_build_agent_command_read_only_rejects_unknown_tool() {
-        let options = AgentCommandOptions {
-            tool: "unknown-tool".to_string(),
-            working_directory: "/tmp/test".to_string(),
-            read_only: true,
-            isolation: "none".to_string(),
-            ..Default::default()
-        };
-
-        let _command = build_agent_command(&options);
-    }
-
-    #[test]
-    fn test_build_screen_stop_command() {
-        let command = build_screen_stop_command("my-session");
-        assert!(command.contains("screen"));
-        assert!(command.contains("-S \"my-session\""));
-        assert!(command.contains("-X quit"));
-    }
-
-    #[test]
-    fn test_build_docker_stop_command() {
-        let command = build_docker_stop_command("my-container");
-        assert!(command.contains("docker stop \"my-container\""));
-        assert!(command.contains("docker rm \"my-container\""));
-    }
-
-    #[test]
-    fn test_build_piped_command_basic() {
-        let command = build_piped_command("Hello World", "mycommand --flag");
-        assert!(command.contains("printf '%s'"));
-        assert!(command.contains("Hello World"));
-        assert!(command.contains("mycommand --flag"));
-    }
-
-    #[test]
-    fn test_build_piped_command_escapes_quotes() {
-        let command = build_piped_command("It's working", "mycommand");
-        assert!(command.contains("'\\''"));
-    }
-}
+// Tests are in rust/tests/command_builder_tests.rs
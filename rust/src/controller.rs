@@ -0,0 +1,989 @@
+//! Agent controller: starts a tool, tracks the run and collects its result
+
+use crate::command_builder::{build_prompt_file_content, should_create_prompt_file};
+use crate::executor::{self, execute_launch, OutputLine, ProcessHandle};
+use crate::isolation::{
+    build_docker_exec_stop_command, build_worktree_add_command, create_worktree, discard_worktree,
+    finish_worktree, generate_isolation_name, is_valid_exec_run_id, mount_worktree_git,
+    stop_docker_exec_run, wait_for_docker_exec_until, WorktreeInfo, WorktreeOptions,
+};
+use crate::permissions::{read_relay_prompt, start_permission_relay};
+use crate::{
+    ask_unsupported_error, build_agent_launch_spec, build_docker_stop_command,
+    build_normalized_result_metadata, build_screen_stop_command, build_tmux_stop_command,
+    create_output_stream, execute_detached_launch, is_tool_supported, parse_ndjson_line,
+    read_only_unsupported_error, resolve_docker_user, start_launch, stop_docker_container,
+    stop_screen_session, stop_tmux_session, supports_ask, supports_read_only, tools,
+    AgentCommandOptions, AgentOptions, AgentResult, AgentStartOptions, AgentStopOptions,
+    BuildMetadataOptions, CaptureFiles, DockerRemoval, HandledPermission, JsonOutputStream,
+    ProcessGroup, ProcessSignal, RunRecord, RunRegistry,
+};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::task::JoinHandle;
+
+/// Live stream of parsed NDJSON messages from a running agent
+///
+/// Obtained from [`Agent::events`]. Each message is yielded as soon as the
+/// agent's stdout line is read; the final [`AgentResult`] from
+/// [`Agent::stop`] still contains every message.
+#[derive(Debug)]
+pub struct AgentEvents {
+    lines: UnboundedReceiver<String>,
+}
+
+impl AgentEvents {
+    /// Receive the next parsed message
+    ///
+    /// Non-JSON lines are skipped. Returns `None` once the agent's stdout closes.
+    pub async fn recv(&mut self) -> Option<Value> {
+        while let Some(line) = self.lines.recv().await {
+            if let Some(message) = parse_ndjson_line(&line) {
+                return Some(message);
+            }
+        }
+        None
+    }
+}
+
+/// Agent controller
+pub struct Agent {
+    options: AgentOptions,
+    process_handle: Option<ProcessHandle>,
+    output_stream: Option<JsonOutputStream>,
+    session_id: Option<String>,
+    prompt_temp_dir: Option<PathBuf>,
+    run: Option<RunRecord>,
+    worktree: Option<WorktreeInfo>,
+    permission_relay: Option<JoinHandle<Vec<HandledPermission>>>,
+}
+
+impl Agent {
+    /// Create a new agent controller
+    ///
+    /// # Arguments
+    /// * `options` - Agent configuration
+    ///
+    /// # Returns
+    /// Result with Agent or error message
+    pub fn new(options: AgentOptions) -> Result<Self, String> {
+        // Validate required options
+        if options.tool.is_empty() {
+            return Err("tool is required".to_string());
+        }
+        if options.working_directory.is_empty() {
+            return Err("working_directory is required".to_string());
+        }
+        if options.isolation == "screen" && options.screen_name.as_deref().is_none_or(str::is_empty)
+        {
+            return Err("screen_name is required for screen isolation".to_string());
+        }
+        if options.isolation == "tmux" && options.tmux_session.as_deref().is_none_or(str::is_empty)
+        {
+            return Err("tmux_session is required for tmux isolation".to_string());
+        }
+        if options.isolation == "bwrap" {
+            if !cfg!(target_os = "linux") {
+                return Err("bwrap isolation is only supported on Linux".to_string());
+            }
+            options.bwrap.validate()?;
+        }
+        if options.isolation == "docker"
+            && options.container_name.as_deref().is_none_or(str::is_empty)
+        {
+            return Err("container_name is required for docker isolation".to_string());
+        }
+        if options.isolation == "docker" {
+            options.docker.validate()?;
+        }
+        if options.isolation == "docker-exec" {
+            if options.container_name.is_none() {
+                return Err("container_name is required for docker-exec isolation".to_string());
+            }
+            options.docker_exec.validate()?;
+            if let Some(ref run_id) = options.run_id {
+                if !is_valid_exec_run_id(run_id) {
+                    return Err(format!("Invalid run ID \"{}\"", run_id));
+                }
+            }
+        }
+        if let Some(ref worktree) = options.worktree {
+            // The existing container cannot see a worktree created on the host
+            if options.isolation == "docker-exec" {
+                return Err("worktree cannot be combined with docker-exec isolation".to_string());
+            }
+            worktree.validate()?;
+        }
+        if (options.read_only || options.plan_only) && !supports_read_only(&options.tool) {
+            return Err(read_only_unsupported_error(&options.tool));
+        }
+        if options.approve_each && !supports_ask(&options.tool) {
+            return Err(ask_unsupported_error(&options.tool));
+        }
+        if options.approve_each && !matches!(options.isolation.as_str(), "none" | "" | "bwrap") {
+            return Err(
+                "approve_each requires isolation none or bwrap, so permission requests can be answered"
+                    .to_string(),
+            );
+        }
+
+        Ok(Self {
+            options,
+            process_handle: None,
+            output_stream: None,
+            session_id: None,
+            prompt_temp_dir: None,
+            run: None,
+            worktree: None,
+            permission_relay: None,
+        })
+    }
+
+    async fn cleanup_prompt_temp_dir(&mut self) {
+        if let Some(dir) = self.prompt_temp_dir.take() {
+            let _ = tokio::fs::remove_dir_all(dir).await;
+        }
+    }
+
+    async fn prepare_prompt_file(&mut self, dry_run: bool) -> Result<Option<String>, String> {
+        if !should_create_prompt_file(&self.options, dry_run) {
+            return Ok(self.options.prompt_file.clone());
+        }
+
+        let unique_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!(
+            "agent-commander-{}-{}",
+            std::process::id(),
+            unique_id
+        ));
+        tokio::fs::create_dir(&temp_dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        self.prompt_temp_dir = Some(temp_dir.clone());
+        let prompt_file = temp_dir.join("prompt.txt");
+        let content = build_prompt_file_content(
+            &self.options.tool,
+            self.options.prompt.as_deref(),
+            self.options.system_prompt.as_deref(),
+        );
+
+        let mut open_options = tokio::fs::OpenOptions::new();
+        open_options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            open_options.mode(0o600);
+        }
+        let mut file = open_options
+            .open(&prompt_file)
+            .await
+            .map_err(|e| e.to_string())?;
+        file.write_all(content.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(Some(prompt_file.to_string_lossy().into_owned()))
+    }
+
+    /// Start the agent (non-blocking)
+    ///
+    /// # Arguments
+    /// * `start_options` - Start options
+    ///
+    /// # Returns
+    /// Result indicating success or error
+    pub async fn start(&mut self, start_options: AgentStartOptions) -> Result<(), String> {
+        let working_directory = match self.options.worktree.clone() {
+            Some(worktree) => {
+                self.prepare_worktree(&worktree, start_options.dry_run)
+                    .await?
+            }
+            None => self.options.working_directory.clone(),
+        };
+        let detached = start_options.detached;
+
+        let started = self.launch(start_options, working_directory).await;
+        if let Some(ref worktree) = self.worktree {
+            if started.is_err() {
+                // Nothing ran in the worktree yet
+                discard_worktree(worktree).await;
+                let _ = tokio::fs::remove_file(self.registry().worktree_base_file(&worktree.path))
+                    .await;
+                self.worktree = None;
+            } else if detached {
+                println!("Worktree: {}", worktree.path.display());
+                println!("Branch: {}", worktree.branch);
+            }
+        }
+        started
+    }
+
+    /// Create the run's worktree, or show it in a dry run
+    ///
+    /// # Returns
+    /// Directory the agent runs in
+    async fn prepare_worktree(
+        &mut self,
+        worktree: &WorktreeOptions,
+        dry_run: bool,
+    ) -> Result<String, String> {
+        let branch = worktree
+            .branch
+            .clone()
+            .unwrap_or_else(generate_isolation_name);
+        let path = worktree
+            .path
+            .as_ref()
+            .map_or_else(|| self.registry().worktree_dir(&branch), PathBuf::from);
+        let base = worktree.base.as_deref().unwrap_or("HEAD");
+
+        if dry_run {
+            println!("Dry run - worktree that would be created:");
+            println!(
+                "{}",
+                build_worktree_add_command(&self.options.working_directory, &branch, base, &path)
+                    .to_shell_command()
+            );
+        } else {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            let base_file = self.registry().worktree_base_file(&path);
+            self.worktree = Some(
+                create_worktree(
+                    &self.options.working_directory,
+                    &branch,
+                    base,
+                    &path,
+                    &base_file,
+                )
+                .await?,
+            );
+        }
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    async fn launch(
+        &mut self,
+        start_options: AgentStartOptions,
+        working_directory: String,
+    ) -> Result<(), String> {
+        if self.options.approve_each && start_options.detached && !start_options.dry_run {
+            return Err(
+                "approve_each requires an attached run, so permission requests can be answered"
+                    .to_string(),
+            );
+        }
+
+        // Create output stream for JSON parsing if in JSON mode; ask mode
+        // always streams JSON, which the permission relay reads
+        if self.options.json || self.options.approve_each {
+            self.output_stream = Some(create_output_stream());
+        }
+
+        let prepared_prompt_file = match self.prepare_prompt_file(start_options.dry_run).await {
+            Ok(prompt_file) => prompt_file,
+            Err(error) => {
+                self.cleanup_prompt_temp_dir().await;
+                return Err(error);
+            }
+        };
+        let prompt_handled_by_temp_file =
+            prepared_prompt_file.is_some() && self.options.prompt_file.is_none();
+
+        // Screen and tmux sessions, and containers docker removes itself, log
+        // to a capture directory that `stop` reads back
+        let session_capture = self.session_capture();
+
+        // A docker exec run is found again inside the container by its run
+        // ID, to be stopped there
+        if self.options.isolation == "docker-exec" && self.options.run_id.is_none() {
+            self.options.run_id = Some(generate_isolation_name());
+        }
+
+        let read_only = self.options.read_only || self.options.plan_only;
+        let mut bwrap = self.options.bwrap.clone();
+        let mut docker = self.options.docker.clone();
+        // Commits made in a worktree write to the main repository's git
+        // directory, which the sandboxes do not expose writable by themselves
+        if let Some(ref worktree) = self.worktree {
+            if matches!(self.options.isolation.as_str(), "bwrap" | "docker") {
+                let mounted =
+                    mount_worktree_git(&worktree.path, read_only, &mut bwrap, &mut docker).await;
+                if let Err(error) = mounted {
+                    self.cleanup_prompt_temp_dir().await;
+                    return Err(error);
+                }
+            }
+        }
+        if self.options.isolation == "docker" {
+            if let Some(user) = docker.user.take() {
+                match resolve_docker_user(&user, &working_directory) {
+                    Ok(user) => docker.user = Some(user),
+                    Err(error) => {
+                        self.cleanup_prompt_temp_dir().await;
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        // Build the command
+        let command_options = AgentCommandOptions {
+            tool: self.options.tool.clone(),
+            working_directory,
+            prompt: if prompt_handled_by_temp_file {
+                None
+            } else {
+                self.options.prompt.clone()
+            },
+            prompt_file: prepared_prompt_file,
+            system_prompt: if prompt_handled_by_temp_file && self.options.tool != "claude" {
+                None
+            } else {
+                self.options.system_prompt.clone()
+            },
+            append_system_prompt: self.options.append_system_prompt.clone(),
+            model: self.options.model.clone(),
+            fallback_model: self.options.fallback_model.clone(),
+            json: self.options.json,
+            verbose: self.options.verbose,
+            replay_user_messages: self.options.replay_user_messages,
+            resume: self.options.resume.clone(),
+            session_id: self.options.session_id.clone(),
+            fork_session: self.options.fork_session,
+            read_only: self.options.read_only,
+            plan_only: self.options.plan_only,
+            approve_each: self.options.approve_each,
+            permission: self.options.permission.clone(),
+            executable: self.options.executable.clone(),
+            extra_args: self.options.extra_args.clone(),
+            extra_env: self.options.extra_env.clone(),
+            skip_default_safety_flags: self.options.skip_default_safety_flags,
+            isolation: self.options.isolation.clone(),
+            screen_name: self.options.screen_name.clone(),
+            tmux_session: self.options.tmux_session.clone(),
+            container_name: self.options.container_name.clone(),
+            docker,
+            capture_dir: session_capture
+                .as_ref()
+                .map(|capture| capture.dir.to_string_lossy().into_owned()),
+            docker_exec: self.options.docker_exec.clone(),
+            bwrap,
+            run_id: self.options.run_id.clone(),
+            detached: start_options.detached,
+        };
+
+        let launch_spec = build_agent_launch_spec(&command_options);
+
+        if start_options.dry_run {
+            println!("Dry run - command that would be executed:");
+            println!("{}", launch_spec.to_shell_command());
+            return Ok(());
+        }
+
+        if let Some(ref capture) = session_capture {
+            if let Err(error) = capture.prepare().await {
+                self.cleanup_prompt_temp_dir().await;
+                return Err(error);
+            }
+        }
+
+        if start_options.detached && self.is_local_process() {
+            // Without isolation, track the run so it can be stopped and
+            // collected later
+            let run = self
+                .registry()
+                .start(&launch_spec, &self.options.tool, self.options.json)
+                .await;
+            // The prompt file is already open as the run's stdin
+            self.cleanup_prompt_temp_dir().await;
+            let run = run?;
+            println!("Agent started in detached mode");
+            println!("Run ID: {}", run.run_id);
+            println!("PID: {}", run.pid);
+            println!("Output: {}", run.stdout_log.display());
+            self.run = Some(run);
+        } else if start_options.detached && self.options.isolation == "docker-exec" {
+            // The wrapper returns once the agent runs in the background, after
+            // docker exec has read the prompt
+            let started = execute_launch(&launch_spec).await;
+            self.cleanup_prompt_temp_dir().await;
+            let started = started.map_err(|e| e.to_string())?;
+            if started.exit_code != 0 {
+                return Err(format!(
+                    "Failed to start agent in container: {}",
+                    started.stderr.trim()
+                ));
+            }
+            println!("Agent started in detached mode");
+            println!(
+                "Container: {}",
+                self.options.container_name.as_deref().unwrap_or_default()
+            );
+            println!("Run ID: {}", self.run_id().unwrap_or_default());
+        } else if start_options.detached {
+            // For detached mode, use execute_detached
+            if let Err(error) = execute_detached_launch(&launch_spec)
+                .await
+                .map_err(|e| e.to_string())
+            {
+                self.cleanup_prompt_temp_dir().await;
+                return Err(error);
+            }
+            println!("Agent started in detached mode");
+            if self.options.isolation == "screen" {
+                if let Some(ref name) = self.options.screen_name {
+                    println!("Screen session: {}", name);
+                }
+            } else if self.options.isolation == "tmux" {
+                if let Some(ref name) = self.options.tmux_session {
+                    println!("tmux session: {}", name);
+                }
+            } else if self.options.isolation == "docker" {
+                if let Some(ref name) = self.options.container_name {
+                    println!("Container: {}", name);
+                }
+            }
+        } else {
+            // For attached mode, start command without waiting
+            let started = async {
+                let prompt = read_relay_prompt(&self.options).await?;
+                let handle = start_launch(&launch_spec)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok::<_, String>((handle, prompt))
+            };
+            let (mut handle, prompt) = match started.await {
+                Ok(started) => started,
+                Err(error) => {
+                    self.cleanup_prompt_temp_dir().await;
+                    return Err(error);
+                }
+            };
+            handle.set_kill_on_drop(self.options.kill_on_drop);
+            self.permission_relay = start_permission_relay(
+                &self.options,
+                &command_options.working_directory,
+                prompt,
+                &mut handle,
+            )
+            .await;
+            self.process_handle = Some(handle);
+        }
+
+        Ok(())
+    }
+
+    /// Stop the agent and collect output
+    ///
+    /// # Arguments
+    /// * `stop_options` - Stop options
+    ///
+    /// # Returns
+    /// Result with agent output or error
+    pub async fn stop(&mut self, stop_options: AgentStopOptions) -> Result<AgentResult, String> {
+        let dry_run = stop_options.dry_run;
+        let mut result = self.stop_run(stop_options).await?;
+        if !dry_run {
+            result.worktree = self.finish_worktree().await?;
+        }
+        Ok(result)
+    }
+
+    async fn stop_run(&mut self, stop_options: AgentStopOptions) -> Result<AgentResult, String> {
+        // An attached docker exec run is collected like a run without
+        // isolation; a detached one is stopped inside the container
+        let detached_exec =
+            self.options.isolation == "docker-exec" && self.process_handle.is_none();
+
+        // For isolation modes, send stop command
+        if matches!(
+            self.options.isolation.as_str(),
+            "screen" | "tmux" | "docker"
+        ) || detached_exec
+        {
+            let container_name = self.options.container_name.clone().unwrap_or_default();
+            let stop_command = match self.options.isolation.as_str() {
+                "screen" => {
+                    let screen_name = self
+                        .options
+                        .screen_name
+                        .as_ref()
+                        .ok_or("screen_name is required to stop screen session")?;
+                    build_screen_stop_command(screen_name)
+                }
+                "tmux" => {
+                    let session = self
+                        .options
+                        .tmux_session
+                        .as_ref()
+                        .ok_or("tmux_session is required to stop tmux session")?;
+                    build_tmux_stop_command(session)
+                }
+                "docker" => {
+                    if container_name.is_empty() {
+                        return Err("container_name is required to stop docker container".into());
+                    }
+                    build_docker_stop_command(&container_name, self.options.docker.removal)
+                }
+                _ => {
+                    let run_id = self
+                        .options
+                        .run_id
+                        .as_ref()
+                        .ok_or("run_id is required to stop a docker exec run")?;
+                    build_docker_exec_stop_command(&container_name, run_id)
+                }
+            };
+
+            if stop_options.dry_run {
+                println!("Dry run - command that would be executed:");
+                println!("{}", stop_command);
+                return Ok(AgentResult {
+                    metadata: build_normalized_result_metadata(BuildMetadataOptions {
+                        tool: &self.options.tool,
+                        exit_code: 0,
+                        plain_output: "",
+                        parsed_output: None,
+                        session_id: None,
+                        usage: None,
+                        timed_out: false,
+                    }),
+                    ..Default::default()
+                });
+            }
+
+            // An attached wrapper ends together with the agent
+            if let Some(ref mut handle) = self.process_handle {
+                let _ = handle.wait_for_exit().await;
+            }
+
+            let collected = match (self.options.isolation.as_str(), self.session_capture()) {
+                ("screen", Some(capture)) => {
+                    let screen_name = self.options.screen_name.clone().unwrap_or_default();
+                    stop_screen_session(&screen_name, &capture).await
+                }
+                ("tmux", Some(capture)) => {
+                    let session = self.options.tmux_session.clone().unwrap_or_default();
+                    stop_tmux_session(&session, &capture).await
+                }
+                ("docker", capture) => {
+                    let removal = self.options.docker.removal;
+                    stop_docker_container(&container_name, removal, capture.as_ref()).await
+                }
+                _ => {
+                    let run_id = self.options.run_id.clone().unwrap_or_default();
+                    stop_docker_exec_run(
+                        &container_name,
+                        &run_id,
+                        &self.options.termination_schedule,
+                    )
+                    .await
+                }
+            };
+            self.cleanup_prompt_temp_dir().await;
+            let collected = collected?;
+
+            // The wrapper cannot tell whether the tool was started in JSON
+            // mode, so pick up whatever NDJSON messages it printed
+            if self.output_stream.is_none() {
+                self.output_stream = Some(create_output_stream());
+            }
+            let tool = self.options.tool.clone();
+            return Ok(self.build_result(
+                &tool,
+                collected.exit_code,
+                false,
+                collected.stdout,
+                collected.stderr,
+                collected.output_log,
+            ));
+        }
+
+        // For no isolation, wait for process to complete and collect output
+        if self.is_local_process() || self.options.isolation == "docker-exec" {
+            if self.process_handle.is_none() && self.tracked_run().await?.is_some() {
+                return self.stop_tracked_run(stop_options).await;
+            }
+            if self.process_handle.is_none() {
+                self.cleanup_prompt_temp_dir().await;
+                return Err("Agent not started or already stopped".to_string());
+            }
+            let handle = self
+                .process_handle
+                .as_mut()
+                .ok_or("Agent not started or already stopped")?;
+
+            // Wait for the process to exit, terminating it once the timeout passes
+            let waited = match self.options.timeout {
+                Some(timeout) if self.options.isolation == "docker-exec" => {
+                    wait_for_docker_exec_until(
+                        handle,
+                        self.options.container_name.as_deref().unwrap_or_default(),
+                        self.options.run_id.as_deref().unwrap_or_default(),
+                        handle.started_at() + timeout,
+                        &self.options.termination_schedule,
+                    )
+                    .await
+                }
+                Some(timeout) => handle
+                    .wait_for_exit_until(
+                        handle.started_at() + timeout,
+                        &self.options.termination_schedule,
+                    )
+                    .await
+                    .map_err(|e| e.to_string()),
+                None => handle
+                    .wait_for_exit()
+                    .await
+                    .map(|exit_code| (exit_code, false))
+                    .map_err(|e| e.to_string()),
+            };
+            let (exit_code, timed_out) = match waited {
+                Ok(waited) => waited,
+                Err(error) => {
+                    self.cleanup_prompt_temp_dir().await;
+                    return Err(error);
+                }
+            };
+
+            let (stdout, stderr, _) = handle.get_output();
+            let (stdout, stderr) = (stdout.to_string(), stderr.to_string());
+            let output_log = handle.get_output_log().to_vec();
+            let tool = self.options.tool.clone();
+            let mut result =
+                self.build_result(&tool, exit_code, timed_out, stdout, stderr, output_log);
+            // The relay ends once the tool's stdout closes
+            if let Some(relay) = self.permission_relay.take() {
+                result.permissions = relay.await.unwrap_or_default();
+            }
+            self.cleanup_prompt_temp_dir().await;
+            return Ok(result);
+        }
+
+        Err(format!(
+            "Unsupported isolation mode: {}",
+            self.options.isolation
+        ))
+    }
+
+    /// Collect the result of a tracked detached run without stopping it
+    ///
+    /// Parses the run's captured stdout and stderr logs into a full
+    /// [`AgentResult`]. The run is taken from [`Agent::start`] or from
+    /// `AgentOptions::run_id`.
+    ///
+    /// # Returns
+    /// Result with agent output, or error if the run is unknown or still running
+    pub async fn collect(&mut self) -> Result<AgentResult, String> {
+        let run = self
+            .tracked_run()
+            .await?
+            .ok_or("No tracked detached run to collect")?;
+        if run.is_running().await {
+            return Err(format!("Run {} is still running", run.run_id));
+        }
+        let exit_code = run.exit_code().await.unwrap_or(-1);
+        let mut result = self.build_tracked_result(&run, exit_code).await?;
+        result.worktree = self.finish_worktree().await?;
+        Ok(result)
+    }
+
+    /// Report the run's worktree, removing it if `cleanup` is set
+    ///
+    /// A controller that did not start the run finds the worktree by
+    /// `WorktreeOptions::path` or `branch`, or through the tracked run.
+    async fn finish_worktree(&mut self) -> Result<Option<WorktreeInfo>, String> {
+        let Some(ref options) = self.options.worktree else {
+            return Ok(None);
+        };
+        let path = match (
+            self.worktree.take(),
+            &options.path,
+            &options.branch,
+            &self.run,
+        ) {
+            (Some(worktree), _, _, _) => worktree.path,
+            (None, Some(path), _, _) => PathBuf::from(path),
+            (None, None, Some(branch), _) => self.registry().worktree_dir(branch),
+            (None, None, None, Some(run)) => PathBuf::from(&run.working_directory),
+            (None, None, None, None) => {
+                return Err("worktree branch or path is required to report the run".to_string())
+            }
+        };
+        let base_file = self.registry().worktree_base_file(&path);
+        finish_worktree(&path, options.cleanup, &base_file)
+            .await
+            .map(Some)
+    }
+
+    /// Get the ID of the tracked detached run, if any
+    pub fn run_id(&self) -> Option<&str> {
+        self.run
+            .as_ref()
+            .map(|run| run.run_id.as_str())
+            .or(self.options.run_id.as_deref())
+    }
+
+    /// Whether the agent runs as a local process group: without isolation,
+    /// or inside the bwrap sandbox
+    fn is_local_process(&self) -> bool {
+        matches!(self.options.isolation.as_str(), "none" | "" | "bwrap")
+    }
+
+    /// Capture files of the screen or tmux session or `auto` container, if any
+    fn session_capture(&self) -> Option<CaptureFiles> {
+        let name = match self.options.isolation.as_str() {
+            "screen" => self.options.screen_name.as_deref(),
+            "tmux" => self.options.tmux_session.as_deref(),
+            // Docker removes an `auto` container as soon as it exits
+            "docker" if self.options.docker.removal == DockerRemoval::Auto => {
+                self.options.container_name.as_deref()
+            }
+            _ => None,
+        }?;
+        Some(CaptureFiles::in_dir(
+            self.registry().capture_dir(&self.options.isolation, name),
+        ))
+    }
+
+    fn registry(&self) -> RunRegistry {
+        self.options
+            .state_dir
+            .as_ref()
+            .map_or_else(RunRegistry::default, RunRegistry::new)
+    }
+
+    async fn tracked_run(&mut self) -> Result<Option<RunRecord>, String> {
+        if self.run.is_none() {
+            if let Some(ref run_id) = self.options.run_id {
+                self.run = Some(self.registry().load(run_id).await?);
+            }
+        }
+        Ok(self.run.clone())
+    }
+
+    /// Terminate a tracked detached run (SIGINT, then SIGTERM, then SIGKILL
+    /// following `termination_schedule`) and collect its result
+    async fn stop_tracked_run(
+        &mut self,
+        stop_options: AgentStopOptions,
+    ) -> Result<AgentResult, String> {
+        let run = self
+            .tracked_run()
+            .await?
+            .ok_or("Agent not started or already stopped")?;
+        let group = run.process_group();
+
+        if stop_options.dry_run {
+            println!("Dry run - would stop run {}:", run.run_id);
+            println!("{}", run.command);
+            return Ok(AgentResult {
+                metadata: build_normalized_result_metadata(BuildMetadataOptions {
+                    tool: &run.tool,
+                    exit_code: 0,
+                    plain_output: "",
+                    parsed_output: None,
+                    session_id: None,
+                    usage: None,
+                    timed_out: false,
+                }),
+                ..Default::default()
+            });
+        }
+
+        let schedule = self.options.termination_schedule;
+        let mut last_signal = None;
+        for (signal, grace) in [
+            (ProcessSignal::Interrupt, schedule.interrupt_grace),
+            (ProcessSignal::Terminate, schedule.terminate_grace),
+            (ProcessSignal::Kill, Duration::from_secs(5)),
+        ] {
+            if !run.is_running().await {
+                break;
+            }
+            group.signal(signal).await.map_err(|e| e.to_string())?;
+            last_signal = Some(signal);
+            run.wait(grace).await;
+        }
+
+        // A run killed before its wrapper could record the code reports the
+        // conventional shell status for the last signal sent
+        let exit_code = match run.exit_code().await {
+            Some(exit_code) => exit_code,
+            None => last_signal.map_or(-1, ProcessSignal::exit_code),
+        };
+        self.build_tracked_result(&run, exit_code).await
+    }
+
+    async fn build_tracked_result(
+        &mut self,
+        run: &RunRecord,
+        exit_code: i32,
+    ) -> Result<AgentResult, String> {
+        let stdout = tokio::fs::read_to_string(&run.stdout_log)
+            .await
+            .map_err(|e| e.to_string())?;
+        let stderr = tokio::fs::read_to_string(&run.stderr_log)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let output_log = executor::captured_output_log(&stdout, &stderr, run.started_at_ms);
+
+        if run.json {
+            self.output_stream = Some(create_output_stream());
+        }
+        Ok(self.build_result(&run.tool, exit_code, false, stdout, stderr, output_log))
+    }
+
+    /// Parse captured output into an [`AgentResult`]
+    fn build_result(
+        &mut self,
+        tool: &str,
+        exit_code: i32,
+        timed_out: bool,
+        stdout: String,
+        stderr: String,
+        output_log: Vec<OutputLine>,
+    ) -> AgentResult {
+        // Combine stdout and stderr for plain output
+        let plain_output = if stderr.is_empty() {
+            stdout.to_string()
+        } else {
+            format!("{}\n{}", stdout, stderr)
+        };
+
+        // Process output through stream if available
+        let mut parsed_output = None;
+        if let Some(ref mut stream) = self.output_stream {
+            stream.process(&stdout);
+            stream.flush();
+            let messages = stream.get_messages();
+            if !messages.is_empty() {
+                parsed_output = Some(messages.to_vec());
+            }
+        }
+
+        // Try to extract session ID
+        if is_tool_supported(tool) {
+            match tool {
+                "claude" => {
+                    self.session_id = tools::claude::extract_session_id(&plain_output);
+                }
+                "codex" => {
+                    self.session_id = tools::codex::extract_session_id(&plain_output);
+                }
+                "opencode" => {
+                    self.session_id = tools::opencode::extract_session_id(&plain_output);
+                }
+                "agent" => {
+                    self.session_id = tools::agent::extract_session_id(&plain_output);
+                }
+                _ => {}
+            }
+        }
+
+        let usage = tools::extract_usage_value(tool, &plain_output);
+        let metadata = build_normalized_result_metadata(BuildMetadataOptions {
+            tool,
+            exit_code,
+            plain_output: &plain_output,
+            parsed_output: parsed_output.as_deref(),
+            session_id: self.session_id.clone(),
+            usage: usage.clone(),
+            timed_out,
+        });
+
+        AgentResult {
+            exit_code,
+            plain_output,
+            stdout,
+            stderr,
+            output_log,
+            parsed_output,
+            session_id: self.session_id.clone(),
+            usage,
+            metadata,
+            worktree: None,
+            permissions: Vec::new(),
+        }
+    }
+
+    /// Ask the agent to stop by sending SIGINT to its whole process group
+    ///
+    /// Tools treat SIGINT like Ctrl+C and usually save their session before
+    /// exiting. Call [`Agent::stop`] afterwards to collect output and metadata.
+    ///
+    /// # Returns
+    /// Result indicating success or error if the agent is not running
+    pub async fn interrupt(&self) -> Result<(), String> {
+        self.signal(ProcessSignal::Interrupt).await
+    }
+
+    /// Kill the agent's whole process group with SIGKILL
+    ///
+    /// Call [`Agent::stop`] afterwards to collect the output read so far.
+    ///
+    /// # Returns
+    /// Result indicating success or error if the agent is not running
+    pub async fn kill(&self) -> Result<(), String> {
+        self.signal(ProcessSignal::Kill).await
+    }
+
+    /// Get a handle for signalling the agent's process group while
+    /// [`Agent::stop`] is waiting, or the group of a tracked detached run
+    /// (isolation `none` or `bwrap` only)
+    pub fn process_group(&self) -> Option<ProcessGroup> {
+        self.process_handle
+            .as_ref()
+            .map(ProcessHandle::process_group)
+            .or_else(|| self.run.as_ref().map(RunRecord::process_group))
+    }
+
+    async fn signal(&self, signal: ProcessSignal) -> Result<(), String> {
+        let group = self
+            .process_group()
+            .ok_or("Agent not started or already stopped")?;
+        group.signal(signal).await.map_err(|e| e.to_string())
+    }
+
+    /// Subscribe to parsed NDJSON messages while the agent runs
+    ///
+    /// Only available for isolation `none` or `bwrap` after [`Agent::start`].
+    /// Messages emitted before the call are replayed first, so no message is
+    /// missed.
+    ///
+    /// # Returns
+    /// Live message stream or error if the agent is not running
+    pub fn events(&self) -> Result<AgentEvents, String> {
+        let handle = self
+            .process_handle
+            .as_ref()
+            .ok_or("Agent not started or already stopped")?;
+        Ok(AgentEvents {
+            lines: handle.subscribe_stdout(),
+        })
+    }
+
+    /// Get the current session ID (if available)
+    pub fn get_session_id(&self) -> Option<&String> {
+        self.session_id.as_ref()
+    }
+
+    /// Get all collected messages from the output stream
+    pub fn get_messages(&self) -> Vec<&Value> {
+        if let Some(ref stream) = self.output_stream {
+            stream.get_messages().iter().collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
@@ -1,8 +1,54 @@
 //! Execute commands using tokio
 
+use crate::launch::{LaunchSpec, LaunchStdin};
+pub use crate::process_group::{ProcessGroup, ProcessSignal, TerminationSchedule};
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::{Child, Command};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+/// Pipe an output line was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputSource {
+    Stdout,
+    Stderr,
+}
+
+/// One line of process output, in the order it was read across both pipes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLine {
+    /// Pipe the line came from
+    pub source: OutputSource,
+    /// Milliseconds since the Unix epoch when the line was read
+    pub timestamp_ms: u64,
+    /// Line text without the trailing newline
+    pub text: String,
+}
+
+/// Build an output log from output captured to files
+///
+/// Files keep no read order across pipes, so stdout lines come first, then
+/// stderr lines, all stamped with `timestamp_ms`.
+pub(crate) fn captured_output_log(
+    stdout: &str,
+    stderr: &str,
+    timestamp_ms: u64,
+) -> Vec<OutputLine> {
+    stdout
+        .lines()
+        .map(|line| (OutputSource::Stdout, line))
+        .chain(stderr.lines().map(|line| (OutputSource::Stderr, line)))
+        .map(|(source, text)| OutputLine {
+            source,
+            timestamp_ms,
+            text: text.to_string(),
+        })
+        .collect()
+}
 
 /// Command execution result
 #[derive(Debug, Clone, Default)]
@@ -10,6 +56,8 @@ pub struct ExecutionResult {
     pub exit_code: i32,
     pub stdout: String,
     pub stderr: String,
+    /// Timestamped stdout and stderr lines, interleaved in read order
+    pub output_log: Vec<OutputLine>,
     pub command: String,
 }
 
@@ -32,54 +80,118 @@ pub async fn execute_command(
         println!("{}", command);
         return Ok(ExecutionResult {
             exit_code: 0,
-            stdout: String::new(),
-            stderr: String::new(),
             command: command.to_string(),
+            ..Default::default()
         });
     }
 
-    let mut child = Command::new("bash")
+    let child = Command::new("bash")
         .arg("-c")
         .arg(command)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()?;
 
-    let mut stdout = String::new();
-    let mut stderr = String::new();
-
-    // Read stdout
-    if let Some(stdout_pipe) = child.stdout.take() {
-        let mut reader = BufReader::new(stdout_pipe).lines();
-        while let Some(line) = reader.next_line().await? {
-            stdout.push_str(&line);
-            stdout.push('\n');
-            if attached {
-                println!("{}", line);
-            }
+    let mut handle = ProcessHandle::spawn_readers(command.to_string(), child, attached, false);
+    let exit_code = handle.wait_for_exit().await?;
+
+    Ok(ExecutionResult {
+        exit_code,
+        stdout: std::mem::take(&mut handle.stdout),
+        stderr: std::mem::take(&mut handle.stderr),
+        output_log: std::mem::take(&mut handle.output_log),
+        command: command.to_string(),
+    })
+}
+
+/// Lines read from a child pipe so far, plus live subscribers to new lines
+#[derive(Debug, Default)]
+struct LineBuffer {
+    text: String,
+    subscribers: Vec<UnboundedSender<String>>,
+    closed: bool,
+}
+
+/// Output captured from both child pipes
+#[derive(Debug, Default)]
+struct OutputCapture {
+    stdout: LineBuffer,
+    stderr: LineBuffer,
+    combined: Vec<OutputLine>,
+}
+
+impl OutputCapture {
+    fn buffer_mut(&mut self, source: OutputSource) -> &mut LineBuffer {
+        match source {
+            OutputSource::Stdout => &mut self.stdout,
+            OutputSource::Stderr => &mut self.stderr,
         }
     }
 
-    // Read stderr
-    if let Some(stderr_pipe) = child.stderr.take() {
-        let mut reader = BufReader::new(stderr_pipe).lines();
-        while let Some(line) = reader.next_line().await? {
-            stderr.push_str(&line);
-            stderr.push('\n');
-            if attached {
-                eprintln!("{}", line);
-            }
-        }
+    /// Append a line to its pipe's buffer and the combined log, and forward
+    /// it to the pipe's live subscribers
+    fn record(&mut self, source: OutputSource, line: String) {
+        let buffer = self.buffer_mut(source);
+        buffer.text.push_str(&line);
+        buffer.text.push('\n');
+        buffer
+            .subscribers
+            .retain(|subscriber| subscriber.send(line.clone()).is_ok());
+        self.combined.push(OutputLine {
+            source,
+            timestamp_ms: now_ms(),
+            text: line,
+        });
+    }
+
+    /// Mark a pipe as closed; dropping the senders ends every subscription
+    fn close(&mut self, source: OutputSource) {
+        let buffer = self.buffer_mut(source);
+        buffer.closed = true;
+        buffer.subscribers.clear();
     }
+}
 
-    let status = child.wait().await?;
-    let exit_code = status.code().unwrap_or(1);
+/// Milliseconds since the Unix epoch
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| {
+            u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+        })
+}
 
-    Ok(ExecutionResult {
-        exit_code,
-        stdout,
-        stderr,
-        command: command.to_string(),
+/// Read a pipe line by line in the background, recording each line and
+/// forwarding it to every live subscriber as soon as it is read
+fn spawn_line_reader<R>(
+    pipe: R,
+    source: OutputSource,
+    capture: Arc<Mutex<OutputCapture>>,
+    echo: bool,
+) -> JoinHandle<std::io::Result<()>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(pipe).lines();
+        let result = loop {
+            match reader.next_line().await {
+                Ok(Some(line)) => {
+                    if echo {
+                        match source {
+                            OutputSource::Stdout => println!("{}", line),
+                            OutputSource::Stderr => eprintln!("{}", line),
+                        }
+                    }
+                    capture.lock().unwrap().record(source, line);
+                }
+                Ok(None) => break Ok(()),
+                Err(error) => break Err(error),
+            }
+        };
+
+        capture.lock().unwrap().close(source);
+        result
     })
 }
 
@@ -87,68 +199,211 @@ pub async fn execute_command(
 pub struct ProcessHandle {
     pub command: String,
     child: Option<Child>,
+    group: ProcessGroup,
+    kill_on_drop: bool,
+    started_at: Instant,
+    capture: Arc<Mutex<OutputCapture>>,
+    readers: Vec<JoinHandle<std::io::Result<()>>>,
+    stdin: Option<ChildStdin>,
     stdout: String,
     stderr: String,
+    output_log: Vec<OutputLine>,
     exit_code: Option<i32>,
 }
 
 impl ProcessHandle {
-    /// Create a new process handle
-    fn new(command: String, child: Child) -> Self {
+    /// Create a new process handle and start draining stdout and stderr
+    /// concurrently in the background
+    ///
+    /// `grouped` tells whether the child leads its own process group.
+    fn spawn_readers(command: String, mut child: Child, echo: bool, grouped: bool) -> Self {
+        let capture = Arc::new(Mutex::new(OutputCapture::default()));
+        let mut readers = Vec::new();
+        if let Some(pipe) = child.stdout.take() {
+            readers.push(spawn_line_reader(
+                pipe,
+                OutputSource::Stdout,
+                capture.clone(),
+                echo,
+            ));
+        }
+        if let Some(pipe) = child.stderr.take() {
+            readers.push(spawn_line_reader(
+                pipe,
+                OutputSource::Stderr,
+                capture.clone(),
+                echo,
+            ));
+        }
+
         Self {
             command,
+            group: ProcessGroup {
+                pid: child.id().filter(|_| grouped),
+                reaped: Arc::default(),
+            },
+            kill_on_drop: false,
             child: Some(child),
+            started_at: Instant::now(),
+            capture,
+            readers,
+            stdin: None,
             stdout: String::new(),
             stderr: String::new(),
+            output_log: Vec::new(),
             exit_code: None,
         }
     }
 
+    /// Subscribe to stdout lines as they are read
+    ///
+    /// Lines read before the call are replayed first, so a late subscriber
+    /// still sees the full output. The receiver yields `None` once stdout
+    /// reaches EOF.
+    pub fn subscribe_stdout(&self) -> UnboundedReceiver<String> {
+        let (sender, receiver) = unbounded_channel();
+        let mut capture = self.capture.lock().unwrap();
+        for line in capture.stdout.text.lines() {
+            let _ = sender.send(line.to_string());
+        }
+        if !capture.stdout.closed {
+            capture.stdout.subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Take the child's stdin pipe (launch specs with [`LaunchStdin::Piped`])
+    ///
+    /// Dropping the returned pipe closes the child's stdin.
+    pub fn take_stdin(&mut self) -> Option<ChildStdin> {
+        self.stdin.take()
+    }
+
     /// Wait for the process to exit
+    ///
+    /// Cancel-safe: if the future is dropped (e.g. by a timeout), calling it
+    /// again resumes waiting without losing output.
     pub async fn wait_for_exit(&mut self) -> Result<i32, std::io::Error> {
         if let Some(exit_code) = self.exit_code {
             return Ok(exit_code);
         }
 
-        if let Some(mut child) = self.child.take() {
-            // Read remaining stdout
-            if let Some(stdout_pipe) = child.stdout.take() {
-                let mut reader = BufReader::new(stdout_pipe).lines();
-                while let Some(line) = reader.next_line().await? {
-                    self.stdout.push_str(&line);
-                    self.stdout.push('\n');
-                }
-            }
-
-            // Read remaining stderr
-            if let Some(stderr_pipe) = child.stderr.take() {
-                let mut reader = BufReader::new(stderr_pipe).lines();
-                while let Some(line) = reader.next_line().await? {
-                    self.stderr.push_str(&line);
-                    self.stderr.push('\n');
-                }
-            }
+        // Both pipes are drained by their own reader, so a chatty stderr
+        // cannot block stdout (or the child) while we wait on the other
+        while let Some(reader) = self.readers.first_mut() {
+            let result = reader.await;
+            self.readers.remove(0);
+            result.map_err(std::io::Error::other)??;
+        }
 
+        if let Some(child) = self.child.as_mut() {
             let status = child.wait().await?;
+            self.group.reaped.store(true, Ordering::SeqCst);
+            self.child = None;
+            {
+                let capture = self.capture.lock().unwrap();
+                self.stdout.clone_from(&capture.stdout.text);
+                self.stderr.clone_from(&capture.stderr.text);
+                self.output_log.clone_from(&capture.combined);
+            }
             self.exit_code = Some(status.code().unwrap_or(1));
         }
 
         Ok(self.exit_code.unwrap_or(1))
     }
 
+    /// Wait for the process to exit, terminating its process group once
+    /// `deadline` passes
+    ///
+    /// After the deadline the group receives SIGINT, SIGTERM and SIGKILL
+    /// following `schedule`, and the output read so far is still collected.
+    ///
+    /// # Arguments
+    /// * `deadline` - Point in time after which the run is terminated
+    /// * `schedule` - Grace periods between termination signals
+    ///
+    /// # Returns
+    /// Exit code and whether the deadline was hit
+    pub async fn wait_for_exit_until(
+        &mut self,
+        deadline: Instant,
+        schedule: &TerminationSchedule,
+    ) -> Result<(i32, bool), std::io::Error> {
+        let deadline = tokio::time::Instant::from_std(deadline);
+        if let Ok(result) = tokio::time::timeout_at(deadline, self.wait_for_exit()).await {
+            return result.map(|exit_code| (exit_code, false));
+        }
+
+        for (signal, grace) in [
+            (ProcessSignal::Interrupt, schedule.interrupt_grace),
+            (ProcessSignal::Terminate, schedule.terminate_grace),
+        ] {
+            self.signal_group(signal).await?;
+            if let Ok(result) = tokio::time::timeout(grace, self.wait_for_exit()).await {
+                return result.map(|exit_code| (exit_code, true));
+            }
+        }
+
+        self.signal_group(ProcessSignal::Kill).await?;
+        self.wait_for_exit()
+            .await
+            .map(|exit_code| (exit_code, true))
+    }
+
+    /// Send a signal to the whole process group of the command
+    ///
+    /// The group covers the `bash -c` wrapper and every process of the
+    /// pipeline it runs. Does nothing once the process has been reaped.
+    pub async fn signal_group(&self, signal: ProcessSignal) -> Result<(), std::io::Error> {
+        self.group.signal(signal).await
+    }
+
+    /// Get a cloneable handle for signalling the process group
+    pub fn process_group(&self) -> ProcessGroup {
+        self.group.clone()
+    }
+
+    /// Kill the whole process group when the handle is dropped before the
+    /// process has exited
+    pub fn set_kill_on_drop(&mut self, kill_on_drop: bool) {
+        self.kill_on_drop = kill_on_drop;
+    }
+
+    /// Get the time the process was started
+    pub fn started_at(&self) -> Instant {
+        self.started_at
+    }
+
     /// Get collected output
     pub fn get_output(&self) -> (&str, &str, Option<i32>) {
         (&self.stdout, &self.stderr, self.exit_code)
     }
 
+    /// Get the timestamped stdout and stderr lines in the order they were read
+    pub fn get_output_log(&self) -> &[OutputLine] {
+        &self.output_log
+    }
+
     /// Check if process has exited
     pub fn has_exited(&self) -> bool {
         self.exit_code.is_some()
     }
 }
 
+impl Drop for ProcessHandle {
+    fn drop(&mut self) {
+        if self.kill_on_drop && self.child.is_some() {
+            let _ = self.group.signal_blocking(ProcessSignal::Kill);
+        }
+    }
+}
+
 /// Start a command execution without waiting for completion
 ///
+/// On Unix the command runs in its own process group (see
+/// [`ProcessHandle::signal_group`]) with stdin detached from the terminal,
+/// since a background process group reading the terminal would be stopped.
+///
 /// # Arguments
 /// * `command` - Command to execute
 /// * `attached` - If true, stream output to console
@@ -159,14 +414,146 @@ pub async fn start_command(
     command: &str,
     _attached: bool,
 ) -> Result<ProcessHandle, std::io::Error> {
-    let child = Command::new("bash")
+    let mut process = Command::new("bash");
+    process
         .arg("-c")
         .arg(command)
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
+        .stderr(Stdio::piped());
+    // Own process group, so the wrapper and its pipeline can be signalled together
+    #[cfg(unix)]
+    process.stdin(Stdio::null()).process_group(0);
+    let child = process.spawn()?;
+
+    Ok(ProcessHandle::spawn_readers(
+        command.to_string(),
+        child,
+        false,
+        true,
+    ))
+}
+
+/// Configure a [`Command`] from a launch spec
+///
+/// Returns the inline stdin bytes, which the caller writes once spawned.
+pub(crate) fn configure_launch(
+    process: &mut Command,
+    spec: &LaunchSpec,
+) -> std::io::Result<Option<Vec<u8>>> {
+    process.args(&spec.args);
+    if let Some(ref cwd) = spec.cwd {
+        process.current_dir(cwd);
+    }
+    for (key, value) in &spec.env {
+        process.env(key, value);
+    }
 
-    Ok(ProcessHandle::new(command.to_string(), child))
+    let mut inline_input = None;
+    match spec.stdin {
+        LaunchStdin::Null => {
+            process.stdin(Stdio::null());
+        }
+        LaunchStdin::Inline(ref input) => {
+            process.stdin(Stdio::piped());
+            inline_input = Some(input.clone());
+        }
+        LaunchStdin::File(ref path) => {
+            process.stdin(std::fs::File::open(path)?);
+        }
+        LaunchStdin::Inherit => {
+            process.stdin(Stdio::inherit());
+        }
+        LaunchStdin::Piped => {
+            process.stdin(Stdio::piped());
+        }
+    }
+    Ok(inline_input)
+}
+
+/// Start a launch spec directly, without a shell, and without waiting for
+/// completion
+///
+/// Unless the spec inherits the caller's stdin (a wrapper attaching to the
+/// terminal must stay in the foreground process group), the process runs
+/// in its own process group on Unix (see [`ProcessHandle::signal_group`]).
+///
+/// # Arguments
+/// * `spec` - Program, argv, cwd, environment and stdin source
+///
+/// # Returns
+/// Process handle
+pub async fn start_launch(spec: &LaunchSpec) -> Result<ProcessHandle, std::io::Error> {
+    let mut process = Command::new(&spec.program);
+    let inline_input = configure_launch(&mut process, spec)?;
+    process.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let grouped = cfg!(unix) && spec.stdin != LaunchStdin::Inherit;
+    #[cfg(unix)]
+    if grouped {
+        process.process_group(0);
+    }
+    let mut child = process.spawn()?;
+
+    // A piped stdin is kept for the caller; inline input is written in the
+    // background so a large prompt cannot block on a full pipe, and dropping
+    // the handle closes the agent's stdin
+    let stdin = child.stdin.take();
+    let kept_stdin = match (inline_input, stdin) {
+        (Some(input), Some(mut stdin)) => {
+            tokio::spawn(async move {
+                let _ = stdin.write_all(&input).await;
+            });
+            None
+        }
+        (None, stdin) if spec.stdin == LaunchStdin::Piped => stdin,
+        _ => None,
+    };
+    let mut handle = ProcessHandle::spawn_readers(spec.to_shell_command(), child, false, grouped);
+    handle.stdin = kept_stdin;
+    Ok(handle)
+}
+
+/// Execute a launch spec without a shell and return the result
+///
+/// # Arguments
+/// * `spec` - Program, argv, cwd, environment and stdin source
+///
+/// # Returns
+/// Execution result
+pub async fn execute_launch(spec: &LaunchSpec) -> Result<ExecutionResult, std::io::Error> {
+    let mut handle = start_launch(spec).await?;
+    let exit_code = handle.wait_for_exit().await?;
+
+    Ok(ExecutionResult {
+        exit_code,
+        stdout: std::mem::take(&mut handle.stdout),
+        stderr: std::mem::take(&mut handle.stderr),
+        output_log: std::mem::take(&mut handle.output_log),
+        command: spec.to_shell_command(),
+    })
+}
+
+/// Start a launch spec in the background (detached), without a shell
+///
+/// # Arguments
+/// * `spec` - Program, argv, cwd, environment and stdin source
+///
+/// # Returns
+/// Process ID if available
+pub async fn execute_detached_launch(spec: &LaunchSpec) -> Result<Option<u32>, std::io::Error> {
+    let mut process = Command::new(&spec.program);
+    let inline_input = configure_launch(&mut process, spec)?;
+    process.stdout(Stdio::null()).stderr(Stdio::null());
+    #[cfg(unix)]
+    process.process_group(0);
+    let mut child = process.spawn()?;
+
+    // Nobody waits on a detached run, so finish writing the prompt before
+    // returning; the caller may exit right away
+    if let (Some(input), Some(mut stdin)) = (inline_input, child.stdin.take()) {
+        stdin.write_all(&input).await?;
+    }
+
+    Ok(child.id())
 }
 
 /// Execute a command in the background (detached)
@@ -232,6 +619,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_execute_command_dry_run() {
@@ -259,6 +647,246 @@ mod tests {
         assert!(stdout.contains("hello"));
     }
 
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_subscribe_stdout_yields_lines_before_exit() {
+        let marker = std::env::temp_dir().join(format!(
+            "agent-commander-subscribe-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let command = format!(
+            "echo first; while [ ! -e '{}' ]; do sleep 0.05; done; echo second",
+            marker.display()
+        );
+        let mut handle = start_command(&command, false).await.unwrap();
+        let mut lines = handle.subscribe_stdout();
+
+        // The first line arrives while the process is still waiting on the marker
+        let first = tokio::time::timeout(std::time::Duration::from_secs(5), lines.recv())
+            .await
+            .unwrap();
+        assert_eq!(first.as_deref(), Some("first"));
+        assert!(!handle.has_exited());
+
+        std::fs::write(&marker, "").unwrap();
+        assert_eq!(handle.wait_for_exit().await.unwrap(), 0);
+        let _ = std::fs::remove_file(&marker);
+        assert_eq!(lines.recv().await.as_deref(), Some("second"));
+        assert_eq!(lines.recv().await, None);
+
+        // Late subscribers get the full replay
+        let mut replay = handle.subscribe_stdout();
+        assert_eq!(replay.recv().await.as_deref(), Some("first"));
+        assert_eq!(replay.recv().await.as_deref(), Some("second"));
+        assert_eq!(replay.recv().await, None);
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_execute_command_drains_large_stderr_before_stdout() {
+        // Far more stderr than a pipe buffer holds, written before any stdout
+        let command = "head -c 1000000 /dev/zero | tr '\\0' 'e' | fold -w 100 >&2; echo done";
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(20),
+            execute_command(command, false, false),
+        )
+        .await
+        .expect("reading stdout before stderr must not deadlock")
+        .unwrap();
+
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "done\n");
+        assert_eq!(result.stderr.lines().count(), 10_000);
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_output_log_keeps_interleaved_order() {
+        let mut handle = start_command(
+            "echo one; sleep 0.1; echo two >&2; sleep 0.1; echo three",
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(handle.wait_for_exit().await.unwrap(), 0);
+
+        let (stdout, stderr, _) = handle.get_output();
+        assert_eq!(stdout, "one\nthree\n");
+        assert_eq!(stderr, "two\n");
+
+        let log = handle.get_output_log();
+        let entries: Vec<(OutputSource, &str)> = log
+            .iter()
+            .map(|line| (line.source, line.text.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (OutputSource::Stdout, "one"),
+                (OutputSource::Stderr, "two"),
+                (OutputSource::Stdout, "three"),
+            ]
+        );
+        assert!(log
+            .windows(2)
+            .all(|pair| pair[0].timestamp_ms <= pair[1].timestamp_ms));
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_wait_for_exit_until_returns_before_deadline() {
+        let mut handle = start_command("echo quick", false).await.unwrap();
+        let deadline = handle.started_at() + Duration::from_secs(30);
+        let (exit_code, timed_out) = handle
+            .wait_for_exit_until(deadline, &TerminationSchedule::default())
+            .await
+            .unwrap();
+
+        assert_eq!(exit_code, 0);
+        assert!(!timed_out);
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_wait_for_exit_until_interrupts_the_process_group() {
+        // The pipeline's right-hand side saves its state on SIGINT
+        let mut handle = start_command(
+            "echo started; sleep 30 | (trap 'echo interrupted; exit 130' INT; sleep 30 >/dev/null 2>&1 & wait)",
+            false,
+        )
+        .await
+        .unwrap();
+        let schedule = TerminationSchedule {
+            interrupt_grace: Duration::from_secs(5),
+            terminate_grace: Duration::from_secs(5),
+        };
+        let deadline = handle.started_at() + Duration::from_millis(300);
+        let (exit_code, timed_out) = tokio::time::timeout(
+            Duration::from_secs(20),
+            handle.wait_for_exit_until(deadline, &schedule),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(timed_out);
+        assert_ne!(exit_code, 0);
+        let (stdout, _, _) = handle.get_output();
+        assert!(stdout.contains("started"));
+        assert!(stdout.contains("interrupted"));
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_wait_for_exit_until_escalates_to_kill() {
+        let mut handle = start_command(
+            "trap '' INT TERM; echo stubborn; sleep 30 & wait; sleep 30",
+            false,
+        )
+        .await
+        .unwrap();
+        let schedule = TerminationSchedule {
+            interrupt_grace: Duration::from_millis(200),
+            terminate_grace: Duration::from_millis(200),
+        };
+        let deadline = handle.started_at() + Duration::from_millis(300);
+        let (_, timed_out) = tokio::time::timeout(
+            Duration::from_secs(20),
+            handle.wait_for_exit_until(deadline, &schedule),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(timed_out);
+        assert!(handle.has_exited());
+        assert!(handle.get_output().0.contains("stubborn"));
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_process_group_kill_reaches_the_whole_pipeline() {
+        let mut handle = start_command("echo started; sleep 30 | sleep 30", false)
+            .await
+            .unwrap();
+        let mut lines = handle.subscribe_stdout();
+        assert_eq!(lines.recv().await.as_deref(), Some("started"));
+
+        let group = handle.process_group();
+        let waiter = tokio::spawn(async move {
+            let exit_code = handle.wait_for_exit().await.unwrap();
+            (exit_code, handle)
+        });
+        group.signal(ProcessSignal::Kill).await.unwrap();
+        let (exit_code, handle) = tokio::time::timeout(Duration::from_secs(10), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_ne!(exit_code, 0);
+        assert!(handle.get_output().0.contains("started"));
+        // Signalling after exit is a no-op
+        group.signal(ProcessSignal::Kill).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_kill_on_drop_stops_the_process_group() {
+        let marker = std::env::temp_dir().join(format!(
+            "agent-commander-kill-on-drop-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let command = format!(
+            "echo started; sleep 1 | sleep 1; touch '{}'",
+            marker.display()
+        );
+        let mut handle = start_command(&command, false).await.unwrap();
+        let mut lines = handle.subscribe_stdout();
+        assert_eq!(lines.recv().await.as_deref(), Some("started"));
+
+        handle.set_kill_on_drop(true);
+        drop(handle);
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        assert!(!marker.exists());
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_start_launch_runs_without_a_shell() {
+        let input = "prompt with 'quotes', $HOME and `pwd`";
+        let spec = LaunchSpec {
+            args: vec![
+                "-c".to_string(),
+                "pwd; printf '%s|' \"$GREETING\" \"$1\"; cat".to_string(),
+                "argv0".to_string(),
+                "$(touch /tmp/should-not-run)".to_string(),
+            ],
+            cwd: Some(std::env::temp_dir()),
+            env: vec![("GREETING".to_string(), "hi there".to_string())],
+            stdin: LaunchStdin::Inline(input.as_bytes().to_vec()),
+            ..LaunchSpec::new("sh")
+        };
+        let mut handle = start_launch(&spec).await.unwrap();
+        assert_eq!(handle.wait_for_exit().await.unwrap(), 0);
+
+        let (stdout, _, _) = handle.get_output();
+        let temp_dir = std::env::temp_dir().canonicalize().unwrap();
+        assert!(stdout.starts_with(&format!("{}\n", temp_dir.display())));
+        assert!(stdout.ends_with(&format!(
+            "hi there|$(touch /tmp/should-not-run)|{}\n",
+            input
+        )));
+    }
+
     #[tokio::test]
     #[cfg(not(target_os = "windows"))]
     async fn test_execute_detached() {
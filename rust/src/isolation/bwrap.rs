@@ -0,0 +1,162 @@
+//! Bubblewrap (`bwrap`) filesystem sandbox isolation
+//!
+//! Unlike the other wrappers, bwrap execs the tool's argv directly, so the
+//! sandboxed run is still a plain local process group: timeouts, signals and
+//! tracked detached runs work as without isolation.
+
+use crate::launch::LaunchSpec;
+
+/// Settings for the bwrap sandbox
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BwrapOptions {
+    /// Give the sandbox its own empty network namespace (`--unshare-net`)
+    pub unshare_net: bool,
+    /// Extra absolute paths bound read-write, e.g. a tool's state directory
+    pub writable_paths: Vec<String>,
+}
+
+impl BwrapOptions {
+    /// Validate every setting
+    ///
+    /// # Returns
+    /// Ok, or the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(path) = self
+            .writable_paths
+            .iter()
+            .find(|path| !path.starts_with('/'))
+        {
+            return Err(format!(
+                "Invalid bwrap writable path \"{}\". Use an absolute path.",
+                path
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Build bwrap isolation launch spec
+///
+/// The whole filesystem is bound read-only with a private `/tmp`, fresh
+/// `/dev` and `/proc`, and the working directory bound on top read-write,
+/// or read-only when `read_only` is set, so a planning run cannot write to
+/// the workspace even if the tool ignores its own plan mode.
+///
+/// # Arguments
+/// * `spec` - Agent launch spec; its environment and stdin are kept
+/// * `working_directory` - Workspace bound into the sandbox
+/// * `read_only` - Bind the workspace read-only
+/// * `bwrap` - Network and extra writable paths
+///
+/// # Returns
+/// Launch spec for `bwrap`
+pub fn build_bwrap_launch_spec(
+    spec: &LaunchSpec,
+    working_directory: &str,
+    read_only: bool,
+    bwrap: &BwrapOptions,
+) -> LaunchSpec {
+    let mut args: Vec<String> = [
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect();
+
+    // Binds later in the list are mounted on top of earlier ones, so these
+    // also work for paths under /tmp
+    for path in &bwrap.writable_paths {
+        args.extend(["--bind".to_string(), path.clone(), path.clone()]);
+    }
+    let workspace_bind = if read_only { "--ro-bind" } else { "--bind" };
+    args.extend([
+        workspace_bind.to_string(),
+        working_directory.to_string(),
+        working_directory.to_string(),
+    ]);
+
+    if bwrap.unshare_net {
+        args.push("--unshare-net".to_string());
+    }
+    args.extend([
+        "--die-with-parent".to_string(),
+        "--chdir".to_string(),
+        working_directory.to_string(),
+        "--".to_string(),
+        spec.program.clone(),
+    ]);
+    args.extend(spec.args.iter().cloned());
+
+    LaunchSpec {
+        program: "bwrap".to_string(),
+        args,
+        ..spec.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launch::LaunchStdin;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_build_bwrap_launch_spec() {
+        let spec = LaunchSpec {
+            args: vec!["-p".to_string(), "hi there".to_string()],
+            cwd: Some(PathBuf::from("/work")),
+            stdin: LaunchStdin::File(PathBuf::from("/tmp/prompt.txt")),
+            ..LaunchSpec::new("claude")
+        }
+        .with_env(&[("CLAUDE_HOME".to_string(), "/state".to_string())]);
+        let bwrap = BwrapOptions {
+            unshare_net: true,
+            writable_paths: vec!["/home/me/.claude".to_string()],
+        };
+        let wrapped = build_bwrap_launch_spec(&spec, "/work", false, &bwrap);
+
+        assert_eq!(wrapped.program, "bwrap");
+        assert_eq!(
+            wrapped.args.join(" "),
+            "--ro-bind / / --dev /dev --proc /proc --tmpfs /tmp \
+             --bind /home/me/.claude /home/me/.claude --bind /work /work \
+             --unshare-net --die-with-parent --chdir /work -- claude -p hi there"
+        );
+        assert_eq!(wrapped.cwd, spec.cwd);
+        assert_eq!(wrapped.env, spec.env);
+        assert_eq!(wrapped.stdin, spec.stdin);
+    }
+
+    #[test]
+    fn test_build_bwrap_launch_spec_read_only_workspace() {
+        let spec = build_bwrap_launch_spec(
+            &LaunchSpec::new("codex"),
+            "/work",
+            true,
+            &BwrapOptions::default(),
+        );
+
+        assert!(spec.args.join(" ").contains("--ro-bind /work /work"));
+        assert!(!spec.args.contains(&"--bind".to_string()));
+        assert!(!spec.args.contains(&"--unshare-net".to_string()));
+    }
+
+    #[test]
+    fn test_bwrap_options_validate() {
+        assert!(BwrapOptions::default().validate().is_ok());
+        assert!(BwrapOptions {
+            writable_paths: vec!["relative/dir".to_string()],
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,647 @@
+//! Docker isolation
+
+use super::{generate_isolation_name, CaptureFiles};
+use crate::executor::{execute_launch, ExecutionResult};
+use crate::launch::{is_valid_env_name, quote_shell_arg, LaunchSpec, LaunchStdin};
+use std::path::Path;
+
+/// Default image for docker isolation
+pub const DEFAULT_DOCKER_IMAGE: &str = "node:18-slim";
+
+/// Runs the agent with its stdout and stderr copied to files, and its exit
+/// code recorded, in a capture directory mounted from the host. The output
+/// still reaches the container's own streams. `$0`, `$1` and `$2` are the
+/// stdout, stderr and exit code files, `$3` the agent's shell command.
+const CAPTURE_WRAPPER: &str = r#"set -o pipefail
+{ { bash -c "$3"; code=$?; echo "$code" > "$2"; exit "$code"; } 2>&1 1>&3 3>&- | tee "$1"; } 3>&1 1>&2 | tee "$0""#;
+
+/// Extra bind mount or named volume for docker isolation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerVolume {
+    /// Absolute host path or named volume
+    pub source: String,
+    /// Absolute path inside the container
+    pub target: String,
+    /// Mount read-only
+    pub read_only: bool,
+}
+
+impl DockerVolume {
+    /// Parse a `source:target[:ro|rw]` volume specification
+    ///
+    /// # Arguments
+    /// * `spec` - Volume specification, e.g. `/home/me/.npmrc:/root/.npmrc:ro`
+    ///
+    /// # Returns
+    /// Parsed volume or error message
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (rest, read_only) = if let Some(rest) = spec.strip_suffix(":ro") {
+            (rest, true)
+        } else {
+            (spec.strip_suffix(":rw").unwrap_or(spec), false)
+        };
+        // The target is a container (Unix) path, so the last colon separates
+        // it even when the source is a Windows path like `C:\data`
+        let (source, target) = rest.rsplit_once(':').ok_or_else(|| {
+            format!(
+                "Invalid docker volume \"{}\". Use SOURCE:TARGET[:ro|rw].",
+                spec
+            )
+        })?;
+        let volume = Self {
+            source: source.to_string(),
+            target: target.to_string(),
+            read_only,
+        };
+        volume.validate()?;
+        Ok(volume)
+    }
+
+    /// Check that the source is an absolute path or volume name and the
+    /// target an absolute container path
+    pub fn validate(&self) -> Result<(), String> {
+        let is_volume_name = !self.source.is_empty()
+            && self
+                .source
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if !is_volume_name && !Path::new(&self.source).is_absolute() {
+            return Err(format!(
+                "Docker volume source \"{}\" must be an absolute path or a volume name",
+                self.source
+            ));
+        }
+        if !self.target.starts_with('/') {
+            return Err(format!(
+                "Docker volume target \"{}\" must be an absolute container path",
+                self.target
+            ));
+        }
+        Ok(())
+    }
+
+    /// Render the `-v` argument
+    pub fn to_arg(&self) -> String {
+        let mode = if self.read_only { ":ro" } else { "" };
+        format!("{}:{}{}", self.source, self.target, mode)
+    }
+}
+
+/// When the container is removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DockerRemoval {
+    /// Removed by `stop` after its logs and exit code are collected
+    #[default]
+    OnStop,
+    /// Removed by docker as soon as it exits (`--rm`); the agent's output
+    /// and exit code are captured to files on the host for `stop` to collect
+    Auto,
+    /// Never removed; `stop` leaves the stopped container in place
+    Keep,
+}
+
+impl DockerRemoval {
+    /// Parse `on-stop`, `auto` or `keep`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "on-stop" => Ok(Self::OnStop),
+            "auto" => Ok(Self::Auto),
+            "keep" => Ok(Self::Keep),
+            _ => Err(format!(
+                "Invalid docker removal policy \"{}\". Choose one of: on-stop, auto, keep.",
+                value
+            )),
+        }
+    }
+}
+
+/// Container settings for docker isolation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerOptions {
+    /// Image to run
+    pub image: String,
+    /// Mounts in addition to the working directory
+    pub volumes: Vec<DockerVolume>,
+    /// Network mode (`none`, `host`, `bridge` or a network name)
+    pub network: Option<String>,
+    /// CPU limit, e.g. `1.5`
+    pub cpus: Option<String>,
+    /// Memory limit, e.g. `512m` or `2g`
+    pub memory: Option<String>,
+    /// `UID[:GID]` or user name to run as; `host` maps to the owner of the
+    /// working directory so created files are not root-owned
+    pub user: Option<String>,
+    /// Host environment variables passed through by name (`-e NAME`)
+    pub env_passthrough: Vec<String>,
+    /// When the container is removed
+    pub removal: DockerRemoval,
+}
+
+impl Default for DockerOptions {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_DOCKER_IMAGE.to_string(),
+            volumes: Vec::new(),
+            network: None,
+            cpus: None,
+            memory: None,
+            user: None,
+            env_passthrough: Vec::new(),
+            removal: DockerRemoval::default(),
+        }
+    }
+}
+
+impl DockerOptions {
+    /// Validate every setting
+    ///
+    /// # Returns
+    /// Ok, or the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        if self.image.is_empty()
+            || self.image.starts_with('-')
+            || self.image.chars().any(char::is_whitespace)
+        {
+            return Err(format!("Invalid docker image \"{}\"", self.image));
+        }
+
+        for volume in &self.volumes {
+            volume.validate()?;
+        }
+
+        if let Some(ref network) = self.network {
+            let valid = !network.is_empty()
+                && network
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c));
+            if !valid {
+                return Err(format!("Invalid docker network \"{}\"", network));
+            }
+        }
+
+        if let Some(ref cpus) = self.cpus {
+            let valid = cpus
+                .parse::<f64>()
+                .is_ok_and(|cpus| cpus.is_finite() && cpus > 0.0);
+            if !valid {
+                return Err(format!(
+                    "Invalid docker CPU limit \"{}\". Use a positive number such as 1.5.",
+                    cpus
+                ));
+            }
+        }
+
+        if let Some(ref memory) = self.memory {
+            let digits = memory.trim_end_matches(|c: char| "bkmgBKMG".contains(c));
+            let valid = memory.len() - digits.len() <= 1
+                && !digits.is_empty()
+                && digits.chars().all(|c| c.is_ascii_digit())
+                && digits.chars().any(|c| c != '0');
+            if !valid {
+                return Err(format!(
+                    "Invalid docker memory limit \"{}\". Use a size such as 512m or 2g.",
+                    memory
+                ));
+            }
+        }
+
+        if let Some(ref user) = self.user {
+            if !is_valid_docker_user(user) {
+                return Err(format!(
+                    "Invalid docker user \"{}\". Use UID[:GID], a user name, or host.",
+                    user
+                ));
+            }
+        }
+
+        if let Some(name) = self
+            .env_passthrough
+            .iter()
+            .find(|name| !is_valid_env_name(name))
+        {
+            return Err(format!(
+                "Invalid docker environment variable name \"{}\"",
+                name
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Check a `UID[:GID]` or `user[:group]` value for `--user`
+pub fn is_valid_docker_user(user: &str) -> bool {
+    let is_name = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    };
+    match user.split_once(':') {
+        Some((user, group)) => is_name(user) && is_name(group),
+        None => is_name(user),
+    }
+}
+
+/// Resolve the `host` docker user to the `UID:GID` owning the working directory
+///
+/// # Arguments
+/// * `user` - Configured user
+/// * `working_directory` - Directory mounted into the container
+///
+/// # Returns
+/// User to pass to `docker run --user`
+pub fn resolve_docker_user(user: &str, working_directory: &str) -> Result<String, String> {
+    if user != "host" {
+        return Ok(user.to_string());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::metadata(working_directory)
+            .map_err(|e| format!("Cannot read owner of {}: {}", working_directory, e))?;
+        Ok(format!("{}:{}", metadata.uid(), metadata.gid()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = working_directory;
+        Err("docker user \"host\" is only supported on Unix".to_string())
+    }
+}
+
+/// Build docker isolation launch spec
+///
+/// # Arguments
+/// * `base_command` - Shell command run inside the container
+/// * `container_name` - Container name (generated when `None`)
+/// * `working_directory` - Directory mounted and used as the container workdir
+/// * `detached` - Run the container in the background (`-d`) instead of `-it`
+/// * `docker` - Image, mounts, network, limits, user, environment and removal
+/// * `capture` - Files on the host to copy the agent's output and exit code
+///   to, for a container that may be gone by the time it is stopped
+///
+/// # Returns
+/// Launch spec for `docker run`
+pub fn build_docker_launch_spec(
+    base_command: &str,
+    container_name: Option<&str>,
+    working_directory: &str,
+    detached: bool,
+    docker: &DockerOptions,
+    capture: Option<&CaptureFiles>,
+) -> LaunchSpec {
+    let name = container_name.map_or_else(generate_isolation_name, str::to_string);
+
+    let mut args = vec!["run".to_string()];
+    if detached {
+        args.push("-d".to_string());
+    } else {
+        args.push("-it".to_string());
+    }
+    if docker.removal == DockerRemoval::Auto {
+        args.push("--rm".to_string());
+    }
+    args.extend([
+        "--name".to_string(),
+        name,
+        "-v".to_string(),
+        format!("{}:{}", working_directory, working_directory),
+    ]);
+    if let Some(capture) = capture {
+        let dir = capture.dir.to_string_lossy();
+        args.push("-v".to_string());
+        args.push(format!("{}:{}", dir, dir));
+    }
+    for volume in &docker.volumes {
+        args.push("-v".to_string());
+        args.push(volume.to_arg());
+    }
+    for (flag, value) in [
+        ("--network", &docker.network),
+        ("--cpus", &docker.cpus),
+        ("--memory", &docker.memory),
+        ("--user", &docker.user),
+    ] {
+        if let Some(value) = value {
+            args.push(flag.to_string());
+            args.push(value.clone());
+        }
+    }
+    for name in &docker.env_passthrough {
+        args.push("-e".to_string());
+        args.push(name.clone());
+    }
+    args.extend([
+        "-w".to_string(),
+        working_directory.to_string(),
+        docker.image.clone(),
+        "bash".to_string(),
+        "-c".to_string(),
+    ]);
+    if let Some(capture) = capture {
+        args.push(CAPTURE_WRAPPER.to_string());
+        for file in [&capture.log, &capture.stderr, &capture.exit_code] {
+            args.push(file.to_string_lossy().into_owned());
+        }
+    }
+    args.push(base_command.to_string());
+
+    LaunchSpec {
+        args,
+        // `-it` needs the caller's terminal
+        stdin: if detached {
+            LaunchStdin::Null
+        } else {
+            LaunchStdin::Inherit
+        },
+        ..LaunchSpec::new("docker")
+    }
+}
+
+/// Build stop command for docker containers
+///
+/// Only [`DockerRemoval::OnStop`] removes the container after stopping it;
+/// docker removes an `auto` container itself and a `keep` one stays.
+///
+/// # Arguments
+/// * `container_name` - Container name
+/// * `removal` - Removal policy the container was started with
+///
+/// # Returns
+/// Stop command
+pub fn build_docker_stop_command(container_name: &str, removal: DockerRemoval) -> String {
+    let name = quote_shell_arg(container_name);
+    match removal {
+        DockerRemoval::OnStop => format!("docker stop {} && docker rm {}", name, name),
+        DockerRemoval::Auto | DockerRemoval::Keep => format!("docker stop {}", name),
+    }
+}
+
+/// Stop a docker container and collect the agent's output
+///
+/// Runs `docker stop`, then `docker logs` (stdout and stderr stay separate),
+/// `docker inspect` for the exit code, and finally `docker rm` unless the
+/// removal policy keeps the container. A [`DockerRemoval::Auto`] container
+/// may already be gone, so its output and exit code are read from the
+/// capture files it was started with; without them the logs are read
+/// before stopping and the exit code is reported as -1.
+///
+/// # Arguments
+/// * `container_name` - Container name
+/// * `removal` - Removal policy the container was started with
+/// * `capture` - Capture files the container was started with
+///
+/// # Returns
+/// The agent's output and the container's exit code
+pub async fn stop_docker_container(
+    container_name: &str,
+    removal: DockerRemoval,
+    capture: Option<&CaptureFiles>,
+) -> Result<ExecutionResult, String> {
+    let docker = |args: &[&str]| LaunchSpec {
+        args: args
+            .iter()
+            .map(|arg| arg.to_string())
+            .chain(std::iter::once(container_name.to_string()))
+            .collect(),
+        ..LaunchSpec::new("docker")
+    };
+    let stop = || async {
+        // An already exited or removed container is left as is
+        execute_launch(&docker(&["stop"]))
+            .await
+            .map_err(|e| e.to_string())
+    };
+
+    if removal == DockerRemoval::Auto {
+        if let Some(capture) = capture {
+            let stopped = stop().await?;
+            if let Some(captured) = capture.collect_streams(stopped).await {
+                return Ok(captured);
+            }
+        }
+    } else {
+        stop().await?;
+    }
+
+    let logs = execute_launch(&docker(&["logs"]))
+        .await
+        .map_err(|e| e.to_string())?;
+    if logs.exit_code != 0 && removal == DockerRemoval::Auto {
+        return Err(format!(
+            "Container {} was removed before its output could be collected: {}",
+            container_name,
+            logs.stderr.trim()
+        ));
+    }
+    if logs.exit_code != 0 {
+        return Err(format!(
+            "Failed to read logs of container {}: {}",
+            container_name,
+            logs.stderr.trim()
+        ));
+    }
+
+    if removal == DockerRemoval::Auto {
+        stop().await?;
+        return Ok(ExecutionResult {
+            exit_code: -1,
+            ..logs
+        });
+    }
+
+    let inspect = execute_launch(&docker(&["inspect", "--format", "{{.State.ExitCode}}"]))
+        .await
+        .map_err(|e| e.to_string())?;
+    let exit_code = inspect.stdout.trim().parse().unwrap_or(-1);
+
+    if removal == DockerRemoval::OnStop {
+        let removed = execute_launch(&docker(&["rm"]))
+            .await
+            .map_err(|e| e.to_string())?;
+        if removed.exit_code != 0 {
+            return Err(format!(
+                "Failed to remove container {}: {}",
+                container_name,
+                removed.stderr.trim()
+            ));
+        }
+    }
+
+    Ok(ExecutionResult { exit_code, ..logs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_docker_stop_command() {
+        assert_eq!(
+            build_docker_stop_command("my-container", DockerRemoval::OnStop),
+            "docker stop my-container && docker rm my-container"
+        );
+        assert_eq!(
+            build_docker_stop_command("my-container", DockerRemoval::Auto),
+            "docker stop my-container"
+        );
+        assert_eq!(
+            build_docker_stop_command("my box", DockerRemoval::Keep),
+            "docker stop 'my box'"
+        );
+    }
+
+    #[test]
+    fn test_build_docker_launch_spec_applies_options() {
+        let docker = DockerOptions {
+            image: "rust:1.80".to_string(),
+            volumes: vec![DockerVolume::parse("/home/me/.cargo:/usr/local/cargo:ro").unwrap()],
+            network: Some("none".to_string()),
+            cpus: Some("2".to_string()),
+            memory: Some("4g".to_string()),
+            user: Some("1000:1000".to_string()),
+            env_passthrough: vec!["ANTHROPIC_API_KEY".to_string()],
+            removal: DockerRemoval::Auto,
+        };
+        let capture = CaptureFiles::in_dir("/state/docker/box");
+        let spec = build_docker_launch_spec(
+            "claude -p hi",
+            Some("box"),
+            "/work",
+            true,
+            &docker,
+            Some(&capture),
+        );
+
+        assert_eq!(
+            spec.args,
+            vec![
+                "run",
+                "-d",
+                "--rm",
+                "--name",
+                "box",
+                "-v",
+                "/work:/work",
+                "-v",
+                "/state/docker/box:/state/docker/box",
+                "-v",
+                "/home/me/.cargo:/usr/local/cargo:ro",
+                "--network",
+                "none",
+                "--cpus",
+                "2",
+                "--memory",
+                "4g",
+                "--user",
+                "1000:1000",
+                "-e",
+                "ANTHROPIC_API_KEY",
+                "-w",
+                "/work",
+                "rust:1.80",
+                "bash",
+                "-c",
+                CAPTURE_WRAPPER,
+                "/state/docker/box/output.log",
+                "/state/docker/box/stderr.log",
+                "/state/docker/box/exit_code",
+                "claude -p hi",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_capture_wrapper_copies_streams_and_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let capture = CaptureFiles::in_dir(dir.path());
+        let output = std::process::Command::new("bash")
+            .args(["-c", CAPTURE_WRAPPER])
+            .args([&capture.log, &capture.stderr, &capture.exit_code])
+            .arg("echo out; echo err >&2; exit 7")
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(7));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+        assert_eq!(std::fs::read_to_string(&capture.log).unwrap(), "out\n");
+        assert_eq!(std::fs::read_to_string(&capture.stderr).unwrap(), "err\n");
+        assert_eq!(std::fs::read_to_string(&capture.exit_code).unwrap(), "7\n");
+    }
+
+    #[test]
+    fn test_docker_volume_parse() {
+        let volume = DockerVolume::parse("cache:/cache").unwrap();
+        assert_eq!(volume.source, "cache");
+        assert!(!volume.read_only);
+        assert_eq!(volume.to_arg(), "cache:/cache");
+
+        assert!(DockerVolume::parse("/data").is_err());
+        assert!(DockerVolume::parse("relative/dir:/data").is_err());
+        assert!(DockerVolume::parse("/data:relative").is_err());
+    }
+
+    #[test]
+    fn test_docker_options_validate_rejects_bad_values() {
+        let invalid = [
+            DockerOptions {
+                image: "node 18".to_string(),
+                ..Default::default()
+            },
+            DockerOptions {
+                network: Some("bad network".to_string()),
+                ..Default::default()
+            },
+            DockerOptions {
+                cpus: Some("-1".to_string()),
+                ..Default::default()
+            },
+            DockerOptions {
+                memory: Some("lots".to_string()),
+                ..Default::default()
+            },
+            DockerOptions {
+                user: Some("1000:".to_string()),
+                ..Default::default()
+            },
+            DockerOptions {
+                env_passthrough: vec!["A B".to_string()],
+                ..Default::default()
+            },
+        ];
+        for options in invalid {
+            assert!(options.validate().is_err(), "{:?}", options);
+        }
+
+        let valid = DockerOptions {
+            network: Some("container:db".to_string()),
+            cpus: Some("0.5".to_string()),
+            memory: Some("512m".to_string()),
+            user: Some("host".to_string()),
+            ..Default::default()
+        };
+        assert!(valid.validate().is_ok());
+        assert!(DockerRemoval::parse("sometimes").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_resolve_docker_user_maps_host_to_directory_owner() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = std::fs::metadata(dir.path()).unwrap();
+        let path = dir.path().to_string_lossy();
+
+        assert_eq!(
+            resolve_docker_user("host", &path).unwrap(),
+            format!("{}:{}", metadata.uid(), metadata.gid())
+        );
+        assert_eq!(resolve_docker_user("1000", &path).unwrap(), "1000");
+    }
+}
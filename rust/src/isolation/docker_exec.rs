@@ -0,0 +1,442 @@
+//! Docker exec isolation: run the agent inside an existing container
+
+use super::docker::is_valid_docker_user;
+use crate::executor::{execute_launch, ExecutionResult, ProcessHandle};
+use crate::launch::{LaunchSpec, LaunchStdin};
+use crate::process_group::{ProcessSignal, TerminationSchedule};
+use std::time::Instant;
+
+/// Directory inside the container holding runs' PIDs, and detached runs'
+/// stdin, output and exit code
+pub const DOCKER_EXEC_STATE_DIR: &str = "/tmp/agent-commander";
+
+/// Saves stdin, then starts the agent as a background job with its own
+/// process group (`set -m`) that records its exit code when it finishes.
+/// The job does not hold on to docker exec's output, so the launch returns.
+/// `$0` is the run directory and `$1` the agent's shell command.
+const DETACHED_WRAPPER: &str = r#"d=$0; mkdir -p "$d" && cat > "$d/stdin" || exit 1
+set -m
+bash -c 'trap : INT TERM; d=$0; "$@" < "$d/stdin" > "$d/stdout" 2> "$d/stderr"; echo $? > "$d/exit_code"' "$d" bash -c "$1" < /dev/null > /dev/null 2>&1 &
+echo $! > "$d/pid""#;
+
+/// Starts the agent with its own process group (`set -m`), records its PID
+/// so a timeout can stop it inside the container, and waits for it with
+/// docker exec's stdin and output attached. The run directory is removed
+/// once the agent exits. `$0` is the run directory and `$1` the agent's
+/// shell command.
+const ATTACHED_WRAPPER: &str = r#"d=$0; mkdir -p "$d" || exit 1
+set -m
+bash -c "$1" &
+pid=$!; set +m
+echo "$pid" > "$d/pid"
+wait "$pid"; code=$?
+rm -rf "$d"
+exit "$code""#;
+
+/// Sends SIGINT, SIGTERM and SIGKILL to the run's process group, waiting
+/// `$1`, `$2` and 5 seconds in between, then prints the exit code, or
+/// `signal NAME` when the run was killed before it could record one.
+const STOP_SCRIPT: &str = r#"d=$0; pid=$(cat "$d/pid") || exit 1
+last=
+for step in "INT $1" "TERM $2" "KILL 5"; do
+  set -- $step
+  [ -s "$d/exit_code" ] && break
+  kill -0 -- "-$pid" 2>/dev/null || break
+  kill -s "$1" -- "-$pid" 2>/dev/null
+  last=$1
+  i=0
+  while [ "$i" -lt "$(($2 * 10))" ] && [ ! -s "$d/exit_code" ] && kill -0 -- "-$pid" 2>/dev/null; do sleep 0.1; i=$((i + 1)); done
+done
+i=0
+while [ -d "$d" ] && [ ! -s "$d/exit_code" ] && [ "$i" -lt 10 ]; do sleep 0.1; i=$((i + 1)); done
+if [ -s "$d/exit_code" ]; then cat "$d/exit_code"; else echo "signal $last"; fi"#;
+
+/// Prints the run's stdout and stderr to the matching streams and removes
+/// the run directory
+const COLLECT_SCRIPT: &str = r#"d=$0; cat "$d/stdout"; cat "$d/stderr" >&2; rm -rf "$d""#;
+
+/// Settings for running the agent in an existing container with `docker exec`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DockerExecOptions {
+    /// Working directory inside the container (defaults to the agent's
+    /// working directory)
+    pub working_directory: Option<String>,
+    /// `UID[:GID]` or user name to run as (defaults to the container's user)
+    pub user: Option<String>,
+}
+
+impl DockerExecOptions {
+    /// Validate every setting
+    ///
+    /// # Returns
+    /// Ok, or the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref working_directory) = self.working_directory {
+            if !working_directory.starts_with('/') {
+                return Err(format!(
+                    "Invalid container working directory \"{}\". Use an absolute path.",
+                    working_directory
+                ));
+            }
+        }
+
+        if let Some(ref user) = self.user {
+            if !is_valid_docker_user(user) {
+                return Err(format!(
+                    "Invalid docker exec user \"{}\". Use UID[:GID] or a user name.",
+                    user
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that a run ID can name a directory inside the container
+pub fn is_valid_exec_run_id(run_id: &str) -> bool {
+    !run_id.is_empty()
+        && run_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        && !run_id.starts_with('.')
+}
+
+fn run_dir(run_id: &str) -> String {
+    format!("{}/{}", DOCKER_EXEC_STATE_DIR, run_id)
+}
+
+/// Build docker exec isolation launch spec
+///
+/// Attached runs pipe the agent's stdin through `docker exec -i` and record
+/// the agent's PID under [`DOCKER_EXEC_STATE_DIR`] in the container. Detached
+/// runs save stdin there as well and start the agent in the background, so
+/// the launch returns as soon as the agent is running.
+///
+/// # Arguments
+/// * `spec` - Agent launch spec; its cwd is the default container workdir
+/// * `container_name` - Existing container
+/// * `run_id` - Name of the run's directory in the container
+/// * `detached` - Start the agent in the background
+/// * `exec` - Container working directory and user
+///
+/// # Returns
+/// Launch spec for `docker exec`
+pub fn build_docker_exec_launch_spec(
+    spec: &LaunchSpec,
+    container_name: &str,
+    run_id: &str,
+    detached: bool,
+    exec: &DockerExecOptions,
+) -> LaunchSpec {
+    // The working directory and stdin are set up by docker exec, not by the
+    // inner command
+    let inner_command = LaunchSpec {
+        cwd: None,
+        stdin: LaunchStdin::Null,
+        ..spec.clone()
+    }
+    .to_shell_command();
+
+    let mut args = vec!["exec".to_string(), "-i".to_string()];
+    if let Some(ref user) = exec.user {
+        args.push("-u".to_string());
+        args.push(user.clone());
+    }
+    let working_directory = exec.working_directory.clone().or_else(|| {
+        spec.cwd
+            .as_ref()
+            .map(|cwd| cwd.to_string_lossy().into_owned())
+    });
+    if let Some(working_directory) = working_directory {
+        args.push("-w".to_string());
+        args.push(working_directory);
+    }
+    args.push(container_name.to_string());
+    args.push("bash".to_string());
+    args.push("-c".to_string());
+    let wrapper = if detached {
+        DETACHED_WRAPPER
+    } else {
+        ATTACHED_WRAPPER
+    };
+    args.extend([wrapper.to_string(), run_dir(run_id), inner_command]);
+
+    LaunchSpec {
+        args,
+        stdin: spec.stdin.clone(),
+        ..LaunchSpec::new("docker")
+    }
+}
+
+fn build_stop_spec(
+    container_name: &str,
+    run_id: &str,
+    schedule: &TerminationSchedule,
+) -> LaunchSpec {
+    LaunchSpec {
+        args: vec![
+            "exec".to_string(),
+            container_name.to_string(),
+            "bash".to_string(),
+            "-c".to_string(),
+            STOP_SCRIPT.to_string(),
+            run_dir(run_id),
+            schedule.interrupt_grace.as_secs().to_string(),
+            schedule.terminate_grace.as_secs().to_string(),
+        ],
+        ..LaunchSpec::new("docker")
+    }
+}
+
+/// Build stop command for a detached docker exec run
+///
+/// # Arguments
+/// * `container_name` - Container the run lives in
+/// * `run_id` - Run ID printed by start
+///
+/// # Returns
+/// Stop command
+pub fn build_docker_exec_stop_command(container_name: &str, run_id: &str) -> String {
+    build_stop_spec(container_name, run_id, &TerminationSchedule::default()).to_shell_command()
+}
+
+/// Wait for an attached docker exec run, stopping the agent inside the
+/// container once `deadline` passes
+///
+/// Signals sent to the local `docker exec` client do not reach the agent,
+/// so after the deadline the agent's process group in the container
+/// receives SIGINT, SIGTERM and SIGKILL following `schedule`. The client is
+/// terminated locally only if it has not ended by the next grace period.
+///
+/// # Arguments
+/// * `handle` - The running `docker exec` client
+/// * `container_name` - Container the run lives in
+/// * `run_id` - Name of the run's directory in the container
+/// * `deadline` - Point in time after which the run is stopped
+/// * `schedule` - Grace periods between termination signals
+///
+/// # Returns
+/// Exit code and whether the deadline was hit
+pub async fn wait_for_docker_exec_until(
+    handle: &mut ProcessHandle,
+    container_name: &str,
+    run_id: &str,
+    deadline: Instant,
+    schedule: &TerminationSchedule,
+) -> Result<(i32, bool), String> {
+    let wait_deadline = tokio::time::Instant::from_std(deadline);
+    if let Ok(result) = tokio::time::timeout_at(wait_deadline, handle.wait_for_exit()).await {
+        return result
+            .map(|exit_code| (exit_code, false))
+            .map_err(|e| e.to_string());
+    }
+
+    // The run may have ended on its own in the meantime, which leaves
+    // nothing to stop
+    let _ = execute_launch(&build_stop_spec(container_name, run_id, schedule)).await;
+    handle
+        .wait_for_exit_until(Instant::now() + schedule.interrupt_grace, schedule)
+        .await
+        .map(|(exit_code, _)| (exit_code, true))
+        .map_err(|e| e.to_string())
+}
+
+/// Stop a detached docker exec run and collect the agent's output
+///
+/// Only the agent's process group inside the container is signalled
+/// (SIGINT, SIGTERM, then SIGKILL following `schedule`); the container
+/// keeps running. The run's stdout, stderr and exit code are then read
+/// back and its directory removed.
+///
+/// # Arguments
+/// * `container_name` - Container the run lives in
+/// * `run_id` - Run ID printed by start
+/// * `schedule` - Grace periods between signals
+///
+/// # Returns
+/// The agent's output and exit code
+pub async fn stop_docker_exec_run(
+    container_name: &str,
+    run_id: &str,
+    schedule: &TerminationSchedule,
+) -> Result<ExecutionResult, String> {
+    let stopped = execute_launch(&build_stop_spec(container_name, run_id, schedule))
+        .await
+        .map_err(|e| e.to_string())?;
+    if stopped.exit_code != 0 {
+        return Err(format!(
+            "Failed to stop run {} in container {}: {}",
+            run_id,
+            container_name,
+            stopped.stderr.trim()
+        ));
+    }
+
+    // A run killed before it recorded its exit code reports the
+    // conventional shell status for the last signal sent
+    let status = stopped.stdout.trim();
+    let exit_code = status.parse().unwrap_or_else(|_| {
+        match status.strip_prefix("signal ").unwrap_or_default() {
+            "INT" => ProcessSignal::Interrupt.exit_code(),
+            "TERM" => ProcessSignal::Terminate.exit_code(),
+            "KILL" => ProcessSignal::Kill.exit_code(),
+            _ => -1,
+        }
+    });
+
+    let collected = execute_launch(&LaunchSpec {
+        args: vec![
+            "exec".to_string(),
+            container_name.to_string(),
+            "bash".to_string(),
+            "-c".to_string(),
+            COLLECT_SCRIPT.to_string(),
+            run_dir(run_id),
+        ],
+        ..LaunchSpec::new("docker")
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(ExecutionResult {
+        exit_code,
+        ..collected
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn agent_spec() -> LaunchSpec {
+        LaunchSpec {
+            args: vec!["-p".to_string(), "hi".to_string()],
+            cwd: Some(PathBuf::from("/work")),
+            stdin: LaunchStdin::File(PathBuf::from("/tmp/prompt.txt")),
+            ..LaunchSpec::new("claude")
+        }
+    }
+
+    #[test]
+    fn test_build_docker_exec_launch_spec_attached() {
+        let exec = DockerExecOptions {
+            working_directory: Some("/workspace".to_string()),
+            user: Some("1000:1000".to_string()),
+        };
+        let spec = build_docker_exec_launch_spec(&agent_spec(), "dev", "run-1", false, &exec);
+
+        assert_eq!(spec.program, "docker");
+        assert_eq!(
+            spec.args,
+            vec![
+                "exec",
+                "-i",
+                "-u",
+                "1000:1000",
+                "-w",
+                "/workspace",
+                "dev",
+                "bash",
+                "-c",
+                ATTACHED_WRAPPER,
+                "/tmp/agent-commander/run-1",
+                "claude -p hi"
+            ]
+        );
+        assert_eq!(
+            spec.stdin,
+            LaunchStdin::File(PathBuf::from("/tmp/prompt.txt"))
+        );
+    }
+
+    #[test]
+    fn test_build_docker_exec_launch_spec_detached() {
+        let spec = build_docker_exec_launch_spec(
+            &agent_spec(),
+            "dev",
+            "run-1",
+            true,
+            &DockerExecOptions::default(),
+        );
+
+        assert_eq!(&spec.args[..4], &["exec", "-i", "-w", "/work"]);
+        assert_eq!(spec.args[7], DETACHED_WRAPPER);
+        assert_eq!(spec.args[8], "/tmp/agent-commander/run-1");
+        assert_eq!(spec.args[9], "claude -p hi");
+    }
+
+    #[test]
+    fn test_build_docker_exec_stop_command() {
+        let command = build_docker_exec_stop_command("dev", "run-1");
+
+        assert!(command.starts_with("docker exec dev bash -c "));
+        assert!(command.ends_with(" /tmp/agent-commander/run-1 10 5"));
+    }
+
+    #[test]
+    fn test_docker_exec_options_validate() {
+        assert!(DockerExecOptions::default().validate().is_ok());
+        assert!(DockerExecOptions {
+            working_directory: Some("relative".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(DockerExecOptions {
+            user: Some("root; rm".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(is_valid_exec_run_id("agent-1700000000000"));
+        assert!(!is_valid_exec_run_id("../etc"));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_detached_wrapper_and_stop_script() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = dir.path().join("run");
+        let run = run.to_str().unwrap();
+        let bash = |args: &[&str], input: &str| {
+            use std::io::Write;
+            let mut child = std::process::Command::new("bash")
+                .arg("-c")
+                .args(args)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(input.as_bytes())
+                .unwrap();
+            child.wait_with_output().unwrap()
+        };
+
+        let started = bash(
+            &[
+                DETACHED_WRAPPER,
+                run,
+                "cat; trap 'echo saving; exit 3' INT; while true; do sleep 0.1; done",
+            ],
+            "hello\n",
+        );
+        assert!(started.status.success());
+        std::thread::sleep(std::time::Duration::from_millis(300));
+
+        let stopped = bash(&[STOP_SCRIPT, run, "2", "1"], "");
+        assert_eq!(String::from_utf8_lossy(&stopped.stdout).trim(), "3");
+
+        let collected = bash(&[COLLECT_SCRIPT, run], "");
+        assert_eq!(
+            String::from_utf8_lossy(&collected.stdout),
+            "hello\nsaving\n"
+        );
+        assert!(!std::path::Path::new(run).exists());
+    }
+}
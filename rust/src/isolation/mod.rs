@@ -0,0 +1,147 @@
+//! Isolation wrappers for agent runs
+//!
+//! Each wrapper turns the tool's rendered shell command into a launch spec
+//! for the isolation program, plus the command that stops it again.
+//! Stopping collects the agent's own output: the screen logfile or tmux
+//! `pipe-pane` transcript, `docker logs` and the container exit code taken
+//! before removal (or the capture files of a container docker removes
+//! itself), or the output files of a run started with `docker exec`.
+//! The bwrap sandbox is the exception: it wraps the tool's argv and runs as
+//! a local process, like a run without isolation. A git worktree is not a
+//! wrapper at all: it only changes the directory the agent runs in, and
+//! combines with any mode except `docker-exec`.
+
+mod bwrap;
+mod docker;
+mod docker_exec;
+mod screen;
+mod tmux;
+mod worktree;
+
+use crate::executor::{captured_output_log, now_ms, ExecutionResult};
+use std::path::PathBuf;
+
+pub use bwrap::{build_bwrap_launch_spec, BwrapOptions};
+pub use docker::{
+    build_docker_launch_spec, build_docker_stop_command, resolve_docker_user,
+    stop_docker_container, DockerOptions, DockerRemoval, DockerVolume, DEFAULT_DOCKER_IMAGE,
+};
+pub use docker_exec::{
+    build_docker_exec_launch_spec, build_docker_exec_stop_command, stop_docker_exec_run,
+    DockerExecOptions, DOCKER_EXEC_STATE_DIR,
+};
+pub(crate) use docker_exec::{is_valid_exec_run_id, wait_for_docker_exec_until};
+pub use screen::{
+    build_screen_launch_spec, build_screen_stop_command, screen_capture_config, stop_screen_session,
+};
+pub use tmux::{build_tmux_launch_spec, build_tmux_stop_command, stop_tmux_session};
+pub use worktree::{
+    build_worktree_add_command, create_worktree, finish_worktree, WorktreeInfo, WorktreeOptions,
+};
+pub(crate) use worktree::{discard_worktree, mount_worktree_git};
+
+/// Files a terminal session's or container's output and exit code are
+/// captured to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureFiles {
+    /// Directory holding the capture files
+    pub dir: PathBuf,
+    /// Terminal transcript written by screen or tmux, or a container's stdout
+    pub log: PathBuf,
+    /// A container's stderr
+    pub stderr: PathBuf,
+    /// Exit code of the agent command, written when it finishes
+    pub exit_code: PathBuf,
+    /// Configuration file passed to screen with `-c`
+    pub config: PathBuf,
+}
+
+impl CaptureFiles {
+    /// Capture files inside `dir`
+    pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Self {
+            log: dir.join("output.log"),
+            stderr: dir.join("stderr.log"),
+            exit_code: dir.join("exit_code"),
+            config: dir.join("screenrc"),
+            dir,
+        }
+    }
+
+    /// Create the directory and screen configuration, discarding the
+    /// capture of an earlier session with the same name
+    pub async fn prepare(&self) -> Result<(), String> {
+        let _ = tokio::fs::remove_dir_all(&self.dir).await;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::fs::write(&self.config, screen_capture_config(&self.log))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Read the transcript and exit code, then remove the directory
+    ///
+    /// The transcript is terminal output, so stdout and stderr arrive merged
+    /// in `stdout`, with CRLF line endings turned back into LF. Without a
+    /// transcript, `stop_result` (the output of the stop command) is returned.
+    pub(crate) async fn collect(&self, stop_result: ExecutionResult) -> ExecutionResult {
+        let Ok(transcript) = tokio::fs::read_to_string(&self.log).await else {
+            return stop_result;
+        };
+        let stdout = transcript.replace("\r\n", "\n");
+        self.finish(stdout, String::new(), stop_result).await
+    }
+
+    /// Read a container's stdout, stderr and exit code, then remove the
+    /// directory
+    ///
+    /// # Returns
+    /// The captured result, or `None` when the container captured nothing
+    pub(crate) async fn collect_streams(
+        &self,
+        stop_result: ExecutionResult,
+    ) -> Option<ExecutionResult> {
+        let stdout = tokio::fs::read_to_string(&self.log).await.ok()?;
+        let stderr = tokio::fs::read_to_string(&self.stderr)
+            .await
+            .unwrap_or_default();
+        Some(self.finish(stdout, stderr, stop_result).await)
+    }
+
+    /// Pair captured output with the recorded exit code (-1 when the agent
+    /// did not finish) and remove the directory
+    async fn finish(
+        &self,
+        stdout: String,
+        stderr: String,
+        stop_result: ExecutionResult,
+    ) -> ExecutionResult {
+        let exit_code = tokio::fs::read_to_string(&self.exit_code)
+            .await
+            .ok()
+            .and_then(|code| code.trim().parse().ok())
+            .unwrap_or(-1);
+        let _ = tokio::fs::remove_dir_all(&self.dir).await;
+
+        ExecutionResult {
+            exit_code,
+            output_log: captured_output_log(&stdout, &stderr, now_ms()),
+            stdout,
+            stderr,
+            command: stop_result.command,
+        }
+    }
+}
+
+/// Generate a unique name for an unnamed session, container or run
+pub(crate) fn generate_isolation_name() -> String {
+    format!(
+        "agent-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    )
+}
@@ -0,0 +1,169 @@
+//! GNU Screen isolation
+
+use super::{generate_isolation_name, CaptureFiles};
+use crate::executor::{execute_launch, ExecutionResult};
+use crate::launch::{quote_shell_arg, LaunchSpec, LaunchStdin};
+use std::path::Path;
+
+/// Screen configuration that logs every window to `log` without buffering
+///
+/// # Arguments
+/// * `log` - Logfile path
+///
+/// # Returns
+/// Contents for a file passed with `screen -c`
+pub fn screen_capture_config(log: &Path) -> String {
+    let log = log
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    format!("logfile \"{}\"\nlogfile flush 0\ndeflog on\n", log)
+}
+
+/// Build screen isolation launch spec
+///
+/// # Arguments
+/// * `base_command` - Shell command run inside the session
+/// * `screen_name` - Session name (generated when `None`)
+/// * `detached` - Start the session detached (`-dmS`) instead of attached
+/// * `capture` - Files to log the session and record the exit code to
+///
+/// # Returns
+/// Launch spec for `screen`
+pub fn build_screen_launch_spec(
+    base_command: &str,
+    screen_name: Option<&str>,
+    detached: bool,
+    capture: Option<&CaptureFiles>,
+) -> LaunchSpec {
+    let session_name = screen_name.map_or_else(generate_isolation_name, str::to_string);
+
+    let mut args = Vec::new();
+    let inner_command = if let Some(capture) = capture {
+        args.push("-c".to_string());
+        args.push(capture.config.to_string_lossy().into_owned());
+        format!(
+            "{}; echo $? > {}",
+            base_command,
+            quote_shell_arg(&capture.exit_code.to_string_lossy())
+        )
+    } else {
+        base_command.to_string()
+    };
+
+    // Start detached (-dmS) or attached (-S) screen session
+    let mode = if detached { "-dmS" } else { "-S" };
+    args.extend([
+        mode.to_string(),
+        session_name,
+        "bash".to_string(),
+        "-c".to_string(),
+        inner_command,
+    ]);
+
+    LaunchSpec {
+        args,
+        // An attached session needs the caller's terminal
+        stdin: if detached {
+            LaunchStdin::Null
+        } else {
+            LaunchStdin::Inherit
+        },
+        ..LaunchSpec::new("screen")
+    }
+}
+
+/// Build stop command for screen sessions
+///
+/// # Arguments
+/// * `screen_name` - Screen session name
+///
+/// # Returns
+/// Stop command
+pub fn build_screen_stop_command(screen_name: &str) -> String {
+    build_quit_spec(screen_name).to_shell_command()
+}
+
+fn build_quit_spec(screen_name: &str) -> LaunchSpec {
+    LaunchSpec {
+        args: vec![
+            "-S".to_string(),
+            screen_name.to_string(),
+            "-X".to_string(),
+            "quit".to_string(),
+        ],
+        ..LaunchSpec::new("screen")
+    }
+}
+
+/// Quit a screen session and collect the agent's output from its logfile
+///
+/// The logfile is a terminal transcript, so stdout and stderr arrive merged
+/// in `stdout`. Sessions started without capture files fall back to the
+/// output of `screen -X quit`.
+///
+/// # Arguments
+/// * `screen_name` - Screen session name
+/// * `capture` - Capture files the session was started with
+///
+/// # Returns
+/// The agent's output and exit code (-1 if the session was quit before the
+/// agent finished)
+pub async fn stop_screen_session(
+    screen_name: &str,
+    capture: &CaptureFiles,
+) -> Result<ExecutionResult, String> {
+    let quit = execute_launch(&build_quit_spec(screen_name))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(capture.collect(quit).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_screen_stop_command() {
+        assert_eq!(
+            build_screen_stop_command("my-session"),
+            "screen -S my-session -X quit"
+        );
+        assert_eq!(
+            build_screen_stop_command("it's \"$(rm -rf ~)\""),
+            "screen -S 'it'\\''s \"$(rm -rf ~)\"' -X quit"
+        );
+    }
+
+    #[test]
+    fn test_build_screen_launch_spec_with_capture() {
+        let capture = CaptureFiles::in_dir("/state/screen/my-session");
+        let spec =
+            build_screen_launch_spec("claude -p hi", Some("my-session"), true, Some(&capture));
+
+        assert_eq!(spec.program, "screen");
+        assert_eq!(
+            spec.args,
+            vec![
+                "-c",
+                "/state/screen/my-session/screenrc",
+                "-dmS",
+                "my-session",
+                "bash",
+                "-c",
+                "claude -p hi; echo $? > /state/screen/my-session/exit_code",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_screen_capture_config_logs_unbuffered() {
+        let config = screen_capture_config(Path::new("/tmp/my \"dir\"/screen.log"));
+
+        assert_eq!(
+            config,
+            "logfile \"/tmp/my \\\"dir\\\"/screen.log\"\nlogfile flush 0\ndeflog on\n"
+        );
+    }
+}
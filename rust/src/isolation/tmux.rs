@@ -0,0 +1,180 @@
+//! tmux isolation
+
+use super::{generate_isolation_name, CaptureFiles};
+use crate::executor::{execute_launch, ExecutionResult};
+use crate::launch::{quote_shell_arg, LaunchSpec, LaunchStdin};
+
+/// Marker created once `pipe-pane` is logging the session
+fn ready_marker(capture: &CaptureFiles) -> String {
+    quote_shell_arg(&capture.dir.join("ready").to_string_lossy())
+}
+
+/// Build tmux isolation launch spec
+///
+/// With capture files, `pipe-pane` is attached in the same tmux invocation
+/// and the agent waits (up to five seconds) until it is logging, so no
+/// output is lost; the exit code is written when the agent finishes.
+///
+/// # Arguments
+/// * `base_command` - Shell command run inside the session
+/// * `session_name` - Session name (generated when `None`)
+/// * `detached` - Start the session detached (`-d`) instead of attached
+/// * `capture` - Files to log the session and record the exit code to
+///
+/// # Returns
+/// Launch spec for `tmux`
+pub fn build_tmux_launch_spec(
+    base_command: &str,
+    session_name: Option<&str>,
+    detached: bool,
+    capture: Option<&CaptureFiles>,
+) -> LaunchSpec {
+    let session_name = session_name.map_or_else(generate_isolation_name, str::to_string);
+
+    let inner_command = if let Some(capture) = capture {
+        format!(
+            "i=0; while [ ! -e {ready} ] && [ $i -lt 50 ]; do sleep 0.1; i=$((i + 1)); done; {}; echo $? > {}",
+            base_command,
+            quote_shell_arg(&capture.exit_code.to_string_lossy()),
+            ready = ready_marker(capture),
+        )
+    } else {
+        base_command.to_string()
+    };
+
+    let mut args = vec!["new-session".to_string()];
+    if detached {
+        args.push("-d".to_string());
+    }
+    args.extend([
+        "-s".to_string(),
+        session_name.clone(),
+        // tmux runs a single shell command string with `sh -c`
+        format!("bash -c {}", quote_shell_arg(&inner_command)),
+    ]);
+    if let Some(capture) = capture {
+        args.extend([
+            ";".to_string(),
+            "pipe-pane".to_string(),
+            "-o".to_string(),
+            "-t".to_string(),
+            session_name,
+            format!(
+                "touch {}; exec cat >> {}",
+                ready_marker(capture),
+                quote_shell_arg(&capture.log.to_string_lossy())
+            ),
+        ]);
+    }
+
+    LaunchSpec {
+        args,
+        // An attached session needs the caller's terminal
+        stdin: if detached {
+            LaunchStdin::Null
+        } else {
+            LaunchStdin::Inherit
+        },
+        ..LaunchSpec::new("tmux")
+    }
+}
+
+/// Build stop command for tmux sessions
+///
+/// # Arguments
+/// * `session_name` - tmux session name
+///
+/// # Returns
+/// Stop command
+pub fn build_tmux_stop_command(session_name: &str) -> String {
+    build_kill_spec(session_name).to_shell_command()
+}
+
+fn build_kill_spec(session_name: &str) -> LaunchSpec {
+    LaunchSpec {
+        args: vec![
+            "kill-session".to_string(),
+            "-t".to_string(),
+            session_name.to_string(),
+        ],
+        ..LaunchSpec::new("tmux")
+    }
+}
+
+/// Kill a tmux session and collect the agent's output from its
+/// `pipe-pane` transcript
+///
+/// Output is read as described for [`CaptureFiles`]; sessions started
+/// without capture files fall back to the output of `tmux kill-session`.
+///
+/// # Arguments
+/// * `session_name` - tmux session name
+/// * `capture` - Capture files the session was started with
+///
+/// # Returns
+/// The agent's output and exit code (-1 if the session was killed before
+/// the agent finished)
+pub async fn stop_tmux_session(
+    session_name: &str,
+    capture: &CaptureFiles,
+) -> Result<ExecutionResult, String> {
+    // A session whose agent already finished has closed by itself
+    let kill = execute_launch(&build_kill_spec(session_name))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(capture.collect(kill).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_tmux_stop_command() {
+        assert_eq!(
+            build_tmux_stop_command("my-session"),
+            "tmux kill-session -t my-session"
+        );
+        assert_eq!(
+            build_tmux_stop_command("a b;`reboot`"),
+            "tmux kill-session -t 'a b;`reboot`'"
+        );
+    }
+
+    #[test]
+    fn test_build_tmux_launch_spec_with_capture() {
+        let capture = CaptureFiles::in_dir("/state/tmux/my-session");
+        let spec = build_tmux_launch_spec("claude -p hi", Some("my-session"), true, Some(&capture));
+
+        assert_eq!(spec.program, "tmux");
+        assert_eq!(&spec.args[..3], &["new-session", "-d", "-s"]);
+        assert!(
+            spec.args[4].starts_with("bash -c 'i=0; while [ ! -e /state/tmux/my-session/ready ]")
+        );
+        assert!(spec.args[4].ends_with("claude -p hi; echo $? > /state/tmux/my-session/exit_code'"));
+        assert_eq!(
+            &spec.args[5..],
+            &[
+                ";",
+                "pipe-pane",
+                "-o",
+                "-t",
+                "my-session",
+                "touch /state/tmux/my-session/ready; exec cat >> /state/tmux/my-session/output.log",
+            ]
+        );
+        assert_eq!(spec.stdin, LaunchStdin::Null);
+    }
+
+    #[test]
+    fn test_build_tmux_launch_spec_attached_without_capture() {
+        let spec = build_tmux_launch_spec("claude -p hi", Some("my-session"), false, None);
+
+        assert_eq!(
+            spec.args,
+            vec!["new-session", "-s", "my-session", "bash -c 'claude -p hi'"]
+        );
+        assert_eq!(spec.stdin, LaunchStdin::Inherit);
+    }
+}
@@ -0,0 +1,494 @@
+//! Git worktree isolation
+//!
+//! A run gets its own `git worktree` of the repository in
+//! `working_directory`, checked out on a new branch, so several agents can
+//! work on one repository without touching each other's files. The commit
+//! the branch started from is recorded in a file outside the repository
+//! (see [`crate::RunRegistry::worktree_base_file`]), where a sandboxed agent
+//! cannot rewrite it, so whoever stops the run can report the commits it
+//! made from the worktree path alone.
+
+use super::{BwrapOptions, DockerOptions, DockerVolume};
+use crate::executor::execute_launch;
+use crate::launch::LaunchSpec;
+use std::path::{Path, PathBuf};
+
+/// Directories of the shared git directory a commit writes to
+const COMMIT_GIT_DIRS: &[&str] = &["objects", "refs", "logs"];
+
+/// Settings for running an agent in a fresh git worktree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorktreeOptions {
+    /// Branch created for the run (default: a generated `agent-<millis>`)
+    pub branch: Option<String>,
+    /// Commit, branch or tag the new branch starts from (default: `HEAD`)
+    pub base: Option<String>,
+    /// Absolute worktree directory (default: `worktrees/<branch>` under the
+    /// run registry's state directory)
+    pub path: Option<String>,
+    /// Remove the worktree once the run is stopped, unless it has
+    /// uncommitted changes; the branch and its commits are kept
+    pub cleanup: bool,
+}
+
+impl WorktreeOptions {
+    /// Validate every setting
+    ///
+    /// # Returns
+    /// Ok, or the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref branch) = self.branch {
+            if !is_valid_branch_name(branch) {
+                return Err(format!("Invalid worktree branch \"{}\"", branch));
+            }
+        }
+        if let Some(ref base) = self.base {
+            if base.is_empty() || base.starts_with('-') {
+                return Err(format!("Invalid worktree base \"{}\"", base));
+            }
+        }
+        if let Some(ref path) = self.path {
+            if !path.starts_with('/') {
+                return Err(format!(
+                    "Invalid worktree path \"{}\". Use an absolute path.",
+                    path
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where a worktree run happened and what it committed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorktreeInfo {
+    /// Branch the run worked on
+    pub branch: String,
+    /// Worktree directory
+    pub path: PathBuf,
+    /// Commit the branch started from
+    pub base_commit: String,
+    /// Commit the branch pointed to when the run was stopped
+    pub head_commit: String,
+    /// The worktree directory was removed after the run
+    pub removed: bool,
+}
+
+impl WorktreeInfo {
+    /// Commits made by the run, as a git revision range
+    ///
+    /// # Returns
+    /// Range such as `1a2b3c..4d5e6f` (empty when nothing was committed)
+    pub fn commit_range(&self) -> String {
+        format!("{}..{}", self.base_commit, self.head_commit)
+    }
+}
+
+/// Check a branch name against the rules of `git check-ref-format --branch`
+fn is_valid_branch_name(branch: &str) -> bool {
+    !branch.is_empty()
+        && !branch.starts_with(['-', '/', '.'])
+        && !branch.ends_with(['/', '.'])
+        && !Path::new(branch)
+            .extension()
+            .is_some_and(|extension| extension == "lock")
+        && !branch.contains("..")
+        && !branch.contains("//")
+        && !branch.contains("/.")
+        && !branch.contains("@{")
+        && !branch
+            .chars()
+            .any(|c| c.is_ascii_control() || c.is_whitespace() || "~^:?*[\\".contains(c))
+}
+
+/// Build the `git worktree add` command creating a run's worktree
+///
+/// # Arguments
+/// * `repository` - Directory inside the repository to branch from
+/// * `branch` - New branch name
+/// * `base` - Commit-ish the branch starts from
+/// * `path` - Worktree directory to create
+///
+/// # Returns
+/// Launch spec running git in `repository`
+pub fn build_worktree_add_command(
+    repository: &str,
+    branch: &str,
+    base: &str,
+    path: &Path,
+) -> LaunchSpec {
+    git(
+        Path::new(repository),
+        &[
+            "worktree",
+            "add",
+            "-b",
+            branch,
+            &path.to_string_lossy(),
+            base,
+        ],
+    )
+}
+
+/// Create a worktree on a new branch for a run
+///
+/// # Arguments
+/// * `repository` - Directory inside the repository to branch from
+/// * `branch` - New branch name
+/// * `base` - Commit-ish the branch starts from
+/// * `path` - Worktree directory to create
+/// * `base_file` - File the base commit is recorded in
+///
+/// # Returns
+/// The new worktree, with no commits yet
+pub async fn create_worktree(
+    repository: &str,
+    branch: &str,
+    base: &str,
+    path: &Path,
+    base_file: &Path,
+) -> Result<WorktreeInfo, String> {
+    let base_commit = run_git(
+        Path::new(repository),
+        &["rev-parse", "--verify", &format!("{}^{{commit}}", base)],
+    )
+    .await?;
+    run_git_spec(&build_worktree_add_command(
+        repository,
+        branch,
+        &base_commit,
+        path,
+    ))
+    .await?;
+
+    if let Some(parent) = base_file.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to record worktree base commit: {}", e))?;
+    }
+    tokio::fs::write(base_file, &base_commit)
+        .await
+        .map_err(|e| format!("Failed to record worktree base commit: {}", e))?;
+
+    Ok(WorktreeInfo {
+        branch: branch.to_string(),
+        path: path.to_path_buf(),
+        head_commit: base_commit.clone(),
+        base_commit,
+        removed: false,
+    })
+}
+
+/// Report a worktree run and optionally remove its worktree
+///
+/// A worktree with uncommitted changes is never removed, so nothing the
+/// agent did is lost; `removed` tells whether the directory is gone.
+///
+/// # Arguments
+/// * `path` - Worktree directory created by [`create_worktree`]
+/// * `cleanup` - Remove the worktree if it is clean
+/// * `base_file` - File [`create_worktree`] recorded the base commit in
+///
+/// # Returns
+/// Branch, path and commit range of the run
+pub async fn finish_worktree(
+    path: &Path,
+    cleanup: bool,
+    base_file: &Path,
+) -> Result<WorktreeInfo, String> {
+    let base_commit = tokio::fs::read_to_string(base_file)
+        .await
+        .map_err(|_| {
+            format!(
+                "{} is not a worktree created by agent-commander",
+                path.display()
+            )
+        })?
+        .trim()
+        .to_string();
+    let branch = run_git(path, &["symbolic-ref", "--short", "HEAD"]).await?;
+    let head_commit = run_git(path, &["rev-parse", "HEAD"]).await?;
+
+    let removed = if cleanup && run_git(path, &["status", "--porcelain"]).await?.is_empty() {
+        // Removing a worktree from inside itself is refused, so run from the
+        // repository's shared git directory
+        let common_dir = git_common_dir(path).await?;
+        run_git(
+            &common_dir,
+            &["worktree", "remove", &path.to_string_lossy()],
+        )
+        .await?;
+        true
+    } else {
+        false
+    };
+    if removed {
+        let _ = tokio::fs::remove_file(base_file).await;
+    }
+
+    Ok(WorktreeInfo {
+        branch,
+        path: path.to_path_buf(),
+        base_commit,
+        head_commit,
+        removed,
+    })
+}
+
+/// Remove a worktree and its branch after a run failed to start
+pub async fn discard_worktree(worktree: &WorktreeInfo) {
+    let Ok(common_dir) = git_common_dir(&worktree.path).await else {
+        return;
+    };
+    let path = worktree.path.to_string_lossy();
+    let _ = run_git(&common_dir, &["worktree", "remove", "--force", &path]).await;
+    let _ = run_git(&common_dir, &["branch", "-D", &worktree.branch]).await;
+}
+
+/// Parts of the git directories a commit in a worktree writes to
+///
+/// Objects, refs and reflogs of the shared git directory, and the
+/// worktree's own git directory (its `HEAD` and index). Hooks and config
+/// are left out, so a sandboxed agent cannot plant code that git later runs
+/// outside the sandbox; neither is `packed-refs`, which git rewrites
+/// through a lock file in the shared directory itself.
+///
+/// # Arguments
+/// * `path` - Worktree directory
+///
+/// # Returns
+/// Absolute directories, created if missing
+async fn worktree_commit_dirs(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let common_dir = git_common_dir(path).await?;
+    let mut dirs: Vec<PathBuf> = COMMIT_GIT_DIRS
+        .iter()
+        .map(|dir| common_dir.join(dir))
+        .collect();
+    for dir in &dirs {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    dirs.push(PathBuf::from(
+        run_git(path, &["rev-parse", "--absolute-git-dir"]).await?,
+    ));
+    Ok(dirs)
+}
+
+/// Let a sandboxed run in a worktree commit
+///
+/// bwrap binds the whole filesystem read-only, so the directories a commit
+/// writes to (see `worktree_commit_dirs`) are bound writable on top. A
+/// docker container sees nothing of the host, so the shared git directory
+/// is also mounted, read-only, under them.
+///
+/// # Arguments
+/// * `path` - Worktree directory
+/// * `read_only` - Mount nothing writable
+/// * `bwrap` - Sandbox settings extended with the writable directories
+/// * `docker` - Container settings extended with the git volumes
+pub async fn mount_worktree_git(
+    path: &Path,
+    read_only: bool,
+    bwrap: &mut BwrapOptions,
+    docker: &mut DockerOptions,
+) -> Result<(), String> {
+    let common_dir = git_common_dir(path).await?;
+    let commit_dirs = worktree_commit_dirs(path).await?;
+    let volume = |dir: &Path, read_only: bool| {
+        let dir = dir.to_string_lossy().into_owned();
+        DockerVolume {
+            source: dir.clone(),
+            target: dir,
+            read_only,
+        }
+    };
+    docker.volumes.push(volume(&common_dir, true));
+    if !read_only {
+        for dir in &commit_dirs {
+            bwrap
+                .writable_paths
+                .push(dir.to_string_lossy().into_owned());
+            docker.volumes.push(volume(dir, false));
+        }
+    }
+    Ok(())
+}
+
+/// Git directory shared by a worktree and its main repository
+///
+/// # Arguments
+/// * `path` - Worktree directory
+///
+/// # Returns
+/// Absolute path such as `/repo/.git`
+pub async fn git_common_dir(path: &Path) -> Result<PathBuf, String> {
+    run_git(
+        path,
+        &["rev-parse", "--path-format=absolute", "--git-common-dir"],
+    )
+    .await
+    .map(PathBuf::from)
+}
+
+fn git(dir: &Path, args: &[&str]) -> LaunchSpec {
+    let mut spec = LaunchSpec::new("git");
+    spec.args = ["-C", &dir.to_string_lossy()]
+        .into_iter()
+        .chain(args.iter().copied())
+        .map(str::to_string)
+        .collect();
+    spec
+}
+
+async fn run_git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    run_git_spec(&git(dir, args)).await
+}
+
+async fn run_git_spec(spec: &LaunchSpec) -> Result<String, String> {
+    let result = execute_launch(spec)
+        .await
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if result.exit_code != 0 {
+        return Err(format!(
+            "{} failed: {}",
+            spec.to_shell_command(),
+            result.stderr.trim()
+        ));
+    }
+    Ok(result.stdout.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn init_repository(dir: &Path) {
+        for args in [
+            vec!["init", "-q"],
+            vec![
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                "init",
+            ],
+        ] {
+            run_git(dir, &args).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_worktree_options_validate() {
+        assert!(WorktreeOptions::default().validate().is_ok());
+        assert!(WorktreeOptions {
+            branch: Some("agent/fix-42".to_string()),
+            base: Some("origin/main".to_string()),
+            path: Some("/tmp/wt".to_string()),
+            cleanup: true,
+        }
+        .validate()
+        .is_ok());
+        for branch in ["", "-x", "a..b", "a b", "a/", "x.lock", "a:b"] {
+            assert!(
+                WorktreeOptions {
+                    branch: Some(branch.to_string()),
+                    ..Default::default()
+                }
+                .validate()
+                .is_err(),
+                "{}",
+                branch
+            );
+        }
+        assert!(WorktreeOptions {
+            path: Some("relative".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_build_worktree_add_command() {
+        let spec = build_worktree_add_command("/repo", "agent/x", "HEAD", Path::new("/wt/x"));
+        assert_eq!(
+            spec.to_shell_command(),
+            "git -C /repo worktree add -b agent/x /wt/x HEAD"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_worktree_reports_commits_and_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = dir.path().join("repo");
+        std::fs::create_dir(&repository).unwrap();
+        init_repository(&repository).await;
+        let repository = repository.to_string_lossy().into_owned();
+
+        let path = dir.path().join("wt");
+        let base_file = dir.path().join("state").join("base");
+        let created = create_worktree(&repository, "agent/test", "HEAD", &path, &base_file)
+            .await
+            .unwrap();
+        let commit_dirs = worktree_commit_dirs(&path).await.unwrap();
+        let common_dir = git_common_dir(&path).await.unwrap();
+        let shared: Vec<_> = COMMIT_GIT_DIRS
+            .iter()
+            .map(|dir| common_dir.join(dir))
+            .collect();
+        assert_eq!(commit_dirs[..3], shared[..]);
+        assert!(commit_dirs[3].starts_with(common_dir.join("worktrees")));
+        assert_eq!(created.base_commit, created.head_commit);
+        run_git(
+            &path,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                "work",
+            ],
+        )
+        .await
+        .unwrap();
+
+        // Uncommitted changes keep the worktree in place
+        std::fs::write(path.join("notes.txt"), "draft").unwrap();
+        let kept = finish_worktree(&path, true, &base_file).await.unwrap();
+        assert!(!kept.removed);
+        assert!(path.exists());
+        assert_eq!(kept.branch, "agent/test");
+        assert_eq!(kept.base_commit, created.base_commit);
+        assert_ne!(kept.head_commit, created.base_commit);
+        let commits = run_git(
+            Path::new(&repository),
+            &["rev-list", "--count", &kept.commit_range()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(commits, "1");
+
+        std::fs::remove_file(path.join("notes.txt")).unwrap();
+        let removed = finish_worktree(&path, true, &base_file).await.unwrap();
+        assert!(removed.removed);
+        assert!(!path.exists());
+        assert!(!base_file.exists());
+        // The branch outlives the worktree
+        run_git(
+            Path::new(&repository),
+            &["rev-parse", "--verify", "agent/test"],
+        )
+        .await
+        .unwrap();
+    }
+}
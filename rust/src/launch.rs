@@ -0,0 +1,202 @@
+//! Shell-free launch description for headless agent runs
+//!
+//! A [`LaunchSpec`] is spawned directly by the executor (no `bash -c`), so
+//! prompts, paths and arguments never pass through shell parsing. The shell
+//! form from [`LaunchSpec::to_shell_command`] is only used for display
+//! (dry runs) and as the inner command of isolation wrappers.
+
+use std::path::PathBuf;
+
+/// Source of the agent's standard input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LaunchStdin {
+    /// No input (`/dev/null`)
+    #[default]
+    Null,
+    /// Bytes written to stdin, which is then closed
+    Inline(Vec<u8>),
+    /// File streamed to stdin
+    File(PathBuf),
+    /// The caller's stdin (needed by wrappers that attach to the terminal)
+    Inherit,
+    /// A pipe the caller keeps writing to (see
+    /// [`crate::ProcessHandle::take_stdin`])
+    Piped,
+}
+
+/// Program, argv, cwd, environment and stdin for a headless agent launch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LaunchSpec {
+    /// Executable to launch (looked up on PATH)
+    pub program: String,
+    /// Shell-free argument vector
+    pub args: Vec<String>,
+    /// Working directory
+    pub cwd: Option<PathBuf>,
+    /// Environment overrides, applied in order
+    pub env: Vec<(String, String)>,
+    /// Standard input source
+    pub stdin: LaunchStdin,
+}
+
+impl LaunchSpec {
+    /// Create a spec for `program` with no arguments
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Add environment overrides
+    ///
+    /// # Panics
+    /// If a name is not a valid environment variable name
+    #[must_use]
+    pub fn with_env(mut self, env: &[(String, String)]) -> Self {
+        for (key, value) in env {
+            assert!(
+                is_valid_env_name(key),
+                "invalid environment variable name: {}",
+                key
+            );
+            self.env.push((key.clone(), value.clone()));
+        }
+        self
+    }
+
+    /// Render the equivalent POSIX shell command
+    ///
+    /// Every word is quoted with [`quote_shell_arg`], so the result is safe
+    /// to paste into a shell or pass to `bash -c`.
+    ///
+    /// # Returns
+    /// Command such as `cd /repo && cat prompt.txt | env K=V tool --flag`
+    pub fn to_shell_command(&self) -> String {
+        let mut parts = Vec::new();
+
+        if let Some(ref cwd) = self.cwd {
+            parts.push(format!("cd {} &&", quote_shell_arg(&cwd.to_string_lossy())));
+        }
+
+        match self.stdin {
+            LaunchStdin::Inline(ref input) => parts.push(format!(
+                "printf '%s' {} |",
+                quote_shell_arg(&String::from_utf8_lossy(input))
+            )),
+            LaunchStdin::File(ref path) => parts.push(format!(
+                "cat {} |",
+                quote_shell_arg(&path.to_string_lossy())
+            )),
+            LaunchStdin::Null | LaunchStdin::Inherit | LaunchStdin::Piped => {}
+        }
+
+        if !self.env.is_empty() {
+            parts.push("env".to_string());
+            for (key, value) in &self.env {
+                parts.push(format!("{}={}", key, quote_shell_arg(value)));
+            }
+        }
+
+        parts.push(quote_shell_arg(&self.program));
+        parts.extend(self.args.iter().map(|arg| quote_shell_arg(arg)));
+        parts.join(" ")
+    }
+}
+
+pub(crate) fn is_valid_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    if first != '_' && !first.is_ascii_alphabetic() {
+        return false;
+    }
+    chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// Quote a word for a POSIX shell
+///
+/// Words made only of characters with no special meaning are returned as is;
+/// everything else is wrapped in single quotes, with embedded single quotes
+/// written as `'\''`.
+pub fn quote_shell_arg(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:,+@%".contains(c));
+    if is_plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_shell_arg_leaves_plain_words() {
+        assert_eq!(quote_shell_arg("--model"), "--model");
+        assert_eq!(quote_shell_arg("opencode/grok-code"), "opencode/grok-code");
+    }
+
+    #[test]
+    fn test_quote_shell_arg_quotes_shell_syntax() {
+        assert_eq!(quote_shell_arg(""), "''");
+        assert_eq!(quote_shell_arg("a;b"), "'a;b'");
+        assert_eq!(quote_shell_arg("$HOME `pwd`"), "'$HOME `pwd`'");
+        assert_eq!(quote_shell_arg("it's"), "'it'\\''s'");
+        assert_eq!(quote_shell_arg("KEY=value"), "'KEY=value'");
+    }
+
+    #[test]
+    fn test_to_shell_command_renders_cwd_stdin_and_env() {
+        let spec = LaunchSpec {
+            args: vec!["exec".to_string(), "--json".to_string()],
+            cwd: Some(PathBuf::from("/tmp/my repo")),
+            stdin: LaunchStdin::Inline(b"Don't run $(rm -rf /)".to_vec()),
+            ..LaunchSpec::new("codex")
+        }
+        .with_env(&[("CODEX_HOME".to_string(), "/tmp/codex home".to_string())]);
+
+        assert_eq!(
+            spec.to_shell_command(),
+            "cd '/tmp/my repo' && printf '%s' 'Don'\\''t run $(rm -rf /)' | env CODEX_HOME='/tmp/codex home' codex exec --json"
+        );
+    }
+
+    #[test]
+    fn test_to_shell_command_streams_prompt_file() {
+        let spec = LaunchSpec {
+            stdin: LaunchStdin::File(PathBuf::from("/tmp/prompt.txt")),
+            ..LaunchSpec::new("qwen")
+        };
+
+        assert_eq!(spec.to_shell_command(), "cat /tmp/prompt.txt | qwen");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_to_shell_command_round_trips_through_bash() {
+        let tricky = "quote ' double \" dollar $HOME backtick `pwd` newline\nend";
+        let spec = LaunchSpec {
+            args: vec!["%s".to_string(), tricky.to_string()],
+            ..LaunchSpec::new("printf")
+        };
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(spec.to_shell_command())
+            .output()
+            .unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), tricky);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid environment variable name")]
+    fn test_with_env_rejects_invalid_names() {
+        let _spec = LaunchSpec::new("claude").with_env(&[("A B".to_string(), "1".to_string())]);
+    }
+}
@@ -0,0 +1,307 @@
+//! Codex approval relay over the app-server JSON-RPC protocol
+//!
+//! `codex exec` couples approvals with its sandbox and has nobody to ask,
+//! but `codex app-server` speaks JSON-RPC over stdio (one object per line,
+//! without the `jsonrpc` field) and asks its client before running a command
+//! or changing files. An `approve_each` codex run starts the server,
+//! initializes it, opens a thread in the working directory with approval
+//! policy `untrusted`, and starts one turn with the prompt. Each
+//! `item/commandExecution/requestApproval` and
+//! `item/fileChange/requestApproval` request is answered through the relay,
+//! and stdin is closed once `turn/completed` arrives, which stops the server.
+
+use super::async_relay::{relay_for_run, spawn_stdin_writer};
+use super::edit_diff::codex_changes_diff;
+use super::risk::is_shell_request;
+use super::{
+    ask_scope, classify_command, AsyncPermissionHandler, HandledPermission,
+    NormalizedPermissionRequest, PermissionDecision,
+};
+use crate::launch::quote_shell_arg;
+use crate::streaming::{parse_ndjson_line, stringify_ndjson_line};
+use crate::tools::codex::map_model_to_id;
+use crate::AgentOptions;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::process::ChildStdin;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+/// Server request asking to run a command
+const COMMAND_APPROVAL_METHOD: &str = "item/commandExecution/requestApproval";
+
+/// Server request asking to apply file changes
+const FILE_CHANGE_APPROVAL_METHOD: &str = "item/fileChange/requestApproval";
+
+/// Ids of the client's own requests
+const INITIALIZE_ID: u64 = 0;
+const THREAD_ID: u64 = 1;
+const TURN_ID: u64 = 2;
+
+/// JSON-RPC error code for server requests the client does not handle
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Normalize an approval request of the codex app-server
+///
+/// Commands sent as an argv are joined into a shell line, with a
+/// `bash -lc <script>` wrapper reduced to the script; file changes name
+/// their paths as the command and carry their diffs.
+pub(super) fn normalize_codex(message: &Value) -> Option<NormalizedPermissionRequest> {
+    let tool_name = match message.get("method").and_then(Value::as_str)? {
+        COMMAND_APPROVAL_METHOD => "commandExecution",
+        FILE_CHANGE_APPROVAL_METHOD => "fileChange",
+        _ => return None,
+    };
+    let id = match message.get("id")? {
+        Value::String(id) => id.clone(),
+        Value::Number(id) => id.to_string(),
+        _ => return None,
+    };
+    let params = message.get("params");
+    let text = |key: &str| {
+        params
+            .and_then(|params| params.get(key))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let changes = params.and_then(|params| params.get("changes"));
+    let command = if is_shell_request(Some(tool_name)) {
+        params.and_then(|params| shell_command(params.get("command")?))
+    } else {
+        let paths: Vec<&str> = changes
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|change| change.get("path").and_then(Value::as_str))
+            .collect();
+        Some(paths.join(" ")).filter(|paths| !paths.is_empty())
+    };
+    let risk = is_shell_request(Some(tool_name))
+        .then(|| classify_command(command.as_deref().unwrap_or_default(), None));
+    Some(NormalizedPermissionRequest {
+        r#type: "permission_request".to_string(),
+        tool: "codex".to_string(),
+        id: Some(id),
+        session_id: text("threadId"),
+        call_id: text("itemId"),
+        tool_name: Some(tool_name.to_string()),
+        title: text("reason").or_else(|| Some(tool_name.to_string())),
+        command,
+        pattern: None,
+        scope: ask_scope("codex").unwrap().to_string(),
+        input: None,
+        diff: changes.and_then(codex_changes_diff),
+        risk,
+        raw: message.clone(),
+    })
+}
+
+/// A command as a shell line: a string as it is, an argv quoted and joined
+fn shell_command(command: &Value) -> Option<String> {
+    if let Some(line) = command.as_str() {
+        return Some(line.to_string());
+    }
+    let argv: Vec<&str> = command
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+    match argv.as_slice() {
+        [shell, "-c" | "-lc", script]
+            if matches!(shell.rsplit('/').next(), Some("bash" | "sh" | "zsh")) =>
+        {
+            Some((*script).to_string())
+        }
+        _ => Some(
+            argv.iter()
+                .map(|arg| quote_shell_arg(arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+    }
+}
+
+/// The JSON-RPC response answering an approval request
+///
+/// `once` is `accept`, `always` is `acceptForSession` and `reject` is
+/// `decline`; the request's id is echoed with its original type.
+pub(super) fn codex_response(
+    request: &NormalizedPermissionRequest,
+    decision: &PermissionDecision,
+) -> Value {
+    let decision = match decision.decision.as_str() {
+        "once" => "accept",
+        "always" => "acceptForSession",
+        _ => "decline",
+    };
+    json!({
+        "id": request.raw.get("id").cloned().unwrap_or(Value::Null),
+        "result": { "decision": decision },
+    })
+}
+
+/// Client frames opening a session: the `initialize` handshake and a new
+/// thread, or the resumed one, in the working directory
+///
+/// # Arguments
+/// * `options` - Options of the run: its `model`, `resume` and `read_only`
+/// * `working_directory` - Directory the thread runs in
+///
+/// # Returns
+/// Frames to write to the server's stdin, in order
+pub fn build_app_server_opening_frames(
+    options: &AgentOptions,
+    working_directory: &str,
+) -> Vec<Value> {
+    let mut thread = json!({ "cwd": working_directory, "approvalPolicy": "untrusted" });
+    if let Some(ref model) = options.model {
+        thread["model"] = json!(map_model_to_id(model));
+    }
+    if options.read_only || options.plan_only {
+        thread["sandbox"] = json!("read-only");
+    }
+    let method = match options.resume {
+        Some(ref thread_id) => {
+            thread["threadId"] = json!(thread_id);
+            "thread/resume"
+        }
+        None => "thread/start",
+    };
+    vec![
+        json!({
+            "method": "initialize",
+            "id": INITIALIZE_ID,
+            "params": {
+                "clientInfo": {
+                    "name": "agent_commander",
+                    "title": "Agent Commander",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            },
+        }),
+        json!({ "method": "initialized" }),
+        json!({ "method": method, "id": THREAD_ID, "params": thread }),
+    ]
+}
+
+/// Drive an [`super::AsyncPermissionRelay`] against a running codex
+/// app-server
+///
+/// Once the thread is open, the prompt (after the system prompt, which
+/// codex has no separate channel for here) starts a turn. File change
+/// approvals only name their item, so the changes announced in its
+/// `item/started` notification are attached to the request. Other server
+/// requests are answered with a JSON-RPC error rather than left hanging.
+/// Stdin is closed after `turn/completed`, an error response, or once the
+/// server's stdout closes.
+///
+/// # Arguments
+/// * `options` - Options of the run
+/// * `working_directory` - Directory the thread runs in
+/// * `handler` - Decision callback
+/// * `prompt` - User prompt of the turn
+/// * `lines` - The server's stdout lines
+/// * `stdin` - The server's stdin pipe
+///
+/// # Returns
+/// Task resolving to every handled request, in arrival order
+pub(super) fn spawn_app_server_relay(
+    options: &AgentOptions,
+    working_directory: &str,
+    handler: AsyncPermissionHandler,
+    prompt: Option<&str>,
+    mut lines: UnboundedReceiver<String>,
+    stdin: ChildStdin,
+) -> JoinHandle<Vec<HandledPermission>> {
+    let (frames, close_stdin) = spawn_stdin_writer(stdin);
+    for frame in build_app_server_opening_frames(options, working_directory) {
+        let _ = frames.send(stringify_ndjson_line(&frame, true));
+    }
+    let prompt = match options.system_prompt {
+        Some(ref system_prompt) => format!("{}\n\n{}", system_prompt, prompt.unwrap_or_default()),
+        None => prompt.unwrap_or_default().to_string(),
+    };
+    let mut client = Some(frames.clone());
+    let mut relay = relay_for_run(options, working_directory, handler, frames);
+    tokio::spawn(async move {
+        let mut file_changes: HashMap<String, Value> = HashMap::new();
+        while let Some(line) = lines.recv().await {
+            let Some(mut message) = parse_ndjson_line(&line) else {
+                continue;
+            };
+            let method = message
+                .get("method")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            match (method.as_deref(), message.get("id").cloned()) {
+                // A response to one of our requests
+                (None, Some(id)) => {
+                    let thread_id = message.pointer("/result/thread/id").and_then(Value::as_str);
+                    if let (Some(THREAD_ID), Some(thread_id)) = (id.as_u64(), thread_id) {
+                        let turn = json!({
+                            "method": "turn/start",
+                            "id": TURN_ID,
+                            "params": {
+                                "threadId": thread_id,
+                                "input": [{ "type": "text", "text": prompt }],
+                            },
+                        });
+                        send(client.as_ref(), &turn);
+                    } else if message.get("error").is_some() {
+                        client = None;
+                        relay.close();
+                    }
+                }
+                (Some("item/started"), None) => {
+                    let item = message.pointer("/params/item");
+                    let id = item.and_then(|item| item.get("id")).and_then(Value::as_str);
+                    let changes = item
+                        .filter(|item| item["type"] == "fileChange")
+                        .and_then(|item| item.get("changes"));
+                    if let (Some(id), Some(changes)) = (id, changes) {
+                        file_changes.insert(id.to_string(), changes.clone());
+                    }
+                }
+                (Some("turn/completed"), None) => {
+                    relay.settle().await;
+                    relay.close();
+                    client = None;
+                }
+                (Some(COMMAND_APPROVAL_METHOD), Some(_)) => {
+                    relay.handle_message(&message);
+                }
+                (Some(FILE_CHANGE_APPROVAL_METHOD), Some(_)) => {
+                    let item_id = message.pointer("/params/itemId").and_then(Value::as_str);
+                    let changes = item_id.and_then(|id| file_changes.remove(id));
+                    if let (Some(params), Some(changes)) = (
+                        message.get_mut("params").and_then(Value::as_object_mut),
+                        changes,
+                    ) {
+                        params.entry("changes").or_insert(changes);
+                    }
+                    relay.handle_message(&message);
+                }
+                (Some(method), Some(id)) => {
+                    let error = json!({
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("agent-commander does not handle {}", method),
+                        },
+                    });
+                    send(client.as_ref(), &error);
+                }
+                _ => {}
+            }
+        }
+        relay.close();
+        drop(close_stdin);
+        relay.finish().await
+    })
+}
+
+fn send(client: Option<&UnboundedSender<String>>, frame: &Value) {
+    if let Some(client) = client {
+        let _ = client.send(stringify_ndjson_line(frame, true));
+    }
+}
@@ -0,0 +1,87 @@
+//! External approver commands
+//!
+//! An approver is any executable, in the spirit of a git hook: it receives
+//! one [`NormalizedPermissionRequest`] as a JSON line on stdin and prints
+//! `once`, `always` or `reject` on stdout. Anything else, including a
+//! failed start or a non-zero exit, rejects the request.
+
+use super::{
+    AsyncPermissionHandler, NormalizedPermissionRequest, PermissionDecision, ASK_DECISIONS,
+};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Source of decisions made by an approver command
+const APPROVER_SOURCE: &str = "approve-command";
+
+impl AsyncPermissionHandler {
+    /// Ask an external command for each decision
+    ///
+    /// The command is started without a shell, once per request, in the
+    /// current directory. It is killed when the decision is abandoned, so
+    /// pair it with [`crate::AgentOptions::permission_timeout`] to bound how
+    /// long it may take.
+    ///
+    /// # Arguments
+    /// * `program` - Path or name of the approver executable
+    ///
+    /// # Returns
+    /// Handler resolving to the approver's decision
+    pub fn command(program: &str) -> Self {
+        let program = program.to_string();
+        Self::new(move |request| {
+            let program = program.clone();
+            async move {
+                run_approver(&program, &request)
+                    .await
+                    .unwrap_or_else(|error| {
+                        PermissionDecision::reject_with_message(&error).with_source(APPROVER_SOURCE)
+                    })
+            }
+        })
+    }
+}
+
+async fn run_approver(
+    program: &str,
+    request: &NormalizedPermissionRequest,
+) -> Result<PermissionDecision, String> {
+    let mut input = serde_json::to_string(request)
+        .map_err(|e| format!("Failed to serialize permission request: {}", e))?;
+    input.push('\n');
+
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start approver {}: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // An approver that decides without reading its input is fine
+        let _ = stdin.write_all(input.as_bytes()).await;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("Approver {} failed: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Approver {} exited with {}",
+            program, output.status
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let decision = stdout.lines().next().unwrap_or_default().trim();
+    if !ASK_DECISIONS.contains(&decision) {
+        return Err(format!(
+            "Approver {} answered \"{}\" instead of one of: {}",
+            program,
+            decision,
+            ASK_DECISIONS.join(", ")
+        ));
+    }
+    Ok(PermissionDecision::new(decision).with_source(APPROVER_SOURCE))
+}
@@ -0,0 +1,491 @@
+//! Asynchronous relay for permission decisions that take a while
+//!
+//! [`AsyncPermissionRelay`] starts deciding each request as soon as it
+//! arrives and keeps reading: decisions are futures, several requests can be
+//! in flight at once, and each response carries its request's id, so they
+//! may be written back in any order. A request not decided within the
+//! relay's timeout is rejected.
+//!
+//! [`spawn_live_relay`] drives the relay against a running tool: it writes
+//! the prompt as the first stdin frame, feeds every stdout line to the
+//! relay, writes each decision back, and closes stdin once the turn's
+//! `result` message arrives. Codex runs are driven over its app-server
+//! protocol instead (see [`super::app_server`]).
+
+use super::app_server::spawn_app_server_relay;
+use super::edit_diff::attach_edit_diff;
+use super::relay::{normalize_in, respond, REMEMBERED_SOURCE};
+use super::session::SessionApprovals;
+use super::{
+    build_initial_input_frame, HandledPermission, NormalizedPermissionRequest, PermissionAudit,
+    PermissionCancellation, PermissionDecision, PermissionHandler, WorkspaceGuard,
+};
+use crate::executor::ProcessHandle;
+use crate::streaming::{parse_ndjson_line, stringify_ndjson_line};
+use crate::AgentOptions;
+use serde_json::Value;
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::ChildStdin;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Future resolving to a consumer's decision
+pub type PermissionDecisionFuture = Pin<Box<dyn Future<Output = PermissionDecision> + Send>>;
+
+/// Message Claude sees when no decision arrived in time
+const TIMEOUT_MESSAGE: &str = "Permission request timed out (ask mode).";
+
+/// Source of rejections made because the handler panicked
+const FAILED_SOURCE: &str = "handler failed";
+
+/// Source of decisions made for the consumer when it took too long
+const TIMEOUT_SOURCE: &str = "timeout";
+
+/// Asynchronous decision callback, e.g. one waiting for a chat reply
+#[derive(Clone)]
+pub struct AsyncPermissionHandler(
+    Arc<dyn Fn(NormalizedPermissionRequest) -> PermissionDecisionFuture + Send + Sync>,
+);
+
+impl AsyncPermissionHandler {
+    /// Wrap an async decision closure resolving to `once`, `always` or
+    /// `reject`, or to a full [`PermissionDecision`]
+    pub fn new<F, Fut, D>(decide: F) -> Self
+    where
+        F: Fn(NormalizedPermissionRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = D> + Send + 'static,
+        D: Into<PermissionDecision>,
+    {
+        Self(Arc::new(move |request| {
+            let decision = decide(request);
+            Box::pin(async move { decision.await.into() })
+        }))
+    }
+
+    /// Start deciding a request
+    pub fn decide(&self, request: NormalizedPermissionRequest) -> PermissionDecisionFuture {
+        (self.0)(request)
+    }
+}
+
+impl fmt::Debug for AsyncPermissionHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AsyncPermissionHandler(..)")
+    }
+}
+
+impl From<PermissionHandler> for AsyncPermissionHandler {
+    /// Run a blocking handler on the blocking pool, one request at a time,
+    /// so a handler prompting a person never sees two requests at once
+    ///
+    /// When the relay gives up on a request (its timeout), the handler is
+    /// cancelled and the next request no longer waits for it.
+    fn from(handler: PermissionHandler) -> Self {
+        let turn = Arc::new(tokio::sync::Mutex::new(()));
+        Self::new(move |request| {
+            let handler = handler.clone();
+            let turn = Arc::clone(&turn);
+            let cancellation = PermissionCancellation::default();
+            let cancel_on_drop = CancelOnDrop(cancellation.clone());
+            async move {
+                let _turn = turn.lock().await;
+                let decided = tokio::task::spawn_blocking(move || {
+                    handler.decide_cancellable(&request, &cancellation)
+                })
+                .await;
+                drop(cancel_on_drop);
+                decided.unwrap_or_else(|_| {
+                    PermissionDecision::new("reject").with_source(FAILED_SOURCE)
+                })
+            }
+        })
+    }
+}
+
+/// Cancels a blocking handler's request when its decision future is
+/// dropped, e.g. aborted after the relay's timeout
+struct CancelOnDrop(PermissionCancellation);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Relay permission requests to an async consumer and forward decisions back
+///
+/// Like [`super::PermissionRelay`] it does not own the child process: each
+/// response is sent as a serialized NDJSON frame on `frames`, for the caller
+/// to write to the tool's stdin.
+pub struct AsyncPermissionRelay {
+    tool: String,
+    handler: AsyncPermissionHandler,
+    timeout: Option<Duration>,
+    frames: Option<UnboundedSender<String>>,
+    approvals: Arc<Mutex<SessionApprovals>>,
+    audit: Option<PermissionAudit>,
+    working_directory: Option<PathBuf>,
+    guard: Option<WorkspaceGuard>,
+    in_flight: Vec<(Option<String>, JoinHandle<HandledPermission>)>,
+    handled: Vec<HandledPermission>,
+}
+
+impl AsyncPermissionRelay {
+    /// Create a new relay
+    ///
+    /// # Arguments
+    /// * `tool` - Relayable tool name (`agent` | `claude` | `codex`)
+    /// * `handler` - Decision callback
+    /// * `timeout` - How long each decision may take before the request is
+    ///   rejected (`None` waits indefinitely)
+    /// * `frames` - Receives each response frame for the tool's stdin
+    pub fn new(
+        tool: &str,
+        handler: AsyncPermissionHandler,
+        timeout: Option<Duration>,
+        frames: UnboundedSender<String>,
+    ) -> Self {
+        Self {
+            tool: tool.to_string(),
+            handler,
+            timeout,
+            frames: Some(frames),
+            approvals: Arc::default(),
+            audit: None,
+            working_directory: None,
+            guard: None,
+            in_flight: Vec::new(),
+            handled: Vec::new(),
+        }
+    }
+
+    /// Record every answered request in an audit trail
+    ///
+    /// A failed write does not hold up the relay.
+    #[must_use]
+    pub fn with_audit(mut self, audit: PermissionAudit) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Attach a diff of each file edit against the files in a directory
+    /// (see [`NormalizedPermissionRequest::with_edit_diff`])
+    #[must_use]
+    pub fn with_working_directory(mut self, working_directory: impl Into<PathBuf>) -> Self {
+        self.working_directory = Some(working_directory.into());
+        self
+    }
+
+    /// Reject requests that leave the workspace before the handler sees
+    /// them; the reason is the decision's source
+    #[must_use]
+    pub fn with_workspace_guard(mut self, guard: WorkspaceGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Process a single parsed output message without waiting for a decision
+    ///
+    /// Must be called within a tokio runtime. A request whose id is already
+    /// in flight (a resent frame) is not decided twice, one leaving the
+    /// workspace of a [`WorkspaceGuard`] is rejected without asking, and one
+    /// covered by an earlier `always` of the session is allowed without
+    /// asking (claude). The diff of a file edit is read on the blocking pool
+    /// before the handler is asked.
+    ///
+    /// # Returns
+    /// The normalized request now being decided, without its diff, or `None`
+    /// when the message is not a new permission request or the relay was
+    /// closed
+    pub fn handle_message(&mut self, message: &Value) -> Option<NormalizedPermissionRequest> {
+        let request = normalize_in(&self.tool, message, self.working_directory.as_deref())?;
+        let frames = self.frames.clone()?;
+        if let Some(ref id) = request.id {
+            if self.in_flight_ids().contains(&id.as_str()) {
+                return None;
+            }
+        }
+
+        let tool = self.tool.clone();
+        let rejected = self.guard.as_ref().and_then(|guard| guard.review(&request));
+        let remembered = rejected.is_none() && lock(&self.approvals).covers(&request);
+        let diff_directory = rejected.is_none().then(|| self.working_directory.clone());
+        let consumer = self.handler.clone();
+        let timeout = self.timeout;
+        let pending = request.clone();
+        let approvals = Arc::clone(&self.approvals);
+        let audit = self.audit.clone();
+        let task = tokio::spawn(async move {
+            let pending = match diff_directory {
+                Some(directory) => attach_edit_diff(pending, directory).await,
+                None => pending,
+            };
+            let decided = if let Some(rejection) = rejected {
+                tokio::spawn(async { rejection })
+            } else if remembered {
+                tokio::spawn(async {
+                    PermissionDecision::new("always").with_source(REMEMBERED_SOURCE)
+                })
+            } else {
+                tokio::spawn(consumer.decide(pending.clone()))
+            };
+            let cancel = decided.abort_handle();
+            let (decision, timed_out) = match timeout {
+                Some(limit) => {
+                    if let Ok(decision) = tokio::time::timeout(limit, decided).await {
+                        (decision.ok(), false)
+                    } else {
+                        cancel.abort();
+                        let timeout = PermissionDecision::reject_with_message(TIMEOUT_MESSAGE)
+                            .with_source(TIMEOUT_SOURCE);
+                        (Some(timeout), true)
+                    }
+                }
+                None => (decided.await.ok(), false),
+            };
+            // A handler that panicked rejects the request
+            let decision = decision
+                .unwrap_or_else(|| PermissionDecision::new("reject").with_source(FAILED_SOURCE));
+            let (decision, response) = respond(&tool, &pending, &decision);
+            lock(&approvals).remember(&pending, &decision);
+            let _ = frames.send(stringify_ndjson_line(&response, true));
+            let handled = HandledPermission {
+                timed_out,
+                remembered,
+                ..HandledPermission::new(pending, decision, response)
+            };
+            if let Some(audit) = audit {
+                let _ = audit.record(&handled);
+            }
+            handled
+        });
+        self.in_flight.push((request.id.clone(), task));
+        Some(request)
+    }
+
+    /// Ids of the requests still waiting for a decision
+    pub fn in_flight_ids(&self) -> Vec<&str> {
+        self.in_flight
+            .iter()
+            .filter(|(_, task)| !task.is_finished())
+            .filter_map(|(id, _)| id.as_deref())
+            .collect()
+    }
+
+    /// Wait until every request received so far has been answered
+    pub async fn settle(&mut self) {
+        for (_, task) in self.in_flight.drain(..) {
+            if let Ok(handled) = task.await {
+                self.handled.push(handled);
+            }
+        }
+    }
+
+    /// Stop taking requests; `frames` closes once in-flight decisions are sent
+    pub fn close(&mut self) {
+        self.frames = None;
+    }
+
+    /// Wait for the requests in flight and return every handled request
+    ///
+    /// # Returns
+    /// Handled requests in the order they arrived
+    pub async fn finish(mut self) -> Vec<HandledPermission> {
+        self.settle().await;
+        self.handled
+    }
+}
+
+fn lock(approvals: &Mutex<SessionApprovals>) -> MutexGuard<'_, SessionApprovals> {
+    approvals.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Drive an [`AsyncPermissionRelay`] against a running tool
+///
+/// Stream-json input keeps the session open for another user message, so
+/// stdin is closed after the turn's `result` message, or as soon as the
+/// tool's stdout closes (it exited without one); the relay ends then too.
+///
+/// # Arguments
+/// * `options` - Options of the run: its tool, `permission_timeout`,
+///   `permission_audit` and `workspace_boundary`
+/// * `working_directory` - Directory the run's file edits are diffed against
+/// * `handler` - Decision callback
+/// * `initial_frame` - First stdin frame (see [`build_initial_input_frame`])
+/// * `lines` - The tool's stdout lines
+/// * `stdin` - The tool's stdin pipe
+///
+/// # Returns
+/// Task resolving to every handled request, in arrival order
+pub fn spawn_live_relay(
+    options: &AgentOptions,
+    working_directory: &str,
+    handler: AsyncPermissionHandler,
+    initial_frame: Value,
+    mut lines: UnboundedReceiver<String>,
+    stdin: ChildStdin,
+) -> JoinHandle<Vec<HandledPermission>> {
+    let (frames, close_stdin) = spawn_stdin_writer(stdin);
+    let _ = frames.send(stringify_ndjson_line(&initial_frame, true));
+    let mut relay = relay_for_run(options, working_directory, handler, frames);
+    tokio::spawn(async move {
+        while let Some(line) = lines.recv().await {
+            let Some(message) = parse_ndjson_line(&line) else {
+                continue;
+            };
+            relay.handle_message(&message);
+            if message.get("type").and_then(Value::as_str) == Some("result") {
+                relay.settle().await;
+                relay.close();
+            }
+        }
+        // Stdout closes when the tool exits or crashes without a result,
+        // and no decision still in flight can reach it any more
+        relay.close();
+        drop(close_stdin);
+        relay.finish().await
+    })
+}
+
+/// Write each frame sent on the returned channel to the tool's stdin
+///
+/// Dropping the pipe when the last sender is gone closes the tool's stdin.
+/// Sending on (or dropping) the returned closer closes it at once, after
+/// the frames already queued, even while decisions in flight still hold a
+/// sender.
+pub(super) fn spawn_stdin_writer(
+    mut stdin: ChildStdin,
+) -> (UnboundedSender<String>, oneshot::Sender<()>) {
+    let (frames, mut outgoing) = unbounded_channel::<String>();
+    let (closer, mut close) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        loop {
+            let line = tokio::select! {
+                biased;
+                line = outgoing.recv() => line,
+                _ = &mut close => break,
+            };
+            let Some(line) = line else {
+                return;
+            };
+            if !write_frame(&mut stdin, &line).await {
+                return;
+            }
+        }
+        while let Ok(line) = outgoing.try_recv() {
+            if !write_frame(&mut stdin, &line).await {
+                return;
+            }
+        }
+    });
+    (frames, closer)
+}
+
+/// Write one frame; a tool that already exited simply stops reading
+async fn write_frame(stdin: &mut ChildStdin, line: &str) -> bool {
+    stdin.write_all(line.as_bytes()).await.is_ok() && stdin.flush().await.is_ok()
+}
+
+/// Relay set up from the options of a run: its tool, `permission_timeout`,
+/// `permission_audit` and `workspace_boundary`
+pub(super) fn relay_for_run(
+    options: &AgentOptions,
+    working_directory: &str,
+    handler: AsyncPermissionHandler,
+    frames: UnboundedSender<String>,
+) -> AsyncPermissionRelay {
+    let mut relay =
+        AsyncPermissionRelay::new(&options.tool, handler, options.permission_timeout, frames)
+            .with_working_directory(working_directory);
+    relay.audit.clone_from(&options.permission_audit);
+    relay.guard = options
+        .workspace_boundary
+        .as_ref()
+        .map(|extra| WorkspaceGuard::new(working_directory, extra));
+    relay
+}
+
+/// The prompt an `approve_each` run sends as its first frame: `prompt`, or
+/// the contents of `prompt_file`
+///
+/// Read before the tool starts, so an unreadable prompt file fails `start`
+/// the way it does when the file is the tool's stdin.
+///
+/// # Returns
+/// The prompt, `None` for runs without a relay or without a prompt, or the
+/// read error
+pub async fn read_relay_prompt(options: &AgentOptions) -> Result<Option<String>, String> {
+    if !options.approve_each {
+        return Ok(None);
+    }
+    match options.prompt_file {
+        Some(ref prompt_file) if options.prompt.is_none() => tokio::fs::read_to_string(prompt_file)
+            .await
+            .map(Some)
+            .map_err(|e| format!("Failed to read prompt file {}: {}", prompt_file, e)),
+        _ => Ok(options.prompt.clone()),
+    }
+}
+
+/// Start relaying the permission requests of an `approve_each` run
+///
+/// Requests are rejected when the options have neither
+/// `on_permission_request_async` nor `on_permission_request`, and recorded
+/// in `permission_audit` when it is set.
+///
+/// # Arguments
+/// * `options` - Options the run was started with
+/// * `working_directory` - Directory the tool runs in, which may be a
+///   worktree rather than `options.working_directory`
+/// * `prompt` - Prompt from [`read_relay_prompt`]
+/// * `handle` - The started tool; its stdin pipe is taken over
+///
+/// # Returns
+/// The relay task, or `None` when the tool was not started with a stdin pipe
+pub async fn start_permission_relay(
+    options: &AgentOptions,
+    working_directory: &str,
+    prompt: Option<String>,
+    handle: &mut ProcessHandle,
+) -> Option<JoinHandle<Vec<HandledPermission>>> {
+    let stdin = handle.take_stdin()?;
+    let handler = match (
+        &options.on_permission_request_async,
+        &options.on_permission_request,
+    ) {
+        (Some(handler), _) => handler.clone(),
+        (None, Some(handler)) => handler.clone().into(),
+        (None, None) => AsyncPermissionHandler::new(|_| async {
+            PermissionDecision::new("reject").with_source("no handler")
+        }),
+    };
+    if options.tool == "codex" {
+        return Some(spawn_app_server_relay(
+            options,
+            working_directory,
+            handler,
+            prompt.as_deref(),
+            handle.subscribe_stdout(),
+            stdin,
+        ));
+    }
+    Some(spawn_live_relay(
+        options,
+        working_directory,
+        handler,
+        build_initial_input_frame(
+            &options.tool,
+            prompt.as_deref(),
+            options.system_prompt.as_deref(),
+        ),
+        handle.subscribe_stdout(),
+        stdin,
+    ))
+}
@@ -0,0 +1,151 @@
+//! Append-only audit trail of permission decisions
+//!
+//! Every request a relay answers becomes one [`PermissionAuditEntry`]: who
+//! asked (tool, session, call), what for (tool name and command), what was
+//! decided and by whom, and the exact frame sent back to the tool. Entries
+//! go to a [`PermissionAuditSink`]; [`JsonlAuditFile`] appends them to a
+//! file as JSON Lines so security can review what each agent was allowed to
+//! do.
+
+use super::{CommandRisk, HandledPermission};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One answered permission request, as written to the audit trail
+// `response` carries `serde_json::Value`, which cannot implement `Eq`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionAuditEntry {
+    /// When the decision was sent, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Backend tool name (`agent` | `claude` | `codex`)
+    pub tool: String,
+    /// Id correlating the request and its response
+    pub id: Option<String>,
+    pub session_id: Option<String>,
+    pub call_id: Option<String>,
+    /// Native tool/action name (e.g. `bash`, `Edit`)
+    pub tool_name: Option<String>,
+    pub command: Option<String>,
+    /// Risk of a shell command (see [`crate::classify_command`])
+    pub risk: Option<CommandRisk>,
+    /// Decision sent (`once` | `always` | `reject`)
+    pub decision: String,
+    /// Who decided (see [`HandledPermission::source`])
+    pub source: String,
+    /// Native response frame written to the tool's stdin
+    pub response: Value,
+}
+
+impl PermissionAuditEntry {
+    /// Describe a handled request, stamped with the current time
+    pub fn from_handled(handled: &HandledPermission) -> Self {
+        let request = &handled.request;
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| {
+                    u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+                }),
+            tool: request.tool.clone(),
+            id: request.id.clone(),
+            session_id: request.session_id.clone(),
+            call_id: request.call_id.clone(),
+            tool_name: request.tool_name.clone(),
+            command: request.command.clone(),
+            risk: request.risk.clone(),
+            decision: handled.decision.clone(),
+            source: handled.source.clone(),
+            response: handled.response.clone(),
+        }
+    }
+}
+
+/// Destination of audit entries
+///
+/// Called once per answered request, possibly from several tasks at once.
+pub trait PermissionAuditSink: Send + Sync {
+    /// Store one entry
+    ///
+    /// # Returns
+    /// Ok, or why the entry could not be stored
+    fn record(&self, entry: &PermissionAuditEntry) -> Result<(), String>;
+}
+
+/// Audit sink appending one JSON object per line to a file
+#[derive(Debug)]
+pub struct JsonlAuditFile {
+    file: Mutex<File>,
+}
+
+impl JsonlAuditFile {
+    /// Open a file for appending, creating it if needed
+    ///
+    /// # Arguments
+    /// * `path` - Audit log path; existing entries are kept
+    ///
+    /// # Returns
+    /// The sink, or an error naming the file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open audit log {}: {}", path.display(), e))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl PermissionAuditSink for JsonlAuditFile {
+    fn record(&self, entry: &PermissionAuditEntry) -> Result<(), String> {
+        let mut line = serde_json::to_string(entry)
+            .map_err(|e| format!("Failed to serialize audit entry: {}", e))?;
+        line.push('\n');
+        // One write per entry, so lines from concurrent writers never interleave
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())
+            .and_then(|()| file.flush())
+            .map_err(|e| format!("Failed to write audit log: {}", e))
+    }
+}
+
+/// Shareable handle to an audit sink, for [`crate::AgentOptions`] and the
+/// permission relays
+#[derive(Clone)]
+pub struct PermissionAudit(Arc<dyn PermissionAuditSink>);
+
+impl PermissionAudit {
+    /// Wrap a sink
+    pub fn new(sink: impl PermissionAuditSink + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    /// Append entries to a JSON Lines file (see [`JsonlAuditFile::open`])
+    pub fn file(path: impl AsRef<Path>) -> Result<Self, String> {
+        JsonlAuditFile::open(path).map(Self::new)
+    }
+
+    /// Record a handled request
+    ///
+    /// # Returns
+    /// Ok, or the sink's error
+    pub fn record(&self, handled: &HandledPermission) -> Result<(), String> {
+        self.0.record(&PermissionAuditEntry::from_handled(handled))
+    }
+}
+
+impl fmt::Debug for PermissionAudit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PermissionAudit(..)")
+    }
+}
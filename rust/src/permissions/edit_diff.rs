@@ -0,0 +1,328 @@
+//! Unified diff previews for file-edit permission requests
+//!
+//! A Claude `Edit`, `MultiEdit` or `Write` request, or an agent edit
+//! permission, only names the file it touches. To show an approver what
+//! would change, the proposed content is rebuilt from the request's input
+//! (`old_string`/`new_string` replacements or the full new content) and
+//! compared line by line with the file currently in the working directory.
+//! Only regular files inside the working directory are read, never
+//! credential or environment files, and only up to a size limit; the relays
+//! attach the diff after a [`super::WorkspaceGuard`] let the request through.
+//! Codex file change approvals carry their own diffs, which are kept.
+
+use super::risk::is_secret_path;
+use super::workspace::resolve;
+use super::NormalizedPermissionRequest;
+use serde_json::Value;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Largest line table compared exactly; bigger edits show the changed
+/// region as removed and re-added
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+/// Largest file read to diff an edit against
+const MAX_CURRENT_BYTES: u64 = 1024 * 1024;
+
+/// Keys naming the edited file, Claude's first, then the agent's metadata
+const FILE_KEYS: &[&str] = &["file_path", "filePath", "filepath"];
+
+impl NormalizedPermissionRequest {
+    /// Attach a unified diff of the proposed file edit
+    ///
+    /// Requests that do not edit a file, or whose edit changes nothing, are
+    /// returned unchanged. A missing file is compared as empty; an edit whose
+    /// `old_string` is not in the file, or of a file that is not read, is
+    /// shown as a diff of the replaced text alone.
+    ///
+    /// # Arguments
+    /// * `working_directory` - Directory relative file paths resolve against
+    ///
+    /// # Returns
+    /// The request with [`Self::diff`] set
+    #[must_use]
+    pub fn with_edit_diff(mut self, working_directory: &Path) -> Self {
+        let diff = match self.tool.as_str() {
+            "claude" => self.input.as_ref().and_then(|input| {
+                claude_edit(self.tool_name.as_deref()?, input, working_directory)
+            }),
+            "agent" => self
+                .raw
+                .get("metadata")
+                .and_then(|metadata| agent_edit(metadata, working_directory)),
+            "codex" => self.diff.take(),
+            _ => None,
+        };
+        self.diff = diff.filter(|diff| !diff.is_empty());
+        self
+    }
+}
+
+/// [`NormalizedPermissionRequest::with_edit_diff`] on the blocking pool, as
+/// it reads the edited file
+pub(super) async fn attach_edit_diff(
+    request: NormalizedPermissionRequest,
+    working_directory: Option<PathBuf>,
+) -> NormalizedPermissionRequest {
+    let Some(directory) = working_directory else {
+        return request;
+    };
+    let fallback = request.clone();
+    tokio::task::spawn_blocking(move || request.with_edit_diff(&directory))
+        .await
+        .unwrap_or(fallback)
+}
+
+/// Diff of a Claude `Edit`, `MultiEdit` or `Write` input
+fn claude_edit(tool_name: &str, input: &Value, working_directory: &Path) -> Option<String> {
+    let path = text(input, "file_path")?;
+    let edits = match tool_name {
+        "Write" => {
+            let content = text(input, "content")?;
+            let current = read_current(path, working_directory)?;
+            return Some(unified_diff(path, &current, content));
+        }
+        "Edit" => vec![input],
+        "MultiEdit" => input.get("edits")?.as_array()?.iter().collect(),
+        _ => return None,
+    };
+    Some(replacement_diff(
+        path,
+        read_current(path, working_directory),
+        &edits,
+    ))
+}
+
+/// Diff of an agent edit permission: its own precomputed `diff`, or one
+/// built from the file and the `content` or `oldString`/`newString` metadata
+fn agent_edit(metadata: &Value, working_directory: &Path) -> Option<String> {
+    if let Some(diff) = text(metadata, "diff") {
+        return Some(diff.to_string());
+    }
+    let path = FILE_KEYS.iter().find_map(|key| text(metadata, key))?;
+    if let Some(content) = text(metadata, "content") {
+        let current = read_current(path, working_directory)?;
+        return Some(unified_diff(path, &current, content));
+    }
+    text(metadata, "oldString")?;
+    Some(replacement_diff(
+        path,
+        read_current(path, working_directory),
+        &[metadata],
+    ))
+}
+
+/// Diff of the changes of a codex file change item: each update's own
+/// unified diff under a header naming the file, and added files in full
+pub(super) fn codex_changes_diff(changes: &Value) -> Option<String> {
+    let mut diff = String::new();
+    for change in changes.as_array()? {
+        let (Some(path), Some(text)) = (text(change, "path"), text(change, "diff")) else {
+            continue;
+        };
+        let kind = change
+            .pointer("/kind/type")
+            .or_else(|| change.get("kind"))
+            .and_then(Value::as_str);
+        if kind == Some("add") {
+            diff.push_str(&unified_diff(path, "", text));
+        } else if text.starts_with("--- ") {
+            diff.push_str(text);
+        } else {
+            diff.push_str(&format!("--- {}\n+++ {}\n{}", path, path, text));
+        }
+        if !diff.ends_with('\n') {
+            diff.push('\n');
+        }
+    }
+    Some(diff).filter(|diff| !diff.is_empty())
+}
+
+/// Apply `old_string`/`new_string` (or `oldString`/`newString`) edits in
+/// order and diff the result, or just the replaced text when an edit does
+/// not apply
+fn replacement_diff(path: &str, current: Option<String>, edits: &[&Value]) -> String {
+    let replacements: Vec<_> = edits
+        .iter()
+        .map(|edit| {
+            let replace_all = ["replace_all", "replaceAll"]
+                .iter()
+                .any(|key| edit.get(*key).and_then(Value::as_bool) == Some(true));
+            (
+                either_text(edit, "old_string", "oldString"),
+                either_text(edit, "new_string", "newString"),
+                replace_all,
+            )
+        })
+        .collect();
+
+    let current = current.unwrap_or_default();
+    let mut proposed = current.clone();
+    for (old, new, replace_all) in &replacements {
+        if old.is_empty() && proposed.is_empty() {
+            proposed = (*new).to_string();
+        } else if old.is_empty() || !proposed.contains(old) {
+            let removed: Vec<_> = replacements.iter().map(|(old, _, _)| *old).collect();
+            let added: Vec<_> = replacements.iter().map(|(_, new, _)| *new).collect();
+            return unified_diff(path, &removed.join("\n"), &added.join("\n"));
+        } else if *replace_all {
+            proposed = proposed.replace(old, new);
+        } else {
+            proposed = proposed.replacen(old, new, 1);
+        }
+    }
+    unified_diff(path, &current, &proposed)
+}
+
+/// The file's current content; `Some("")` when it does not exist yet and
+/// `None` when it cannot or must not be read: outside the working directory,
+/// a credential or environment file, not a regular file, or too large
+fn read_current(path: &str, working_directory: &Path) -> Option<String> {
+    let root = resolve(working_directory);
+    let file = resolve(&working_directory.join(path));
+    let inside = file.strip_prefix(&root).ok()?.to_string_lossy();
+    if is_secret_path(path) || is_secret_path(&inside) {
+        return None;
+    }
+    match std::fs::metadata(&file) {
+        Ok(metadata) if metadata.is_file() && metadata.len() <= MAX_CURRENT_BYTES => {
+            let mut content = String::new();
+            std::fs::File::open(&file)
+                .ok()?
+                .take(MAX_CURRENT_BYTES)
+                .read_to_string(&mut content)
+                .ok()?;
+            Some(content)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Some(String::new()),
+        _ => None,
+    }
+}
+
+fn text<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+/// Claude's snake_case field or the agent's camelCase one, or `""`
+fn either_text<'a>(value: &'a Value, snake: &str, camel: &str) -> &'a str {
+    text(value, snake)
+        .or_else(|| text(value, camel))
+        .unwrap_or_default()
+}
+
+/// Line-by-line unified diff of two texts
+///
+/// # Arguments
+/// * `path` - File name shown in the `---`/`+++` headers
+/// * `old` - Current text
+/// * `new` - Proposed text
+///
+/// # Returns
+/// The diff with three lines of context, or an empty string when the texts
+/// have the same lines
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    let lines = diff_lines(&old, &new);
+    let changes: Vec<_> = (0..lines.len()).filter(|&i| lines[i].0 != ' ').collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", path, path);
+    let mut group_start = 0;
+    for index in 1..=changes.len() {
+        let closes_group =
+            index == changes.len() || changes[index] - changes[index - 1] > 2 * CONTEXT_LINES + 1;
+        if closes_group {
+            let start = changes[group_start].saturating_sub(CONTEXT_LINES);
+            let end = (changes[index - 1] + CONTEXT_LINES + 1).min(lines.len());
+            push_hunk(&mut diff, &lines, start, end);
+            group_start = index;
+        }
+    }
+    diff
+}
+
+/// Append the `@@` header and lines of one hunk
+fn push_hunk(diff: &mut String, lines: &[(char, &str)], start: usize, end: usize) {
+    let count = |range: &[(char, &str)], skipped: char| {
+        range
+            .iter()
+            .filter(|(marker, _)| *marker != skipped)
+            .count()
+    };
+    let (old_before, new_before) = (count(&lines[..start], '+'), count(&lines[..start], '-'));
+    let (old_count, new_count) = (
+        count(&lines[start..end], '+'),
+        count(&lines[start..end], '-'),
+    );
+    // An empty side is numbered after the line it follows
+    let first = |before: usize, count: usize| if count == 0 { before } else { before + 1 };
+    diff.push_str(&format!(
+        "@@ -{},{} +{},{} @@\n",
+        first(old_before, old_count),
+        old_count,
+        first(new_before, new_count),
+        new_count
+    ));
+    for (marker, line) in &lines[start..end] {
+        diff.push_str(&format!("{}{}\n", marker, line));
+    }
+}
+
+/// Every line of both texts marked ` ` (kept), `-` (removed) or `+` (added),
+/// in order, from a longest common subsequence of the changed region
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_mid, new_mid) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut lines: Vec<_> = old[..prefix].iter().map(|line| (' ', *line)).collect();
+    let width = new_mid.len() + 1;
+    if old_mid.len().saturating_mul(width) > MAX_TABLE_CELLS {
+        lines.extend(old_mid.iter().map(|line| ('-', *line)));
+        lines.extend(new_mid.iter().map(|line| ('+', *line)));
+    } else {
+        // common[i * width + j]: LCS length of old_mid[i..] and new_mid[j..]
+        let mut common = vec![0_usize; (old_mid.len() + 1) * width];
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                common[i * width + j] = if old_mid[i] == new_mid[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old_mid.len() || j < new_mid.len() {
+            if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+                lines.push((' ', old_mid[i]));
+                i += 1;
+                j += 1;
+            } else if j == new_mid.len()
+                || (i < old_mid.len() && common[(i + 1) * width + j] >= common[i * width + j + 1])
+            {
+                lines.push(('-', old_mid[i]));
+                i += 1;
+            } else {
+                lines.push(('+', new_mid[j]));
+                j += 1;
+            }
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|line| (' ', *line)));
+    lines
+}
@@ -8,19 +8,55 @@
 //! native response frame that the CLI expects on its stdin.
 //!
 //! Only tools with a *drivable* JSON request/response permission protocol can be
-//! relayed (see [`ASK_SUPPORTED_TOOLS`]). Tools whose only native approval
-//! mechanism is a static policy (`opencode`), a sandbox coupling (`codex`), or a
+//! relayed (see [`ASK_SUPPORTED_TOOLS`]); codex is driven through its
+//! app-server JSON-RPC protocol rather than `codex exec`. Tools whose only
+//! native approval mechanism is a static policy (`opencode`) or a
 //! non-streaming approval flag (`qwen`, `gemini`) are documented in the parity
 //! table but fail clearly when ask mode is requested — mirroring the
 //! `--read-only` unsupported-tool pattern.
 //!
-//! This is the Rust mirror of `js/src/permissions/`.
+//! This is the Rust mirror of `js/src/permissions/`, except for the codex
+//! app-server relay, which the JavaScript package does not have yet.
 
-use crate::streaming::stringify_ndjson_line;
+mod app_server;
+mod approve_command;
+mod async_relay;
+mod audit;
+mod edit_diff;
+mod policy;
+mod relay;
+mod risk;
+mod session;
+mod static_policy;
+mod terminal;
+mod workspace;
+
+use serde::Serialize;
 use serde_json::{json, Value};
 
+pub use app_server::build_app_server_opening_frames;
+pub(crate) use async_relay::{read_relay_prompt, start_permission_relay};
+pub use async_relay::{AsyncPermissionHandler, AsyncPermissionRelay, PermissionDecisionFuture};
+pub use audit::{JsonlAuditFile, PermissionAudit, PermissionAuditEntry, PermissionAuditSink};
+pub use edit_diff::unified_diff;
+pub use policy::{PermissionPolicy, PermissionRule, PolicyDecision, POLICY_DECISIONS};
+pub use relay::{
+    build_initial_input_frame, HandledPermission, PermissionCancellation, PermissionHandler,
+    PermissionRelay,
+};
+pub use risk::{classify_command, risk_rank, CommandRisk, RISK_LEVELS};
+pub use static_policy::{
+    NativePermissionPolicy, StaticPermissionPolicy, StaticPermissionRule,
+    STATIC_PERMISSION_CATEGORIES, STATIC_POLICY_TOOLS,
+};
+pub use terminal::{prompt_for_decision, render_permission_request};
+pub use workspace::WorkspaceGuard;
+
 /// Tools that expose a relayable per-command approval protocol over JSON.
-pub const ASK_SUPPORTED_TOOLS: &[&str] = &["agent", "claude"];
+///
+/// `codex` is relayed by the Rust package only; the JavaScript list is
+/// `agent` and `claude`.
+pub const ASK_SUPPORTED_TOOLS: &[&str] = &["agent", "claude", "codex"];
 
 /// Normalized decisions a consumer may return for a permission request.
 pub const ASK_DECISIONS: &[&str] = &["once", "always", "reject"];
@@ -29,15 +65,14 @@ pub const ASK_DECISIONS: &[&str] = &["once", "always", "reject"];
 /// that "always" does not mean the same thing across CLIs, so the normalized
 /// event and the parity table both carry this scope.
 ///
-/// - `session`    — `always` auto-approves later matching requests for the rest
-///   of the session (agent's native `always`).
-/// - `tool-input` — approval binds to the tool name + input shape; Claude has no
-///   native session-wide `always`, so `once` and `always` both map to a single
-///   allow decision bound to that tool call's input.
+/// - `session` — `always` auto-approves later matching requests for the rest
+///   of the session. Agent and codex (`acceptForSession`) do this natively.
+///   Claude's handshake only allows the call at hand, so the relays remember
+///   its `always` decisions and answer later requests for the same tool name
+///   and command or path themselves.
 pub fn ask_scope(tool: &str) -> Option<&'static str> {
     match tool {
-        "agent" => Some("session"),
-        "claude" => Some("tool-input"),
+        "agent" | "claude" | "codex" => Some("session"),
         _ => None,
     }
 }
@@ -60,11 +95,11 @@ pub fn ask_unsupported_error(tool: &str) -> String {
 /// A normalized permission request, uniform across every relayable backend.
 // `raw`/`input` carry `serde_json::Value`, which cannot implement `Eq`.
 #[allow(clippy::derive_partial_eq_without_eq)]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct NormalizedPermissionRequest {
     /// Always `"permission_request"`.
     pub r#type: String,
-    /// Backend tool name (`agent` | `claude`).
+    /// Backend tool name (`agent` | `claude` | `codex`).
     pub tool: String,
     /// Opaque id used to correlate the response with this request.
     pub id: Option<String>,
@@ -80,6 +115,12 @@ pub struct NormalizedPermissionRequest {
     pub scope: String,
     /// Original tool input payload (Claude only), needed to echo `updatedInput`.
     pub input: Option<Value>,
+    /// Unified diff of a file edit against the file in the working directory
+    /// (see [`NormalizedPermissionRequest::with_edit_diff`]).
+    pub diff: Option<String>,
+    /// How dangerous a shell command looks (bash requests only; see
+    /// [`classify_command`]).
+    pub risk: Option<CommandRisk>,
     /// The raw native frame this was normalized from.
     pub raw: Value,
 }
@@ -104,6 +145,33 @@ fn derive_claude_command(tool_name: Option<&str>, input: Option<&Value>) -> Opti
     tool_name.map(|s| s.to_string())
 }
 
+/// Keys of a tool's input (or of agent's request metadata) holding file paths
+const PATH_KEYS: &[&str] = &["file_path", "filePath", "filepath", "notebook_path", "path"];
+
+/// File paths a request touches: path-like keys of Claude's tool input and
+/// of agent's request metadata, and the files of a codex file change
+pub(super) fn request_paths(request: &NormalizedPermissionRequest) -> Vec<String> {
+    let metadata = request.raw.get("metadata");
+    let codex_changes = request
+        .raw
+        .pointer("/params/changes")
+        .and_then(Value::as_array)
+        .filter(|_| request.tool == "codex");
+    [request.input.as_ref(), metadata]
+        .into_iter()
+        .flatten()
+        .flat_map(|object| PATH_KEYS.iter().filter_map(|key| object.get(*key)))
+        .chain(
+            codex_changes
+                .into_iter()
+                .flatten()
+                .filter_map(|change| change.get("path")),
+        )
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect()
+}
+
 /// Normalize a native permission request frame into a uniform event.
 ///
 /// Returns `None` when the message is not a permission request for the tool, so
@@ -116,6 +184,10 @@ pub fn normalize_permission_request(
         return None;
     }
 
+    if tool == "codex" {
+        return app_server::normalize_codex(message);
+    }
+
     if tool == "agent" {
         if message.get("type").and_then(|v| v.as_str()) != Some("permission_request") {
             return None;
@@ -134,6 +206,9 @@ pub fn normalize_permission_request(
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string())
         });
+        let tool_name = value_str(message, "tool");
+        let risk = risk::is_shell_request(tool_name.as_deref())
+            .then(|| classify_command(command.as_deref().unwrap_or_default(), None));
         return Some(NormalizedPermissionRequest {
             r#type: "permission_request".to_string(),
             tool: "agent".to_string(),
@@ -141,12 +216,14 @@ pub fn normalize_permission_request(
             session_id: value_str(message, "sessionID")
                 .or_else(|| value_str(message, "session_id")),
             call_id: value_str(message, "callID").or_else(|| value_str(message, "call_id")),
-            tool_name: value_str(message, "tool"),
+            tool_name,
             title,
             command,
             pattern,
             scope: ask_scope("agent").unwrap().to_string(),
             input: None,
+            diff: None,
+            risk,
             raw: message.clone(),
         });
     }
@@ -168,6 +245,9 @@ pub fn normalize_permission_request(
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
         let input = request.get("input").filter(|v| v.is_object()).cloned();
+        let command = derive_claude_command(tool_name.as_deref(), input.as_ref());
+        let risk = risk::is_shell_request(tool_name.as_deref())
+            .then(|| classify_command(command.as_deref().unwrap_or_default(), None));
         return Some(NormalizedPermissionRequest {
             r#type: "permission_request".to_string(),
             tool: "claude".to_string(),
@@ -179,10 +259,12 @@ pub fn normalize_permission_request(
                 .map(|s| s.to_string()),
             tool_name: tool_name.clone(),
             title: tool_name.clone(),
-            command: derive_claude_command(tool_name.as_deref(), input.as_ref()),
+            command,
             pattern: None,
             scope: ask_scope("claude").unwrap().to_string(),
             input,
+            diff: None,
+            risk,
             raw: message.clone(),
         });
     }
@@ -190,6 +272,123 @@ pub fn normalize_permission_request(
     None
 }
 
+/// Message Claude sees when a request is rejected without a reason
+const DEFAULT_DENY_MESSAGE: &str = "Denied by consumer (ask mode).";
+
+/// A consumer's answer to a permission request
+///
+/// Besides the plain decision, an allow can replace the tool input (e.g. a
+/// rewritten shell command or a narrowed file path) and a rejection can
+/// explain itself to the agent. Handlers may return a plain decision string,
+/// which converts into a decision with neither.
+// `updated_input` carries `serde_json::Value`, which cannot implement `Eq`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermissionDecision {
+    /// `once` | `always` | `reject`
+    pub decision: String,
+    /// Tool input to run instead of the requested one (allow decisions)
+    pub updated_input: Option<Value>,
+    /// Why the request was rejected, shown to the agent (reject decisions)
+    pub message: Option<String>,
+    /// Who decided, for the audit trail (e.g. `policy: rule 1 -> reject`);
+    /// `None` stands for the consumer's handler
+    pub source: Option<String>,
+}
+
+impl PermissionDecision {
+    /// A plain decision (`once` | `always` | `reject`)
+    pub fn new(decision: &str) -> Self {
+        Self {
+            decision: decision.to_string(),
+            updated_input: None,
+            message: None,
+            source: None,
+        }
+    }
+
+    /// Allow this call with a replacement tool input
+    pub fn allow_with_input(input: Value) -> Self {
+        Self {
+            updated_input: Some(input),
+            ..Self::new("once")
+        }
+    }
+
+    /// Reject with a message the agent sees
+    pub fn reject_with_message(message: &str) -> Self {
+        Self {
+            message: Some(message.to_string()),
+            ..Self::new("reject")
+        }
+    }
+
+    /// Record who made the decision (see [`Self::source`])
+    #[must_use]
+    pub fn with_source(self, source: &str) -> Self {
+        Self {
+            source: Some(source.to_string()),
+            ..self
+        }
+    }
+
+    /// Reduce the decision to what a tool's native protocol can express
+    ///
+    /// An unknown decision becomes a plain `reject`; a replacement input is
+    /// only kept on an allow, and a message only on a rejection. Claude takes
+    /// both. Agent's `permission_response` and codex's approval responses
+    /// carry neither, so their message is dropped and an allow with a
+    /// replacement input becomes a `reject`: the agent never runs an input the
+    /// consumer did not approve. The source is always kept.
+    ///
+    /// # Arguments
+    /// * `tool` - Relayable tool name (`agent` | `claude` | `codex`)
+    ///
+    /// # Returns
+    /// The decision as it will be sent
+    #[must_use]
+    pub fn for_tool(&self, tool: &str) -> Self {
+        Self {
+            source: self.source.clone(),
+            ..self.reduce_for(tool)
+        }
+    }
+
+    fn reduce_for(&self, tool: &str) -> Self {
+        if !ASK_DECISIONS.contains(&self.decision.as_str()) {
+            return Self::new("reject");
+        }
+        if self.decision == "reject" {
+            return Self {
+                message: self.message.clone().filter(|_| tool == "claude"),
+                ..Self::new("reject")
+            };
+        }
+        if tool == "claude" {
+            return Self {
+                updated_input: self.updated_input.clone(),
+                ..Self::new(&self.decision)
+            };
+        }
+        if self.updated_input.is_some() {
+            return Self::new("reject");
+        }
+        Self::new(&self.decision)
+    }
+}
+
+impl From<&str> for PermissionDecision {
+    fn from(decision: &str) -> Self {
+        Self::new(decision)
+    }
+}
+
+impl From<String> for PermissionDecision {
+    fn from(decision: String) -> Self {
+        Self::new(&decision)
+    }
+}
+
 /// Build the native response frame for a normalized decision.
 ///
 /// Returns `Err` for an invalid decision or an unsupported tool.
@@ -198,12 +397,27 @@ pub fn build_permission_response(
     request: &NormalizedPermissionRequest,
     decision: &str,
 ) -> Result<Value, String> {
-    if !ASK_DECISIONS.contains(&decision) {
+    build_decision_response(tool, request, &PermissionDecision::new(decision))
+}
+
+/// Build the native response frame for a [`PermissionDecision`]
+///
+/// The decision is first reduced to what the tool can express (see
+/// [`PermissionDecision::for_tool`]).
+///
+/// Returns `Err` for an invalid decision or an unsupported tool.
+pub fn build_decision_response(
+    tool: &str,
+    request: &NormalizedPermissionRequest,
+    decision: &PermissionDecision,
+) -> Result<Value, String> {
+    if !ASK_DECISIONS.contains(&decision.decision.as_str()) {
         return Err(format!(
             "Invalid permission decision \"{}\". Expected one of: once, always, reject.",
-            decision
+            decision.decision
         ));
     }
+    let decision = decision.for_tool(tool);
 
     let id = request.id.clone().unwrap_or_default();
 
@@ -212,15 +426,15 @@ pub fn build_permission_response(
         return Ok(json!({
             "type": "permission_response",
             "permissionID": id,
-            "response": decision,
+            "response": decision.decision,
         }));
     }
 
     if tool == "claude" {
         // Claude's stream-json control protocol expects an allow/deny behavior.
         // It has no native session-wide "always", so once and always both map to
-        // a single allow bound to this tool call's input (scope: tool-input).
-        if decision == "reject" {
+        // a single allow; the relays remember "always" for the session.
+        if decision.decision == "reject" {
             return Ok(json!({
                 "type": "control_response",
                 "response": {
@@ -228,12 +442,15 @@ pub fn build_permission_response(
                     "request_id": id,
                     "response": {
                         "behavior": "deny",
-                        "message": "Denied by consumer (ask mode).",
+                        "message": decision.message.as_deref().unwrap_or(DEFAULT_DENY_MESSAGE),
                     },
                 },
             }));
         }
-        let updated_input = request.input.clone().unwrap_or_else(|| json!({}));
+        let updated_input = decision
+            .updated_input
+            .or_else(|| request.input.clone())
+            .unwrap_or_else(|| json!({}));
         return Ok(json!({
             "type": "control_response",
             "response": {
@@ -247,6 +464,10 @@ pub fn build_permission_response(
         }));
     }
 
+    if tool == "codex" {
+        return Ok(app_server::codex_response(request, &decision));
+    }
+
     Err(ask_unsupported_error(tool))
 }
 
@@ -277,16 +498,16 @@ pub fn permission_parity() -> Vec<PermissionParityRow> {
         PermissionParityRow {
             tool: "claude",
             native_mechanism: "--permission-mode default (stream-json can_use_tool)",
-            scope: "tool-input",
+            scope: "session",
             relay: true,
-            notes: "control_request/control_response handshake; no session-wide always, so once and always both allow this call.",
+            notes: "control_request/control_response handshake; no native session-wide always, so the relay remembers always decisions per tool name and command or path and answers repeats itself.",
         },
         PermissionParityRow {
             tool: "codex",
-            native_mechanism: "--ask-for-approval (coupled with --sandbox)",
-            scope: "sandbox-coupled",
-            relay: false,
-            notes: "Approval is coupled with the sandbox policy and not exposed as a tool-agnostic JSON request/response stream.",
+            native_mechanism: "codex app-server (JSON-RPC requestApproval)",
+            scope: "session",
+            relay: true,
+            notes: "Rust only; the JavaScript package does not relay codex yet. `codex exec` couples approval with the sandbox, so ask mode drives `codex app-server`; its command and file change approval requests take accept | acceptForSession | decline for once | always | reject.",
         },
         PermissionParityRow {
             tool: "qwen",
@@ -311,74 +532,3 @@ pub fn permission_parity() -> Vec<PermissionParityRow> {
         },
     ]
 }
-
-/// Relay native permission requests to a consumer and forward decisions back.
-///
-/// A `PermissionRelay` sits between a backend CLI's streaming output and a
-/// consumer: it watches parsed output messages for native permission requests,
-/// normalizes them, asks the consumer for a decision, and writes the native
-/// response frame back to the CLI's stdin as NDJSON.
-///
-/// The relay is intentionally transport-agnostic — it does not own the child
-/// process. The caller supplies a `write` closure (typically the child's stdin)
-/// and feeds it parsed messages, which keeps it fully unit-testable.
-pub struct PermissionRelay<'a> {
-    tool: String,
-    on_request: Box<dyn FnMut(&NormalizedPermissionRequest) -> String + 'a>,
-    write: Box<dyn FnMut(&str) + 'a>,
-    compact: bool,
-    handled: Vec<(NormalizedPermissionRequest, String, Value)>,
-}
-
-impl<'a> PermissionRelay<'a> {
-    /// Create a new relay.
-    ///
-    /// * `on_request` resolves a normalized request to a decision
-    ///   (`once` | `always` | `reject`).
-    /// * `write` receives a serialized NDJSON frame to forward to the tool stdin.
-    pub fn new<F, W>(tool: &str, on_request: F, write: W) -> Self
-    where
-        F: FnMut(&NormalizedPermissionRequest) -> String + 'a,
-        W: FnMut(&str) + 'a,
-    {
-        Self {
-            tool: tool.to_string(),
-            on_request: Box::new(on_request),
-            write: Box::new(write),
-            compact: true,
-            handled: Vec::new(),
-        }
-    }
-
-    /// Process a single parsed output message. When the message is a permission
-    /// request, resolves the consumer's decision and writes the native response.
-    /// Returns the normalized request and the applied decision, or `None` when the
-    /// message is not a permission request.
-    pub fn handle_message(
-        &mut self,
-        message: &Value,
-    ) -> Option<(NormalizedPermissionRequest, String)> {
-        let request = normalize_permission_request(&self.tool, message)?;
-
-        let mut decision = (self.on_request)(&request);
-        // Default to the safe choice if the consumer returns nothing usable.
-        if !ASK_DECISIONS.contains(&decision.as_str()) {
-            decision = "reject".to_string();
-        }
-
-        // build_permission_response only fails for unsupported tools / decisions,
-        // both of which are excluded above, so the frame is always available here.
-        let frame = build_permission_response(&self.tool, &request, &decision)
-            .expect("relayable tool with validated decision");
-        (self.write)(&stringify_ndjson_line(&frame, self.compact));
-
-        self.handled
-            .push((request.clone(), decision.clone(), frame));
-        Some((request, decision))
-    }
-
-    /// All permission requests handled so far (for inspection/testing).
-    pub fn get_handled(&self) -> &[(NormalizedPermissionRequest, String, Value)] {
-        &self.handled
-    }
-}
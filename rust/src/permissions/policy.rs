@@ -0,0 +1,506 @@
+//! Declarative permission policies for ask mode
+//!
+//! A policy is an ordered list of rules loaded from TOML or JSON. The first
+//! rule whose matchers all match a [`NormalizedPermissionRequest`] decides
+//! it; requests no rule matches get the policy's `default` decision:
+//!
+//! ```toml
+//! default = "ask-human"
+//!
+//! [[rules]]
+//! name = "no recursive deletes"
+//! command_regex = '\brm\s+-\w*r'
+//! decision = "reject"
+//! message = "Delete files one at a time instead."
+//!
+//! [[rules]]
+//! risk = "high"
+//! decision = "ask-human"
+//!
+//! [[rules]]
+//! tool_name = "Bash"
+//! command = "git status*"
+//! decision = "once"
+//!
+//! [[rules]]
+//! path = "/repo/src/*"
+//! decision = "always"
+//! ```
+//!
+//! Globs support `*` (any run of characters, `/` included) and `?` (one
+//! character), the same wildcards agent and opencode use in their own
+//! permission patterns. Regexes are unanchored.
+//!
+//! A `command` glob is matched against each simple command of a pipeline
+//! or list, and `path` matchers against the request's paths with `.` and
+//! `..` applied (and, with [`PermissionPolicy::with_working_directory`],
+//! resolved against the working directory with symlinks followed). Rules
+//! allowing a request (`once`, `always`) need every simple command and every
+//! path to match, and never allow a command with a `$(..)`, backtick or
+//! `<(..)` substitution through a glob; `reject` and `ask-human` rules match
+//! when any of them does. So `git status*` allows `git status -s` but not
+//! `git status; rm -rf ~`.
+
+use super::risk::{risk_rank, RISK_LEVELS};
+use super::workspace::{resolve, simple_commands};
+use super::{request_paths, NormalizedPermissionRequest, PermissionDecision, PermissionHandler};
+use regex::Regex;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Decisions a policy rule can map a request to
+///
+/// `ask-human` defers to a person (see [`PermissionPolicy::handler`]); the
+/// rest are relayed to the tool as they are.
+pub const POLICY_DECISIONS: &[&str] = &["once", "always", "reject", "ask-human"];
+
+/// One rule of a [`PermissionPolicy`], as written in the policy file
+///
+/// Every matcher that is set must match; a rule without matchers matches
+/// every request. A matcher on a field the request does not carry never
+/// matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionRule {
+    /// Label used when explaining a decision
+    pub name: Option<String>,
+    /// Glob over the native tool name (e.g. `Bash`, `Edit*`)
+    pub tool_name: Option<String>,
+    /// Glob over each simple command of the request's command
+    pub command: Option<String>,
+    /// Regex over the request's command
+    pub command_regex: Option<String>,
+    /// Glob over the request's permission pattern (agent)
+    pub pattern: Option<String>,
+    /// Regex over the request's permission pattern (agent)
+    pub pattern_regex: Option<String>,
+    /// Glob over the normalized file paths in the request's input
+    pub path: Option<String>,
+    /// Regex over the normalized file paths in the request's input
+    pub path_regex: Option<String>,
+    /// Lowest shell command risk that matches (`low` | `medium` | `high`;
+    /// see [`crate::classify_command`])
+    pub risk: Option<String>,
+    /// `once` | `always` | `reject` | `ask-human`
+    pub decision: String,
+    /// Shown to the agent when the rule rejects a request (claude)
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default = "default_decision")]
+    default: String,
+    #[serde(default)]
+    rules: Vec<PermissionRule>,
+}
+
+fn default_decision() -> String {
+    "ask-human".to_string()
+}
+
+/// A compiled glob or regex and the rule field it came from
+#[derive(Debug)]
+struct Matcher {
+    field: &'static str,
+    regex: Regex,
+}
+
+/// A rule's matchers, compiled once when the policy is loaded
+#[derive(Debug)]
+struct CompiledRule {
+    rule: PermissionRule,
+    tool_name: Option<Matcher>,
+    command: Option<Matcher>,
+    pattern: Option<Matcher>,
+    path: Option<Matcher>,
+    risk: Option<usize>,
+}
+
+/// The outcome of evaluating a policy for one request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyDecision {
+    /// `once` | `always` | `reject` | `ask-human`
+    pub decision: String,
+    /// Index of the rule that fired, or `None` for the policy default
+    pub rule: Option<usize>,
+    /// The rule's rejection message
+    pub message: Option<String>,
+    /// Which rule fired and why, e.g. `rule 1 "no recursive deletes"
+    /// (command_regex) -> reject`
+    pub explanation: String,
+}
+
+impl fmt::Display for PolicyDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.explanation)
+    }
+}
+
+/// Ordered permission rules with a default decision
+#[derive(Debug)]
+pub struct PermissionPolicy {
+    default: String,
+    rules: Vec<CompiledRule>,
+    working_directory: Option<PathBuf>,
+}
+
+impl PermissionPolicy {
+    /// Build a policy from rules
+    ///
+    /// # Arguments
+    /// * `rules` - Rules, first match wins
+    /// * `default` - Decision when no rule matches
+    ///
+    /// # Returns
+    /// The policy, or the first invalid decision, glob or regex
+    pub fn new(rules: Vec<PermissionRule>, default: &str) -> Result<Self, String> {
+        check_decision(default, "default")?;
+        let rules = rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, rule)| compile_rule(index + 1, rule))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            default: default.to_string(),
+            rules,
+            working_directory: None,
+        })
+    }
+
+    /// Resolve relative request paths against a directory, with symlinks
+    /// followed, before `path` matchers see them
+    #[must_use]
+    pub fn with_working_directory(mut self, working_directory: impl Into<PathBuf>) -> Self {
+        self.working_directory = Some(working_directory.into());
+        self
+    }
+
+    /// Parse a JSON policy (`{"default": .., "rules": [..]}`)
+    pub fn from_json_str(source: &str) -> Result<Self, String> {
+        let file: PolicyFile = serde_json::from_str(source)
+            .map_err(|e| format!("Invalid permission policy: {}", e))?;
+        Self::new(file.rules, &file.default)
+    }
+
+    /// Parse a TOML policy (`default = ..` and `[[rules]]` tables)
+    pub fn from_toml_str(source: &str) -> Result<Self, String> {
+        let file: PolicyFile =
+            toml::from_str(source).map_err(|e| format!("Invalid permission policy: {}", e))?;
+        Self::new(file.rules, &file.default)
+    }
+
+    /// Load a policy file, as TOML when it ends in `.toml` and JSON otherwise
+    ///
+    /// # Arguments
+    /// * `path` - Policy file path
+    ///
+    /// # Returns
+    /// The policy, or a read or parse error naming the file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read permission policy {}: {}", path, e))?;
+        let is_toml = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+        let policy = if is_toml {
+            Self::from_toml_str(&source)
+        } else {
+            Self::from_json_str(&source)
+        };
+        policy.map_err(|e| format!("{} ({})", e, path))
+    }
+
+    /// Decide a request
+    ///
+    /// # Arguments
+    /// * `request` - Normalized permission request
+    ///
+    /// # Returns
+    /// The decision with the rule that fired
+    pub fn evaluate(&self, request: &NormalizedPermissionRequest) -> PolicyDecision {
+        let paths: Vec<String> = request_paths(request)
+            .iter()
+            .map(|path| self.normalize_path(path))
+            .collect();
+        let commands = request.command.as_deref().map(command_parts);
+        for (index, compiled) in self.rules.iter().enumerate() {
+            let Some(fields) = compiled.matches(request, commands.as_ref(), &paths) else {
+                continue;
+            };
+            let rule = &compiled.rule;
+            let label = match rule.name {
+                Some(ref name) => format!("rule {} \"{}\"", index + 1, name),
+                None => format!("rule {}", index + 1),
+            };
+            let reason = if fields.is_empty() {
+                "matches every request".to_string()
+            } else {
+                fields.join(", ")
+            };
+            return PolicyDecision {
+                decision: rule.decision.clone(),
+                rule: Some(index),
+                message: rule.message.clone(),
+                explanation: format!("{} ({}) -> {}", label, reason, rule.decision),
+            };
+        }
+        PolicyDecision {
+            decision: self.default.clone(),
+            rule: None,
+            message: None,
+            explanation: format!("no rule matched -> default {}", self.default),
+        }
+    }
+
+    /// A request path with `.` and `..` applied, resolved against the
+    /// working directory when the policy has one
+    fn normalize_path(&self, path: &str) -> String {
+        if let Some(ref directory) = self.working_directory {
+            return resolve(&directory.join(path))
+                .to_string_lossy()
+                .into_owned();
+        }
+        let mut normalized = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir
+                    if matches!(
+                        normalized.components().next_back(),
+                        Some(Component::Normal(_))
+                    ) =>
+                {
+                    normalized.pop();
+                }
+                Component::ParentDir if normalized.has_root() => {}
+                _ => normalized.push(component),
+            }
+        }
+        normalized.to_string_lossy().into_owned()
+    }
+
+    /// Decision callback evaluating this policy, for
+    /// [`crate::AgentOptions::on_permission_request`] or a
+    /// [`crate::PermissionRelay`] (`|request| handler.decide(request)`)
+    ///
+    /// # Arguments
+    /// * `ask_human` - Decides requests the policy routes to `ask-human`;
+    ///   they are rejected when it is `None`
+    /// * `explain` - Called with every evaluation, e.g. to log which rule fired
+    ///
+    /// # Returns
+    /// Handler returning the matched decision, with the rule's message and
+    /// the explanation as its source (`human` for a person's answer)
+    pub fn handler<E>(self, ask_human: Option<PermissionHandler>, explain: E) -> PermissionHandler
+    where
+        E: Fn(&NormalizedPermissionRequest, &PolicyDecision) + Send + Sync + 'static,
+    {
+        PermissionHandler::cancellable(move |request, cancellation| {
+            let decision = self.evaluate(request);
+            explain(request, &decision);
+            let source = format!("policy: {}", decision.explanation);
+            if decision.decision != "ask-human" {
+                return PermissionDecision {
+                    message: decision.message,
+                    ..PermissionDecision::new(&decision.decision)
+                }
+                .with_source(&source);
+            }
+            match ask_human {
+                Some(ref human) => {
+                    let answer = human.decide_cancellable(request, cancellation);
+                    let source = answer.source.clone();
+                    answer.with_source(source.as_deref().unwrap_or("human"))
+                }
+                None => PermissionDecision::new("reject").with_source(&source),
+            }
+        })
+    }
+}
+
+/// The simple commands of a shell command, each as its words and
+/// redirections joined by spaces, and whether it substitutes the output of
+/// another command
+#[derive(Debug)]
+struct SimpleCommands {
+    parts: Vec<String>,
+    substitutes: bool,
+}
+
+fn command_parts(command: &str) -> SimpleCommands {
+    SimpleCommands {
+        parts: simple_commands(command)
+            .into_iter()
+            .map(|simple| {
+                let mut words: Vec<String> =
+                    simple.words.into_iter().map(|word| word.text).collect();
+                for (operator, target) in simple.redirects {
+                    words.push(operator);
+                    words.push(target.text);
+                }
+                words.join(" ")
+            })
+            .collect(),
+        substitutes: ["$(", "`", "<(", ">("]
+            .iter()
+            .any(|substitution| command.contains(substitution)),
+    }
+}
+
+impl CompiledRule {
+    /// Fields whose matchers matched, or `None` if any did not
+    ///
+    /// A rule allowing the request needs every value to match; any value
+    /// matching, or a `command` glob matching the whole command, is enough
+    /// for the others.
+    fn matches(
+        &self,
+        request: &NormalizedPermissionRequest,
+        commands: Option<&SimpleCommands>,
+        paths: &[String],
+    ) -> Option<Vec<&'static str>> {
+        let allows = matches!(self.rule.decision.as_str(), "once" | "always");
+        let command_glob = self
+            .command
+            .as_ref()
+            .is_some_and(|matcher| matcher.field == "command");
+        let command_values: Vec<&str> = match commands {
+            Some(commands) if command_glob => {
+                if allows && commands.substitutes {
+                    return None;
+                }
+                let mut values: Vec<&str> = commands.parts.iter().map(String::as_str).collect();
+                if !allows {
+                    values.extend(request.command.as_deref());
+                }
+                values
+            }
+            _ => request.command.as_deref().into_iter().collect(),
+        };
+        let checks = [
+            (
+                &self.tool_name,
+                request.tool_name.as_deref().into_iter().collect::<Vec<_>>(),
+            ),
+            (&self.command, command_values),
+            (
+                &self.pattern,
+                request.pattern.as_deref().into_iter().collect(),
+            ),
+            (&self.path, paths.iter().map(String::as_str).collect()),
+        ];
+        let mut fields = Vec::new();
+        for (matcher, values) in checks {
+            let Some(matcher) = matcher else {
+                continue;
+            };
+            let hit = if allows {
+                !values.is_empty() && values.iter().all(|value| matcher.regex.is_match(value))
+            } else {
+                values.iter().any(|value| matcher.regex.is_match(value))
+            };
+            if !hit {
+                return None;
+            }
+            fields.push(matcher.field);
+        }
+        if let Some(lowest) = self.risk {
+            if !request
+                .risk
+                .as_ref()
+                .is_some_and(|risk| risk.rank() >= lowest)
+            {
+                return None;
+            }
+            fields.push("risk");
+        }
+        Some(fields)
+    }
+}
+
+fn check_decision(decision: &str, context: &str) -> Result<(), String> {
+    if POLICY_DECISIONS.contains(&decision) {
+        return Ok(());
+    }
+    Err(format!(
+        "Invalid {} decision \"{}\". Expected one of: {}.",
+        context,
+        decision,
+        POLICY_DECISIONS.join(", ")
+    ))
+}
+
+fn compile_rule(number: usize, rule: PermissionRule) -> Result<CompiledRule, String> {
+    let context = format!("permission policy rule {}", number);
+    check_decision(&rule.decision, &context)?;
+    let compile = |glob: &Option<String>,
+                   regex: &Option<String>,
+                   fields: (&'static str, &'static str)| {
+        match (glob, regex) {
+            (Some(_), Some(_)) => Err(format!(
+                "{}: set only one of {} and {}",
+                context, fields.0, fields.1
+            )),
+            (Some(glob), None) => Ok(Some(Matcher {
+                field: fields.0,
+                regex: glob_to_regex(glob),
+            })),
+            (None, Some(regex)) => Regex::new(regex)
+                .map(|regex| {
+                    Some(Matcher {
+                        field: fields.1,
+                        regex,
+                    })
+                })
+                .map_err(|e| format!("{}: invalid {}: {}", context, fields.1, e)),
+            (None, None) => Ok(None),
+        }
+    };
+    Ok(CompiledRule {
+        tool_name: compile(&rule.tool_name, &None, ("tool_name", "tool_name"))?,
+        command: compile(
+            &rule.command,
+            &rule.command_regex,
+            ("command", "command_regex"),
+        )?,
+        pattern: compile(
+            &rule.pattern,
+            &rule.pattern_regex,
+            ("pattern", "pattern_regex"),
+        )?,
+        path: compile(&rule.path, &rule.path_regex, ("path", "path_regex"))?,
+        risk: rule
+            .risk
+            .as_deref()
+            .map(|level| {
+                risk_rank(level).ok_or_else(|| {
+                    format!(
+                        "{}: invalid risk \"{}\". Expected one of: {}.",
+                        context,
+                        level,
+                        RISK_LEVELS.join(", ")
+                    )
+                })
+            })
+            .transpose()?,
+        rule,
+    })
+}
+
+/// Translate a `*`/`?` glob into an anchored regex
+fn glob_to_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    // Only escaped literals and `.`/`.*` remain, which always compile
+    Regex::new(&pattern).expect("escaped glob")
+}
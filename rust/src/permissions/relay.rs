@@ -0,0 +1,326 @@
+//! Synchronous relay between a tool's permission requests and a decision
+//! callback
+//!
+//! [`PermissionRelay`] is transport-agnostic and answers each request before
+//! returning; see [`super::AsyncPermissionRelay`] for decisions that take a
+//! while, and for the relay that drives a running agent.
+
+use super::session::SessionApprovals;
+use super::{
+    build_decision_response, normalize_permission_request, NormalizedPermissionRequest,
+    PermissionAudit, PermissionDecision, WorkspaceGuard,
+};
+use crate::streaming::stringify_ndjson_line;
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Decision callback for the permission requests of a running agent
+///
+/// Wraps a shareable closure so it can live in [`crate::AgentOptions`]. The
+/// closure returns `once`, `always` or `reject` (or a full
+/// [`PermissionDecision`]) and may block, e.g. while a
+/// human decides: a running agent calls it on a blocking thread, one request
+/// at a time. A blocking call cannot be interrupted, so a handler built with
+/// [`Self::cancellable`] is told when the relay gave up on the request and
+/// should return promptly.
+#[derive(Clone)]
+pub struct PermissionHandler(
+    Arc<
+        dyn Fn(&NormalizedPermissionRequest, &PermissionCancellation) -> PermissionDecision
+            + Send
+            + Sync,
+    >,
+);
+
+impl PermissionHandler {
+    /// Wrap a decision closure
+    pub fn new<F, D>(decide: F) -> Self
+    where
+        F: Fn(&NormalizedPermissionRequest) -> D + Send + Sync + 'static,
+        D: Into<PermissionDecision>,
+    {
+        Self(Arc::new(move |request, _| decide(request).into()))
+    }
+
+    /// Wrap a decision closure that stops waiting once the request is
+    /// cancelled, e.g. because the relay's timeout already rejected it
+    pub fn cancellable<F, D>(decide: F) -> Self
+    where
+        F: Fn(&NormalizedPermissionRequest, &PermissionCancellation) -> D + Send + Sync + 'static,
+        D: Into<PermissionDecision>,
+    {
+        Self(Arc::new(move |request, cancellation| {
+            decide(request, cancellation).into()
+        }))
+    }
+
+    /// Ask for the decision on a request
+    pub fn decide(&self, request: &NormalizedPermissionRequest) -> PermissionDecision {
+        (self.0)(request, &PermissionCancellation::default())
+    }
+
+    /// Ask for the decision on a request the caller may give up on
+    pub fn decide_cancellable(
+        &self,
+        request: &NormalizedPermissionRequest,
+        cancellation: &PermissionCancellation,
+    ) -> PermissionDecision {
+        (self.0)(request, cancellation)
+    }
+}
+
+impl fmt::Debug for PermissionHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PermissionHandler(..)")
+    }
+}
+
+/// Tells a [`PermissionHandler`] that its decision is no longer wanted
+#[derive(Debug, Clone, Default)]
+pub struct PermissionCancellation(Arc<AtomicBool>);
+
+impl PermissionCancellation {
+    /// Give up on the request
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the request was given up on
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Source of decisions answered from an earlier `always` of the session
+pub(super) const REMEMBERED_SOURCE: &str = "session";
+
+/// A permission request answered during a run
+// `request`/`response` carry `serde_json::Value`, which cannot implement `Eq`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandledPermission {
+    /// The normalized request
+    pub request: NormalizedPermissionRequest,
+    /// Applied decision (`once` | `always` | `reject`)
+    pub decision: String,
+    /// Replacement tool input sent with an allow
+    pub updated_input: Option<Value>,
+    /// Rejection message sent to the agent
+    pub message: Option<String>,
+    /// Native response frame written to the tool's stdin
+    pub response: Value,
+    /// No decision arrived within the relay's timeout, so it was rejected
+    pub timed_out: bool,
+    /// Answered from an earlier `always` of the session, without asking the
+    /// consumer (claude)
+    pub remembered: bool,
+    /// Who decided: `handler`, `human`, `policy: <explanation>`,
+    /// `approve-command`, `session` (an earlier `always`), `timeout` or
+    /// `no handler`
+    pub source: String,
+}
+
+impl HandledPermission {
+    /// Describe a request answered with `decision`, as sent in `response`,
+    /// neither timed out nor remembered
+    pub(super) fn new(
+        request: NormalizedPermissionRequest,
+        decision: PermissionDecision,
+        response: Value,
+    ) -> Self {
+        Self {
+            request,
+            remembered: false,
+            timed_out: false,
+            source: decision.source.unwrap_or_else(|| "handler".to_string()),
+            decision: decision.decision,
+            updated_input: decision.updated_input,
+            message: decision.message,
+            response,
+        }
+    }
+}
+
+/// Build the first stdin frame of a stream-json session, carrying the prompt.
+///
+/// Claude expects an Anthropic-style message envelope; agent takes a plain
+/// message string with the system prompt combined in, since it has no
+/// separate system-prompt channel here.
+pub fn build_initial_input_frame(
+    tool: &str,
+    prompt: Option<&str>,
+    system_prompt: Option<&str>,
+) -> Value {
+    let prompt = prompt.unwrap_or_default();
+    if tool == "claude" {
+        return json!({
+            "type": "user",
+            "message": { "role": "user", "content": prompt },
+        });
+    }
+
+    let message = match system_prompt {
+        Some(system_prompt) => format!("{}\n\n{}", system_prompt, prompt),
+        None => prompt.to_string(),
+    };
+    json!({ "type": "user", "message": message })
+}
+
+/// Reduce a consumer's decision to what the tool can express and build the
+/// native response frame
+///
+/// Anything other than `once`, `always` or `reject` becomes `reject`, the
+/// safe choice.
+pub(super) fn respond(
+    tool: &str,
+    request: &NormalizedPermissionRequest,
+    decision: &PermissionDecision,
+) -> (PermissionDecision, Value) {
+    let decision = decision.for_tool(tool);
+    // build_decision_response only fails for unsupported tools / decisions,
+    // both of which are excluded here, so the frame is always available.
+    let frame = build_decision_response(tool, request, &decision)
+        .expect("relayable tool with validated decision");
+    (decision, frame)
+}
+
+/// Normalize a request, with a workspace-aware risk of its shell command
+/// when the working directory is known
+///
+/// The diff of a file edit is attached only once the request passed the
+/// workspace guard, so files outside the workspace are never read.
+pub(super) fn normalize_in(
+    tool: &str,
+    message: &Value,
+    working_directory: Option<&Path>,
+) -> Option<NormalizedPermissionRequest> {
+    let request = normalize_permission_request(tool, message)?;
+    Some(match working_directory {
+        Some(directory) => request.with_command_risk(directory),
+        None => request,
+    })
+}
+
+/// Relay native permission requests to a consumer and forward decisions back.
+///
+/// A `PermissionRelay` sits between a backend CLI's streaming output and a
+/// consumer: it watches parsed output messages for native permission requests,
+/// normalizes them, asks the consumer for a decision, and writes the native
+/// response frame back to the CLI's stdin as NDJSON.
+///
+/// The relay is intentionally transport-agnostic — it does not own the child
+/// process. The caller supplies a `write` closure (typically the child's stdin)
+/// and feeds it parsed messages, which keeps it fully unit-testable.
+///
+/// Claude has no session-wide `always`, so the relay remembers `always`
+/// decisions and answers later requests for the same tool and command or
+/// path itself, without calling `on_request`. With a [`WorkspaceGuard`],
+/// requests leaving the workspace are rejected before either.
+pub struct PermissionRelay<'a> {
+    tool: String,
+    on_request: Box<dyn FnMut(&NormalizedPermissionRequest) -> PermissionDecision + 'a>,
+    write: Box<dyn FnMut(&str) + 'a>,
+    compact: bool,
+    approvals: SessionApprovals,
+    audit: Option<PermissionAudit>,
+    working_directory: Option<PathBuf>,
+    guard: Option<WorkspaceGuard>,
+    handled: Vec<(NormalizedPermissionRequest, String, Value)>,
+}
+
+impl<'a> PermissionRelay<'a> {
+    /// Create a new relay.
+    ///
+    /// * `on_request` resolves a normalized request to a decision
+    ///   (`once` | `always` | `reject`, or a full [`PermissionDecision`]).
+    /// * `write` receives a serialized NDJSON frame to forward to the tool stdin.
+    pub fn new<F, W, D>(tool: &str, mut on_request: F, write: W) -> Self
+    where
+        F: FnMut(&NormalizedPermissionRequest) -> D + 'a,
+        W: FnMut(&str) + 'a,
+        D: Into<PermissionDecision>,
+    {
+        Self {
+            tool: tool.to_string(),
+            on_request: Box::new(move |request| on_request(request).into()),
+            write: Box::new(write),
+            compact: true,
+            approvals: SessionApprovals::default(),
+            audit: None,
+            working_directory: None,
+            guard: None,
+            handled: Vec::new(),
+        }
+    }
+
+    /// Record every answered request in an audit trail
+    ///
+    /// A failed write does not hold up the relay.
+    #[must_use]
+    pub fn with_audit(mut self, audit: PermissionAudit) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Attach a diff of each file edit against the files in a directory
+    /// (see [`NormalizedPermissionRequest::with_edit_diff`])
+    #[must_use]
+    pub fn with_working_directory(mut self, working_directory: impl Into<PathBuf>) -> Self {
+        self.working_directory = Some(working_directory.into());
+        self
+    }
+
+    /// Reject requests that leave the workspace before `on_request` sees
+    /// them; the reason is the decision's source
+    #[must_use]
+    pub fn with_workspace_guard(mut self, guard: WorkspaceGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Process a single parsed output message. When the message is a permission
+    /// request, resolves the consumer's decision and writes the native response.
+    /// Returns the normalized request and the applied decision, or `None` when the
+    /// message is not a permission request.
+    pub fn handle_message(
+        &mut self,
+        message: &Value,
+    ) -> Option<(NormalizedPermissionRequest, String)> {
+        let request = normalize_in(&self.tool, message, self.working_directory.as_deref())?;
+
+        let rejected = self.guard.as_ref().and_then(|guard| guard.review(&request));
+        let request = match self.working_directory {
+            Some(ref directory) if rejected.is_none() => request.with_edit_diff(directory),
+            _ => request,
+        };
+        let remembered = rejected.is_none() && self.approvals.covers(&request);
+        let decision = if let Some(rejection) = rejected {
+            rejection
+        } else if remembered {
+            PermissionDecision::new("always").with_source(REMEMBERED_SOURCE)
+        } else {
+            (self.on_request)(&request)
+        };
+        let (decision, frame) = respond(&self.tool, &request, &decision);
+        self.approvals.remember(&request, &decision);
+        (self.write)(&stringify_ndjson_line(&frame, self.compact));
+        if let Some(ref audit) = self.audit {
+            let _ = audit.record(&HandledPermission {
+                remembered,
+                ..HandledPermission::new(request.clone(), decision.clone(), frame.clone())
+            });
+        }
+
+        self.handled
+            .push((request.clone(), decision.decision.clone(), frame));
+        Some((request, decision.decision))
+    }
+
+    /// All permission requests handled so far (for inspection/testing).
+    pub fn get_handled(&self) -> &[(NormalizedPermissionRequest, String, Value)] {
+        &self.handled
+    }
+}
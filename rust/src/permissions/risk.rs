@@ -0,0 +1,377 @@
+//! Risk classification of shell commands in permission requests
+//!
+//! Every normalized bash request carries a [`CommandRisk`]: `low`, `medium`
+//! or `high`, with one reason per dangerous pattern found. Policies can
+//! match on it (`risk = "high"`) and approvers see it next to the command.
+//! The classifier reads the command the way a reviewer skimming it would;
+//! it is a warning sign, not a sandbox.
+
+use super::workspace::{resolve, simple_commands, SimpleCommand, Word};
+use super::NormalizedPermissionRequest;
+use regex::Regex;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Risk levels, lowest first
+pub const RISK_LEVELS: &[&str] = &["low", "medium", "high"];
+
+/// Ranks of the levels findings are reported at
+const MEDIUM: usize = 1;
+const HIGH: usize = 2;
+
+/// Files and directories holding credentials, as they appear in commands
+const CREDENTIAL_PATHS: &[&str] = &[
+    ".ssh/",
+    ".aws/credentials",
+    ".aws/config",
+    ".netrc",
+    ".npmrc",
+    ".pypirc",
+    ".git-credentials",
+    ".docker/config.json",
+    ".kube/config",
+    ".gnupg/",
+    ".config/gh/hosts.yml",
+    ".cargo/credentials",
+    "/etc/shadow",
+    "/etc/sudoers",
+];
+
+/// Commands that publish a package or image, as leading words
+const PUBLISH_COMMANDS: &[&[&str]] = &[
+    &["npm", "publish"],
+    &["yarn", "publish"],
+    &["yarn", "npm", "publish"],
+    &["pnpm", "publish"],
+    &["bun", "publish"],
+    &["cargo", "publish"],
+    &["twine", "upload"],
+    &["poetry", "publish"],
+    &["gem", "push"],
+    &["docker", "push"],
+    &["dotnet", "nuget", "push"],
+];
+
+/// Wrappers run before the actual command, each with its options that take
+/// a separate value (space-separated) and the number of operands it reads
+/// before the command
+const WRAPPERS: &[(&str, &str, usize)] = &[
+    (
+        "sudo",
+        "-u -g -h -p -C -D -R -T -U -r -t --user --group --host --prompt --close-from \
+         --chdir --chroot --command-timeout --other-user --role --type",
+        0,
+    ),
+    ("env", "-u -C --unset --chdir", 0),
+    ("command", "", 0),
+    ("exec", "-a", 0),
+    ("nohup", "", 0),
+    ("time", "-f -o --format --output", 0),
+    (
+        "xargs",
+        "-a -d -E -I -L -n -P -s --arg-file --delimiter --max-args --max-procs --max-chars \
+         --max-lines --process-slot-var",
+        0,
+    ),
+    ("nice", "-n --adjustment", 0),
+    ("timeout", "-s -k --signal --kill-after", 1),
+];
+
+/// How dangerous a shell command looks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandRisk {
+    /// `low` | `medium` | `high`
+    pub level: String,
+    /// One entry per dangerous pattern found, e.g. `git push --force
+    /// rewrites remote history`; empty for `low`
+    pub reasons: Vec<String>,
+}
+
+impl CommandRisk {
+    /// Position of the level in [`RISK_LEVELS`]
+    pub fn rank(&self) -> usize {
+        risk_rank(&self.level).unwrap_or_default()
+    }
+}
+
+/// Position of a level in [`RISK_LEVELS`], or `None` for an unknown level
+pub fn risk_rank(level: &str) -> Option<usize> {
+    RISK_LEVELS.iter().position(|known| *known == level)
+}
+
+/// Whether a request runs a shell command: `bash`, or codex's
+/// `commandExecution`
+pub(super) fn is_shell_request(tool_name: Option<&str>) -> bool {
+    tool_name.is_some_and(|name| name.eq_ignore_ascii_case("bash") || name == "commandExecution")
+}
+
+impl NormalizedPermissionRequest {
+    /// Re-classify a shell command knowing the workspace it runs in, so
+    /// deletes are judged by whether they stay inside it
+    ///
+    /// # Arguments
+    /// * `working_directory` - Directory the command runs in
+    ///
+    /// # Returns
+    /// The request with [`Self::risk`] updated; other requests unchanged
+    #[must_use]
+    pub fn with_command_risk(mut self, working_directory: &Path) -> Self {
+        if self.risk.is_some() {
+            let command = self.command.as_deref().unwrap_or_default();
+            self.risk = Some(classify_command(command, Some(working_directory)));
+        }
+        self
+    }
+}
+
+/// Classify a shell command
+///
+/// Flags recursive deletes (`high` when a target is outside the workspace,
+/// the workspace itself, or not known before the command runs), downloads
+/// piped into a shell, force pushes, world-writable permissions, credential
+/// file reads, package publishing, `sudo`, and commands discarding local git
+/// changes.
+///
+/// # Arguments
+/// * `command` - Shell command line
+/// * `working_directory` - Directory it runs in; without one, absolute
+///   delete targets count as outside the workspace
+///
+/// # Returns
+/// The highest level found, with every reason
+pub fn classify_command(command: &str, working_directory: Option<&Path>) -> CommandRisk {
+    let mut findings: Vec<(usize, String)> = Vec::new();
+    if piped_download().is_match(command) {
+        findings.push((HIGH, "runs a downloaded script in a shell".to_string()));
+    }
+    for simple in simple_commands(command) {
+        classify_simple(&simple, working_directory, &mut findings);
+    }
+
+    let rank = findings.iter().map(|(rank, _)| *rank).max().unwrap_or(0);
+    let mut reasons = Vec::new();
+    for (_, reason) in findings {
+        if !reasons.contains(&reason) {
+            reasons.push(reason);
+        }
+    }
+    CommandRisk {
+        level: RISK_LEVELS[rank].to_string(),
+        reasons,
+    }
+}
+
+/// `curl ... | sh`, `bash <(wget ...)`, `sh -c "$(curl ...)"`
+fn piped_download() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r#"\b(curl|wget)\b[^;&|]*\|\s*(sudo\s+)?(\S*/)?(ba|z|da|k|fi)?sh\b|\b(ba|z|da|k)?sh\s+(-c\s+)?["']?(<|\$)\(\s*(curl|wget)\b"#,
+        )
+        .expect("valid regex")
+    })
+}
+
+fn classify_simple(
+    simple: &SimpleCommand,
+    working_directory: Option<&Path>,
+    findings: &mut Vec<(usize, String)>,
+) {
+    // Skip variable assignments and wrappers such as `sudo` or `env`, with
+    // their options, option values and operands
+    let texts: Vec<&str> = simple.words.iter().map(|word| word.text.as_str()).collect();
+    let mut skipped = 0;
+    let mut value_options = "";
+    let mut operands = 0;
+    let mut in_options = false;
+    while let Some(&first) = texts.get(skipped) {
+        skipped += 1;
+        if in_options && first == "--" {
+            in_options = false;
+        } else if in_options && first.starts_with('-') {
+            if value_options
+                .split_whitespace()
+                .any(|option| option == first)
+            {
+                skipped += 1;
+            }
+        } else if operands > 0 {
+            operands -= 1;
+            in_options = false;
+        } else if let Some(&(name, options, wrapper_operands)) =
+            WRAPPERS.iter().find(|(name, ..)| *name == first)
+        {
+            if name == "sudo" {
+                findings.push((MEDIUM, "runs as root (sudo)".to_string()));
+            }
+            value_options = options;
+            operands = wrapper_operands;
+            in_options = true;
+        } else if !(first.contains('=') && !first.starts_with('-')) {
+            skipped -= 1;
+            break;
+        }
+    }
+    let skipped = skipped.min(texts.len());
+    let words = &texts[skipped..];
+    let arguments = &simple.words[skipped..];
+
+    // Here-documents and here-strings carry text, not paths
+    let targets = simple
+        .redirects
+        .iter()
+        .filter(|(operator, _)| !operator.starts_with("<<"))
+        .map(|(_, word)| word);
+    for text in simple
+        .words
+        .iter()
+        .chain(targets)
+        .map(|word| word.text.as_str())
+    {
+        if names_credentials(text) {
+            findings.push((HIGH, format!("reads credentials ({})", text)));
+        } else if names_env_file(text) {
+            findings.push((MEDIUM, format!("reads environment secrets ({})", text)));
+        }
+    }
+
+    match words {
+        ["rm", ..] => classify_rm(&arguments[1..], working_directory, findings),
+        ["git", "push", rest @ ..] => {
+            if rest.iter().any(|arg| arg.starts_with("--force-with-lease")) {
+                findings.push((
+                    MEDIUM,
+                    "git push --force-with-lease rewrites remote history".to_string(),
+                ));
+            } else if rest.iter().any(|arg| {
+                *arg == "--force" || *arg == "-f" || (arg.len() > 1 && arg.starts_with('+'))
+            }) {
+                findings.push((HIGH, "git push --force rewrites remote history".to_string()));
+            }
+        }
+        ["git", "reset", rest @ ..] if rest.contains(&"--hard") => {
+            findings.push((
+                MEDIUM,
+                "git reset --hard discards local changes".to_string(),
+            ));
+        }
+        ["git", "clean", rest @ ..]
+            if rest
+                .iter()
+                .any(|arg| arg.starts_with('-') && arg.contains('f')) =>
+        {
+            findings.push((MEDIUM, "git clean deletes untracked files".to_string()));
+        }
+        ["chmod", rest @ ..] if rest.iter().any(|mode| world_writable(mode)) => {
+            findings.push((MEDIUM, "chmod makes files writable by everyone".to_string()));
+        }
+        _ => {}
+    }
+
+    for publish in PUBLISH_COMMANDS {
+        if words.starts_with(publish) {
+            findings.push((HIGH, format!("publishes a package ({})", publish.join(" "))));
+        }
+    }
+}
+
+/// Whether a path names a credential file or an environment file, whose
+/// contents must not be copied into a request
+pub(super) fn is_secret_path(path: &str) -> bool {
+    names_credentials(path) || names_env_file(path)
+}
+
+fn names_credentials(text: &str) -> bool {
+    CREDENTIAL_PATHS.iter().any(|path| text.contains(path))
+}
+
+fn names_env_file(text: &str) -> bool {
+    let name = text.rsplit('/').next().unwrap_or(text);
+    name == ".env" || name.starts_with(".env.")
+}
+
+/// Whether a chmod mode (`777`, `o+w`, `a=rwx`) lets everyone write
+fn world_writable(mode: &str) -> bool {
+    if (3..=4).contains(&mode.len()) && mode.chars().all(|c| c.is_digit(8)) {
+        return mode
+            .chars()
+            .last()
+            .and_then(|c| c.to_digit(8))
+            .is_some_and(|others| others & 2 != 0);
+    }
+    mode.split(',').any(|clause| {
+        let Some(operator) = clause.find(['+', '=']) else {
+            return false;
+        };
+        let (who, permissions) = clause.split_at(operator);
+        who.chars().all(|c| "ugoa".contains(c))
+            && (who.is_empty() || who.contains(['o', 'a']))
+            && permissions.contains('w')
+    })
+}
+
+/// Flag recursive deletes, judged by where their targets are
+fn classify_rm(
+    arguments: &[Word],
+    working_directory: Option<&Path>,
+    findings: &mut Vec<(usize, String)>,
+) {
+    let mut recursive = false;
+    let mut options_done = false;
+    let mut targets = Vec::new();
+    for word in arguments {
+        let text = word.text.as_str();
+        if options_done || !text.starts_with('-') || text == "-" {
+            targets.push(word);
+        } else if text == "--" {
+            options_done = true;
+        } else if text.starts_with("--") {
+            recursive |= text == "--recursive";
+        } else {
+            recursive |= text.contains(['r', 'R']);
+        }
+    }
+    if !recursive {
+        return;
+    }
+
+    let outside: Vec<&str> = targets
+        .iter()
+        .filter(|word| leaves_workspace(word, working_directory))
+        .map(|word| word.text.as_str())
+        .collect();
+    if outside.is_empty() {
+        let shown: Vec<&str> = targets.iter().map(|word| word.text.as_str()).collect();
+        findings.push((MEDIUM, format!("deletes recursively ({})", shown.join(" "))));
+    } else {
+        findings.push((
+            HIGH,
+            format!(
+                "deletes recursively outside the workspace ({})",
+                outside.join(" ")
+            ),
+        ));
+    }
+}
+
+/// Whether a delete target is outside the workspace, is the workspace
+/// itself, or cannot be known before the command runs
+fn leaves_workspace(word: &Word, working_directory: Option<&Path>) -> bool {
+    if word.dynamic || word.tilde {
+        return true;
+    }
+    let text = word.text.as_str();
+    if let Some(directory) = working_directory {
+        let root = resolve(directory);
+        let target = resolve(&directory.join(text));
+        return target == root || !target.starts_with(&root);
+    }
+    let path = Path::new(text);
+    path.is_absolute()
+        || path
+            .components()
+            .next()
+            .is_some_and(|component| component.as_os_str() == "..")
+        || matches!(text, "." | "./")
+}
@@ -0,0 +1,66 @@
+//! Session-wide `always` for tools without one
+//!
+//! Agent remembers an `always` decision itself and stops asking for the
+//! rest of the session. Claude's `can_use_tool` handshake only allows the
+//! call at hand, so the relays remember `always` decisions here and answer
+//! matching requests locally, giving both tools the same `session` scope.
+
+use super::{NormalizedPermissionRequest, PermissionDecision};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Input keys naming what a Claude tool call acts on, most specific first
+const TARGET_KEYS: &[&str] = &["command", "file_path", "notebook_path", "path", "url"];
+
+/// Whether the relay has to emulate a session-wide `always` for a tool
+pub(super) fn emulates_always(tool: &str) -> bool {
+    tool == "claude"
+}
+
+/// `always` decisions made so far in a session
+#[derive(Debug, Default)]
+pub(super) struct SessionApprovals {
+    approved: HashSet<(String, String)>,
+}
+
+impl SessionApprovals {
+    /// Whether an earlier `always` covers the request
+    pub(super) fn covers(&self, request: &NormalizedPermissionRequest) -> bool {
+        emulates_always(&request.tool) && self.approved.contains(&session_key(request))
+    }
+
+    /// Remember the decision sent for a request, if it was a plain `always`
+    ///
+    /// An `always` with a replacement input is not remembered: later calls
+    /// would run their own input, which the consumer never saw.
+    pub(super) fn remember(
+        &mut self,
+        request: &NormalizedPermissionRequest,
+        decision: &PermissionDecision,
+    ) {
+        if emulates_always(&request.tool)
+            && decision.decision == "always"
+            && decision.updated_input.is_none()
+        {
+            self.approved.insert(session_key(request));
+        }
+    }
+}
+
+/// Tool name plus the whitespace-normalized command or path the call acts
+/// on; calls with neither are keyed on their whole input
+fn session_key(request: &NormalizedPermissionRequest) -> (String, String) {
+    let input = request.input.as_ref();
+    let target = input
+        .and_then(|input| {
+            TARGET_KEYS
+                .iter()
+                .find_map(|key| input.get(*key).and_then(Value::as_str))
+        })
+        .or(request.pattern.as_deref())
+        .map_or_else(
+            || input.map(Value::to_string).unwrap_or_default(),
+            |target| target.split_whitespace().collect::<Vec<_>>().join(" "),
+        );
+    (request.tool_name.clone().unwrap_or_default(), target)
+}
@@ -0,0 +1,408 @@
+//! Tool-agnostic allow/deny policies compiled to each tool's static form
+//!
+//! Tools without a relayable ask protocol can still be restricted up front.
+//! A [`StaticPermissionPolicy`] states what is allowed per category
+//! (`bash`, `edit`, `read`, `webfetch`, `task`), optionally narrowed to a
+//! command or path pattern, and [`StaticPermissionPolicy::compile`] turns it
+//! into the tool's native arguments and environment:
+//!
+//! - `opencode`: an `OPENCODE_PERMISSION` JSON policy
+//! - `agent`: the same JSON policy, passed with `--permission`
+//! - `claude`: `--allowedTools`/`--disallowedTools` rules, run without
+//!   `--dangerously-skip-permissions` when anything is denied
+//! - `gemini`, `qwen`: the approval mode closest to the policy
+//!
+//! Whatever the tool cannot express is listed in
+//! [`NativePermissionPolicy::unsupported`] instead of being dropped.
+
+use crate::AgentOptions;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// Categories a static rule can cover
+pub const STATIC_PERMISSION_CATEGORIES: &[&str] = &["bash", "edit", "read", "webfetch", "task"];
+
+/// Tools a static policy compiles for
+pub const STATIC_POLICY_TOOLS: &[&str] = &["opencode", "agent", "claude", "gemini", "qwen"];
+
+/// One rule of a [`StaticPermissionPolicy`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticPermissionRule {
+    /// `bash` | `edit` | `read` | `webfetch` | `task`
+    pub category: String,
+    /// Command glob (`bash`) or path glob (`edit`, `read`); without one the
+    /// rule covers the whole category
+    pub pattern: Option<String>,
+    /// `allow` | `deny`
+    pub action: String,
+}
+
+/// Allow/deny rules with a default for everything they do not cover
+///
+/// A rule with a pattern takes precedence over its category's rule, which
+/// takes precedence over the default.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticPermissionPolicy {
+    /// `allow` | `deny`
+    #[serde(default = "default_action")]
+    pub default: String,
+    #[serde(default)]
+    pub rules: Vec<StaticPermissionRule>,
+}
+
+fn default_action() -> String {
+    "allow".to_string()
+}
+
+/// A static policy in a tool's native form
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NativePermissionPolicy {
+    /// Tool the policy was compiled for
+    pub tool: String,
+    /// Arguments to append to the tool's command
+    pub args: Vec<String>,
+    /// JSON policy for the tool's own `--permission` option (`agent`)
+    pub permission: Option<String>,
+    /// Environment variables to set for the tool
+    pub env: Vec<(String, String)>,
+    /// The default autonomy flags (`--dangerously-skip-permissions`,
+    /// `--yolo`) must be left out for the policy to hold
+    pub skip_default_safety_flags: bool,
+    /// Parts of the policy the tool cannot express, one description each
+    pub unsupported: Vec<String>,
+}
+
+impl NativePermissionPolicy {
+    /// Add the policy to an agent's options
+    ///
+    /// The policy's arguments and environment would override the tool's
+    /// read-only settings (the later `--approval-mode` or
+    /// `OPENCODE_PERMISSION` wins), so it is not combined with `read_only`
+    /// or `plan_only`.
+    ///
+    /// # Arguments
+    /// * `options` - Options of an agent running [`Self::tool`]
+    ///
+    /// # Returns
+    /// Ok, or why the policy cannot be added
+    pub fn apply(&self, options: &mut AgentOptions) -> Result<(), String> {
+        if options.tool != self.tool {
+            return Err(format!(
+                "Permission policy compiled for \"{}\" cannot be applied to \"{}\"",
+                self.tool, options.tool
+            ));
+        }
+        if options.read_only || options.plan_only {
+            return Err(
+                "A static permission policy cannot be combined with read_only or plan_only"
+                    .to_string(),
+            );
+        }
+        if self.permission.is_some() {
+            if options.permission.is_some() {
+                return Err("The agent already has a permission policy".to_string());
+            }
+            options.permission.clone_from(&self.permission);
+        }
+        options.extra_args.extend(self.args.iter().cloned());
+        options.extra_env.extend(self.env.iter().cloned());
+        options.skip_default_safety_flags |= self.skip_default_safety_flags;
+        Ok(())
+    }
+}
+
+impl StaticPermissionPolicy {
+    /// Build a policy from rules
+    ///
+    /// # Arguments
+    /// * `rules` - Rules; at most one per category and pattern
+    /// * `default` - Action for everything no rule covers
+    ///
+    /// # Returns
+    /// The policy, or the first invalid category, action or duplicate rule
+    pub fn new(rules: Vec<StaticPermissionRule>, default: &str) -> Result<Self, String> {
+        let policy = Self {
+            default: default.to_string(),
+            rules,
+        };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Parse a JSON policy (`{"default": .., "rules": [..]}`)
+    pub fn from_json_str(source: &str) -> Result<Self, String> {
+        let policy: Self = serde_json::from_str(source)
+            .map_err(|e| format!("Invalid static permission policy: {}", e))?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Parse a TOML policy (`default = ..` and `[[rules]]` tables)
+    pub fn from_toml_str(source: &str) -> Result<Self, String> {
+        let policy: Self = toml::from_str(source)
+            .map_err(|e| format!("Invalid static permission policy: {}", e))?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Check every action and category, and that no rule repeats another
+    pub fn validate(&self) -> Result<(), String> {
+        check_action(&self.default, "default")?;
+        for (index, rule) in self.rules.iter().enumerate() {
+            let context = format!("static permission rule {}", index + 1);
+            check_action(&rule.action, &context)?;
+            if !STATIC_PERMISSION_CATEGORIES.contains(&rule.category.as_str()) {
+                return Err(format!(
+                    "Invalid {} category \"{}\". Expected one of: {}.",
+                    context,
+                    rule.category,
+                    STATIC_PERMISSION_CATEGORIES.join(", ")
+                ));
+            }
+            let repeats = self.rules[..index].iter().any(|earlier| {
+                earlier.category == rule.category && earlier.pattern == rule.pattern
+            });
+            if repeats {
+                return Err(format!("{} repeats an earlier rule", context));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile the policy into a tool's native static form
+    ///
+    /// # Arguments
+    /// * `tool` - One of [`STATIC_POLICY_TOOLS`]
+    ///
+    /// # Returns
+    /// Native arguments and environment with the unsupported parts listed,
+    /// or an error for an invalid policy or a tool without static policies
+    pub fn compile(&self, tool: &str) -> Result<NativePermissionPolicy, String> {
+        self.validate()?;
+        let mut native = NativePermissionPolicy {
+            tool: tool.to_string(),
+            ..Default::default()
+        };
+        match tool {
+            "opencode" => {
+                let policy = self.opencode_policy(&mut native.unsupported);
+                native.env.push(("OPENCODE_PERMISSION".to_string(), policy));
+            }
+            "agent" => {
+                native.permission = Some(self.opencode_policy(&mut native.unsupported));
+            }
+            "claude" => self.compile_claude(&mut native),
+            "gemini" | "qwen" => self.compile_approval_mode(&mut native),
+            _ => {
+                return Err(format!(
+                    "Tool \"{}\" has no static permission policy. Choose one of: {}.",
+                    tool,
+                    STATIC_POLICY_TOOLS.join(", ")
+                ))
+            }
+        }
+        Ok(native)
+    }
+
+    /// Action of a category's own rule, or the default
+    fn category_action(&self, category: &str) -> &str {
+        self.rules
+            .iter()
+            .find(|rule| rule.category == category && rule.pattern.is_none())
+            .map_or(&self.default, |rule| &rule.action)
+    }
+
+    /// Rules with a pattern in a category, in order
+    fn pattern_rules<'a>(
+        &'a self,
+        category: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.category == category)
+            .filter_map(|rule| Some((rule.pattern.as_deref()?, rule.action.as_str())))
+    }
+
+    /// OpenCode's `{edit, bash, webfetch, task}` policy; only `bash` takes
+    /// patterns, and reads are always allowed
+    fn opencode_policy(&self, unsupported: &mut Vec<String>) -> String {
+        let mut policy = Map::new();
+        for category in ["edit", "bash", "webfetch", "task"] {
+            let action = self.category_action(category);
+            let patterns: Vec<_> = self.pattern_rules(category).collect();
+            if category == "bash" && !patterns.is_empty() {
+                let mut commands = Map::new();
+                for (pattern, pattern_action) in patterns {
+                    commands.insert(pattern.to_string(), json!(pattern_action));
+                }
+                commands.insert("*".to_string(), json!(action));
+                policy.insert(category.to_string(), Value::Object(commands));
+                continue;
+            }
+            for (pattern, _) in patterns {
+                unsupported.push(format!(
+                    "{} pattern \"{}\": only bash rules take patterns",
+                    category, pattern
+                ));
+            }
+            policy.insert(category.to_string(), json!(action));
+        }
+        self.report_read_denials(unsupported);
+        Value::Object(policy).to_string()
+    }
+
+    /// Claude allow/deny rules; deny rules win over allow rules in Claude,
+    /// so a denied category is left out of `--allowedTools` instead, which
+    /// headless Claude refuses unless bypassing permissions
+    fn compile_claude(&self, native: &mut NativePermissionPolicy) {
+        let mut allowed = Vec::new();
+        let mut disallowed = Vec::new();
+        let restricted = self.default == "deny"
+            || ["bash", "edit", "webfetch", "task"]
+                .iter()
+                .any(|category| self.category_action(category) == "deny");
+
+        for category in STATIC_PERMISSION_CATEGORIES {
+            let tool_name = claude_tool_name(category);
+            let action = self.category_action(category);
+            if *category == "read" {
+                // Claude reads without asking, so only a rule can deny them
+                if action == "deny" {
+                    disallowed.push(tool_name.to_string());
+                }
+            } else if restricted && action == "allow" {
+                allowed.push(tool_name.to_string());
+            }
+            for (pattern, pattern_action) in self.pattern_rules(category) {
+                if pattern_action == action {
+                    continue;
+                }
+                let Some(rule) = claude_rule(category, pattern) else {
+                    native.unsupported.push(format!(
+                        "{} pattern \"{}\": Claude rules take a command with an optional trailing * or an absolute path",
+                        category, pattern
+                    ));
+                    continue;
+                };
+                if pattern_action == "deny" {
+                    disallowed.push(rule);
+                } else if *category == "read" {
+                    native.unsupported.push(format!(
+                        "read pattern \"{}\": Claude deny rules win, so reads cannot be allowed once Read is denied",
+                        pattern
+                    ));
+                } else {
+                    allowed.push(rule);
+                }
+            }
+        }
+
+        for (flag, rules) in [
+            ("--allowedTools", allowed),
+            ("--disallowedTools", disallowed),
+        ] {
+            for rule in rules {
+                native.args.extend([flag.to_string(), rule]);
+            }
+        }
+        native.skip_default_safety_flags = restricted;
+    }
+
+    /// Gemini and Qwen only choose how much runs without approval; headless,
+    /// anything that would need approval is refused
+    fn compile_approval_mode(&self, native: &mut NativePermissionPolicy) {
+        let bash = self.category_action("bash") == "allow";
+        let edit = self.category_action("edit") == "allow";
+        let mode = match (edit, bash) {
+            (true, true) => "yolo",
+            (true, false) if native.tool == "qwen" => "auto-edit",
+            (true, false) => "auto_edit",
+            (false, _) => "default",
+        };
+        if bash && !edit {
+            native.unsupported.push(
+                "bash allow: commands cannot run without approval while edits need it".to_string(),
+            );
+        }
+        // Web fetches and tasks run without approval only in yolo mode
+        for category in ["webfetch", "task"] {
+            let allowed = self.category_action(category) == "allow";
+            if allowed != (mode == "yolo") {
+                native.unsupported.push(format!(
+                    "{} {}: the {} approval mode {} it",
+                    category,
+                    self.category_action(category),
+                    mode,
+                    if allowed { "does not allow" } else { "allows" }
+                ));
+            }
+        }
+        for category in ["bash", "edit", "read", "webfetch", "task"] {
+            for (pattern, _) in self.pattern_rules(category) {
+                native.unsupported.push(format!(
+                    "{} pattern \"{}\": approval modes cannot tell commands or paths apart",
+                    category, pattern
+                ));
+            }
+        }
+        self.report_read_denials(&mut native.unsupported);
+        native
+            .args
+            .extend(["--approval-mode".to_string(), mode.to_string()]);
+        native.skip_default_safety_flags = true;
+    }
+
+    /// Tools whose reads are always allowed cannot deny them
+    fn report_read_denials(&self, unsupported: &mut Vec<String>) {
+        if self.category_action("read") == "deny" {
+            unsupported.push("read deny: reads are always allowed".to_string());
+        }
+    }
+}
+
+fn check_action(action: &str, context: &str) -> Result<(), String> {
+    if action == "allow" || action == "deny" {
+        return Ok(());
+    }
+    Err(format!(
+        "Invalid {} action \"{}\". Expected allow or deny.",
+        context, action
+    ))
+}
+
+fn claude_tool_name(category: &str) -> &'static str {
+    match category {
+        "bash" => "Bash",
+        "edit" => "Edit",
+        "read" => "Read",
+        "webfetch" => "WebFetch",
+        _ => "Task",
+    }
+}
+
+/// Claude rule for a pattern: `Bash(cmd)` or `Bash(prefix:*)` for commands,
+/// `Edit(//abs/path/**)` for absolute paths, where `*` may cross `/`
+fn claude_rule(category: &str, pattern: &str) -> Option<String> {
+    let tool_name = claude_tool_name(category);
+    match category {
+        "bash" => {
+            let prefix = pattern.strip_suffix('*').unwrap_or(pattern);
+            if prefix.contains(['*', '?']) {
+                return None;
+            }
+            if prefix.len() == pattern.len() {
+                Some(format!("{}({})", tool_name, pattern))
+            } else {
+                Some(format!("{}({}:*)", tool_name, prefix.trim_end()))
+            }
+        }
+        "edit" | "read" if pattern.starts_with('/') && !pattern.contains('?') => Some(format!(
+            "{}(/{})",
+            tool_name,
+            pattern.replace("**", "*").replace('*', "**")
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,297 @@
+//! Interactive approval prompt on the controlling terminal
+//!
+//! An attached `start-agent --approve-each` run owns the tool's stdin and
+//! stdout, so the prompt talks to the terminal directly through `/dev/tty`:
+//! each request is rendered with its tool, command or edit diff, risk and
+//! scope, and the person answers once, always, reject, or reject with a
+//! message.
+
+use super::{
+    NormalizedPermissionRequest, PermissionCancellation, PermissionDecision, PermissionHandler,
+};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often a waiting prompt checks whether its request was cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Render a request for a person to decide
+///
+/// # Arguments
+/// * `request` - Normalized permission request
+///
+/// # Returns
+/// Multi-line description ending in a newline, with control characters in
+/// the request's text escaped
+pub fn render_permission_request(request: &NormalizedPermissionRequest) -> String {
+    let mut text = format!(
+        "Permission request from {} (scope: {})\n",
+        visible(&request.tool, false),
+        visible(&request.scope, false)
+    );
+    if let Some(ref tool_name) = request.tool_name {
+        text.push_str(&format!("  Tool:    {}\n", visible(tool_name, false)));
+    }
+    if let Some(ref command) = request.command {
+        if request.tool_name.as_ref() != Some(command) {
+            text.push_str(&format!("  Command: {}\n", visible(command, false)));
+        }
+    }
+    if let Some(ref pattern) = request.pattern {
+        text.push_str(&format!("  Pattern: {}\n", visible(pattern, false)));
+    }
+    if let Some(risk) = request.risk.as_ref().filter(|risk| risk.rank() > 0) {
+        text.push_str(&format!(
+            "  Risk:    {} ({})\n",
+            risk.level,
+            visible(&risk.reasons.join("; "), false)
+        ));
+    }
+    match request.diff {
+        Some(ref diff) => {
+            for line in diff.lines() {
+                text.push_str(&format!("  {}\n", visible(line, true)));
+            }
+        }
+        None => text.push_str(&render_edit(request.input.as_ref())),
+    }
+    text
+}
+
+/// Agent-controlled text with control characters (escape sequences,
+/// carriage returns, backspaces, newlines) and bidirectional overrides
+/// escaped, so it cannot rewrite or hide what the terminal shows
+fn visible(text: &str, keep_tabs: bool) -> String {
+    let mut shown = String::with_capacity(text.len());
+    for c in text.chars() {
+        let hidden = c.is_control()
+            || matches!(c, '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}');
+        if hidden && !(keep_tabs && c == '\t') {
+            shown.extend(c.escape_default());
+        } else {
+            shown.push(c);
+        }
+    }
+    shown
+}
+
+/// Removed and added text of an edit without a diff, one `-`/`+` line each
+fn render_edit(input: Option<&Value>) -> String {
+    let field = |key: &str| {
+        input
+            .and_then(|input| input.get(key))
+            .and_then(Value::as_str)
+    };
+    let mut text = String::new();
+    for (key, marker) in [("old_string", '-'), ("new_string", '+'), ("content", '+')] {
+        for line in field(key).into_iter().flat_map(str::lines) {
+            text.push_str(&format!("  {} {}\n", marker, visible(line, true)));
+        }
+    }
+    text
+}
+
+/// Ask a person to decide a request
+///
+/// Re-asks until the answer is one of `o`/`once`, `a`/`always`,
+/// `r`/`reject`, or (claude only, which can show it to the agent)
+/// `m`/`message` followed by the rejection message. Closed input rejects.
+///
+/// # Arguments
+/// * `request` - Normalized permission request
+/// * `input` - Where answers are read from
+/// * `output` - Where the request and the questions are written
+///
+/// # Returns
+/// The person's decision
+pub fn prompt_for_decision<R: BufRead, W: Write>(
+    request: &NormalizedPermissionRequest,
+    input: &mut R,
+    output: &mut W,
+) -> PermissionDecision {
+    let with_message = request.tool == "claude";
+    let question = if with_message {
+        "Allow? [o]nce, [a]lways, [r]eject, reject with [m]essage: "
+    } else {
+        "Allow? [o]nce, [a]lways, [r]eject: "
+    };
+    let _ = write!(output, "\n{}", render_permission_request(request));
+    loop {
+        let _ = write!(output, "{}", question);
+        let _ = output.flush();
+        let Some(answer) = read_answer(input) else {
+            return PermissionDecision::new("reject");
+        };
+        match answer.to_ascii_lowercase().as_str() {
+            "o" | "once" => return PermissionDecision::new("once"),
+            "a" | "always" => return PermissionDecision::new("always"),
+            "r" | "reject" => return PermissionDecision::new("reject"),
+            "m" | "message" if with_message => {
+                let _ = write!(output, "Message for the agent: ");
+                let _ = output.flush();
+                return match read_answer(input) {
+                    Some(message) if !message.is_empty() => {
+                        PermissionDecision::reject_with_message(&message)
+                    }
+                    _ => PermissionDecision::new("reject"),
+                };
+            }
+            _ => {
+                let _ = writeln!(output, "Please answer one of the letters in brackets.");
+            }
+        }
+    }
+}
+
+/// Next trimmed line, or `None` once the input is closed
+fn read_answer<R: BufRead>(input: &mut R) -> Option<String> {
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+impl PermissionHandler {
+    /// Ask the person at the controlling terminal to decide each request
+    ///
+    /// A request the relay gives up on (its timeout) stops being asked
+    /// about, and answers typed before the next request is shown are
+    /// dropped rather than applied to it.
+    ///
+    /// # Returns
+    /// The handler, or an error when the process has no controlling
+    /// terminal
+    pub fn terminal() -> Result<Self, String> {
+        let open = || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .map_err(|e| format!("Failed to open the controlling terminal: {}", e))
+        };
+        let input = TerminalInput::spawn(BufReader::new(open()?));
+        let tty: Arc<Mutex<(TerminalInput, File)>> = Arc::new(Mutex::new((input, open()?)));
+        Ok(Self::cancellable(
+            move |request: &NormalizedPermissionRequest, cancellation: &PermissionCancellation| {
+                let mut tty = tty.lock().unwrap_or_else(|e| e.into_inner());
+                let (ref mut input, ref mut output) = *tty;
+                input.drop_stale_answers();
+                let decision = {
+                    let mut answers = CancellableAnswers {
+                        input,
+                        cancellation,
+                        buffer: Vec::new(),
+                        position: 0,
+                    };
+                    prompt_for_decision(request, &mut answers, output)
+                };
+                if cancellation.is_cancelled() {
+                    let _ = writeln!(output, "\nNo answer in time; the request was rejected.");
+                }
+                drop(tty);
+                decision.with_source("human")
+            },
+        ))
+    }
+}
+
+/// Lines of the terminal, read on a thread only while a prompt waits for
+/// one, so a prompt can give up without a read left blocking it
+struct TerminalInput {
+    wants: Sender<()>,
+    lines: Receiver<Option<String>>,
+    reading: bool,
+    closed: bool,
+}
+
+impl TerminalInput {
+    fn spawn<R: BufRead + Send + 'static>(mut reader: R) -> Self {
+        let (wants, wanted) = channel::<()>();
+        let (sender, lines) = channel();
+        std::thread::spawn(move || {
+            while wanted.recv().is_ok() {
+                let mut line = String::new();
+                let line = match reader.read_line(&mut line) {
+                    Ok(0) | Err(_) => None,
+                    Ok(_) => Some(line),
+                };
+                let closed = line.is_none();
+                if sender.send(line).is_err() || closed {
+                    break;
+                }
+            }
+        });
+        Self {
+            wants,
+            lines,
+            reading: false,
+            closed: false,
+        }
+    }
+
+    /// Forget lines typed while no prompt was shown
+    fn drop_stale_answers(&mut self) {
+        while let Ok(line) = self.lines.try_recv() {
+            self.reading = false;
+            self.closed |= line.is_none();
+        }
+    }
+}
+
+/// Answers for one prompt, ending when the terminal closes or the request
+/// is cancelled
+struct CancellableAnswers<'a> {
+    input: &'a mut TerminalInput,
+    cancellation: &'a PermissionCancellation,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl Read for CancellableAnswers<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for CancellableAnswers<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        while self.position == self.buffer.len() && !self.input.closed {
+            if !self.input.reading {
+                if self.input.wants.send(()).is_err() {
+                    self.input.closed = true;
+                    break;
+                }
+                self.input.reading = true;
+            }
+            if self.cancellation.is_cancelled() {
+                break;
+            }
+            match self.input.lines.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(Some(line)) => {
+                    self.input.reading = false;
+                    self.buffer = line.into_bytes();
+                    self.position = 0;
+                }
+                Ok(None) | Err(RecvTimeoutError::Disconnected) => {
+                    self.input.reading = false;
+                    self.input.closed = true;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.buffer.len());
+    }
+}
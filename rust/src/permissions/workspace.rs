@@ -0,0 +1,433 @@
+//! Workspace boundary guard
+//!
+//! A [`WorkspaceGuard`] rejects permission requests that reach outside the
+//! working directory and an allowlist of extra directories, before any
+//! handler is asked. It checks the paths of file tool inputs and, for shell
+//! commands, the directories `cd`/`pushd` change into and the files
+//! redirections read or write; `cd -` and `popd`, whose targets it cannot
+//! resolve, are rejected. Paths are resolved against the working
+//! directory with symlinks followed, so a link pointing out of the workspace
+//! is caught too. A codex request naming its own `cwd` has that directory
+//! checked first and its relative paths resolved against it.
+
+use super::risk::is_shell_request;
+use super::{request_paths, NormalizedPermissionRequest, PermissionDecision};
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+
+/// Device files redirections may always use
+const DEVICE_FILES: &[&str] = &["/dev/null", "/dev/stdout", "/dev/stderr"];
+
+/// Words that can precede a command name without changing which command
+/// runs: reserved words and the builtins that run a builtin by name
+const COMMAND_PREFIXES: &[&str] = &[
+    "!", "{", "if", "then", "elif", "else", "while", "until", "do", "time", "builtin", "command",
+];
+
+/// Option letters of `cd` and `pushd`; `-` makes up `--`
+const DIRECTORY_OPTIONS: &str = "LPe@n-";
+
+/// Source prefix of the guard's rejections
+const GUARD_SOURCE: &str = "workspace guard";
+
+/// Rejects requests whose paths resolve outside the allowed directories
+#[derive(Debug, Clone)]
+pub struct WorkspaceGuard {
+    working_directory: PathBuf,
+    allowed: Vec<PathBuf>,
+}
+
+impl WorkspaceGuard {
+    /// Guard a working directory
+    ///
+    /// # Arguments
+    /// * `working_directory` - Directory relative paths resolve against
+    /// * `extra_directories` - Other directories requests may reach;
+    ///   relative ones resolve against the working directory
+    pub fn new<P: AsRef<Path>>(
+        working_directory: impl AsRef<Path>,
+        extra_directories: &[P],
+    ) -> Self {
+        let working_directory = resolve(working_directory.as_ref());
+        let mut allowed = vec![working_directory.clone()];
+        allowed.extend(
+            extra_directories
+                .iter()
+                .map(|directory| resolve(&working_directory.join(directory))),
+        );
+        Self {
+            working_directory,
+            allowed,
+        }
+    }
+
+    /// Check every path a request reaches
+    ///
+    /// # Arguments
+    /// * `request` - Normalized permission request
+    ///
+    /// # Returns
+    /// Ok, or why the request leaves the workspace
+    pub fn check(&self, request: &NormalizedPermissionRequest) -> Result<(), String> {
+        let input = match request.tool.as_str() {
+            "agent" => request.raw.get("metadata"),
+            _ => request.input.as_ref(),
+        };
+        let base = self.request_directory(request)?;
+        for path in request_paths(request) {
+            self.check_path(&base.join(&path), &path)?;
+        }
+
+        if is_shell_request(request.tool_name.as_deref()) {
+            let command = input
+                .and_then(|input| input.get("command"))
+                .and_then(Value::as_str)
+                .or(request.command.as_deref())
+                .unwrap_or_default();
+            for (path, shown) in shell_targets(command, &base)? {
+                self.check_path(&path, &shown)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The guard's decision on a request, if it rejects it
+    ///
+    /// # Returns
+    /// A `reject` explaining which path leaves the workspace, or `None` to
+    /// let the request through to the handler
+    pub fn review(&self, request: &NormalizedPermissionRequest) -> Option<PermissionDecision> {
+        let reason = self.check(request).err()?;
+        Some(
+            PermissionDecision::reject_with_message(&reason)
+                .with_source(&format!("{}: {}", GUARD_SOURCE, reason)),
+        )
+    }
+
+    /// Directory the request's relative paths resolve against: codex's
+    /// `params.cwd` once it is inside the workspace, or the working directory
+    fn request_directory(&self, request: &NormalizedPermissionRequest) -> Result<PathBuf, String> {
+        let cwd = request
+            .raw
+            .pointer("/params/cwd")
+            .and_then(Value::as_str)
+            .filter(|_| request.tool == "codex");
+        let Some(cwd) = cwd else {
+            return Ok(self.working_directory.clone());
+        };
+        let directory = self.working_directory.join(cwd);
+        self.check_path(&directory, cwd)?;
+        Ok(resolve(&directory))
+    }
+
+    fn check_path(&self, path: &Path, shown: &str) -> Result<(), String> {
+        let resolved = resolve(path);
+        if self.allowed.iter().any(|root| resolved.starts_with(root)) {
+            return Ok(());
+        }
+        Err(format!(
+            "{} resolves to {}, outside the workspace",
+            shown,
+            resolved.display()
+        ))
+    }
+}
+
+/// Directories changed into and files redirected to by a shell command,
+/// each with the text it was written as; the command starts in `directory`
+fn shell_targets(command: &str, directory: &Path) -> Result<Vec<(PathBuf, String)>, String> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut base = directory.to_path_buf();
+    let mut targets = Vec::new();
+
+    for simple in simple_commands(command) {
+        // Redirections open their files before the command runs
+        let mut words: Vec<(&Word, bool)> = simple
+            .redirects
+            .iter()
+            .filter(|(operator, word)| {
+                // Here-documents and here-strings carry text, not paths, and
+                // duplicating a descriptor (`2>&1`, `>&-`) opens no file
+                !operator.starts_with("<<")
+                    && !(operator.ends_with('&')
+                        && (word.text == "-" || word.text.chars().all(|c| c.is_ascii_digit())))
+            })
+            .map(|(_, word)| (word, false))
+            .collect();
+        let change = directory_change(&simple.words)?;
+        words.extend(change.as_ref().map(|target| (target, true)));
+
+        for (word, changes_directory) in words {
+            if DEVICE_FILES.contains(&word.text.as_str()) {
+                continue;
+            }
+            let path = if word.dynamic {
+                return Err(format!(
+                    "{} cannot be resolved before the command runs",
+                    word.text
+                ));
+            } else if let Some(rest) = word.text.strip_prefix('~').filter(|_| word.tilde) {
+                let home = home.clone().ok_or("~ cannot be resolved without HOME")?;
+                home.join(rest.trim_start_matches('/'))
+            } else {
+                base.join(&word.text)
+            };
+            if changes_directory {
+                base.clone_from(&path);
+            }
+            targets.push((path, word.text.clone()));
+        }
+    }
+    Ok(targets)
+}
+
+/// The directory a simple command changes into with `cd` or `pushd`; `~`
+/// for a bare one
+///
+/// Looks past variable assignments, reserved words and `builtin`/`command`,
+/// and past the options of `cd`. `cd -`, `popd` and `pushd +N` return to a
+/// directory on a stack the guard cannot see, so they are refused.
+fn directory_change(words: &[Word]) -> Result<Option<Word>, String> {
+    let mut words = words.iter().peekable();
+    while words
+        .next_if(|word| is_assignment(&word.text) || COMMAND_PREFIXES.contains(&word.text.as_str()))
+        .is_some()
+    {}
+    // `command -v cd` looks the command up without running it
+    if words
+        .peek()
+        .is_some_and(|word| word.text == "-v" || word.text == "-V")
+    {
+        return Ok(None);
+    }
+    words.next_if(|word| word.text == "-p");
+
+    let Some(name) = words.next() else {
+        return Ok(None);
+    };
+    match name.text.as_str() {
+        "cd" | "pushd" => {}
+        "popd" => {
+            return Err(format!(
+                "{} returns to a directory that cannot be resolved before the command runs",
+                name.text
+            ))
+        }
+        _ => return Ok(None),
+    }
+    while let Some(option) = words.next_if(|word| {
+        word.text.len() > 1
+            && word.text.starts_with('-')
+            && word.text[1..]
+                .chars()
+                .all(|c| DIRECTORY_OPTIONS.contains(c))
+    }) {
+        if option.text == "--" {
+            break;
+        }
+    }
+    match words.next() {
+        Some(word)
+            if word.text == "-" || (name.text == "pushd" && word.text.starts_with(['+', '-'])) =>
+        {
+            Err(format!(
+                "{} {} returns to a directory that cannot be resolved before the command runs",
+                name.text, word.text
+            ))
+        }
+        Some(word) => Ok(Some(word.clone())),
+        None => Ok(Some(Word::home())),
+    }
+}
+
+/// Whether a word is a variable assignment such as `LANG=C`
+fn is_assignment(text: &str) -> bool {
+    text.split_once('=').is_some_and(|(name, _)| {
+        name.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Resolve a path with symlinks followed; the part that does not exist yet
+/// is appended to the longest existing ancestor, with `..` applied
+pub(super) fn resolve(path: &Path) -> PathBuf {
+    let components: Vec<Component> = path.components().collect();
+    for split in (1..=components.len()).rev() {
+        let prefix: PathBuf = components[..split].iter().collect();
+        if let Ok(mut resolved) = prefix.canonicalize() {
+            for component in &components[split..] {
+                match component {
+                    Component::ParentDir => {
+                        resolved.pop();
+                    }
+                    Component::Normal(name) => resolved.push(name),
+                    _ => {}
+                }
+            }
+            return resolved;
+        }
+    }
+    path.to_path_buf()
+}
+
+/// One shell word after quote removal
+#[derive(Debug, Clone, Default)]
+pub(super) struct Word {
+    pub(super) text: String,
+    /// Contains an unquoted or double-quoted `$` or backtick expansion
+    pub(super) dynamic: bool,
+    /// Starts with an unquoted `~`
+    pub(super) tilde: bool,
+}
+
+impl Word {
+    /// The target of a bare `cd`
+    fn home() -> Self {
+        Self {
+            text: "~".to_string(),
+            dynamic: false,
+            tilde: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum Token {
+    Word(Word),
+    /// `>`, `>>`, `<`, `2>`, `&>`, `>&`, `<<`, ...
+    Redirect(String),
+    /// `;`, `&`, `&&`, `|`, `||`, newline or parenthesis
+    Separator,
+}
+
+/// One command of a pipeline or list: its words and its redirections, each
+/// operator with the word after it
+#[derive(Debug, Default)]
+pub(super) struct SimpleCommand {
+    pub(super) words: Vec<Word>,
+    pub(super) redirects: Vec<(String, Word)>,
+}
+
+/// Split a shell command into the simple commands between its separators
+///
+/// Both the policy matcher and the risk classifier read commands through
+/// this, so they agree on where one command ends and the next begins.
+pub(super) fn simple_commands(command: &str) -> Vec<SimpleCommand> {
+    let mut commands = vec![SimpleCommand::default()];
+    let mut tokens = tokenize(command).into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let current = commands.last_mut().expect("at least one command");
+        match token {
+            Token::Word(word) => current.words.push(word),
+            Token::Redirect(operator) => {
+                if let Some(Token::Word(word)) =
+                    tokens.next_if(|token| matches!(token, Token::Word(_)))
+                {
+                    current.redirects.push((operator, word));
+                }
+            }
+            Token::Separator => commands.push(SimpleCommand::default()),
+        }
+    }
+    commands.retain(|simple| !simple.words.is_empty() || !simple.redirects.is_empty());
+    commands
+}
+
+/// Split a shell command into words, redirections and separators
+///
+/// Handles single and double quotes and backslash escapes; everything else
+/// about the shell's grammar is left to the shell.
+pub(super) fn tokenize(command: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = Word::default();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+
+    let finish = |tokens: &mut Vec<Token>, word: &mut Word, in_word: &mut bool| {
+        if *in_word {
+            tokens.push(Token::Word(std::mem::take(word)));
+            *in_word = false;
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                word.text.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.text.extend(chars.next()),
+                        '$' | '`' => {
+                            word.dynamic = true;
+                            word.text.push(c);
+                        }
+                        _ => word.text.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.text.extend(chars.next().filter(|&c| c != '\n'));
+            }
+            '$' | '`' => {
+                in_word = true;
+                word.dynamic = true;
+                word.text.push(c);
+            }
+            '~' if !in_word => {
+                in_word = true;
+                word.tilde = true;
+                word.text.push(c);
+            }
+            '<' | '>' => {
+                // A descriptor number right before the operator belongs to it
+                let mut operator = String::new();
+                if in_word && !word.text.is_empty() && word.text.chars().all(|c| c.is_ascii_digit())
+                {
+                    operator = std::mem::take(&mut word.text);
+                    word = Word::default();
+                    in_word = false;
+                }
+                finish(&mut tokens, &mut word, &mut in_word);
+                operator.push(c);
+                while let Some(&next) = chars.peek() {
+                    if !matches!(next, '<' | '>' | '&' | '|') {
+                        break;
+                    }
+                    operator.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Redirect(operator));
+            }
+            '&' if chars.peek() == Some(&'>') => {
+                finish(&mut tokens, &mut word, &mut in_word);
+                chars.next();
+                let mut operator = "&>".to_string();
+                if chars.next_if_eq(&'>').is_some() {
+                    operator.push('>');
+                }
+                tokens.push(Token::Redirect(operator));
+            }
+            ';' | '&' | '|' | '\n' | '(' | ')' => {
+                finish(&mut tokens, &mut word, &mut in_word);
+                if !matches!(tokens.last(), Some(Token::Separator)) {
+                    tokens.push(Token::Separator);
+                }
+            }
+            c if c.is_whitespace() => finish(&mut tokens, &mut word, &mut in_word),
+            _ => {
+                in_word = true;
+                word.text.push(c);
+            }
+        }
+    }
+    finish(&mut tokens, &mut word, &mut in_word);
+    tokens
+}
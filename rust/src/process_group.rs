@@ -0,0 +1,134 @@
+//! Process groups and the signals used to stop agents
+
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Signal sent to an agent's process group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessSignal {
+    /// SIGINT, lets the tool save its session before exiting
+    Interrupt,
+    /// SIGTERM
+    Terminate,
+    /// SIGKILL
+    Kill,
+}
+
+impl ProcessSignal {
+    /// Signal name as accepted by `kill -s`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Interrupt => "INT",
+            Self::Terminate => "TERM",
+            Self::Kill => "KILL",
+        }
+    }
+
+    /// Exit code a shell reports for a process killed by this signal
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Interrupt => 130,
+            Self::Terminate => 143,
+            Self::Kill => 137,
+        }
+    }
+}
+
+/// Cloneable handle for signalling the process group of a started command
+///
+/// Obtained from [`ProcessHandle::process_group`]; it can signal the group
+/// from another task while [`ProcessHandle::wait_for_exit`] is pending.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessGroup {
+    pub(crate) pid: Option<u32>,
+    pub(crate) reaped: Arc<AtomicBool>,
+}
+
+impl ProcessGroup {
+    /// Handle for an existing group led by `pid`, e.g. a tracked detached run
+    pub fn from_pid(pid: u32) -> Self {
+        Self {
+            pid: Some(pid),
+            reaped: Arc::default(),
+        }
+    }
+
+    /// Build the platform command that delivers `signal` to the group
+    fn kill_command(&self, signal: ProcessSignal) -> Option<std::process::Command> {
+        // Once the leader is reaped its PID (and group ID) may be reused
+        if self.reaped.load(Ordering::SeqCst) {
+            return None;
+        }
+        let pid = self.pid?;
+
+        #[cfg(unix)]
+        let command = {
+            let mut command = std::process::Command::new("kill");
+            command
+                .arg("-s")
+                .arg(signal.name())
+                .arg("--")
+                .arg(format!("-{}", pid));
+            command
+        };
+
+        // Windows has no signals; every signal terminates the process tree
+        #[cfg(not(unix))]
+        let command = {
+            let _ = signal;
+            let mut command = std::process::Command::new("taskkill");
+            command.arg("/PID").arg(pid.to_string()).arg("/T").arg("/F");
+            command
+        };
+
+        let mut command = command;
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        Some(command)
+    }
+
+    /// Send a signal to every process in the group
+    ///
+    /// Does nothing once the command has exited. A group that disappears
+    /// before the signal is delivered is not an error.
+    pub async fn signal(&self, signal: ProcessSignal) -> Result<(), std::io::Error> {
+        if let Some(command) = self.kill_command(signal) {
+            Command::from(command).status().await?;
+        }
+        Ok(())
+    }
+
+    /// Blocking variant of [`ProcessGroup::signal`], usable from `Drop`
+    pub fn signal_blocking(&self, signal: ProcessSignal) -> Result<(), std::io::Error> {
+        if let Some(mut command) = self.kill_command(signal) {
+            command.status()?;
+        }
+        Ok(())
+    }
+}
+
+/// How long to wait after each termination signal before escalating
+///
+/// Once a run exceeds its timeout the process group gets SIGINT, then
+/// SIGTERM after `interrupt_grace`, then SIGKILL after `terminate_grace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminationSchedule {
+    /// Wait after SIGINT before sending SIGTERM
+    pub interrupt_grace: Duration,
+    /// Wait after SIGTERM before sending SIGKILL
+    pub terminate_grace: Duration,
+}
+
+impl Default for TerminationSchedule {
+    fn default() -> Self {
+        Self {
+            interrupt_grace: Duration::from_secs(10),
+            terminate_grace: Duration::from_secs(5),
+        }
+    }
+}
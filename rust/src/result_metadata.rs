@@ -32,6 +32,8 @@ pub struct ResultMetadata {
     pub error_during_execution: bool,
     pub error_type: Option<String>,
     pub error_message: Option<String>,
+    /// The run exceeded its timeout and was terminated
+    pub timed_out: bool,
 }
 
 /// Inputs used to build normalized result metadata.
@@ -43,6 +45,8 @@ pub struct BuildMetadataOptions<'a> {
     pub parsed_output: Option<&'a [Value]>,
     pub session_id: Option<String>,
     pub usage: Option<Value>,
+    /// The run was terminated after exceeding its timeout
+    pub timed_out: bool,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -525,12 +529,21 @@ pub fn build_normalized_result_metadata(options: BuildMetadataOptions<'_>) -> Re
         <[Value]>::to_vec,
     );
     let usage_limit = detect_usage_limit(options.plain_output);
-    let execution_error = detect_execution_error(
-        options.tool,
-        options.exit_code,
-        options.plain_output,
-        &messages,
-    );
+    // Whatever the tool reported while being terminated, the cause was the timeout
+    let execution_error = if options.timed_out {
+        ExecutionError {
+            has_error: true,
+            error_type: Some("timeout".to_string()),
+            message: Some("Agent run timed out and was terminated".to_string()),
+        }
+    } else {
+        detect_execution_error(
+            options.tool,
+            options.exit_code,
+            options.plain_output,
+            &messages,
+        )
+    };
     let session_id = extract_session_id(options.session_id, &messages);
     let public_pricing_estimate = public_pricing_estimate(options.tool, options.usage.as_ref());
     let pricing_info = public_pricing_estimate.map(|total_cost_usd| PricingInfo {
@@ -541,7 +554,10 @@ pub fn build_normalized_result_metadata(options: BuildMetadataOptions<'_>) -> Re
     ResultMetadata {
         tool: options.tool.to_string(),
         exit_code: options.exit_code,
-        success: options.exit_code == 0 && !usage_limit.reached && !execution_error.has_error,
+        success: options.exit_code == 0
+            && !options.timed_out
+            && !usage_limit.reached
+            && !execution_error.has_error,
         session_id,
         limit_reached: usage_limit.reached,
         limit_reset_time: usage_limit.reset_time,
@@ -559,6 +575,7 @@ pub fn build_normalized_result_metadata(options: BuildMetadataOptions<'_>) -> Re
         error_during_execution: execution_error.has_error,
         error_type: execution_error.error_type,
         error_message: execution_error.message,
+        timed_out: options.timed_out,
     }
 }
 
@@ -581,6 +598,7 @@ mod tests {
             parsed_output: None,
             session_id: None,
             usage: Some(usage.clone()),
+            timed_out: false,
         });
 
         assert_eq!(metadata.tool, "codex");
@@ -618,6 +636,7 @@ mod tests {
             parsed_output: Some(&messages),
             session_id: None,
             usage: Some(usage.clone()),
+            timed_out: false,
         });
 
         assert_eq!(metadata.tool, "agent");
@@ -635,4 +654,22 @@ mod tests {
         assert_eq!(sub_agent_calls.len(), 1);
         assert_eq!(sub_agent_calls[0].get("id"), Some(&json!("call-1")));
     }
+
+    #[test]
+    fn marks_timed_out_runs_as_failed() {
+        let metadata = build_normalized_result_metadata(BuildMetadataOptions {
+            tool: "claude",
+            exit_code: 0,
+            plain_output: "{\"type\":\"assistant\",\"message\":{\"content\":\"Working\"}}",
+            parsed_output: None,
+            session_id: None,
+            usage: None,
+            timed_out: true,
+        });
+
+        assert!(metadata.timed_out);
+        assert!(!metadata.success);
+        assert!(metadata.error_during_execution);
+        assert_eq!(metadata.error_type, Some("timeout".to_string()));
+    }
 }
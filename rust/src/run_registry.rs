@@ -0,0 +1,479 @@
+//! Registry of tracked detached runs (isolation `none`)
+//!
+//! A detached run without isolation has no screen session or container to
+//! find it by, so its PID, process group, command, start time and output log
+//! paths are recorded as JSON in a local state directory. `stop-agent
+//! --pid`/`--run-id` and [`crate::Agent::stop`] use the record to terminate
+//! the run and parse its captured output into an [`crate::AgentResult`].
+
+use crate::executor::{configure_launch, ProcessGroup};
+use crate::launch::LaunchSpec;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Environment variable overriding the default state directory
+pub const STATE_DIR_ENV: &str = "AGENT_COMMANDER_STATE_DIR";
+
+/// Everything needed to find, stop and collect a detached run later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunRecord {
+    /// Unique run identifier
+    pub run_id: String,
+    /// Tool that was started (used to parse the output)
+    pub tool: String,
+    /// Whether the tool was started in JSON output mode
+    pub json: bool,
+    /// PID of the run's process group leader
+    pub pid: u32,
+    /// Process group ID (same as `pid`; `None` where groups are unsupported)
+    pub process_group: Option<u32>,
+    /// Shell rendering of the launched command, for display
+    pub command: String,
+    /// Working directory of the run
+    pub working_directory: String,
+    /// Start time in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+    /// File receiving the run's stdout
+    pub stdout_log: PathBuf,
+    /// File receiving the run's stderr
+    pub stderr_log: PathBuf,
+    /// File the exit code is written to when the run finishes (Unix only)
+    pub exit_code_file: Option<PathBuf>,
+}
+
+impl RunRecord {
+    /// Handle for signalling the run's process group
+    pub fn process_group(&self) -> ProcessGroup {
+        ProcessGroup::from_pid(self.process_group.unwrap_or(self.pid))
+    }
+
+    /// Read the exit code written when the run finished
+    ///
+    /// # Returns
+    /// Exit code, or None while the run is still going or if it was killed
+    /// before the code could be written
+    pub async fn exit_code(&self) -> Option<i32> {
+        let path = self.exit_code_file.as_ref()?;
+        let content = tokio::fs::read_to_string(path).await.ok()?;
+        content.trim().parse().ok()
+    }
+
+    /// Wait until the run finishes
+    ///
+    /// # Returns
+    /// True if the run finished within `timeout`
+    pub async fn wait(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.is_running().await {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        true
+    }
+
+    /// Check whether any process of the run is still alive
+    pub async fn is_running(&self) -> bool {
+        if self.exit_code().await.is_some() {
+            return false;
+        }
+        process_exists(
+            self.process_group.unwrap_or(self.pid),
+            self.process_group.is_some(),
+        )
+        .await
+    }
+}
+
+/// Check whether a process (or, on Unix, a whole process group) exists
+async fn process_exists(pid: u32, group: bool) -> bool {
+    #[cfg(unix)]
+    let mut command = {
+        let target = if group {
+            format!("-{}", pid)
+        } else {
+            pid.to_string()
+        };
+        let mut command = Command::new("kill");
+        command.arg("-0").arg("--").arg(target);
+        command
+    };
+
+    #[cfg(not(unix))]
+    let mut command = {
+        let _ = group;
+        let mut command = Command::new("tasklist");
+        command.arg("/FI").arg(format!("PID eq {}", pid)).arg("/NH");
+        command
+    };
+
+    let Ok(output) = command
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await
+    else {
+        return false;
+    };
+
+    if cfg!(unix) {
+        output.status.success()
+    } else {
+        String::from_utf8_lossy(&output.stdout).contains(&pid.to_string())
+    }
+}
+
+/// Default location of the run registry
+///
+/// Uses `AGENT_COMMANDER_STATE_DIR` if set, then `$XDG_STATE_HOME/agent-commander`,
+/// then `~/.local/state/agent-commander`, and finally the system temp directory.
+pub fn default_state_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(STATE_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = std::env::var_os("XDG_STATE_HOME") {
+        return PathBuf::from(dir).join("agent-commander");
+    }
+    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+        return PathBuf::from(home)
+            .join(".local")
+            .join("state")
+            .join("agent-commander");
+    }
+    std::env::temp_dir().join("agent-commander")
+}
+
+/// A file name standing for `text` alone
+fn encode_file_name(text: &str) -> String {
+    let mut name = String::new();
+    for (index, byte) in text.bytes().enumerate() {
+        if byte.is_ascii_alphanumeric() || b"-_".contains(&byte) || (byte == b'.' && index > 0) {
+            name.push(char::from(byte));
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+/// Run records stored under `<state dir>/runs/<run id>/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunRegistry {
+    state_dir: PathBuf,
+}
+
+impl Default for RunRegistry {
+    fn default() -> Self {
+        Self::new(default_state_dir())
+    }
+}
+
+impl RunRegistry {
+    /// Create a registry rooted at `state_dir`
+    pub fn new(state_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            state_dir: state_dir.into(),
+        }
+    }
+
+    /// Directory holding a run's record and logs
+    pub fn run_dir(&self, run_id: &str) -> PathBuf {
+        self.state_dir.join("runs").join(run_id)
+    }
+
+    /// Directory holding the captured output of a named isolated session
+    ///
+    /// # Arguments
+    /// * `isolation` - Isolation mode, e.g. `screen`
+    /// * `name` - Session name, encoded as by [`Self::worktree_base_file`]
+    pub fn capture_dir(&self, isolation: &str, name: &str) -> PathBuf {
+        self.state_dir.join(isolation).join(encode_file_name(name))
+    }
+
+    /// Default directory for the git worktree of a run on `branch`
+    pub fn worktree_dir(&self, branch: &str) -> PathBuf {
+        self.capture_dir("worktrees", branch)
+    }
+
+    /// File recording the base commit of the worktree at `worktree`
+    ///
+    /// Kept in the state directory rather than the repository, where an
+    /// agent that can commit could rewrite it. Characters other than ASCII
+    /// letters, digits, `-`, `_` and `.` are percent-encoded, and so is a
+    /// leading `.`, so every worktree path gets its own file and no name
+    /// becomes `.` or `..`.
+    pub fn worktree_base_file(&self, worktree: &Path) -> PathBuf {
+        self.state_dir
+            .join("worktree-bases")
+            .join(encode_file_name(&worktree.to_string_lossy()))
+    }
+
+    fn record_path(&self, run_id: &str) -> PathBuf {
+        self.run_dir(run_id).join("run.json")
+    }
+
+    /// Write a run record
+    pub async fn save(&self, record: &RunRecord) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(record).map_err(|e| e.to_string())?;
+        tokio::fs::create_dir_all(self.run_dir(&record.run_id))
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::fs::write(self.record_path(&record.run_id), content)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Load a run record by ID
+    pub async fn load(&self, run_id: &str) -> Result<RunRecord, String> {
+        if run_id.is_empty() || run_id.contains(['/', '\\']) || run_id.starts_with('.') {
+            return Err(format!("Invalid run ID: {}", run_id));
+        }
+        let content = tokio::fs::read_to_string(self.record_path(run_id))
+            .await
+            .map_err(|_| format!("No tracked run with ID {}", run_id))?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid run record {}: {}", run_id, e))
+    }
+
+    /// List all recorded runs, oldest first
+    pub async fn list(&self) -> Vec<RunRecord> {
+        let mut records = Vec::new();
+        let Ok(mut entries) = tokio::fs::read_dir(self.state_dir.join("runs")).await else {
+            return records;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(record) = self.load(&entry.file_name().to_string_lossy()).await {
+                records.push(record);
+            }
+        }
+        records.sort_by_key(|record| record.started_at_ms);
+        records
+    }
+
+    /// Find the most recent run started with `pid`
+    pub async fn find_by_pid(&self, pid: u32) -> Result<RunRecord, String> {
+        self.list()
+            .await
+            .into_iter()
+            .rev()
+            .find(|record| record.pid == pid)
+            .ok_or_else(|| format!("No tracked run with PID {}", pid))
+    }
+
+    /// Delete a run's record and logs
+    pub async fn remove(&self, run_id: &str) -> Result<(), String> {
+        let record = self.load(run_id).await?;
+        tokio::fs::remove_dir_all(self.run_dir(&record.run_id))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Start a launch spec detached, with its output captured to log files,
+    /// and record it
+    ///
+    /// On Unix the spec runs under a small `sh` wrapper that receives the
+    /// program and arguments as positional parameters (they are never parsed
+    /// by the shell) and writes the exit code once the run finishes.
+    ///
+    /// # Arguments
+    /// * `spec` - Program, argv, cwd, environment and stdin source
+    /// * `tool` - Tool name, used to parse the output later
+    /// * `json` - Whether the tool runs in JSON output mode
+    ///
+    /// # Returns
+    /// The saved run record
+    pub async fn start(
+        &self,
+        spec: &LaunchSpec,
+        tool: &str,
+        json: bool,
+    ) -> Result<RunRecord, String> {
+        let started_at_ms = u64::try_from(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?
+                .as_millis(),
+        )
+        .unwrap_or(u64::MAX);
+        let run_id = format!(
+            "run-{}-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?
+                .as_nanos(),
+            std::process::id()
+        );
+        let run_dir = self.run_dir(&run_id);
+        tokio::fs::create_dir_all(&run_dir)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let stdout_log = run_dir.join("stdout.log");
+        let stderr_log = run_dir.join("stderr.log");
+        let exit_code_file = cfg!(unix).then(|| run_dir.join("exit_code"));
+        let pid = spawn_logged(spec, &stdout_log, &stderr_log, exit_code_file.as_deref())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let record = RunRecord {
+            run_id,
+            tool: tool.to_string(),
+            json,
+            pid,
+            process_group: cfg!(unix).then_some(pid),
+            command: spec.to_shell_command(),
+            working_directory: spec
+                .cwd
+                .as_ref()
+                .map(|cwd| cwd.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            started_at_ms,
+            stdout_log,
+            stderr_log,
+            exit_code_file,
+        };
+        self.save(&record).await?;
+        Ok(record)
+    }
+}
+
+/// Spawn a launch spec in its own process group with stdout and stderr
+/// redirected to files
+async fn spawn_logged(
+    spec: &LaunchSpec,
+    stdout_log: &Path,
+    stderr_log: &Path,
+    exit_code_file: Option<&Path>,
+) -> std::io::Result<u32> {
+    let mut process = match exit_code_file {
+        Some(exit_code_file) => {
+            // Trapping INT/TERM keeps the wrapper alive long enough to record
+            // the code; the tool itself still gets default signal handling
+            let mut process = Command::new("sh");
+            process
+                .arg("-c")
+                .arg("trap : INT TERM; exit_file=$1; shift; \"$@\"; echo $? > \"$exit_file\"")
+                .arg("sh")
+                .arg(exit_code_file)
+                .arg(&spec.program);
+            process
+        }
+        None => Command::new(&spec.program),
+    };
+    let inline_input = configure_launch(&mut process, spec)?;
+    process
+        .stdout(std::fs::File::create(stdout_log)?)
+        .stderr(std::fs::File::create(stderr_log)?);
+    #[cfg(unix)]
+    process.process_group(0);
+    let mut child = process.spawn()?;
+    let pid = child
+        .id()
+        .ok_or_else(|| std::io::Error::other("detached run exited before it was recorded"))?;
+
+    // Nobody waits on a detached run, so finish writing the prompt before
+    // returning; the caller may exit right away
+    if let (Some(input), Some(mut stdin)) = (inline_input, child.stdin.take()) {
+        stdin.write_all(&input).await?;
+    }
+
+    Ok(pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(run_id: &str, pid: u32, started_at_ms: u64) -> RunRecord {
+        RunRecord {
+            run_id: run_id.to_string(),
+            tool: "claude".to_string(),
+            json: true,
+            pid,
+            process_group: Some(pid),
+            command: "claude -p hi".to_string(),
+            working_directory: "/tmp".to_string(),
+            started_at_ms,
+            stdout_log: PathBuf::from("/tmp/stdout.log"),
+            stderr_log: PathBuf::from("/tmp/stderr.log"),
+            exit_code_file: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_saves_loads_and_finds_runs() {
+        let state_dir = tempfile::tempdir().unwrap();
+        let registry = RunRegistry::new(state_dir.path());
+        registry.save(&record("run-1", 100, 1)).await.unwrap();
+        registry.save(&record("run-2", 100, 2)).await.unwrap();
+
+        assert_eq!(registry.load("run-1").await.unwrap().pid, 100);
+        assert_eq!(registry.list().await.len(), 2);
+        assert_eq!(registry.find_by_pid(100).await.unwrap().run_id, "run-2");
+        assert!(registry.find_by_pid(7).await.is_err());
+
+        registry.remove("run-2").await.unwrap();
+        assert!(registry.load("run-2").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_registry_rejects_path_like_run_ids() {
+        let registry = RunRegistry::new("/tmp");
+        assert!(registry.load("../etc").await.is_err());
+        assert!(registry.load("a/b").await.is_err());
+    }
+
+    #[test]
+    fn test_capture_dirs_stay_inside_the_isolation_dir() {
+        let registry = RunRegistry::new("/state");
+        assert_eq!(
+            registry.capture_dir("screen", "my-session.1"),
+            PathBuf::from("/state/screen/my-session.1")
+        );
+        assert_eq!(
+            registry.capture_dir("screen", ".."),
+            PathBuf::from("/state/screen/%2E.")
+        );
+        assert_eq!(
+            registry.capture_dir("tmux", "."),
+            PathBuf::from("/state/tmux/%2E")
+        );
+        assert_ne!(
+            registry.capture_dir("screen", "a/b"),
+            registry.capture_dir("screen", "a_b")
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_os = "windows"))]
+    async fn test_start_captures_output_and_exit_code() {
+        let state_dir = tempfile::tempdir().unwrap();
+        let registry = RunRegistry::new(state_dir.path());
+        let spec = LaunchSpec {
+            args: vec![
+                "-c".to_string(),
+                "echo out; echo err >&2; exit 3".to_string(),
+            ],
+            ..LaunchSpec::new("sh")
+        };
+
+        let record = registry.start(&spec, "sh", false).await.unwrap();
+        assert!(record.wait(Duration::from_secs(5)).await);
+
+        assert_eq!(record.exit_code().await, Some(3));
+        assert_eq!(
+            std::fs::read_to_string(&record.stdout_log).unwrap(),
+            "out\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&record.stderr_log).unwrap(),
+            "err\n"
+        );
+        assert_eq!(registry.load(&record.run_id).await.unwrap(), record);
+    }
+}
@@ -6,8 +6,8 @@
 //! (auto | plan | readonly | ask) and an OpenCode-compatible `--permission`
 //! JSON policy.
 
+use crate::launch::{LaunchSpec, LaunchStdin};
 use crate::streaming::parse_ndjson;
-use crate::tools::shell::{build_command_head, escape_arg, escape_single_quotes};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -148,49 +148,52 @@ pub fn build_args(options: &AgentBuildOptions) -> Vec<String> {
     args
 }
 
-/// Build complete command string for Agent
+/// Build the shell-free launch spec for Agent
 /// Agent uses stdin for prompt input (NDJSON streaming supported)
 ///
 /// # Arguments
 /// * `options` - Build options
 ///
 /// # Returns
-/// Complete command string
-pub fn build_command(options: &AgentBuildOptions) -> String {
-    let args = build_args(options);
-    let args_str: Vec<String> = args.iter().map(|a| escape_arg(a)).collect();
-    let executable = options.executable.as_deref().unwrap_or("agent");
-    let command_head = format!(
-        "{} {}",
-        build_command_head(executable, &options.extra_env, &[]),
-        args_str.join(" ")
-    )
-    .trim()
-    .to_string();
-
+/// Program, argv, environment and stdin source
+pub fn build_launch_spec(options: &AgentBuildOptions) -> LaunchSpec {
     // In stream-input mode the caller owns the child's stdin and writes the
     // prompt and permission responses as NDJSON frames (per-command approval
     // relay), so no prompt is piped here.
-    if options.stream_input {
-        return command_head;
-    }
-
-    // Agent expects prompt via stdin, combine system and user prompts
-    let combined_prompt = match (&options.system_prompt, &options.prompt) {
-        (Some(sys), Some(prompt)) => format!("{}\n\n{}", sys, prompt),
-        (Some(sys), None) => sys.clone(),
-        (None, Some(prompt)) => prompt.clone(),
-        (None, None) => String::new(),
+    let stdin = if options.stream_input {
+        LaunchStdin::Piped
+    } else if let Some(ref prompt_file) = options.prompt_file {
+        LaunchStdin::File(prompt_file.into())
+    } else {
+        // Agent expects prompt via stdin, combine system and user prompts
+        let combined_prompt = match (&options.system_prompt, &options.prompt) {
+            (Some(sys), Some(prompt)) => format!("{}\n\n{}", sys, prompt),
+            (Some(sys), None) => sys.clone(),
+            (None, Some(prompt)) => prompt.clone(),
+            (None, None) => String::new(),
+        };
+        LaunchStdin::Inline(combined_prompt.into_bytes())
     };
 
-    // Build command with stdin piping
-    let input_command = options.prompt_file.as_ref().map_or_else(
-        || format!("printf '%s' '{}'", escape_single_quotes(&combined_prompt)),
-        |prompt_file| format!("cat {}", escape_arg(prompt_file)),
-    );
-    format!("{} | {}", input_command, command_head)
-        .trim()
-        .to_string()
+    let executable = options.executable.as_deref().unwrap_or("agent");
+    LaunchSpec {
+        args: build_args(options),
+        stdin,
+        ..LaunchSpec::new(executable)
+    }
+    .with_env(&options.extra_env)
+}
+
+/// Build complete command string for Agent
+/// Agent uses stdin for prompt input (NDJSON streaming supported)
+///
+/// # Arguments
+/// * `options` - Build options
+///
+/// # Returns
+/// Complete command string
+pub fn build_command(options: &AgentBuildOptions) -> String {
+    build_launch_spec(options).to_shell_command()
 }
 
 /// Parse JSON messages from Agent output
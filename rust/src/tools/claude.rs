@@ -1,8 +1,8 @@
 //! Claude CLI tool configuration
 //! Based on hive-mind's claude.lib.mjs implementation
 
+use crate::launch::{LaunchSpec, LaunchStdin};
 use crate::streaming::parse_ndjson;
-use crate::tools::shell::{build_command_head, escape_arg};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -100,6 +100,12 @@ pub fn build_args(options: &ClaudeBuildOptions) -> Vec<String> {
         // active instead of bypassing it; the relay answers each request.
         args.push("--permission-mode".to_string());
         args.push("default".to_string());
+        if options.stream_input {
+            // Route can_use_tool requests to stdout instead of a terminal
+            // prompt, so the relay can answer them on stdin
+            args.push("--permission-prompt-tool".to_string());
+            args.push("stdio".to_string());
+        }
     } else if options.read_only {
         args.push("--permission-mode".to_string());
         args.push("plan".to_string());
@@ -187,35 +193,42 @@ pub fn build_args(options: &ClaudeBuildOptions) -> Vec<String> {
     args
 }
 
-/// Build complete command string for Claude
+/// Build the shell-free launch spec for Claude
 ///
 /// # Arguments
 /// * `options` - Build options
 ///
 /// # Returns
-/// Complete command string
-pub fn build_command(options: &ClaudeBuildOptions) -> String {
-    let args = build_args(options);
-    let args_str: Vec<String> = args.iter().map(|a| escape_arg(a)).collect();
+/// Program, argv, environment and stdin source
+pub fn build_launch_spec(options: &ClaudeBuildOptions) -> LaunchSpec {
     let executable = options.executable.as_deref().unwrap_or("claude");
-    let command = format!(
-        "{} {}",
-        build_command_head(executable, &options.extra_env, &[]),
-        args_str.join(" ")
-    )
-    .trim()
-    .to_string();
 
     // In stream-input mode the caller owns the child's stdin and writes the
     // prompt and permission responses as NDJSON frames (per-command approval
     // relay), so no prompt is piped here.
-    if options.stream_input {
-        command
-    } else if let Some(prompt_file) = &options.prompt_file {
-        format!("cat {} | {}", escape_arg(prompt_file), command)
-    } else {
-        command
+    let stdin = match options.prompt_file {
+        _ if options.stream_input => LaunchStdin::Piped,
+        Some(ref prompt_file) => LaunchStdin::File(prompt_file.into()),
+        None => LaunchStdin::Null,
+    };
+
+    LaunchSpec {
+        args: build_args(options),
+        stdin,
+        ..LaunchSpec::new(executable)
     }
+    .with_env(&options.extra_env)
+}
+
+/// Build complete command string for Claude
+///
+/// # Arguments
+/// * `options` - Build options
+///
+/// # Returns
+/// Complete command string
+pub fn build_command(options: &ClaudeBuildOptions) -> String {
+    build_launch_spec(options).to_shell_command()
 }
 
 /// Parse JSON messages from Claude output
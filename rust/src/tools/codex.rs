@@ -1,8 +1,8 @@
 //! Codex CLI tool configuration
 //! Based on hive-mind's codex.lib.mjs implementation
 
+use crate::launch::{LaunchSpec, LaunchStdin};
 use crate::streaming::parse_ndjson;
-use crate::tools::shell::{build_command_head, escape_arg, escape_single_quotes};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -71,6 +71,14 @@ pub struct CodexBuildOptions {
     pub skip_default_safety_flags: bool,
     pub sandbox_mode: Option<String>,
     pub approval_mode: Option<String>,
+    /// Run `codex app-server` for a per-command approval relay: the caller
+    /// owns the child's stdin and sends the prompt and decisions as JSON-RPC
+    /// frames, so no prompt is piped and the run's settings travel in the
+    /// thread request instead of flags. Only `extra_args` and `extra_env`
+    /// apply to the server itself; it takes no autonomy flags, so
+    /// `skip_default_safety_flags` has nothing to skip, and `json`,
+    /// `sandbox_mode` and `approval_mode` are ignored.
+    pub app_server: bool,
 }
 
 /// Build command line arguments for Codex
@@ -122,49 +130,70 @@ pub fn build_args(options: &CodexBuildOptions) -> Vec<String> {
     args
 }
 
-/// Build complete command string for Codex
+/// Build the shell-free launch spec for Codex
 /// Codex uses stdin for prompt input
 ///
 /// # Arguments
 /// * `options` - Build options
 ///
 /// # Returns
-/// Complete command string
-pub fn build_command(options: &CodexBuildOptions) -> String {
-    let args = build_args(options);
-    let args_str: Vec<String> = args.iter().map(|a| escape_arg(a)).collect();
+/// Program, argv, environment and stdin source
+pub fn build_launch_spec(options: &CodexBuildOptions) -> LaunchSpec {
+    let executable = options.executable.as_deref().unwrap_or("codex");
+    if options.app_server {
+        let mut args = vec!["app-server".to_string()];
+        args.extend(options.extra_args.clone());
+        return LaunchSpec {
+            args,
+            stdin: LaunchStdin::Piped,
+            ..LaunchSpec::new(executable)
+        }
+        .with_env(&options.extra_env);
+    }
 
     // Codex expects prompt via stdin, combine system and user prompts
-    let combined_prompt = match (&options.system_prompt, &options.prompt) {
-        (Some(sys), Some(prompt)) => format!("{}\n\n{}", sys, prompt),
-        (Some(sys), None) => sys.clone(),
-        (None, Some(prompt)) => prompt.clone(),
-        (None, None) => String::new(),
-    };
-
-    // Build command with stdin piping
-    let input_command = options.prompt_file.as_ref().map_or_else(
-        || format!("printf '%s' '{}'", escape_single_quotes(&combined_prompt)),
-        |prompt_file| format!("cat {}", escape_arg(prompt_file)),
+    let stdin = options.prompt_file.as_ref().map_or_else(
+        || {
+            let combined_prompt = match (&options.system_prompt, &options.prompt) {
+                (Some(sys), Some(prompt)) => format!("{}\n\n{}", sys, prompt),
+                (Some(sys), None) => sys.clone(),
+                (None, Some(prompt)) => prompt.clone(),
+                (None, None) => String::new(),
+            };
+            LaunchStdin::Inline(combined_prompt.into_bytes())
+        },
+        |prompt_file| LaunchStdin::File(prompt_file.into()),
     );
-    let executable = options.executable.as_deref().unwrap_or("codex");
-    let mut prefix_args = Vec::new();
+
+    // Global approval flags must precede the `exec` subcommand
+    let mut args = Vec::new();
     if options.read_only {
-        prefix_args.push("--ask-for-approval".to_string());
-        prefix_args.push("never".to_string());
+        args.push("--ask-for-approval".to_string());
+        args.push("never".to_string());
     } else if let Some(ref approval_mode) = options.approval_mode {
-        prefix_args.push("--ask-for-approval".to_string());
-        prefix_args.push(approval_mode.clone());
+        args.push("--ask-for-approval".to_string());
+        args.push(approval_mode.clone());
     }
+    args.extend(build_args(options));
 
-    format!(
-        "{} | {} {}",
-        input_command,
-        build_command_head(executable, &options.extra_env, &prefix_args),
-        args_str.join(" ")
-    )
-    .trim()
-    .to_string()
+    LaunchSpec {
+        args,
+        stdin,
+        ..LaunchSpec::new(executable)
+    }
+    .with_env(&options.extra_env)
+}
+
+/// Build complete command string for Codex
+/// Codex uses stdin for prompt input
+///
+/// # Arguments
+/// * `options` - Build options
+///
+/// # Returns
+/// Complete command string
+pub fn build_command(options: &CodexBuildOptions) -> String {
+    build_launch_spec(options).to_shell_command()
 }
 
 /// Parse JSON messages from Codex output
@@ -180,7 +209,8 @@ pub fn parse_output(output: &str) -> Vec<Value> {
 }
 
 /// Extract session/thread ID from Codex output
-/// Codex uses thread_id instead of session_id
+/// Codex uses thread_id instead of session_id; the app-server reports the
+/// thread in its `thread/start` response
 ///
 /// # Arguments
 /// * `output` - Raw output string
@@ -197,6 +227,9 @@ pub fn extract_session_id(output: &str) -> Option<String> {
         if let Some(session_id) = msg.get("session_id").and_then(|v| v.as_str()) {
             return Some(session_id.to_string());
         }
+        if let Some(thread_id) = msg.pointer("/result/thread/id").and_then(|v| v.as_str()) {
+            return Some(thread_id.to_string());
+        }
     }
 
     None
@@ -260,7 +293,7 @@ impl Default for CodexTool {
             supports_system_prompt: false, // System prompt is combined with user prompt
             supports_resume: true,
             supports_read_only: true, // Supports --sandbox read-only
-            supports_ask: false, // Approval is coupled with the sandbox, not a relayable JSON stream
+            supports_ask: true,       // Relayed over the `codex app-server` JSON-RPC protocol
             default_model: "gpt-5.5", // hive-mind PR #1657
         }
     }
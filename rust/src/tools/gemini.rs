@@ -1,8 +1,8 @@
 //! Gemini CLI tool configuration
 //! Based on Google's official gemini-cli: https://github.com/google-gemini/gemini-cli
 
+use crate::launch::{LaunchSpec, LaunchStdin};
 use crate::streaming::parse_ndjson;
-use crate::tools::shell::{build_command_head, escape_arg};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -130,14 +130,14 @@ pub fn build_args(options: &GeminiBuildOptions) -> Vec<String> {
     args
 }
 
-/// Build complete command string for Gemini CLI
+/// Build the shell-free launch spec for Gemini CLI
 ///
 /// # Arguments
 /// * `options` - Build options
 ///
 /// # Returns
-/// Complete command string
-pub fn build_command(options: &GeminiBuildOptions) -> String {
+/// Program, argv, environment and stdin source
+pub fn build_launch_spec(options: &GeminiBuildOptions) -> LaunchSpec {
     // Gemini CLI supports system prompt via GEMINI_SYSTEM_PROMPT env var
     // or via .gemini/system.md file. For now, combine with user prompt.
     let combined_prompt = if options.prompt_file.is_some() {
@@ -157,22 +157,30 @@ pub fn build_command(options: &GeminiBuildOptions) -> String {
         ..options.clone()
     };
 
-    let args = build_args(&modified_options);
-    let args_str: Vec<String> = args.iter().map(|a| escape_arg(a)).collect();
+    let stdin = options
+        .prompt_file
+        .as_ref()
+        .map_or(LaunchStdin::Null, |prompt_file| {
+            LaunchStdin::File(prompt_file.into())
+        });
     let executable = options.executable.as_deref().unwrap_or("gemini");
-    let command = format!(
-        "{} {}",
-        build_command_head(executable, &options.extra_env, &[]),
-        args_str.join(" ")
-    )
-    .trim()
-    .to_string();
-
-    if let Some(prompt_file) = &options.prompt_file {
-        return format!("cat {} | {}", escape_arg(prompt_file), command);
+    LaunchSpec {
+        args: build_args(&modified_options),
+        stdin,
+        ..LaunchSpec::new(executable)
     }
+    .with_env(&options.extra_env)
+}
 
-    command
+/// Build complete command string for Gemini CLI
+///
+/// # Arguments
+/// * `options` - Build options
+///
+/// # Returns
+/// Complete command string
+pub fn build_command(options: &GeminiBuildOptions) -> String {
+    build_launch_spec(options).to_shell_command()
 }
 
 /// Parse JSON messages from Gemini CLI output
@@ -7,8 +7,8 @@ pub mod codex;
 pub mod gemini;
 pub mod opencode;
 pub mod qwen;
-pub(crate) mod shell;
 
+use serde_json::{json, Value};
 use std::collections::HashMap;
 
 pub use agent::{AgentBuildOptions, AgentTool, AgentUsage, ErrorResult};
@@ -270,4 +270,53 @@ pub fn is_tool_supported(tool_name: &str) -> bool {
     ["claude", "codex", "opencode", "agent", "gemini", "qwen"].contains(&tool_name)
 }
 
+/// Extract a tool's token usage from its output as JSON
+///
+/// # Arguments
+/// * `tool` - Name of the tool
+/// * `output` - Combined stdout and stderr of the run
+///
+/// # Returns
+/// Usage object with camelCase counters, or None for tools without usage parsing
+pub fn extract_usage_value(tool: &str, output: &str) -> Option<Value> {
+    match tool {
+        "claude" => {
+            let usage = claude::extract_usage(output);
+            Some(json!({
+                "inputTokens": usage.input_tokens,
+                "outputTokens": usage.output_tokens,
+                "cacheCreationTokens": usage.cache_creation_tokens,
+                "cacheReadTokens": usage.cache_read_tokens,
+            }))
+        }
+        "codex" => {
+            let usage = codex::extract_usage(output);
+            Some(json!({
+                "inputTokens": usage.input_tokens,
+                "outputTokens": usage.output_tokens,
+            }))
+        }
+        "opencode" => {
+            let usage = opencode::extract_usage(output);
+            Some(json!({
+                "inputTokens": usage.input_tokens,
+                "outputTokens": usage.output_tokens,
+            }))
+        }
+        "agent" => {
+            let usage = agent::extract_usage(output);
+            Some(json!({
+                "inputTokens": usage.input_tokens,
+                "outputTokens": usage.output_tokens,
+                "reasoningTokens": usage.reasoning_tokens,
+                "cacheReadTokens": usage.cache_read_tokens,
+                "cacheWriteTokens": usage.cache_write_tokens,
+                "totalCost": usage.total_cost,
+                "stepCount": usage.step_count,
+            }))
+        }
+        _ => None,
+    }
+}
+
 // Tests are in rust/tests/tools_tests.rs
@@ -1,8 +1,8 @@
 //! OpenCode CLI tool configuration
 //! Based on hive-mind's opencode.lib.mjs implementation
 
+use crate::launch::{LaunchSpec, LaunchStdin};
 use crate::streaming::parse_ndjson;
-use crate::tools::shell::{build_command_head, escape_arg, escape_single_quotes};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -86,32 +86,29 @@ pub fn build_args(options: &OpencodeBuildOptions) -> Vec<String> {
     args
 }
 
-/// Build complete command string for OpenCode
+/// Build the shell-free launch spec for OpenCode
 /// OpenCode uses stdin for prompt input
 ///
 /// # Arguments
 /// * `options` - Build options
 ///
 /// # Returns
-/// Complete command string
-pub fn build_command(options: &OpencodeBuildOptions) -> String {
-    let args = build_args(options);
-    let args_str: Vec<String> = args.iter().map(|a| escape_arg(a)).collect();
-
+/// Program, argv, environment and stdin source
+pub fn build_launch_spec(options: &OpencodeBuildOptions) -> LaunchSpec {
     // OpenCode expects prompt via stdin, combine system and user prompts
-    let combined_prompt = match (&options.system_prompt, &options.prompt) {
-        (Some(sys), Some(prompt)) => format!("{}\n\n{}", sys, prompt),
-        (Some(sys), None) => sys.clone(),
-        (None, Some(prompt)) => prompt.clone(),
-        (None, None) => String::new(),
-    };
-
-    // Build command with stdin piping
-    let input_command = options.prompt_file.as_ref().map_or_else(
-        || format!("printf '%s' '{}'", escape_single_quotes(&combined_prompt)),
-        |prompt_file| format!("cat {}", escape_arg(prompt_file)),
+    let stdin = options.prompt_file.as_ref().map_or_else(
+        || {
+            let combined_prompt = match (&options.system_prompt, &options.prompt) {
+                (Some(sys), Some(prompt)) => format!("{}\n\n{}", sys, prompt),
+                (Some(sys), None) => sys.clone(),
+                (None, Some(prompt)) => prompt.clone(),
+                (None, None) => String::new(),
+            };
+            LaunchStdin::Inline(combined_prompt.into_bytes())
+        },
+        |prompt_file| LaunchStdin::File(prompt_file.into()),
     );
-    let executable = options.executable.as_deref().unwrap_or("opencode");
+
     let mut extra_env = Vec::new();
     if options.read_only {
         extra_env.push((
@@ -121,14 +118,25 @@ pub fn build_command(options: &OpencodeBuildOptions) -> String {
     }
     extra_env.extend(options.extra_env.clone());
 
-    format!(
-        "{} | {} {}",
-        input_command,
-        build_command_head(executable, &extra_env, &[]),
-        args_str.join(" ")
-    )
-    .trim()
-    .to_string()
+    let executable = options.executable.as_deref().unwrap_or("opencode");
+    LaunchSpec {
+        args: build_args(options),
+        stdin,
+        ..LaunchSpec::new(executable)
+    }
+    .with_env(&extra_env)
+}
+
+/// Build complete command string for OpenCode
+/// OpenCode uses stdin for prompt input
+///
+/// # Arguments
+/// * `options` - Build options
+///
+/// # Returns
+/// Complete command string
+pub fn build_command(options: &OpencodeBuildOptions) -> String {
+    build_launch_spec(options).to_shell_command()
 }
 
 /// Parse JSON messages from OpenCode output
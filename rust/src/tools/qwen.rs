@@ -2,8 +2,8 @@
 //! Based on https://github.com/QwenLM/qwen-code
 //! Qwen Code is an open-source AI agent optimized for Qwen3-Coder models
 
+use crate::launch::{LaunchSpec, LaunchStdin};
 use crate::streaming::parse_ndjson;
-use crate::tools::shell::{build_command_head, escape_arg};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -137,14 +137,14 @@ pub fn build_args(options: &QwenBuildOptions) -> Vec<String> {
     args
 }
 
-/// Build complete command string for Qwen Code
+/// Build the shell-free launch spec for Qwen Code
 ///
 /// # Arguments
 /// * `options` - Build options
 ///
 /// # Returns
-/// Complete command string
-pub fn build_command(options: &QwenBuildOptions) -> String {
+/// Program, argv, environment and stdin source
+pub fn build_launch_spec(options: &QwenBuildOptions) -> LaunchSpec {
     // Create a modified options with combined prompts
     let mut combined_options = options.clone();
 
@@ -164,22 +164,30 @@ pub fn build_command(options: &QwenBuildOptions) -> String {
     }
     combined_options.system_prompt = None;
 
-    let args = build_args(&combined_options);
-    let args_str: Vec<String> = args.iter().map(|a| escape_arg(a)).collect();
+    let stdin = options
+        .prompt_file
+        .as_ref()
+        .map_or(LaunchStdin::Null, |prompt_file| {
+            LaunchStdin::File(prompt_file.into())
+        });
     let executable = options.executable.as_deref().unwrap_or("qwen");
-    let command = format!(
-        "{} {}",
-        build_command_head(executable, &options.extra_env, &[]),
-        args_str.join(" ")
-    )
-    .trim()
-    .to_string();
-
-    if let Some(prompt_file) = &options.prompt_file {
-        return format!("cat {} | {}", escape_arg(prompt_file), command);
+    LaunchSpec {
+        args: build_args(&combined_options),
+        stdin,
+        ..LaunchSpec::new(executable)
     }
+    .with_env(&options.extra_env)
+}
 
-    command
+/// Build complete command string for Qwen Code
+///
+/// # Arguments
+/// * `options` - Build options
+///
+/// # Returns
+/// Complete command string
+pub fn build_command(options: &QwenBuildOptions) -> String {
+    build_launch_spec(options).to_shell_command()
 }
 
 /// Parse JSON messages from Qwen Code output
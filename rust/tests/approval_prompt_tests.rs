@@ -0,0 +1,138 @@
+//! Tests for the interactive approval prompt of attached ask-mode runs
+
+use agent_commander::{
+    normalize_permission_request, prompt_for_decision, render_permission_request,
+    NormalizedPermissionRequest,
+};
+use serde_json::json;
+use std::io::Cursor;
+
+fn claude_request(tool_name: &str, input: serde_json::Value) -> NormalizedPermissionRequest {
+    normalize_permission_request(
+        "claude",
+        &json!({
+            "type": "control_request",
+            "request_id": "r1",
+            "request": { "subtype": "can_use_tool", "tool_name": tool_name, "input": input },
+        }),
+    )
+    .unwrap()
+}
+
+fn agent_request() -> NormalizedPermissionRequest {
+    normalize_permission_request(
+        "agent",
+        &json!({
+            "type": "permission_request",
+            "permissionID": "p1",
+            "tool": "bash",
+            "pattern": "git push *",
+            "metadata": { "command": "git push origin main" },
+        }),
+    )
+    .unwrap()
+}
+
+fn prompt(request: &NormalizedPermissionRequest, answers: &str) -> (String, String, String) {
+    let mut output = Vec::new();
+    let decision = prompt_for_decision(request, &mut Cursor::new(answers), &mut output);
+    (
+        decision.decision,
+        decision.message.unwrap_or_default(),
+        String::from_utf8(output).unwrap(),
+    )
+}
+
+#[test]
+fn renders_tool_command_scope_and_edits() {
+    let bash = render_permission_request(&claude_request("Bash", json!({ "command": "ls -la" })));
+    assert_eq!(
+        bash,
+        "Permission request from claude (scope: session)\n  Tool:    Bash\n  Command: ls -la\n"
+    );
+
+    let edit = render_permission_request(&claude_request(
+        "Edit",
+        json!({ "file_path": "/repo/a.rs", "old_string": "let a = 1;", "new_string": "let a = 2;\nlet b = 3;" }),
+    ));
+    assert!(edit.contains("  Command: /repo/a.rs\n"));
+    assert!(edit.ends_with("  - let a = 1;\n  + let a = 2;\n  + let b = 3;\n"));
+
+    let agent = render_permission_request(&agent_request());
+    assert!(agent.starts_with("Permission request from agent (scope: session)\n  Tool:    bash\n"));
+    assert!(agent.contains("  Command: git push origin main\n  Pattern: git push *\n"));
+}
+
+#[test]
+fn escapes_control_characters_the_agent_could_hide_a_request_with() {
+    let bash = render_permission_request(&claude_request(
+        "Bash",
+        json!({ "command": "rm -rf ~\u{1b}[2K\rls -la" }),
+    ));
+    assert!(
+        bash.contains("  Command: rm -rf ~\\u{1b}[2K\\rls -la\n"),
+        "{}",
+        bash
+    );
+    assert!(!bash.contains('\u{1b}') && !bash.contains('\r'));
+
+    let write = render_permission_request(&claude_request(
+        "Write",
+        json!({ "file_path": "a.txt", "content": "\tok\u{8}\u{8}\u{202e}txt.exe\nnext" }),
+    ));
+    assert!(
+        write.ends_with("  + \tok\\u{8}\\u{8}\\u{202e}txt.exe\n  + next\n"),
+        "{}",
+        write
+    );
+}
+
+#[test]
+fn accepts_letters_and_words_and_asks_again_otherwise() {
+    let request = claude_request("Bash", json!({ "command": "ls" }));
+    for (answers, expected) in [
+        ("o\n", "once"),
+        ("ALWAYS\n", "always"),
+        (" r \n", "reject"),
+        ("yes\n\na\n", "always"),
+    ] {
+        assert_eq!(prompt(&request, answers).0, expected, "{:?}", answers);
+    }
+
+    let (decision, _, output) = prompt(&request, "maybe\nonce\n");
+    assert_eq!(decision, "once");
+    assert!(output.contains("Command: ls"));
+    assert_eq!(
+        output
+            .matches("Allow? [o]nce, [a]lways, [r]eject, reject with [m]essage: ")
+            .count(),
+        2
+    );
+    assert!(output.contains("Please answer one of the letters in brackets."));
+}
+
+#[test]
+fn rejects_with_a_message_for_claude_only() {
+    let request = claude_request("Bash", json!({ "command": "rm -rf build" }));
+    let (decision, message, output) = prompt(&request, "m\nUse cargo clean instead\n");
+    assert_eq!(decision, "reject");
+    assert_eq!(message, "Use cargo clean instead");
+    assert!(output.ends_with("Message for the agent: "));
+    // An empty message is a plain rejection
+    let (decision, message, _) = prompt(&request, "m\n\n");
+    assert_eq!((decision.as_str(), message.as_str()), ("reject", ""));
+
+    // Agent cannot show a message, so the option is not offered
+    let (decision, _, output) = prompt(&agent_request(), "m\nr\n");
+    assert_eq!(decision, "reject");
+    assert!(output.contains("Allow? [o]nce, [a]lways, [r]eject: "));
+    assert!(!output.contains("[m]essage"));
+    assert!(output.contains("Please answer"));
+}
+
+#[test]
+fn closed_input_rejects() {
+    let request = claude_request("Bash", json!({ "command": "ls" }));
+    assert_eq!(prompt(&request, "").0, "reject");
+    assert_eq!(prompt(&request, "what\n").0, "reject");
+}
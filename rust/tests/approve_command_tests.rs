@@ -0,0 +1,156 @@
+//! Tests for external approver commands (`start-agent --approve-command`)
+
+#![cfg(not(target_os = "windows"))]
+
+use agent_commander::{
+    normalize_permission_request, AsyncPermissionHandler, AsyncPermissionRelay,
+    NormalizedPermissionRequest,
+};
+use serde_json::json;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+fn write_approver(dir: &Path, name: &str, script: &str) -> String {
+    let path = dir.join(name);
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+fn bash_request(id: &str, command: &str) -> NormalizedPermissionRequest {
+    normalize_permission_request(
+        "claude",
+        &json!({
+            "type": "control_request",
+            "request_id": id,
+            "session_id": "s1",
+            "request": {
+                "subtype": "can_use_tool",
+                "tool_name": "Bash",
+                "input": { "command": command },
+            },
+        }),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn approver_reads_the_request_and_prints_the_decision() {
+    let dir = tempfile::tempdir().unwrap();
+    let seen = dir.path().join("seen.json");
+    let approver = write_approver(
+        dir.path(),
+        "approve",
+        &format!(
+            r#"#!/usr/bin/env bash
+read -r request
+echo "$request" > {}
+case "$request" in
+  *'"command":"rm '*) echo reject ;;
+  *) echo always ;;
+esac
+"#,
+            seen.display()
+        ),
+    );
+    let handler = AsyncPermissionHandler::command(&approver);
+
+    let decision = handler.decide(bash_request("r1", "cargo test")).await;
+    assert_eq!(decision.decision, "always");
+    assert_eq!(decision.source.as_deref(), Some("approve-command"));
+    let request: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&seen).unwrap()).unwrap();
+    assert_eq!(request["type"], "permission_request");
+    assert_eq!(request["tool"], "claude");
+    assert_eq!(request["id"], "r1");
+    assert_eq!(request["session_id"], "s1");
+    assert_eq!(request["tool_name"], "Bash");
+    assert_eq!(request["command"], "cargo test");
+    assert_eq!(request["input"], json!({ "command": "cargo test" }));
+
+    let decision = handler.decide(bash_request("r2", "rm -rf /")).await;
+    assert_eq!(decision.decision, "reject");
+    assert_eq!(decision.message, None);
+}
+
+#[tokio::test]
+async fn approver_failures_reject_the_request() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, script, reason) in [
+        (
+            "crash",
+            "#!/usr/bin/env bash\necho once\nexit 3\n",
+            "exited with",
+        ),
+        (
+            "chatty",
+            "#!/usr/bin/env bash\necho 'sure, go ahead'\n",
+            "answered",
+        ),
+        ("silent", "#!/usr/bin/env bash\n", "answered \"\""),
+    ] {
+        let approver = write_approver(dir.path(), name, script);
+        let decision = AsyncPermissionHandler::command(&approver)
+            .decide(bash_request("r1", "ls"))
+            .await;
+        assert_eq!(decision.decision, "reject", "{}", name);
+        assert!(
+            decision.message.as_deref().unwrap().contains(reason),
+            "{}: {:?}",
+            name,
+            decision.message
+        );
+    }
+
+    let missing = dir.path().join("missing").to_string_lossy().into_owned();
+    let decision = AsyncPermissionHandler::command(&missing)
+        .decide(bash_request("r1", "ls"))
+        .await;
+    assert_eq!(decision.decision, "reject");
+    assert!(decision
+        .message
+        .unwrap()
+        .contains("Failed to start approver"));
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn slow_approvers_are_killed_when_the_request_times_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("approver.pid");
+    let approver = write_approver(
+        dir.path(),
+        "slow",
+        &format!(
+            "#!/usr/bin/env bash\necho $$ > {}\nexec sleep 30\n",
+            pid_file.display()
+        ),
+    );
+    let (frames, _written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay = AsyncPermissionRelay::new(
+        "claude",
+        AsyncPermissionHandler::command(&approver),
+        Some(Duration::from_millis(500)),
+        frames,
+    );
+    relay.handle_message(&bash_request("r1", "ls").raw);
+    let answered = relay.finish().await;
+
+    assert!(answered[0].timed_out);
+    assert_eq!(answered[0].decision, "reject");
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    // The approver is gone shortly after its decision was abandoned
+    let mut alive = true;
+    for _ in 0..50 {
+        alive = Path::new(&format!("/proc/{}", pid.trim())).exists()
+            && !std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()))
+                .unwrap_or_default()
+                .contains(") Z ");
+        if !alive {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(!alive, "approver {} still running", pid.trim());
+}
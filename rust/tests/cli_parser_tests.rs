@@ -0,0 +1,784 @@
+//! Tests for CLI argument parsing and validation
+
+use agent_commander::cli_parser::{
+    parse_args, parse_bwrap_options, parse_docker_exec_options, parse_docker_options,
+    parse_duration_seconds, parse_start_agent_args, parse_stop_agent_args, parse_worktree_options,
+    validate_start_agent_options, validate_stop_agent_options, StartAgentOptions, StopAgentOptions,
+};
+use agent_commander::DockerRemoval;
+use std::time::Duration;
+
+#[test]
+fn test_parse_args_basic_flags() {
+    let args: Vec<String> = vec!["--foo".into(), "bar".into(), "--baz".into()];
+    let result = parse_args(&args);
+
+    assert_eq!(result.get("foo"), Some(&"bar".to_string()));
+    assert!(result.has_flag("baz"));
+}
+
+#[test]
+fn test_parse_args_with_positional() {
+    let args: Vec<String> = vec!["--foo".into(), "bar".into(), "positional1".into()];
+    let result = parse_args(&args);
+
+    assert_eq!(result.get("foo"), Some(&"bar".to_string()));
+    assert_eq!(result.positional, vec!["positional1"]);
+}
+
+#[test]
+fn test_parse_start_agent_args_basic() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--prompt".into(),
+        "Hello".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.tool, Some("claude".to_string()));
+    assert_eq!(result.working_directory, Some("/tmp/test".to_string()));
+    assert_eq!(result.prompt, Some("Hello".to_string()));
+    assert_eq!(result.isolation, "none");
+}
+
+#[test]
+fn test_parse_start_agent_args_with_isolation() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--isolation".into(),
+        "screen".into(),
+        "--screen-name".into(),
+        "my-session".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.isolation, "screen");
+    assert_eq!(result.screen_name, Some("my-session".to_string()));
+}
+
+#[test]
+fn test_parse_start_agent_args_dry_run() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--dry-run".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(result.dry_run);
+}
+
+#[test]
+fn test_parse_start_agent_args_read_only() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--read-only".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(result.read_only);
+}
+
+#[test]
+fn test_parse_start_agent_args_with_raw_passthrough_options() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--tool-executable".into(),
+        "/opt/claude".into(),
+        "--tool-env".into(),
+        "MCP_TIMEOUT=10000".into(),
+        "--tool-env".into(),
+        "CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC=1".into(),
+        "--tool-arg".into(),
+        "--mcp-config".into(),
+        "--tool-arg".into(),
+        "/tmp/mcp.json".into(),
+        "--skip-default-safety-flags".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.tool_executable, Some("/opt/claude".to_string()));
+    assert_eq!(
+        result.tool_env,
+        vec![
+            "MCP_TIMEOUT=10000".to_string(),
+            "CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC=1".to_string()
+        ]
+    );
+    assert_eq!(
+        result.tool_args,
+        vec!["--mcp-config".to_string(), "/tmp/mcp.json".to_string()]
+    );
+    assert!(result.skip_default_safety_flags);
+}
+
+#[test]
+fn test_parse_start_agent_args_approve_each() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--approve-each".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(result.approve_each);
+}
+
+#[test]
+fn test_validate_approve_each_needs_an_attached_local_run() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--approve-each".into(),
+    ];
+    let options = parse_start_agent_args(&args);
+    assert!(validate_start_agent_options(&options).valid);
+
+    for options in [
+        StartAgentOptions {
+            detached: true,
+            ..options.clone()
+        },
+        StartAgentOptions {
+            isolation: "tmux".to_string(),
+            tmux_session: Some("ask".to_string()),
+            ..options
+        },
+    ] {
+        let result = validate_start_agent_options(&options);
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("--approve-each is only supported for attached runs")));
+    }
+}
+
+#[test]
+fn test_approve_command_needs_approve_each() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--approve-command".into(),
+        "./approve.sh".into(),
+        "--approve-timeout".into(),
+        "5".into(),
+    ];
+    let options = parse_start_agent_args(&args);
+    assert_eq!(options.approve_command.as_deref(), Some("./approve.sh"));
+    assert_eq!(options.approve_timeout.as_deref(), Some("5"));
+    let result = validate_start_agent_options(&options);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--approve-command and --approve-timeout require --approve-each")));
+
+    let options = StartAgentOptions {
+        approve_each: true,
+        ..options
+    };
+    assert!(validate_start_agent_options(&options).valid);
+
+    let options = StartAgentOptions {
+        approve_timeout: Some("soon".to_string()),
+        ..options
+    };
+    let result = validate_start_agent_options(&options);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--approve-timeout must be a number of seconds")));
+}
+
+#[test]
+fn test_parse_start_agent_args_permission_mode_ask_alias() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--permission-mode".into(),
+        "ask".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(result.approve_each);
+}
+
+#[test]
+fn test_parse_start_agent_args_permission_mode_other_is_not_approve_each() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--permission-mode".into(),
+        "default".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(!result.approve_each);
+}
+
+#[test]
+fn test_parse_start_agent_args_plan_only_alias() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--plan-only".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(result.read_only);
+}
+
+#[test]
+fn test_parse_start_agent_args_with_model_and_fallback_model() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--model".into(),
+        "opus".into(),
+        "--fallback-model".into(),
+        "sonnet".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.model, Some("opus".to_string()));
+    assert_eq!(result.fallback_model, Some("sonnet".to_string()));
+}
+
+#[test]
+fn test_parse_start_agent_args_with_session_management_options() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--resume".into(),
+        "abc123".into(),
+        "--session-id".into(),
+        "123e4567-e89b-12d3-a456-426614174000".into(),
+        "--fork-session".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.resume, Some("abc123".to_string()));
+    assert_eq!(
+        result.session_id,
+        Some("123e4567-e89b-12d3-a456-426614174000".to_string())
+    );
+    assert!(result.fork_session);
+}
+
+#[test]
+fn test_parse_start_agent_args_with_append_system_prompt() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--system-prompt".into(),
+        "You are helpful".into(),
+        "--append-system-prompt".into(),
+        "Extra instructions".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.system_prompt, Some("You are helpful".to_string()));
+    assert_eq!(
+        result.append_system_prompt,
+        Some("Extra instructions".to_string())
+    );
+}
+
+#[test]
+fn test_parse_start_agent_args_with_prompt_file() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "codex".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--prompt-file".into(),
+        "/tmp/prompt.txt".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.prompt_file, Some("/tmp/prompt.txt".to_string()));
+}
+
+#[test]
+fn test_parse_start_agent_args_with_verbose_and_replay_user_messages() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--verbose".into(),
+        "--replay-user-messages".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(result.verbose);
+    assert!(result.replay_user_messages);
+}
+
+#[test]
+fn test_parse_start_agent_args_defaults_for_new_options() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert!(!result.verbose);
+    assert!(!result.replay_user_messages);
+    assert!(!result.fork_session);
+    assert!(result.model.is_none());
+    assert!(result.fallback_model.is_none());
+    assert!(result.resume.is_none());
+    assert!(result.session_id.is_none());
+    assert!(result.append_system_prompt.is_none());
+    assert!(result.prompt_file.is_none());
+}
+
+#[test]
+fn test_parse_stop_agent_args_screen() {
+    let args: Vec<String> = vec![
+        "--isolation".into(),
+        "screen".into(),
+        "--screen-name".into(),
+        "my-session".into(),
+    ];
+    let result = parse_stop_agent_args(&args);
+
+    assert_eq!(result.isolation, Some("screen".to_string()));
+    assert_eq!(result.screen_name, Some("my-session".to_string()));
+}
+
+#[test]
+fn test_validate_start_agent_options_valid() {
+    let options = StartAgentOptions {
+        tool: Some("claude".to_string()),
+        working_directory: Some("/tmp/test".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+    let result = validate_start_agent_options(&options);
+
+    assert!(result.valid);
+    assert!(result.errors.is_empty());
+}
+
+#[test]
+fn test_validate_start_agent_options_missing_tool() {
+    let options = StartAgentOptions {
+        working_directory: Some("/tmp/test".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+    let result = validate_start_agent_options(&options);
+
+    assert!(!result.valid);
+    assert!(result.errors.iter().any(|e| e.contains("tool")));
+}
+
+#[test]
+fn test_validate_start_agent_options_screen_without_name() {
+    let options = StartAgentOptions {
+        tool: Some("claude".to_string()),
+        working_directory: Some("/tmp/test".to_string()),
+        isolation: "screen".to_string(),
+        ..Default::default()
+    };
+    let result = validate_start_agent_options(&options);
+
+    assert!(!result.valid);
+    assert!(result.errors.iter().any(|e| e.contains("screen-name")));
+}
+
+#[test]
+fn test_parse_start_agent_args_timeout() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--timeout".into(),
+        "90".into(),
+        "--interrupt-grace".into(),
+        "2.5".into(),
+    ];
+    let result = parse_start_agent_args(&args);
+
+    assert_eq!(result.timeout, Some("90".to_string()));
+    assert_eq!(
+        result
+            .interrupt_grace
+            .as_deref()
+            .and_then(parse_duration_seconds),
+        Some(Duration::from_millis(2500))
+    );
+    assert!(result.terminate_grace.is_none());
+    assert!(validate_start_agent_options(&result).valid);
+}
+
+#[test]
+fn test_validate_start_agent_options_invalid_timeout() {
+    let options = StartAgentOptions {
+        tool: Some("claude".to_string()),
+        working_directory: Some("/tmp/test".to_string()),
+        isolation: "screen".to_string(),
+        screen_name: Some("my-session".to_string()),
+        timeout: Some("soon".to_string()),
+        ..Default::default()
+    };
+    let result = validate_start_agent_options(&options);
+
+    assert!(!result.valid);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--timeout must be a number")));
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("without isolation")));
+}
+
+#[test]
+fn test_validate_stop_agent_options_valid() {
+    let options = StopAgentOptions {
+        isolation: Some("screen".to_string()),
+        screen_name: Some("my-session".to_string()),
+        ..Default::default()
+    };
+    let result = validate_stop_agent_options(&options);
+
+    assert!(result.valid);
+    assert!(result.errors.is_empty());
+}
+
+#[test]
+fn test_validate_stop_agent_options_missing_isolation() {
+    let options = StopAgentOptions::default();
+    let result = validate_stop_agent_options(&options);
+
+    assert!(!result.valid);
+    assert!(result.errors.iter().any(|e| e.contains("isolation")));
+}
+
+#[test]
+fn test_validate_stop_agent_options_tracked_run() {
+    let args: Vec<String> = vec!["--run-id".into(), "run-1".into()];
+    let result = parse_stop_agent_args(&args);
+
+    assert_eq!(result.run_id, Some("run-1".to_string()));
+    assert!(validate_stop_agent_options(&result).valid);
+
+    let options = StopAgentOptions {
+        isolation: Some("none".to_string()),
+        pid: Some("4242".to_string()),
+        ..Default::default()
+    };
+    assert!(validate_stop_agent_options(&options).valid);
+}
+
+#[test]
+fn test_validate_stop_agent_options_rejects_bad_tracked_run() {
+    let options = StopAgentOptions {
+        isolation: Some("screen".to_string()),
+        screen_name: Some("my-session".to_string()),
+        pid: Some("not-a-pid".to_string()),
+        run_id: Some("run-1".to_string()),
+        ..Default::default()
+    };
+    let result = validate_stop_agent_options(&options);
+
+    assert!(!result.valid);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("cannot be used together")));
+    assert!(result.errors.iter().any(|e| e.contains("--pid must be")));
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("only supported for isolation none")));
+
+    let options = StopAgentOptions {
+        isolation: Some("none".to_string()),
+        ..Default::default()
+    };
+    assert!(!validate_stop_agent_options(&options).valid);
+}
+
+#[test]
+fn test_parse_docker_options() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--isolation".into(),
+        "docker".into(),
+        "--container-name".into(),
+        "agent-1".into(),
+        "--docker-image".into(),
+        "node:20".into(),
+        "--docker-volume".into(),
+        "/data:/data:ro".into(),
+        "--docker-volume".into(),
+        "/cache:/root/.cache".into(),
+        "--docker-network".into(),
+        "none".into(),
+        "--docker-memory".into(),
+        "2g".into(),
+        "--docker-env".into(),
+        "ANTHROPIC_API_KEY".into(),
+        "--docker-rm".into(),
+        "keep".into(),
+    ];
+    let options = parse_start_agent_args(&args);
+    assert!(validate_start_agent_options(&options).valid);
+
+    let docker = parse_docker_options(&options).unwrap();
+    assert_eq!(docker.image, "node:20");
+    assert_eq!(docker.volumes.len(), 2);
+    assert!(docker.volumes[0].read_only);
+    assert!(!docker.volumes[1].read_only);
+    assert_eq!(docker.network, Some("none".to_string()));
+    assert_eq!(docker.memory, Some("2g".to_string()));
+    assert_eq!(docker.env_passthrough, vec!["ANTHROPIC_API_KEY"]);
+    assert_eq!(docker.removal, DockerRemoval::Keep);
+}
+
+#[test]
+fn test_validate_docker_options() {
+    let options = StartAgentOptions {
+        tool: Some("claude".to_string()),
+        working_directory: Some("/tmp/test".to_string()),
+        isolation: "docker".to_string(),
+        container_name: Some("agent-1".to_string()),
+        docker_volumes: vec!["no-target".to_string()],
+        docker_rm: Some("sometimes".to_string()),
+        ..Default::default()
+    };
+    assert!(!validate_start_agent_options(&options).valid);
+
+    let options = StartAgentOptions {
+        tool: Some("claude".to_string()),
+        working_directory: Some("/tmp/test".to_string()),
+        isolation: "none".to_string(),
+        docker_image: Some("node:20".to_string()),
+        ..Default::default()
+    };
+    let result = validate_start_agent_options(&options);
+    assert!(!result.valid);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("require --isolation docker")));
+}
+
+#[test]
+fn test_validate_docker_exec_options() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--isolation".into(),
+        "docker-exec".into(),
+        "--container-name".into(),
+        "devbox".into(),
+        "--exec-workdir".into(),
+        "/workspace".into(),
+        "--exec-user".into(),
+        "1000:1000".into(),
+    ];
+    let options = parse_start_agent_args(&args);
+    assert!(validate_start_agent_options(&options).valid);
+    let exec = parse_docker_exec_options(&options).unwrap();
+    assert_eq!(exec.working_directory, Some("/workspace".to_string()));
+    assert_eq!(exec.user, Some("1000:1000".to_string()));
+
+    let options = StartAgentOptions {
+        exec_workdir: Some("workspace".to_string()),
+        container_name: None,
+        ..options
+    };
+    assert_eq!(validate_start_agent_options(&options).errors.len(), 2);
+
+    let options = StopAgentOptions {
+        isolation: Some("docker-exec".to_string()),
+        container_name: Some("devbox".to_string()),
+        run_id: Some("agent-1".to_string()),
+        ..Default::default()
+    };
+    assert!(validate_stop_agent_options(&options).valid);
+
+    let options = StopAgentOptions {
+        run_id: None,
+        pid: Some("42".to_string()),
+        ..options
+    };
+    let result = validate_stop_agent_options(&options);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--run-id is required")));
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--pid is only supported")));
+}
+
+#[test]
+fn test_validate_tmux_isolation() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--isolation".into(),
+        "tmux".into(),
+    ];
+    let options = parse_start_agent_args(&args);
+    let result = validate_start_agent_options(&options);
+    assert!(!result.valid);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--tmux-session is required")));
+
+    let options = StartAgentOptions {
+        tmux_session: Some("my-agent".to_string()),
+        ..options
+    };
+    assert!(validate_start_agent_options(&options).valid);
+
+    let args: Vec<String> = vec![
+        "--isolation".into(),
+        "tmux".into(),
+        "--tmux-session".into(),
+        "my-agent".into(),
+    ];
+    let options = parse_stop_agent_args(&args);
+    assert_eq!(options.tmux_session, Some("my-agent".to_string()));
+    assert!(validate_stop_agent_options(&options).valid);
+}
+
+#[test]
+fn test_validate_bwrap_isolation() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "codex".into(),
+        "--working-directory".into(),
+        "/tmp/test".into(),
+        "--isolation".into(),
+        "bwrap".into(),
+        "--bwrap-unshare-net".into(),
+        "--bwrap-writable".into(),
+        "/home/me/.codex".into(),
+        "--timeout".into(),
+        "60".into(),
+    ];
+    let options = parse_start_agent_args(&args);
+    assert!(validate_start_agent_options(&options).valid);
+    let bwrap = parse_bwrap_options(&options).unwrap();
+    assert!(bwrap.unshare_net);
+    assert_eq!(bwrap.writable_paths, vec!["/home/me/.codex"]);
+
+    let options = StartAgentOptions {
+        isolation: "none".to_string(),
+        ..options
+    };
+    let result = validate_start_agent_options(&options);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--bwrap-* options require --isolation bwrap")));
+
+    let options = StopAgentOptions {
+        isolation: Some("bwrap".to_string()),
+        run_id: Some("run-1".to_string()),
+        ..Default::default()
+    };
+    assert!(validate_stop_agent_options(&options).valid);
+}
+
+#[test]
+fn test_validate_worktree_options() {
+    let args: Vec<String> = vec![
+        "--tool".into(),
+        "claude".into(),
+        "--working-directory".into(),
+        "/repo".into(),
+        "--worktree".into(),
+        "--worktree-branch".into(),
+        "agent/issue-42".into(),
+        "--worktree-base".into(),
+        "main".into(),
+        "--worktree-cleanup".into(),
+    ];
+    let options = parse_start_agent_args(&args);
+    assert!(validate_start_agent_options(&options).valid);
+    let worktree = parse_worktree_options(&options).unwrap().unwrap();
+    assert_eq!(worktree.branch.as_deref(), Some("agent/issue-42"));
+    assert_eq!(worktree.base.as_deref(), Some("main"));
+    assert!(worktree.cleanup);
+
+    let options = StartAgentOptions {
+        worktree: false,
+        ..options
+    };
+    let result = validate_start_agent_options(&options);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--worktree-* options require --worktree")));
+
+    let options = StopAgentOptions {
+        isolation: Some("tmux".to_string()),
+        tmux_session: Some("my-agent".to_string()),
+        worktree: true,
+        ..Default::default()
+    };
+    let result = validate_stop_agent_options(&options);
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| e.contains("--worktree-branch or --worktree-path is required")));
+
+    let options = StopAgentOptions {
+        worktree_branch: Some("agent/issue-42".to_string()),
+        ..options
+    };
+    assert!(validate_stop_agent_options(&options).valid);
+}
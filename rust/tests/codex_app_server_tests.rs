@@ -0,0 +1,255 @@
+//! Tests for the codex approval relay over the app-server JSON-RPC protocol
+
+use agent_commander::{
+    build_app_server_opening_frames, build_decision_response, normalize_permission_request,
+    render_permission_request, AgentOptions, NormalizedPermissionRequest, PermissionDecision,
+    PermissionPolicy,
+};
+use serde_json::{json, Value};
+
+/// Stand-in for `codex app-server`: answers the handshake, asks to run a
+/// command and to add a file, sends a request the client does not handle,
+/// and finally reports every frame the client sent
+#[cfg(not(target_os = "windows"))]
+const FAKE_APP_SERVER: &str = r#"#!/usr/bin/env bash
+[ "$1" = app-server ] || { echo "expected app-server, got: $*" >&2; exit 2; }
+read -r initialize
+read -r initialized
+read -r thread
+echo '{"id":0,"result":{"userAgent":"fake-codex"}}'
+echo '{"id":1,"result":{"thread":{"id":"thr_1"}}}'
+read -r turn
+echo '{"id":2,"result":{"turn":{"id":"turn_1","status":"inProgress"}}}'
+echo '{"id":7,"method":"item/commandExecution/requestApproval","params":{"threadId":"thr_1","turnId":"turn_1","itemId":"item_1","reason":"Run the tests","command":["bash","-lc","cargo test"]}}'
+read -r command_decision
+echo '{"method":"item/started","params":{"threadId":"thr_1","turnId":"turn_1","item":{"type":"fileChange","id":"item_2","status":"inProgress","changes":[{"path":"notes.md","kind":{"type":"add"},"diff":"hello\n"}]}}}'
+echo '{"id":"req-8","method":"item/fileChange/requestApproval","params":{"threadId":"thr_1","turnId":"turn_1","itemId":"item_2"}}'
+read -r change_decision
+echo '{"id":9,"method":"item/tool/requestUserInput","params":{"threadId":"thr_1"}}'
+read -r unhandled
+echo "{\"method\":\"turn/completed\",\"params\":{\"threadId\":\"thr_1\",\"turn\":{\"id\":\"turn_1\",\"status\":\"completed\"}},\"sent\":[$initialize,$initialized,$thread,$turn,$command_decision,$change_decision,$unhandled]}"
+# The client closes stdin once the turn is over
+if read -r extra; then echo "{\"unexpected\":$extra}"; fi
+"#;
+
+fn command_request(id: Value, command: Value) -> Value {
+    json!({
+        "id": id,
+        "method": "item/commandExecution/requestApproval",
+        "params": { "threadId": "thr_1", "turnId": "turn_1", "itemId": "item_1", "command": command },
+    })
+}
+
+fn normalize(message: &Value) -> NormalizedPermissionRequest {
+    normalize_permission_request("codex", message).unwrap()
+}
+
+#[test]
+fn command_approvals_normalize_like_other_shell_requests() {
+    let request = normalize(&command_request(json!(7), json!("git push --force")));
+    assert_eq!(request.tool, "codex");
+    assert_eq!(request.id.as_deref(), Some("7"));
+    assert_eq!(request.session_id.as_deref(), Some("thr_1"));
+    assert_eq!(request.call_id.as_deref(), Some("item_1"));
+    assert_eq!(request.tool_name.as_deref(), Some("commandExecution"));
+    assert_eq!(request.command.as_deref(), Some("git push --force"));
+    assert_eq!(request.scope, "session");
+    assert_eq!(request.risk.unwrap().level, "high");
+
+    let wrapped = normalize(&command_request(
+        json!("a"),
+        json!(["/bin/bash", "-lc", "rm -rf build"]),
+    ));
+    assert_eq!(wrapped.command.as_deref(), Some("rm -rf build"));
+    let argv = normalize(&command_request(json!("b"), json!(["ls", "my dir"])));
+    assert_eq!(argv.command.as_deref(), Some("ls 'my dir'"));
+
+    assert!(normalize_permission_request(
+        "codex",
+        &json!({ "method": "turn/completed", "params": {} })
+    )
+    .is_none());
+}
+
+#[test]
+fn file_change_approvals_carry_their_paths_and_diffs() {
+    let request = normalize(&json!({
+        "id": 8,
+        "method": "item/fileChange/requestApproval",
+        "params": {
+            "threadId": "thr_1",
+            "itemId": "item_2",
+            "reason": "Write the notes",
+            "changes": [
+                { "path": "notes.md", "kind": { "type": "add" }, "diff": "hello\n" },
+                { "path": "src/lib.rs", "kind": { "type": "update" }, "diff": "@@ -1 +1 @@\n-a\n+b\n" },
+            ],
+        },
+    }));
+
+    assert_eq!(request.tool_name.as_deref(), Some("fileChange"));
+    assert_eq!(request.title.as_deref(), Some("Write the notes"));
+    assert_eq!(request.command.as_deref(), Some("notes.md src/lib.rs"));
+    assert_eq!(request.risk, None);
+    assert_eq!(
+        request.diff.as_deref(),
+        Some(
+            "--- notes.md\n+++ notes.md\n@@ -0,0 +1,1 @@\n+hello\n\
+             --- src/lib.rs\n+++ src/lib.rs\n@@ -1 +1 @@\n-a\n+b\n"
+        )
+    );
+    assert!(render_permission_request(&request).ends_with("  -a\n  +b\n"));
+
+    // Path rules see the changed files
+    let policy =
+        PermissionPolicy::from_toml_str("[[rules]]\npath = \"src/*\"\ndecision = \"reject\"\n")
+            .unwrap();
+    assert_eq!(policy.evaluate(&request).decision, "reject");
+}
+
+#[test]
+fn decisions_answer_the_request_id_with_codex_decisions() {
+    let request = normalize(&command_request(json!(7), json!("ls")));
+    for (decision, native) in [
+        ("once", "accept"),
+        ("always", "acceptForSession"),
+        ("reject", "decline"),
+    ] {
+        let response =
+            build_decision_response("codex", &request, &PermissionDecision::new(decision)).unwrap();
+        assert_eq!(
+            response,
+            json!({ "id": 7, "result": { "decision": native } })
+        );
+    }
+
+    // Codex cannot run an edited command or show a message
+    let edited = PermissionDecision::allow_with_input(json!({ "command": "ls -a" }));
+    let string_id = normalize(&command_request(json!("req-1"), json!("ls")));
+    assert_eq!(
+        build_decision_response("codex", &string_id, &edited).unwrap(),
+        json!({ "id": "req-1", "result": { "decision": "decline" } })
+    );
+    assert_eq!(
+        PermissionDecision::reject_with_message("no").for_tool("codex"),
+        PermissionDecision::new("reject")
+    );
+}
+
+#[test]
+fn opening_frames_start_or_resume_an_untrusted_thread() {
+    let options = AgentOptions {
+        tool: "codex".to_string(),
+        model: Some("gpt5".to_string()),
+        ..Default::default()
+    };
+    let frames = build_app_server_opening_frames(&options, "/repo");
+    assert_eq!(frames[0]["method"], "initialize");
+    assert_eq!(frames[0]["params"]["clientInfo"]["name"], "agent_commander");
+    assert_eq!(frames[1], json!({ "method": "initialized" }));
+    assert_eq!(
+        frames[2],
+        json!({
+            "method": "thread/start",
+            "id": 1,
+            "params": { "cwd": "/repo", "approvalPolicy": "untrusted", "model": "gpt-5" },
+        })
+    );
+
+    let resumed = build_app_server_opening_frames(
+        &AgentOptions {
+            resume: Some("thr_0".to_string()),
+            ..options
+        },
+        "/repo",
+    );
+    assert_eq!(resumed[2]["method"], "thread/resume");
+    assert_eq!(resumed[2]["params"]["threadId"], "thr_0");
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn agent_relays_codex_approvals_over_the_app_server() {
+    use agent_commander::{agent, AgentStartOptions, AgentStopOptions, PermissionHandler};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let fake_codex = dir.path().join("codex");
+    std::fs::write(&fake_codex, FAKE_APP_SERVER).unwrap();
+    std::fs::set_permissions(&fake_codex, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let working_directory = dir.path().to_string_lossy().into_owned();
+
+    let mut controller = agent(AgentOptions {
+        tool: "codex".to_string(),
+        working_directory: working_directory.clone(),
+        prompt: Some("write the notes".to_string()),
+        system_prompt: Some("Be brief.".to_string()),
+        executable: Some(fake_codex.to_string_lossy().into_owned()),
+        approve_each: true,
+        on_permission_request: Some(PermissionHandler::new(
+            |request: &NormalizedPermissionRequest| match request.tool_name.as_deref() {
+                Some("commandExecution") => "always",
+                _ => "reject",
+            },
+        )),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    let answered: Vec<_> = result
+        .permissions
+        .iter()
+        .map(|handled| {
+            (
+                handled.request.command.clone().unwrap_or_default(),
+                handled.decision.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        answered,
+        vec![
+            ("cargo test".to_string(), "always".to_string()),
+            ("notes.md".to_string(), "reject".to_string()),
+        ]
+    );
+    assert!(result.permissions[1].request.diff.is_some());
+    assert_eq!(result.session_id.as_deref(), Some("thr_1"));
+
+    let output = result.parsed_output.unwrap();
+    assert!(!output
+        .iter()
+        .any(|message| message.get("unexpected").is_some()));
+    let sent = output
+        .iter()
+        .find(|message| message["method"] == "turn/completed")
+        .unwrap()["sent"]
+        .clone();
+    assert_eq!(sent[2]["params"]["cwd"], json!(working_directory));
+    assert_eq!(
+        sent[3],
+        json!({
+            "method": "turn/start",
+            "id": 2,
+            "params": {
+                "threadId": "thr_1",
+                "input": [{ "type": "text", "text": "Be brief.\n\nwrite the notes" }],
+            },
+        })
+    );
+    assert_eq!(
+        sent[4],
+        json!({ "id": 7, "result": { "decision": "acceptForSession" } })
+    );
+    assert_eq!(
+        sent[5],
+        json!({ "id": "req-8", "result": { "decision": "decline" } })
+    );
+    assert_eq!(sent[6]["id"], 9);
+    assert_eq!(sent[6]["error"]["code"], -32601);
+}
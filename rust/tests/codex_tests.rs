@@ -76,6 +76,14 @@ fn test_extract_session_id_with_session_id() {
     assert_eq!(session_id, Some("sess-456".to_string()));
 }
 
+#[test]
+fn test_extract_session_id_from_app_server_thread() {
+    let output =
+        "{\"id\":0,\"result\":{}}\n{\"id\":1,\"result\":{\"thread\":{\"id\":\"thr_789\"}}}";
+    let session_id = extract_session_id(output);
+    assert_eq!(session_id, Some("thr_789".to_string()));
+}
+
 #[test]
 fn test_extract_usage() {
     let output = "{\"usage\":{\"input_tokens\":100,\"output_tokens\":50}}";
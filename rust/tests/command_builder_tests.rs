@@ -0,0 +1,708 @@
+//! Tests for launch spec and command building
+
+use agent_commander::command_builder::{
+    build_agent_command, build_agent_launch_spec, build_piped_command, AgentCommandOptions,
+};
+use agent_commander::LaunchStdin;
+
+#[test]
+fn test_build_agent_command_basic_claude() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Hello".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.starts_with("cd /tmp/test && claude "));
+    assert!(command.contains("claude"));
+    assert!(command.contains("--prompt"));
+    assert!(command.contains("Hello"));
+}
+
+#[test]
+fn test_build_agent_command_with_system_prompt() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Hello".to_string()),
+        system_prompt: Some("You are helpful".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--prompt"));
+    assert!(command.contains("--system-prompt"));
+    assert!(command.contains("You are helpful"));
+}
+
+#[test]
+fn test_build_agent_command_claude_with_fallback_model() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        model: Some("opus".to_string()),
+        fallback_model: Some("sonnet".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--model"));
+    assert!(command.contains("claude-opus-4-7"));
+    assert!(command.contains("--fallback-model"));
+    assert!(command.contains("claude-sonnet-4-6"));
+}
+
+#[test]
+fn test_build_agent_command_claude_with_append_system_prompt() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        append_system_prompt: Some("Extra instructions".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--append-system-prompt"));
+    assert!(command.contains("Extra instructions"));
+}
+
+#[test]
+fn test_build_agent_command_claude_with_session_management() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        resume: Some("abc123".to_string()),
+        session_id: Some("123e4567-e89b-12d3-a456-426614174000".to_string()),
+        fork_session: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--resume"));
+    assert!(command.contains("abc123"));
+    assert!(command.contains("--session-id"));
+    assert!(command.contains("123e4567-e89b-12d3-a456-426614174000"));
+    assert!(command.contains("--fork-session"));
+}
+
+#[test]
+fn test_build_agent_command_claude_with_verbose_streaming() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        verbose: true,
+        replay_user_messages: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--verbose"));
+    assert!(command.contains("--replay-user-messages"));
+}
+
+#[test]
+fn test_build_agent_command_claude_raw_passthrough() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Hello".to_string()),
+        executable: Some("/opt/Claude Code/bin/claude".to_string()),
+        extra_env: vec![
+            (
+                "CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC".to_string(),
+                "1".to_string(),
+            ),
+            ("MCP_TIMEOUT".to_string(), "10000".to_string()),
+        ],
+        extra_args: vec![
+            "--mcp-config".to_string(),
+            "/tmp/mcp config.json".to_string(),
+            "--permission-mode".to_string(),
+            "default".to_string(),
+        ],
+        skip_default_safety_flags: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("env"));
+    assert!(command.contains("CLAUDE_CODE_DISABLE_NONESSENTIAL_TRAFFIC=1"));
+    assert!(command.contains("MCP_TIMEOUT=10000"));
+    assert!(command.contains("/opt/Claude Code/bin/claude"));
+    assert!(command.contains("--mcp-config"));
+    assert!(command.contains("/tmp/mcp config.json"));
+    assert!(command.contains("--permission-mode"));
+    assert!(command.contains("default"));
+    assert!(!command.contains("--dangerously-skip-permissions"));
+}
+
+#[test]
+fn test_build_agent_command_qwen_raw_passthrough() {
+    let options = AgentCommandOptions {
+        tool: "qwen".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt_file: Some("/tmp/prompt.txt".to_string()),
+        executable: Some("/opt/qwen code/qwen".to_string()),
+        extra_env: vec![("QWEN_HOME".to_string(), "/tmp/qwen home".to_string())],
+        extra_args: vec![
+            "--checkpointing".to_string(),
+            "--approval-mode".to_string(),
+            "default".to_string(),
+        ],
+        skip_default_safety_flags: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("cat"));
+    assert!(command.contains("/tmp/prompt.txt"));
+    assert!(command.contains("| env QWEN_HOME="));
+    assert!(command.contains("/tmp/qwen home"));
+    assert!(command.contains("/opt/qwen code/qwen"));
+    assert!(command.contains("--checkpointing"));
+    assert!(command.contains("--approval-mode"));
+    assert!(command.contains("default"));
+    assert!(!command.contains("--yolo"));
+}
+
+#[test]
+fn test_build_agent_command_gemini_raw_passthrough() {
+    let options = AgentCommandOptions {
+        tool: "gemini".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt_file: Some("/tmp/prompt.txt".to_string()),
+        executable: Some("/opt/gemini cli/gemini".to_string()),
+        extra_env: vec![("GEMINI_HOME".to_string(), "/tmp/gemini home".to_string())],
+        extra_args: vec!["--telemetry".to_string(), "false".to_string()],
+        skip_default_safety_flags: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("cat"));
+    assert!(command.contains("/tmp/prompt.txt"));
+    assert!(command.contains("| env GEMINI_HOME="));
+    assert!(command.contains("/tmp/gemini home"));
+    assert!(command.contains("/opt/gemini cli/gemini"));
+    assert!(command.contains("--telemetry"));
+    assert!(command.contains("false"));
+    assert!(!command.contains("--yolo"));
+}
+
+#[test]
+fn test_build_agent_command_unknown_tool() {
+    let options = AgentCommandOptions {
+        tool: "unknown-tool".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Hello".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.starts_with("cd /tmp/test && "));
+    assert!(command.contains("unknown-tool"));
+    assert!(command.contains("--prompt"));
+}
+
+#[test]
+fn test_build_agent_launch_spec_passes_prompt_as_plain_argv() {
+    let prompt = "Fix it; rm -rf / $(whoami) `id` 'quoted' \"double\"";
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/my project".to_string(),
+        prompt: Some(prompt.to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let spec = build_agent_launch_spec(&options);
+    assert_eq!(spec.program, "claude");
+    assert_eq!(spec.cwd, Some(std::path::PathBuf::from("/tmp/my project")));
+    let prompt_index = spec.args.iter().position(|arg| arg == "--prompt").unwrap();
+    assert_eq!(spec.args[prompt_index + 1], prompt);
+    assert_eq!(spec.stdin, LaunchStdin::Null);
+}
+
+#[test]
+fn test_build_agent_launch_spec_uses_stdin_sources() {
+    let inline = build_agent_launch_spec(&AgentCommandOptions {
+        tool: "codex".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("It's $HOME".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    });
+    assert_eq!(inline.program, "codex");
+    assert_eq!(inline.stdin, LaunchStdin::Inline(b"It's $HOME".to_vec()));
+
+    let file = build_agent_launch_spec(&AgentCommandOptions {
+        tool: "qwen".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt_file: Some("/tmp/agent prompt.txt".to_string()),
+        extra_env: vec![("QWEN_HOME".to_string(), "/tmp/qwen home".to_string())],
+        isolation: "none".to_string(),
+        ..Default::default()
+    });
+    assert_eq!(
+        file.stdin,
+        LaunchStdin::File(std::path::PathBuf::from("/tmp/agent prompt.txt"))
+    );
+    assert_eq!(
+        file.env,
+        vec![("QWEN_HOME".to_string(), "/tmp/qwen home".to_string())]
+    );
+}
+
+#[test]
+fn test_build_agent_launch_spec_wraps_isolation_as_argv() {
+    let spec = build_agent_launch_spec(&AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("It's done".to_string()),
+        isolation: "screen".to_string(),
+        screen_name: Some("my session".to_string()),
+        detached: true,
+        ..Default::default()
+    });
+
+    assert_eq!(spec.program, "screen");
+    assert_eq!(&spec.args[..4], &["-dmS", "my session", "bash", "-c"]);
+    assert!(spec.args[4].starts_with("cd /tmp/test && claude "));
+    assert!(spec.args[4].contains("'It'\\''s done'"));
+    assert_eq!(spec.cwd, None);
+}
+
+#[test]
+fn test_build_agent_command_screen_isolation() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "screen".to_string(),
+        screen_name: Some("my-session".to_string()),
+        detached: true,
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("screen"));
+    assert!(command.contains("-dmS"));
+    assert!(command.contains("my-session"));
+}
+
+#[test]
+fn test_build_agent_command_tmux_isolation() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "tmux".to_string(),
+        tmux_session: Some("my-session".to_string()),
+        detached: true,
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.starts_with("tmux new-session -d -s my-session "));
+    assert!(command.contains("cd /tmp/test && claude"));
+}
+
+#[test]
+fn test_build_agent_command_docker_isolation() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "docker".to_string(),
+        container_name: Some("my-container".to_string()),
+        detached: true,
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("docker run"));
+    assert!(command.contains("-d"));
+    assert!(command.contains("--name my-container"));
+    assert!(command.contains("-v /tmp/test:/tmp/test"));
+}
+
+#[test]
+fn test_build_agent_command_with_model() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        model: Some("opus".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--model"));
+    assert!(command.contains("claude-opus-4-7"));
+}
+
+#[test]
+fn test_build_agent_command_codex() {
+    let options = AgentCommandOptions {
+        tool: "codex".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Hello".to_string()),
+        json: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("codex"));
+    assert!(command.contains("exec"));
+    assert!(command.contains("--json"));
+}
+
+#[test]
+fn test_build_agent_command_codex_prompt_file() {
+    let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
+    let options = AgentCommandOptions {
+        tool: "codex".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some(inline_prompt.to_string()),
+        system_prompt: Some("System instructions".to_string()),
+        prompt_file: Some("/tmp/agent prompt.txt".to_string()),
+        json: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("cat"));
+    assert!(command.contains("/tmp/agent prompt.txt"));
+    assert!(command.contains("codex"));
+    assert!(!command.contains(inline_prompt));
+    assert!(!command.contains("System instructions"));
+}
+
+#[test]
+fn test_build_agent_command_claude_prompt_file() {
+    let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some(inline_prompt.to_string()),
+        system_prompt: Some("You are helpful".to_string()),
+        prompt_file: Some("/tmp/agent prompt.txt".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("cat"));
+    assert!(command.contains("/tmp/agent prompt.txt"));
+    assert!(command.contains("claude"));
+    assert!(command.contains("--system-prompt"));
+    assert!(command.contains("You are helpful"));
+    assert!(!command.contains(inline_prompt));
+}
+
+#[test]
+fn test_build_agent_command_qwen_prompt_file() {
+    let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
+    let options = AgentCommandOptions {
+        tool: "qwen".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some(inline_prompt.to_string()),
+        system_prompt: Some("System instructions".to_string()),
+        prompt_file: Some("/tmp/agent prompt.txt".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("cat"));
+    assert!(command.contains("/tmp/agent prompt.txt"));
+    assert!(command.contains("qwen"));
+    assert!(!command.contains(inline_prompt));
+    assert!(!command.contains("System instructions"));
+}
+
+#[test]
+fn test_build_agent_command_gemini_prompt_file() {
+    let inline_prompt = "Secret prompt with 'quotes', $HOME, and `pwd`";
+    let options = AgentCommandOptions {
+        tool: "gemini".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some(inline_prompt.to_string()),
+        system_prompt: Some("System instructions".to_string()),
+        prompt_file: Some("/tmp/agent prompt.txt".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("cat"));
+    assert!(command.contains("/tmp/agent prompt.txt"));
+    assert!(command.contains("gemini"));
+    assert!(!command.contains(inline_prompt));
+    assert!(!command.contains("System instructions"));
+}
+
+#[test]
+fn test_build_agent_command_opencode() {
+    let options = AgentCommandOptions {
+        tool: "opencode".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Hello".to_string()),
+        json: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("opencode"));
+    assert!(command.contains("run"));
+    assert!(command.contains("--format"));
+}
+
+#[test]
+fn test_build_agent_command_agent_tool() {
+    let options = AgentCommandOptions {
+        tool: "agent".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        model: Some("grok".to_string()),
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("agent"));
+    assert!(command.contains("--model"));
+    assert!(command.contains("opencode/grok-code"));
+}
+
+#[test]
+fn test_build_agent_command_claude_read_only() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Plan only".to_string()),
+        read_only: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--permission-mode"));
+    assert!(command.contains("plan"));
+    assert!(!command.contains("--dangerously-skip-permissions"));
+}
+
+#[test]
+fn test_build_agent_command_codex_read_only() {
+    let options = AgentCommandOptions {
+        tool: "codex".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Plan only".to_string()),
+        read_only: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("codex --ask-for-approval never exec"));
+    assert!(command.contains("--sandbox"));
+    assert!(command.contains("read-only"));
+    assert!(!command.contains("--dangerously-bypass-approvals-and-sandbox"));
+}
+
+#[test]
+fn test_build_agent_command_opencode_read_only() {
+    let options = AgentCommandOptions {
+        tool: "opencode".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Plan only".to_string()),
+        read_only: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("OPENCODE_PERMISSION="));
+    assert!(command.contains("bash"));
+    assert!(command.contains("edit"));
+    assert!(command.contains("deny"));
+}
+
+#[test]
+fn test_build_agent_command_agent_read_only_uses_readonly_mode() {
+    let options = AgentCommandOptions {
+        tool: "agent".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Inspect only".to_string()),
+        read_only: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--permission-mode"));
+    assert!(command.contains("readonly"));
+    assert!(!command.contains("plan"));
+}
+
+#[test]
+fn test_build_agent_command_agent_plan_only_uses_plan_mode() {
+    let options = AgentCommandOptions {
+        tool: "agent".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Plan only".to_string()),
+        plan_only: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--permission-mode"));
+    assert!(command.contains("plan"));
+    assert!(!command.contains("readonly"));
+}
+
+#[test]
+fn test_build_agent_command_agent_approve_each_uses_ask_mode() {
+    let options = AgentCommandOptions {
+        tool: "agent".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Do work".to_string()),
+        approve_each: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--permission-mode"));
+    assert!(command.contains("ask"));
+    // Ask mode requires streaming stdin so requests can be answered mid-turn.
+    assert!(command.contains("--input-format"));
+    assert!(command.contains("stream-json"));
+    assert!(!command.contains("readonly"));
+}
+
+#[test]
+fn test_build_agent_command_claude_approve_each_uses_default_mode() {
+    let options = AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Do work".to_string()),
+        approve_each: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let command = build_agent_command(&options);
+    assert!(command.contains("--permission-mode"));
+    assert!(command.contains("default"));
+    // Default mode keeps Claude's own per-tool prompting active instead of
+    // bypassing it.
+    assert!(!command.contains("--dangerously-skip-permissions"));
+    assert!(!command.contains("plan"));
+}
+
+#[test]
+fn test_build_agent_launch_spec_claude_approve_each_streams_stdin() {
+    let spec = build_agent_launch_spec(&AgentCommandOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Do work".to_string()),
+        approve_each: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    });
+
+    let args = spec.args.join(" ");
+    assert!(args.contains("--permission-prompt-tool stdio"));
+    assert!(args.contains("--input-format stream-json"));
+    assert!(args.contains("--output-format stream-json"));
+    assert!(spec.args.contains(&"-p".to_string()));
+    // The prompt goes to stdin as the first stream-json frame
+    assert!(!args.contains("Do work"));
+    assert_eq!(spec.stdin, LaunchStdin::Piped);
+}
+
+#[test]
+fn test_build_agent_launch_spec_codex_approve_each_runs_app_server() {
+    let spec = build_agent_launch_spec(&AgentCommandOptions {
+        tool: "codex".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        prompt: Some("Do work".to_string()),
+        model: Some("gpt-5.5".to_string()),
+        approve_each: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    });
+
+    // The prompt, model and approval policy travel as JSON-RPC requests
+    assert_eq!(spec.args, vec!["app-server".to_string()]);
+    assert_eq!(spec.stdin, LaunchStdin::Piped);
+}
+
+#[test]
+#[should_panic(expected = "does not support enforceable per-command approval")]
+fn test_build_agent_command_approve_each_rejects_opencode() {
+    let options = AgentCommandOptions {
+        tool: "opencode".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        approve_each: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let _command = build_agent_command(&options);
+}
+
+#[test]
+#[should_panic(expected = "does not support enforceable per-command approval")]
+fn test_build_agent_command_approve_each_rejects_unknown_tool() {
+    let options = AgentCommandOptions {
+        tool: "unknown-tool".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        approve_each: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let _command = build_agent_command(&options);
+}
+
+#[test]
+#[should_panic(expected = "does not support enforceable read-only mode")]
+fn test_build_agent_command_read_only_rejects_unknown_tool() {
+    let options = AgentCommandOptions {
+        tool: "unknown-tool".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        read_only: true,
+        isolation: "none".to_string(),
+        ..Default::default()
+    };
+
+    let _command = build_agent_command(&options);
+}
+
+#[test]
+fn test_build_piped_command_basic() {
+    let command = build_piped_command("Hello World", "mycommand --flag");
+    assert!(command.contains("printf '%s'"));
+    assert!(command.contains("Hello World"));
+    assert!(command.contains("mycommand --flag"));
+}
+
+#[test]
+fn test_build_piped_command_escapes_quotes() {
+    let command = build_piped_command("It's working", "mycommand");
+    assert!(command.contains("'\\''"));
+}
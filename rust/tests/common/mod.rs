@@ -0,0 +1,51 @@
+//! Helpers shared by the integration tests
+
+#[cfg(not(target_os = "windows"))]
+pub static PATH_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[cfg(not(target_os = "windows"))]
+pub struct PathGuard {
+    pub previous_path: String,
+    pub temp_dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Drop for PathGuard {
+    fn drop(&mut self) {
+        std::env::set_var("PATH", &self.previous_path);
+        let _ = std::fs::remove_dir_all(&self.temp_dir);
+    }
+}
+
+/// Put an executable `name` script first on PATH until the guards drop
+#[cfg(not(target_os = "windows"))]
+pub async fn install_fake_tool(
+    name: &str,
+    script: &str,
+) -> (tokio::sync::MutexGuard<'static, ()>, PathGuard) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = std::env::temp_dir().join(format!(
+        "agent-commander-test-bin-{}-{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    let fake_tool = temp_dir.join(name);
+    std::fs::write(&fake_tool, script).unwrap();
+    std::fs::set_permissions(&fake_tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path_lock = PATH_LOCK.lock().await;
+    let previous_path = std::env::var("PATH").unwrap_or_default();
+    std::env::set_var("PATH", format!("{}:{}", temp_dir.display(), previous_path));
+    (
+        path_lock,
+        PathGuard {
+            previous_path,
+            temp_dir,
+        },
+    )
+}
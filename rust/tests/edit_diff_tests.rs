@@ -0,0 +1,284 @@
+//! Tests for diff previews of file-edit permission requests
+
+use agent_commander::{
+    normalize_permission_request, render_permission_request, unified_diff,
+    NormalizedPermissionRequest, PermissionRelay, WorkspaceGuard,
+};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+fn claude_frame(tool_name: &str, input: Value) -> Value {
+    json!({
+        "type": "control_request",
+        "request_id": "r1",
+        "request": { "subtype": "can_use_tool", "tool_name": tool_name, "input": input },
+    })
+}
+
+fn claude_request(tool_name: &str, input: Value, dir: &Path) -> NormalizedPermissionRequest {
+    normalize_permission_request("claude", &claude_frame(tool_name, input))
+        .unwrap()
+        .with_edit_diff(dir)
+}
+
+fn agent_request(metadata: Value, dir: &Path) -> NormalizedPermissionRequest {
+    normalize_permission_request(
+        "agent",
+        &json!({
+            "type": "permission_request",
+            "permissionID": "p1",
+            "tool": "edit",
+            "metadata": metadata,
+        }),
+    )
+    .unwrap()
+    .with_edit_diff(dir)
+}
+
+#[test]
+fn unified_diff_shows_changes_with_three_lines_of_context() {
+    let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n";
+    let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\nseventeen\n";
+    assert_eq!(
+        unified_diff("notes.txt", old, new),
+        "--- notes.txt\n+++ notes.txt\n\
+         @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+         @@ -14,3 +14,4 @@\n 14\n 15\n 16\n+seventeen\n"
+    );
+}
+
+#[test]
+fn unified_diff_merges_nearby_changes_and_numbers_new_files() {
+    let merged = unified_diff("a", "a\nb\nc\nd\ne\nf\ng\nh\n", "A\nb\nc\nd\ne\nf\ng\nH\n");
+    assert_eq!(merged.matches("@@").count(), 2, "{}", merged);
+
+    assert_eq!(
+        unified_diff("new.rs", "", "fn main() {}\n"),
+        "--- new.rs\n+++ new.rs\n@@ -0,0 +1,1 @@\n+fn main() {}\n"
+    );
+    assert_eq!(unified_diff("same", "x\ny\n", "x\ny\n"), "");
+}
+
+#[test]
+fn claude_edit_is_diffed_against_the_file_in_the_working_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("lib.rs"),
+        "fn a() {}\nfn b() {}\nfn c() {}\n",
+    )
+    .unwrap();
+
+    let edit = claude_request(
+        "Edit",
+        json!({ "file_path": "lib.rs", "old_string": "fn b() {}", "new_string": "fn b() -> u8 { 1 }" }),
+        dir.path(),
+    );
+    assert_eq!(
+        edit.diff.as_deref(),
+        Some(
+            "--- lib.rs\n+++ lib.rs\n@@ -1,3 +1,3 @@\n fn a() {}\n-fn b() {}\n+fn b() -> u8 { 1 }\n fn c() {}\n"
+        )
+    );
+    // The command still names the file, as before
+    assert_eq!(edit.command.as_deref(), Some("lib.rs"));
+}
+
+#[test]
+fn claude_multi_edit_and_write_are_diffed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "a = 1\nb = 1\nc = 1\n").unwrap();
+    let file_path = path.to_str().unwrap();
+
+    let multi = claude_request(
+        "MultiEdit",
+        json!({
+            "file_path": file_path,
+            "edits": [
+                { "old_string": "1", "new_string": "2", "replace_all": true },
+                { "old_string": "c = 2", "new_string": "c = 3" },
+            ],
+        }),
+        dir.path(),
+    );
+    let diff = multi.diff.unwrap();
+    assert!(
+        diff.contains("-a = 1\n-b = 1\n-c = 1\n+a = 2\n+b = 2\n+c = 3\n"),
+        "{}",
+        diff
+    );
+
+    let write = claude_request(
+        "Write",
+        json!({ "file_path": "new.md", "content": "# Title\n" }),
+        dir.path(),
+    );
+    assert_eq!(
+        write.diff.as_deref(),
+        Some("--- new.md\n+++ new.md\n@@ -0,0 +1,1 @@\n+# Title\n")
+    );
+}
+
+#[test]
+fn edit_that_does_not_apply_shows_the_replaced_text() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "unrelated\n").unwrap();
+
+    let edit = claude_request(
+        "Edit",
+        json!({ "file_path": "a.txt", "old_string": "old line", "new_string": "new line" }),
+        dir.path(),
+    );
+    assert_eq!(
+        edit.diff.as_deref(),
+        Some("--- a.txt\n+++ a.txt\n@@ -1,1 +1,1 @@\n-old line\n+new line\n")
+    );
+}
+
+#[test]
+fn requests_without_file_edits_get_no_diff() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "text\n").unwrap();
+
+    for (tool_name, input) in [
+        ("Bash", json!({ "command": "ls" })),
+        ("Read", json!({ "file_path": "a.txt" })),
+        (
+            "Write",
+            json!({ "file_path": "a.txt", "content": "text\n" }),
+        ),
+    ] {
+        let request = claude_request(tool_name, input, dir.path());
+        assert_eq!(request.diff, None, "{}", tool_name);
+    }
+}
+
+#[test]
+fn files_that_must_not_be_read_are_not_copied_into_the_diff() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    fs::create_dir_all(&workspace).unwrap();
+    fs::write(dir.path().join("outside.txt"), "outside secret\n").unwrap();
+    fs::write(workspace.join(".env"), "TOKEN=secret\nA=1\n").unwrap();
+    fs::write(workspace.join("big.log"), "x".repeat(2 * 1024 * 1024)).unwrap();
+
+    for path in ["../outside.txt", "/dev/zero", "big.log"] {
+        let write = claude_request(
+            "Write",
+            json!({ "file_path": path, "content": "new\n" }),
+            &workspace,
+        );
+        assert_eq!(write.diff, None, "{}", path);
+    }
+
+    // Only the replaced text is shown
+    let edit = claude_request(
+        "Edit",
+        json!({ "file_path": ".env", "old_string": "A=1", "new_string": "A=2" }),
+        &workspace,
+    );
+    assert_eq!(
+        edit.diff.as_deref(),
+        Some("--- .env\n+++ .env\n@@ -1,1 +1,1 @@\n-A=1\n+A=2\n")
+    );
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let status = std::process::Command::new("mkfifo")
+            .arg(workspace.join("pipe"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let fifo = claude_request(
+            "Write",
+            json!({ "file_path": "pipe", "content": "new\n" }),
+            &workspace,
+        );
+        assert_eq!(fifo.diff, None);
+    }
+}
+
+#[test]
+fn relay_diffs_only_requests_the_workspace_guard_lets_through() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    fs::create_dir_all(&workspace).unwrap();
+    fs::write(dir.path().join("notes.txt"), "outside\n").unwrap();
+    fs::write(workspace.join("a.txt"), "before\n").unwrap();
+
+    let mut relay =
+        PermissionRelay::new("claude", |_: &NormalizedPermissionRequest| "once", |_| {})
+            .with_working_directory(&workspace)
+            .with_workspace_guard(WorkspaceGuard::new(&workspace, &[] as &[&str]));
+    for path in ["../notes.txt", "a.txt"] {
+        relay.handle_message(&claude_frame(
+            "Write",
+            json!({ "file_path": path, "content": "after\n" }),
+        ));
+    }
+
+    let handled = relay.get_handled();
+    assert_eq!(handled[0].1, "reject");
+    assert_eq!(handled[0].0.diff, None);
+    assert!(handled[1]
+        .0
+        .diff
+        .as_deref()
+        .unwrap()
+        .contains("-before\n+after\n"));
+}
+
+#[test]
+fn agent_edit_uses_its_own_diff_or_builds_one() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("main.go"), "package main\n").unwrap();
+
+    let precomputed = agent_request(
+        json!({ "filePath": "main.go", "diff": "--- main.go\n+++ main.go\n" }),
+        dir.path(),
+    );
+    assert_eq!(
+        precomputed.diff.as_deref(),
+        Some("--- main.go\n+++ main.go\n")
+    );
+
+    let built = agent_request(
+        json!({ "filePath": "main.go", "oldString": "main", "newString": "app" }),
+        dir.path(),
+    );
+    assert_eq!(
+        built.diff.as_deref(),
+        Some("--- main.go\n+++ main.go\n@@ -1,1 +1,1 @@\n-package main\n+package app\n")
+    );
+}
+
+#[test]
+fn relay_with_a_working_directory_hands_diffs_to_the_handler() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "before\n").unwrap();
+
+    let mut seen = Vec::new();
+    {
+        let mut relay = PermissionRelay::new(
+            "claude",
+            |request: &NormalizedPermissionRequest| {
+                seen.push(render_permission_request(request));
+                "once"
+            },
+            |_| {},
+        )
+        .with_working_directory(dir.path());
+        relay.handle_message(&claude_frame(
+            "Edit",
+            json!({ "file_path": "a.txt", "old_string": "before", "new_string": "after" }),
+        ));
+    }
+
+    assert_eq!(seen.len(), 1);
+    assert!(
+        seen[0].ends_with("  --- a.txt\n  +++ a.txt\n  @@ -1,1 +1,1 @@\n  -before\n  +after\n"),
+        "{}",
+        seen[0]
+    );
+}
@@ -0,0 +1,708 @@
+//! Tests for running agents under isolation wrappers
+
+use agent_commander::{agent, AgentOptions, AgentStartOptions, AgentStopOptions};
+
+mod common;
+
+#[cfg(not(target_os = "windows"))]
+use common::install_fake_tool;
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_docker_stop_collects_container_logs_and_exit_code() {
+    let removed = tempfile::tempdir().unwrap();
+    let _fake_docker = install_fake_tool(
+        "docker",
+        &format!(
+            r#"#!/usr/bin/env bash
+case "$1" in
+  stop) echo "$2" ;;
+  logs)
+    printf '%s\n' '{{"type":"system","session_id":"docker-session"}}'
+    echo 'warning: from container' >&2
+    printf '%s\n' '{{"type":"result","session_id":"docker-session","result":"Done."}}'
+    ;;
+  inspect) echo 3 ;;
+  rm) touch '{}/removed' ;;
+esac
+"#,
+            removed.path().display()
+        ),
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        isolation: "docker".to_string(),
+        container_name: Some("agent-box".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 3);
+    assert_eq!(result.stderr, "warning: from container\n");
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+    assert_eq!(result.session_id.as_deref(), Some("docker-session"));
+    assert!(!result.metadata.success);
+    assert!(removed.path().join("removed").exists());
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_docker_auto_removal_keeps_output_of_a_container_already_gone() {
+    use agent_commander::{DockerOptions, DockerRemoval};
+    use std::os::unix::fs::PermissionsExt;
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let tool_dir = tempfile::tempdir().unwrap();
+    let fake_claude = tool_dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+printf '%s\n' '{"type":"system","session_id":"docker-session"}'
+echo 'warning: from container' >&2
+printf '%s\n' '{"type":"result","session_id":"docker-session","result":"Done."}'
+exit 3
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // The container runs to completion and is removed before `stop`
+    let _fake_docker = install_fake_tool(
+        "docker",
+        r#"#!/usr/bin/env bash
+if [ "$1" = run ]; then
+  while [ "$1" != bash ]; do shift; done
+  "$@" > /dev/null 2>&1
+  exit 0
+fi
+echo "Error response from daemon: No such container: ${@: -1}" >&2
+exit 1
+"#,
+    )
+    .await;
+
+    let options = AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        isolation: "docker".to_string(),
+        container_name: Some("agent-box".to_string()),
+        docker: DockerOptions {
+            removal: DockerRemoval::Auto,
+            ..Default::default()
+        },
+        state_dir: Some(state_dir.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    let mut controller = agent(options.clone()).unwrap();
+    controller
+        .start(AgentStartOptions {
+            detached: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let capture = state_dir.path().join("docker").join("agent-box");
+    for _ in 0..200 {
+        if capture.join("exit_code").exists() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+
+    let mut stopper = agent(options).unwrap();
+    let result = stopper.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 3);
+    assert_eq!(result.stderr, "warning: from container\n");
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+    assert_eq!(result.session_id.as_deref(), Some("docker-session"));
+    assert!(!capture.exists());
+
+    // Without a capture there is nothing left to collect
+    let error = stopper.stop(AgentStopOptions::default()).await.unwrap_err();
+    assert!(
+        error.contains("agent-box was removed before its output could be collected"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_docker_exec_stop_signals_only_the_agent() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // The fake docker runs the exec'd command on the host and records the
+    // container it was asked to use
+    let containers = tempfile::tempdir().unwrap();
+    let _fake_docker = install_fake_tool(
+        "docker",
+        &format!(
+            r#"#!/usr/bin/env bash
+[ "$1" = exec ] || exit 1
+shift
+while [ "$1" = -i ] || [ "$1" = -u ] || [ "$1" = -w ]; do
+  if [ "$1" = -i ]; then shift; else shift 2; fi
+done
+echo "$1" >> '{}/containers'
+shift
+exec "$@"
+"#,
+            containers.path().display()
+        ),
+    )
+    .await;
+
+    let tool_dir = tempfile::tempdir().unwrap();
+    let fake_claude = tool_dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+trap 'echo "{\"type\":\"result\",\"session_id\":\"exec-session\"}"; exit 130' INT
+echo "prompt: $(cat)"
+while true; do sleep 0.1; done
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let run_id = format!("test-exec-{}", std::process::id());
+    let options = AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        isolation: "docker-exec".to_string(),
+        container_name: Some("devbox".to_string()),
+        run_id: Some(run_id.clone()),
+        ..Default::default()
+    };
+    let mut controller = agent(options.clone()).unwrap();
+    controller
+        .start(AgentStartOptions {
+            detached: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let stdout = std::path::Path::new(agent_commander::DOCKER_EXEC_STATE_DIR)
+        .join(&run_id)
+        .join("stdout");
+    for _ in 0..50 {
+        if std::fs::read_to_string(&stdout).is_ok_and(|output| !output.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let mut stopper = agent(options).unwrap();
+    let result = stopper.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 130);
+    assert!(result.stdout.starts_with("prompt: hello\n"));
+    assert_eq!(result.session_id.as_deref(), Some("exec-session"));
+    assert!(!stdout.exists());
+    let containers = std::fs::read_to_string(containers.path().join("containers")).unwrap();
+    assert_eq!(containers, "devbox\ndevbox\ndevbox\n");
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_docker_exec_timeout_stops_the_agent_inside_the_container() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // As in a container, the agent runs in its own process group, so
+    // signals to the local docker exec client do not reach it
+    let containers = tempfile::tempdir().unwrap();
+    let _fake_docker = install_fake_tool(
+        "docker",
+        &format!(
+            r#"#!/usr/bin/env bash
+[ "$1" = exec ] || exit 1
+shift
+while [ "$1" = -i ] || [ "$1" = -u ] || [ "$1" = -w ]; do
+  if [ "$1" = -i ]; then shift; else shift 2; fi
+done
+echo "$1" >> '{}/containers'
+shift
+exec "$@"
+"#,
+            containers.path().display()
+        ),
+    )
+    .await;
+
+    let tool_dir = tempfile::tempdir().unwrap();
+    let fake_claude = tool_dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+trap 'echo "{\"type\":\"result\",\"session_id\":\"exec-session\"}"; exit 130' INT
+echo "prompt: $(cat)"
+while true; do sleep 0.1; done
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let run_id = format!("test-exec-timeout-{}", std::process::id());
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        isolation: "docker-exec".to_string(),
+        container_name: Some("devbox".to_string()),
+        run_id: Some(run_id.clone()),
+        timeout: Some(std::time::Duration::from_secs(1)),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert!(result.metadata.timed_out);
+    assert_eq!(result.exit_code, 130);
+    assert!(result.stdout.starts_with("prompt: hello\n"));
+    assert_eq!(result.session_id.as_deref(), Some("exec-session"));
+    assert!(
+        !std::path::Path::new(agent_commander::DOCKER_EXEC_STATE_DIR)
+            .join(&run_id)
+            .exists()
+    );
+    // One exec started the run and a second one stopped it
+    let containers = std::fs::read_to_string(containers.path().join("containers")).unwrap();
+    assert_eq!(containers, "devbox\ndevbox\n");
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_screen_stop_collects_session_logfile() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let tool_dir = tempfile::tempdir().unwrap();
+    let fake_claude = tool_dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+printf '%s\n' '{"type":"system","session_id":"screen-session"}'
+printf '%s\n' '{"type":"result","session_id":"screen-session","result":"Done."}'
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Runs the session synchronously, writing a CRLF transcript like screen
+    let _fake_screen = install_fake_tool(
+        "screen",
+        r#"#!/usr/bin/env bash
+if [ "$1" = "-c" ]; then
+  log=$(sed -n 's/^logfile "\(.*\)"$/\1/p' "$2")
+  shift 2
+fi
+case "$1" in
+  -dmS) shift 2; "$@" 2>&1 | sed 's/$/\r/' > "$log" ;;
+  -S) echo "No screen session found." >&2; exit 1 ;;
+esac
+"#,
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        isolation: "screen".to_string(),
+        screen_name: Some("review-agent".to_string()),
+        state_dir: Some(state_dir.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions {
+            detached: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let exit_code_file = state_dir
+        .path()
+        .join("screen")
+        .join("review-agent")
+        .join("exit_code");
+    for _ in 0..200 {
+        if exit_code_file.exists() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 0);
+    assert!(!result.stdout.contains('\r'));
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+    assert_eq!(result.session_id.as_deref(), Some("screen-session"));
+    assert!(result.metadata.success);
+    assert!(!state_dir
+        .path()
+        .join("screen")
+        .join("review-agent")
+        .exists());
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_tmux_stop_collects_pipe_pane_transcript() {
+    use std::os::unix::fs::PermissionsExt;
+
+    if std::process::Command::new("tmux")
+        .arg("-V")
+        .output()
+        .is_err()
+    {
+        eprintln!("tmux not installed, skipping");
+        return;
+    }
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let tool_dir = tempfile::tempdir().unwrap();
+    let fake_claude = tool_dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+printf '%s\n' '{"type":"system","session_id":"tmux-session"}'
+printf '%s\n' '{"type":"result","session_id":"tmux-session","result":"Done."}'
+exit 2
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let session = format!("agent-commander-test-{}", std::process::id());
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        isolation: "tmux".to_string(),
+        tmux_session: Some(session.clone()),
+        state_dir: Some(state_dir.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions {
+            detached: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let capture_dir = state_dir.path().join("tmux").join(&session);
+    for _ in 0..200 {
+        if capture_dir.join("exit_code").exists() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 2);
+    assert!(!result.stdout.contains('\r'));
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+    assert_eq!(result.session_id.as_deref(), Some("tmux-session"));
+    assert!(!capture_dir.exists());
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn test_bwrap_runs_tool_in_read_only_sandbox() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempfile::tempdir().unwrap();
+    let recorded = workspace.path().join("bwrap-args");
+    // The fake bwrap records its arguments and execs the tool after `--`
+    let _fake_bwrap = install_fake_tool(
+        "bwrap",
+        &format!(
+            r#"#!/usr/bin/env bash
+echo "$*" > '{}'
+while [ "$1" != "--" ]; do shift; done
+shift
+exec "$@"
+"#,
+            recorded.display()
+        ),
+    )
+    .await;
+
+    let tool_dir = tempfile::tempdir().unwrap();
+    let fake_claude = tool_dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+printf '%s\n' "{\"type\":\"result\",\"session_id\":\"bwrap-session\",\"cwd\":\"$PWD\"}"
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let working_directory = workspace.path().to_string_lossy().into_owned();
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: working_directory.clone(),
+        prompt: Some("review".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        read_only: true,
+        isolation: "bwrap".to_string(),
+        bwrap: agent_commander::BwrapOptions {
+            unshare_net: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 0);
+    assert_eq!(result.session_id.as_deref(), Some("bwrap-session"));
+    let args = std::fs::read_to_string(&recorded).unwrap();
+    assert!(args.starts_with("--ro-bind / / --dev /dev --proc /proc --tmpfs /tmp "));
+    assert!(args.contains(&format!(
+        "--ro-bind {0} {0} --unshare-net --die-with-parent --chdir {0} -- ",
+        working_directory
+    )));
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_worktree_run_reports_branch_and_commits() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = |dir: &std::path::Path, args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let repository = dir.path().join("repo");
+    std::fs::create_dir(&repository).unwrap();
+    git(&repository, &["init", "-q"]);
+    git(
+        &repository,
+        &["commit", "-q", "--allow-empty", "-m", "init"],
+    );
+    let base = git(&repository, &["rev-parse", "HEAD"]);
+
+    // The fake tool commits a file in whatever directory it is started in
+    let fake_claude = dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+echo fixed > fix.txt
+git add fix.txt
+git -c user.name=agent -c user.email=agent@example.com commit -qm "Fix"
+echo "{\"type\":\"result\",\"session_id\":\"wt-session\"}"
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let worktree_path = dir.path().join("wt");
+    let run = |cleanup: bool, branch: &str, path: &std::path::Path| AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: repository.to_string_lossy().into_owned(),
+        prompt: Some("fix".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        worktree: Some(agent_commander::WorktreeOptions {
+            branch: Some(branch.to_string()),
+            path: Some(path.to_string_lossy().into_owned()),
+            cleanup,
+            ..Default::default()
+        }),
+        state_dir: Some(dir.path().join("state").to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let mut controller = agent(run(false, "agent/fix", &worktree_path)).unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 0);
+    let worktree = result.worktree.unwrap();
+    assert_eq!(worktree.branch, "agent/fix");
+    assert_eq!(worktree.path, worktree_path);
+    assert_eq!(worktree.base_commit, base);
+    assert!(!worktree.removed);
+    assert_eq!(
+        git(
+            &repository,
+            &["log", "--format=%s", &worktree.commit_range()]
+        ),
+        "Fix"
+    );
+    // The main working tree is untouched
+    assert!(!repository.join("fix.txt").exists());
+    assert!(worktree_path.join("fix.txt").exists());
+
+    let cleaned_path = dir.path().join("wt-clean");
+    let mut controller = agent(run(true, "agent/clean", &cleaned_path)).unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let worktree = controller
+        .stop(AgentStopOptions::default())
+        .await
+        .unwrap()
+        .worktree
+        .unwrap();
+    assert!(worktree.removed);
+    assert!(!cleaned_path.exists());
+    assert_eq!(
+        git(&repository, &["rev-parse", "agent/clean"]),
+        worktree.head_commit
+    );
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn test_bwrap_worktree_run_binds_only_what_commits_need() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let recorded = dir_path.join("bwrap-args");
+    let _fake_bwrap = install_fake_tool(
+        "bwrap",
+        &format!(
+            r#"#!/usr/bin/env bash
+echo "$*" > '{}'
+while [ "$1" != "--" ]; do shift; done
+shift
+exec "$@"
+"#,
+            recorded.display()
+        ),
+    )
+    .await;
+
+    let repository = dir_path.join("repo");
+    std::fs::create_dir(&repository).unwrap();
+    for args in [
+        vec!["init", "-q"],
+        vec![
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "init",
+        ],
+    ] {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&repository)
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    let fake_claude = dir_path.join("claude");
+    std::fs::write(
+        &fake_claude,
+        "#!/usr/bin/env bash\necho '{\"type\":\"result\"}'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let worktree_path = dir_path.join("wt");
+    let state_dir = dir_path.join("state");
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: repository.to_string_lossy().into_owned(),
+        prompt: Some("fix".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        isolation: "bwrap".to_string(),
+        worktree: Some(agent_commander::WorktreeOptions {
+            branch: Some("agent/sandboxed".to_string()),
+            path: Some(worktree_path.to_string_lossy().into_owned()),
+            ..Default::default()
+        }),
+        state_dir: Some(state_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+    assert_eq!(result.worktree.unwrap().branch, "agent/sandboxed");
+
+    let git_dir = repository.join(".git").to_string_lossy().into_owned();
+    let args = std::fs::read_to_string(&recorded).unwrap();
+    for writable in ["objects", "refs", "logs", "worktrees/wt"] {
+        assert!(
+            args.contains(&format!("--bind {0}/{1} {0}/{1} ", git_dir, writable)),
+            "{}: {}",
+            writable,
+            args
+        );
+    }
+    assert!(
+        !args.contains(&format!("--bind {0} {0} ", git_dir)),
+        "{}",
+        args
+    );
+    assert!(
+        !args.contains("hooks") && !args.contains("config"),
+        "{}",
+        args
+    );
+    // The base commit is recorded outside the repository
+    assert!(std::fs::read_dir(state_dir.join("worktree-bases"))
+        .unwrap()
+        .next()
+        .is_some());
+}
+
+#[test]
+fn test_agent_rejects_worktree_with_docker_exec() {
+    let result = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "docker-exec".to_string(),
+        container_name: Some("devbox".to_string()),
+        worktree: Some(agent_commander::WorktreeOptions::default()),
+        ..Default::default()
+    });
+    assert!(result.is_err());
+}
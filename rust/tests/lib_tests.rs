@@ -1,23 +1,11 @@
 //! Tests for Agent controller
 
-use agent_commander::{agent, AgentOptions, AgentStartOptions, AgentStopOptions};
+use agent_commander::{agent, AgentOptions, AgentStartOptions, AgentStopOptions, RunRegistry};
 
-#[cfg(not(target_os = "windows"))]
-static PATH_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+mod common;
 
 #[cfg(not(target_os = "windows"))]
-struct PathGuard {
-    previous_path: String,
-    temp_dir: std::path::PathBuf,
-}
-
-#[cfg(not(target_os = "windows"))]
-impl Drop for PathGuard {
-    fn drop(&mut self) {
-        std::env::set_var("PATH", &self.previous_path);
-        let _ = std::fs::remove_dir_all(&self.temp_dir);
-    }
-}
+use common::{install_fake_tool, PathGuard, PATH_LOCK};
 
 #[test]
 fn test_agent_throws_without_tool() {
@@ -60,6 +48,34 @@ fn test_agent_throws_for_screen_without_name() {
     }
 }
 
+#[test]
+fn test_agent_throws_for_screen_with_empty_name() {
+    let options = AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "screen".to_string(),
+        screen_name: Some(String::new()),
+        ..Default::default()
+    };
+    let Err(error) = agent(options) else {
+        panic!("an empty screen_name was accepted");
+    };
+    assert!(error.contains("screen_name is required"));
+}
+
+#[test]
+fn test_agent_throws_for_tmux_without_session() {
+    let options = AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "tmux".to_string(),
+        ..Default::default()
+    };
+    assert!(agent(options)
+        .err()
+        .is_some_and(|e| e.contains("tmux_session is required")));
+}
+
 #[test]
 fn test_agent_throws_for_docker_without_name() {
     let options = AgentOptions {
@@ -75,6 +91,26 @@ fn test_agent_throws_for_docker_without_name() {
     }
 }
 
+#[test]
+fn test_agent_validates_docker_exec_options() {
+    let options = AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "docker-exec".to_string(),
+        ..Default::default()
+    };
+    assert!(agent(options.clone())
+        .err()
+        .is_some_and(|e| e.contains("container_name is required")));
+
+    let options = AgentOptions {
+        container_name: Some("devbox".to_string()),
+        run_id: Some("../escape".to_string()),
+        ..options
+    };
+    assert!(agent(options).is_err());
+}
+
 #[test]
 fn test_agent_creates_with_valid_options() {
     let options = AgentOptions {
@@ -223,6 +259,29 @@ async fn test_agent_stop_in_dry_run_mode_with_screen() {
     assert!(result.parsed_output.is_none());
 }
 
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_approve_each_start_fails_on_an_unreadable_prompt_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let prompt_file = dir.path().join("missing.txt");
+    let _fake_agent = install_fake_tool("agent", "#!/usr/bin/env bash\ntouch started\n").await;
+    let mut controller = agent(AgentOptions {
+        tool: "agent".to_string(),
+        working_directory: dir.path().to_string_lossy().into_owned(),
+        prompt_file: Some(prompt_file.to_string_lossy().into_owned()),
+        approve_each: true,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let Err(error) = controller.start(AgentStartOptions::default()).await else {
+        panic!("the run started without its prompt");
+    };
+    let expected = format!("Failed to read prompt file {}", prompt_file.display());
+    assert!(error.starts_with(&expected), "{}", error);
+    assert!(!dir.path().join("started").exists());
+}
+
 #[tokio::test]
 #[cfg(not(target_os = "windows"))]
 async fn test_agent_start_and_stop_with_no_isolation() {
@@ -413,3 +472,518 @@ printf '%s\n' '{"type":"result","subtype":"success","session_id":"claude-session
         Some(7)
     );
 }
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_agent_events_yield_messages_while_the_agent_runs() {
+    let marker = std::env::temp_dir().join(format!(
+        "agent-commander-events-marker-{}-{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    let _fake_tool = install_fake_tool(
+        "claude",
+        &format!(
+            r#"#!/usr/bin/env bash
+printf '%s\n' '{{"type":"system","session_id":"live-session"}}'
+while [ ! -e '{}' ]; do sleep 0.05; done
+printf '%s\n' '{{"type":"result","session_id":"live-session","result":"Done."}}'
+"#,
+            marker.display()
+        ),
+    )
+    .await;
+
+    let options = AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        isolation: "none".to_string(),
+        json: true,
+        ..Default::default()
+    };
+    let mut controller = agent(options).unwrap();
+    controller
+        .start(AgentStartOptions {
+            attached: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let mut events = controller.events().unwrap();
+    let first = tokio::time::timeout(std::time::Duration::from_secs(10), events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first["type"], "system");
+
+    // The agent is still blocked on the marker, so this message was live
+    std::fs::write(&marker, "").unwrap();
+    let second = events.recv().await.unwrap();
+    let _ = std::fs::remove_file(&marker);
+    assert_eq!(second["type"], "result");
+    assert!(events.recv().await.is_none());
+
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+    assert_eq!(result.exit_code, 0);
+    assert_eq!(result.session_id, Some("live-session".to_string()));
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_agent_stop_separates_stdout_and_stderr() {
+    use agent_commander::OutputSource;
+
+    let _fake_tool = install_fake_tool(
+        "claude",
+        r#"#!/usr/bin/env bash
+printf '%s\n' '{"type":"system","session_id":"split-session"}'
+sleep 0.1
+echo 'warning: slow network' >&2
+sleep 0.1
+printf '%s\n' '{"type":"result","session_id":"split-session","result":"Done."}'
+"#,
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        isolation: "none".to_string(),
+        json: true,
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.stderr, "warning: slow network\n");
+    assert!(!result.stdout.contains("warning"));
+    assert_eq!(result.stdout.lines().count(), 2);
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+
+    let sources: Vec<OutputSource> = result.output_log.iter().map(|line| line.source).collect();
+    assert_eq!(
+        sources,
+        vec![
+            OutputSource::Stdout,
+            OutputSource::Stderr,
+            OutputSource::Stdout
+        ]
+    );
+    assert_eq!(result.output_log[1].text, "warning: slow network");
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_agent_stop_terminates_runs_that_exceed_the_timeout() {
+    let _fake_tool = install_fake_tool(
+        "claude",
+        r#"#!/usr/bin/env bash
+trap 'printf "%s\n" "{\"type\":\"result\",\"session_id\":\"slow-session\",\"result\":\"Interrupted.\"}"; exit 130' INT
+printf '%s\n' '{"type":"system","session_id":"slow-session"}'
+sleep 30 >/dev/null 2>&1 &
+wait
+"#,
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        isolation: "none".to_string(),
+        json: true,
+        timeout: Some(std::time::Duration::from_millis(500)),
+        termination_schedule: agent_commander::TerminationSchedule {
+            interrupt_grace: std::time::Duration::from_secs(5),
+            terminate_grace: std::time::Duration::from_secs(5),
+        },
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let started = std::time::Instant::now();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert!(result.metadata.timed_out);
+    assert!(!result.metadata.success);
+    assert_eq!(result.metadata.error_type, Some("timeout".to_string()));
+    // Output written before and while handling SIGINT is kept
+    assert_eq!(result.session_id, Some("slow-session".to_string()));
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_agent_interrupt_lets_the_tool_save_its_session() {
+    let _fake_tool = install_fake_tool(
+        "claude",
+        r#"#!/usr/bin/env bash
+trap 'printf "%s\n" "{\"type\":\"result\",\"session_id\":\"saved-session\",\"result\":\"Saved.\"}"; exit 130' INT
+printf '%s\n' '{"type":"system","session_id":"saved-session"}'
+sleep 30 >/dev/null 2>&1 &
+wait
+"#,
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        isolation: "none".to_string(),
+        json: true,
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let mut events = controller.events().unwrap();
+    assert_eq!(events.recv().await.unwrap()["type"], "system");
+
+    controller.interrupt().await.unwrap();
+    let result = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        controller.stop(AgentStopOptions::default()),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(result.exit_code, 130);
+    assert_eq!(result.session_id, Some("saved-session".to_string()));
+    assert_eq!(result.metadata.result_summary, Some("Saved.".to_string()));
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_agent_kill_stops_the_whole_pipeline() {
+    let _fake_tool = install_fake_tool(
+        "claude",
+        r#"#!/usr/bin/env bash
+trap '' INT TERM
+printf '%s\n' '{"type":"system","session_id":"killed-session"}'
+sleep 30
+"#,
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        isolation: "none".to_string(),
+        json: true,
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let mut events = controller.events().unwrap();
+    assert_eq!(events.recv().await.unwrap()["type"], "system");
+
+    controller.kill().await.unwrap();
+    let result = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        controller.stop(AgentStopOptions::default()),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert_ne!(result.exit_code, 0);
+    assert_eq!(result.session_id, Some("killed-session".to_string()));
+    assert!(controller.kill().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_agent_interrupt_requires_a_started_agent() {
+    let controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert!(controller.process_group().is_none());
+    assert!(controller
+        .interrupt()
+        .await
+        .unwrap_err()
+        .contains("Agent not started"));
+}
+
+#[test]
+fn test_agent_events_require_a_started_agent() {
+    let controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert!(controller
+        .events()
+        .unwrap_err()
+        .contains("Agent not started"));
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_detached_run_is_tracked_and_collected_later() {
+    let state_dir = tempfile::tempdir().unwrap();
+    let _fake_tool = install_fake_tool(
+        "claude",
+        r#"#!/usr/bin/env bash
+printf '%s\n' '{"type":"system","session_id":"detached-session"}'
+echo 'warning: detached' >&2
+printf '%s\n' '{"type":"result","session_id":"detached-session","result":"Done."}'
+"#,
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        isolation: "none".to_string(),
+        json: true,
+        state_dir: Some(state_dir.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions {
+            detached: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let run_id = controller.run_id().unwrap().to_string();
+
+    // A separate controller finds the run through the registry
+    let mut later = agent(AgentOptions {
+        tool: "dummy".to_string(),
+        working_directory: "/tmp".to_string(),
+        run_id: Some(run_id.clone()),
+        state_dir: Some(state_dir.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+    let run = RunRegistry::new(state_dir.path())
+        .load(&run_id)
+        .await
+        .unwrap();
+    assert!(run.wait(std::time::Duration::from_secs(10)).await);
+    let result = later.collect().await.unwrap();
+
+    assert_eq!(result.exit_code, 0);
+    assert_eq!(result.stderr, "warning: detached\n");
+    assert_eq!(result.parsed_output.map(|messages| messages.len()), Some(2));
+    assert_eq!(result.session_id.as_deref(), Some("detached-session"));
+    assert_eq!(result.output_log.len(), 3);
+    assert!(result.metadata.success);
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_stop_terminates_tracked_detached_run() {
+    let state_dir = tempfile::tempdir().unwrap();
+    let _fake_tool = install_fake_tool(
+        "claude",
+        r#"#!/usr/bin/env bash
+trap 'echo "saving session"; exit 130' INT
+echo started
+while true; do sleep 0.1; done
+"#,
+    )
+    .await;
+
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp".to_string(),
+        prompt: Some("hello".to_string()),
+        isolation: "none".to_string(),
+        state_dir: Some(state_dir.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions {
+            detached: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let run = RunRegistry::new(state_dir.path())
+        .load(controller.run_id().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert!(run.is_running().await);
+    assert!(controller.collect().await.is_err());
+
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert!(!run.is_running().await);
+    assert_eq!(result.exit_code, 130);
+    assert_eq!(result.stdout, "started\nsaving session\n");
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_approve_each_relays_permission_requests_to_the_callback() {
+    use agent_commander::PermissionHandler;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::{Arc, Mutex};
+
+    // The fake tool asks for one Bash command mid-turn, then reports the
+    // prompt frame and the decision frame it received on stdin
+    let dir = tempfile::tempdir().unwrap();
+    let fake_claude = dir.path().join("claude");
+    std::fs::write(
+        &fake_claude,
+        r#"#!/usr/bin/env bash
+read -r prompt
+echo '{"type":"control_request","request_id":"r1","request":{"subtype":"can_use_tool","tool_name":"Bash","input":{"command":"ls"}}}'
+read -r decision
+echo "{\"type\":\"result\",\"prompt\":$prompt,\"decision\":$decision}"
+read -r rest || echo '{"type":"closed"}'
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen_by_handler = Arc::clone(&seen);
+    let mut controller = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: dir.path().to_string_lossy().into_owned(),
+        prompt: Some("list the files".to_string()),
+        executable: Some(fake_claude.to_string_lossy().into_owned()),
+        approve_each: true,
+        on_permission_request: Some(PermissionHandler::new(move |request| {
+            seen_by_handler
+                .lock()
+                .unwrap()
+                .push(request.command.clone().unwrap_or_default());
+            "once".to_string()
+        })),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.exit_code, 0, "{}", result.stderr);
+    assert_eq!(*seen.lock().unwrap(), vec!["ls".to_string()]);
+    assert_eq!(result.permissions.len(), 1);
+    assert_eq!(result.permissions[0].decision, "once");
+    assert_eq!(
+        result.permissions[0].request.tool_name.as_deref(),
+        Some("Bash")
+    );
+
+    let messages = result.parsed_output.unwrap();
+    let reported = messages
+        .iter()
+        .find(|message| message["type"] == "result")
+        .unwrap();
+    assert_eq!(reported["prompt"]["message"]["content"], "list the files");
+    assert_eq!(reported["decision"]["type"], "control_response");
+    assert_eq!(
+        reported["decision"]["response"]["request_id"],
+        serde_json::json!("r1")
+    );
+    // stdin is closed once the turn's result arrives, ending the session
+    assert!(messages.iter().any(|message| message["type"] == "closed"));
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn test_approve_each_rejects_requests_without_a_callback() {
+    use agent_commander::PermissionAudit;
+
+    let (_path_lock, _path_guard) = install_fake_tool(
+        "agent",
+        r#"#!/usr/bin/env bash
+read -r prompt
+echo '{"type":"permission_request","permissionID":"p1","tool":"bash","metadata":{"command":"rm -rf build"}}'
+read -r decision
+echo "{\"type\":\"result\",\"prompt\":$prompt,\"decision\":$decision}"
+"#,
+    )
+    .await;
+
+    let audit_dir = tempfile::tempdir().unwrap();
+    let audit_log = audit_dir.path().join("permissions.jsonl");
+    let mut controller = agent(AgentOptions {
+        tool: "agent".to_string(),
+        working_directory: std::env::temp_dir().to_string_lossy().into_owned(),
+        prompt: Some("clean up".to_string()),
+        system_prompt: Some("be careful".to_string()),
+        approve_each: true,
+        permission_audit: Some(PermissionAudit::file(&audit_log).unwrap()),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.permissions.len(), 1);
+    assert_eq!(result.permissions[0].decision, "reject");
+    let messages = result.parsed_output.unwrap();
+    let reported = messages
+        .iter()
+        .find(|message| message["type"] == "result")
+        .unwrap();
+    assert_eq!(reported["prompt"]["message"], "be careful\n\nclean up");
+    assert_eq!(reported["decision"], result.permissions[0].response);
+
+    let audit = std::fs::read_to_string(&audit_log).unwrap();
+    let entry: serde_json::Value = serde_json::from_str(audit.trim_end()).unwrap();
+    assert_eq!(entry["command"], "rm -rf build");
+    assert_eq!(entry["decision"], "reject");
+    assert_eq!(entry["source"], "no handler");
+    assert_eq!(entry["response"], result.permissions[0].response);
+}
+
+#[test]
+fn test_approve_each_requires_a_relayable_isolation() {
+    let result = agent(AgentOptions {
+        tool: "claude".to_string(),
+        working_directory: "/tmp/test".to_string(),
+        isolation: "screen".to_string(),
+        screen_name: Some("ask".to_string()),
+        approve_each: true,
+        ..Default::default()
+    });
+    match result {
+        Err(e) => assert!(e.contains("approve_each requires isolation")),
+        Ok(_) => panic!("approve_each under screen should be rejected"),
+    }
+}
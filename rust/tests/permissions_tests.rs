@@ -1,30 +1,34 @@
 //! Tests for the uniform per-command approval ("ask" mode) permission relay.
-//! Rust mirror of `js/test/permissions.test.mjs`.
+//! Rust mirror of `js/test/permissions.test.mjs`; the codex app-server relay
+//! is Rust only, so the codex cases have no JavaScript counterpart.
 
 use agent_commander::{
-    ask_scope, ask_unsupported_error, build_permission_response, normalize_permission_request,
-    permission_parity, supports_ask, NormalizedPermissionRequest, PermissionRelay, ASK_DECISIONS,
-    ASK_SUPPORTED_TOOLS,
+    ask_scope, ask_unsupported_error, build_decision_response, build_initial_input_frame,
+    build_permission_response, normalize_permission_request, permission_parity, supports_ask,
+    AsyncPermissionHandler, AsyncPermissionRelay, NormalizedPermissionRequest, PermissionAudit,
+    PermissionAuditEntry, PermissionAuditSink, PermissionCancellation, PermissionDecision,
+    PermissionHandler, PermissionRelay, ASK_DECISIONS, ASK_SUPPORTED_TOOLS,
 };
 use serde_json::json;
+use std::time::Duration;
 
 #[test]
-fn supports_ask_only_agent_and_claude_are_relayable() {
+fn supports_ask_only_agent_claude_and_codex_are_relayable() {
     assert!(supports_ask("agent"));
     assert!(supports_ask("claude"));
-    assert!(!supports_ask("codex"));
+    assert!(supports_ask("codex"));
     assert!(!supports_ask("qwen"));
     assert!(!supports_ask("gemini"));
     assert!(!supports_ask("opencode"));
-    assert_eq!(ASK_SUPPORTED_TOOLS, &["agent", "claude"]);
+    assert_eq!(ASK_SUPPORTED_TOOLS, &["agent", "claude", "codex"]);
 }
 
 #[test]
 fn ask_unsupported_error_mentions_tool_and_supported_tools() {
-    let message = ask_unsupported_error("codex");
-    assert!(message.contains("Tool \"codex\""));
+    let message = ask_unsupported_error("qwen");
+    assert!(message.contains("Tool \"qwen\""));
     assert!(message.contains("per-command approval"));
-    assert!(message.contains("agent, claude"));
+    assert!(message.contains("agent, claude, codex"));
     assert!(message.contains("--approve-each"));
 }
 
@@ -38,8 +42,9 @@ fn ask_decisions_exactly_once_always_reject() {
 #[test]
 fn ask_scope_documents_per_backend_always_semantics() {
     assert_eq!(ask_scope("agent"), Some("session"));
-    assert_eq!(ask_scope("claude"), Some("tool-input"));
-    assert_eq!(ask_scope("codex"), None);
+    assert_eq!(ask_scope("claude"), Some("session"));
+    assert_eq!(ask_scope("codex"), Some("session"));
+    assert_eq!(ask_scope("qwen"), None);
 }
 
 #[test]
@@ -107,7 +112,7 @@ fn normalize_claude_can_use_tool_control_request() {
     assert_eq!(normalized.call_id.as_deref(), Some("tu-9"));
     assert_eq!(normalized.tool_name.as_deref(), Some("Bash"));
     assert_eq!(normalized.command.as_deref(), Some("npm test"));
-    assert_eq!(normalized.scope, "tool-input");
+    assert_eq!(normalized.scope, "session");
     assert_eq!(normalized.input, Some(json!({ "command": "npm test" })));
 }
 
@@ -214,7 +219,7 @@ fn build_response_rejects_invalid_decision() {
 #[test]
 fn build_response_rejects_unsupported_tool() {
     let request = agent_request("x");
-    let err = build_permission_response("codex", &request, "once").unwrap_err();
+    let err = build_permission_response("gemini", &request, "once").unwrap_err();
     assert!(err.contains("does not support enforceable per-command approval"));
 }
 
@@ -238,7 +243,9 @@ fn permission_parity_covers_all_six_tools_with_scope_and_relay() {
         .map(|row| row.tool)
         .collect();
     relayable.sort_unstable();
-    assert_eq!(relayable, vec!["agent", "claude"]);
+    assert_eq!(relayable, vec!["agent", "claude", "codex"]);
+    let codex = parity.iter().find(|row| row.tool == "codex").unwrap();
+    assert!(codex.notes.starts_with("Rust only"), "{}", codex.notes);
 }
 
 #[test]
@@ -348,3 +355,609 @@ fn relay_claude_allow_forwards_updated_input() {
         json!({ "command": "npm test" })
     );
 }
+
+#[test]
+fn initial_input_frame_matches_each_tool() {
+    assert_eq!(
+        build_initial_input_frame("claude", Some("fix it"), Some("be brief")),
+        json!({
+            "type": "user",
+            "message": { "role": "user", "content": "fix it" },
+        })
+    );
+    assert_eq!(
+        build_initial_input_frame("agent", Some("fix it"), Some("be brief")),
+        json!({ "type": "user", "message": "be brief\n\nfix it" })
+    );
+    assert_eq!(
+        build_initial_input_frame("agent", Some("fix it"), None),
+        json!({ "type": "user", "message": "fix it" })
+    );
+}
+
+fn agent_permission(id: &str, command: &str) -> serde_json::Value {
+    json!({
+        "type": "permission_request",
+        "permissionID": id,
+        "tool": "bash",
+        "metadata": { "command": command },
+    })
+}
+
+#[tokio::test]
+async fn async_relay_answers_requests_in_flight_by_id() {
+    // "slow" is decided after "fast", so its response is written second
+    let handler = AsyncPermissionHandler::new(|request: NormalizedPermissionRequest| async move {
+        if request.command.as_deref() == Some("slow") {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            "always".to_string()
+        } else {
+            "once".to_string()
+        }
+    });
+    let (frames, mut written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay = AsyncPermissionRelay::new("agent", handler, None, frames);
+
+    assert!(relay
+        .handle_message(&agent_permission("p1", "slow"))
+        .is_some());
+    assert!(relay
+        .handle_message(&agent_permission("p2", "fast"))
+        .is_some());
+    assert!(relay.handle_message(&json!({ "type": "text" })).is_none());
+    // A resent request is not decided twice
+    assert!(relay
+        .handle_message(&agent_permission("p1", "slow"))
+        .is_none());
+    assert_eq!(relay.in_flight_ids(), vec!["p1", "p2"]);
+
+    let first: serde_json::Value = serde_json::from_str(&written.recv().await.unwrap()).unwrap();
+    let second: serde_json::Value = serde_json::from_str(&written.recv().await.unwrap()).unwrap();
+    assert_eq!(first["permissionID"], "p2");
+    assert_eq!(first["response"], "once");
+    assert_eq!(second["permissionID"], "p1");
+    assert_eq!(second["response"], "always");
+
+    let answered = relay.finish().await;
+    let ids: Vec<_> = answered
+        .iter()
+        .map(|handled| handled.request.id.as_deref().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["p1", "p2"]);
+    assert!(answered.iter().all(|handled| !handled.timed_out));
+    // Every sender is gone once the relay is finished
+    assert!(written.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn async_relay_rejects_requests_not_decided_in_time() {
+    let handler = AsyncPermissionHandler::new(|_| async {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        "once".to_string()
+    });
+    let (frames, mut written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay =
+        AsyncPermissionRelay::new("claude", handler, Some(Duration::from_millis(50)), frames);
+    relay.handle_message(&json!({
+        "type": "control_request",
+        "request_id": "req-t",
+        "request": {
+            "subtype": "can_use_tool",
+            "tool_name": "Bash",
+            "input": { "command": "make deploy" },
+        },
+    }));
+
+    let frame: serde_json::Value = serde_json::from_str(&written.recv().await.unwrap()).unwrap();
+    assert_eq!(frame["response"]["request_id"], "req-t");
+    assert_eq!(frame["response"]["response"]["behavior"], "deny");
+    let answered = relay.finish().await;
+    assert_eq!(answered.len(), 1);
+    assert_eq!(answered[0].decision, "reject");
+    assert!(answered[0].timed_out);
+    assert_eq!(
+        frame["response"]["response"]["message"],
+        "Permission request timed out (ask mode)."
+    );
+}
+
+#[tokio::test]
+async fn async_relay_cancels_a_timed_out_blocking_handler_and_asks_the_next_request() {
+    let cancelled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let seen = std::sync::Arc::clone(&cancelled);
+    let handler: AsyncPermissionHandler = PermissionHandler::cancellable(
+        move |request: &NormalizedPermissionRequest, cancellation: &PermissionCancellation| {
+            if request.command.as_deref() != Some("stuck") {
+                return "once";
+            }
+            // A prompt nobody answers, until the relay gives up on it
+            while !cancellation.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            seen.store(true, std::sync::atomic::Ordering::SeqCst);
+            "always"
+        },
+    )
+    .into();
+    let (frames, _written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay =
+        AsyncPermissionRelay::new("agent", handler, Some(Duration::from_millis(100)), frames);
+    relay.handle_message(&agent_permission("p1", "stuck"));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    relay.handle_message(&agent_permission("p2", "ls"));
+
+    let answered = relay.finish().await;
+    assert!(answered[0].timed_out);
+    assert_eq!(answered[0].decision, "reject");
+    assert!(!answered[1].timed_out);
+    assert_eq!(answered[1].decision, "once");
+    assert!(cancelled.load(std::sync::atomic::Ordering::SeqCst));
+
+    // A handler that ignores cancellation no longer holds up the next request
+    let handler: AsyncPermissionHandler = PermissionHandler::new(|request| {
+        if request.command.as_deref() == Some("stuck") {
+            std::thread::sleep(Duration::from_secs(2));
+        }
+        "once"
+    })
+    .into();
+    let (frames, _written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay =
+        AsyncPermissionRelay::new("agent", handler, Some(Duration::from_millis(100)), frames);
+    let started = std::time::Instant::now();
+    relay.handle_message(&agent_permission("p1", "stuck"));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    relay.handle_message(&agent_permission("p2", "ls"));
+    let answered = relay.finish().await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(answered[0].timed_out);
+    assert_eq!(answered[1].decision, "once");
+}
+
+#[tokio::test]
+async fn async_relay_runs_blocking_handlers_and_stops_after_close() {
+    let handler: AsyncPermissionHandler = PermissionHandler::new(|request| {
+        std::thread::sleep(Duration::from_millis(10));
+        if request.command.as_deref() == Some("ls") {
+            "once".to_string()
+        } else {
+            // Unknown decisions fall back to reject
+            "sure".to_string()
+        }
+    })
+    .into();
+    let (frames, _written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay = AsyncPermissionRelay::new("agent", handler, None, frames);
+    relay.handle_message(&agent_permission("p1", "ls"));
+    relay.handle_message(&agent_permission("p2", "rm"));
+    relay.settle().await;
+    relay.close();
+    assert!(relay
+        .handle_message(&agent_permission("p3", "ls"))
+        .is_none());
+
+    let decisions: Vec<_> = relay
+        .finish()
+        .await
+        .into_iter()
+        .map(|handled| handled.decision)
+        .collect();
+    assert_eq!(decisions, vec!["once", "reject"]);
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn agent_rejects_permission_requests_after_the_timeout() {
+    use agent_commander::{agent, AgentOptions, AgentStartOptions, AgentStopOptions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let fake_agent = dir.path().join("agent");
+    std::fs::write(
+        &fake_agent,
+        r#"#!/usr/bin/env bash
+read -r prompt
+echo '{"type":"permission_request","permissionID":"p1","tool":"bash","metadata":{"command":"deploy"}}'
+read -r decision
+echo "{\"type\":\"result\",\"decision\":$decision}"
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_agent, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut controller = agent(AgentOptions {
+        tool: "agent".to_string(),
+        working_directory: dir.path().to_string_lossy().into_owned(),
+        prompt: Some("ship it".to_string()),
+        executable: Some(fake_agent.to_string_lossy().into_owned()),
+        approve_each: true,
+        // Takes precedence over the blocking handler, which would allow it
+        on_permission_request: Some(PermissionHandler::new(|_| "always".to_string())),
+        on_permission_request_async: Some(AsyncPermissionHandler::new(|_| async {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            "always".to_string()
+        })),
+        permission_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert_eq!(result.permissions.len(), 1);
+    assert_eq!(result.permissions[0].decision, "reject");
+    assert!(result.permissions[0].timed_out);
+    let reported = result
+        .parsed_output
+        .unwrap()
+        .into_iter()
+        .find(|message| message["type"] == "result")
+        .unwrap();
+    assert_eq!(reported["decision"]["response"], "reject");
+}
+
+#[tokio::test]
+#[cfg(not(target_os = "windows"))]
+async fn live_relay_closes_stdin_once_the_tool_closes_stdout_without_a_result() {
+    use agent_commander::{agent, AgentOptions, AgentStartOptions, AgentStopOptions};
+    use std::os::unix::fs::PermissionsExt;
+
+    // The tool gives up on the request, closes stdout and waits for the end
+    // of its input before it exits
+    let dir = tempfile::tempdir().unwrap();
+    let stdin_closed = dir.path().join("stdin-closed");
+    let fake_agent = dir.path().join("agent");
+    std::fs::write(
+        &fake_agent,
+        format!(
+            r#"#!/usr/bin/env bash
+read -r prompt
+echo '{{"type":"permission_request","permissionID":"p1","tool":"bash","metadata":{{"command":"deploy"}}}}'
+exec 1>&-
+cat > /dev/null
+touch '{}'
+"#,
+            stdin_closed.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake_agent, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // The decision is still pending when stdout closes
+    let marker = stdin_closed.clone();
+    let mut controller = agent(AgentOptions {
+        tool: "agent".to_string(),
+        working_directory: dir.path().to_string_lossy().into_owned(),
+        prompt: Some("ship it".to_string()),
+        executable: Some(fake_agent.to_string_lossy().into_owned()),
+        approve_each: true,
+        on_permission_request_async: Some(AsyncPermissionHandler::new(move |_| {
+            let marker = marker.clone();
+            async move {
+                for _ in 0..50 {
+                    if marker.exists() {
+                        return "once".to_string();
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                "reject".to_string()
+            }
+        })),
+        ..Default::default()
+    })
+    .unwrap();
+    controller
+        .start(AgentStartOptions::default())
+        .await
+        .unwrap();
+    let result = controller.stop(AgentStopOptions::default()).await.unwrap();
+
+    assert!(stdin_closed.exists());
+    assert_eq!(result.permissions.len(), 1);
+    assert_eq!(result.permissions[0].decision, "once");
+}
+
+#[test]
+fn claude_decisions_carry_edited_input_and_deny_messages() {
+    let request = claude_request("req-e", "rm -rf build");
+
+    let allowed = build_decision_response(
+        "claude",
+        &request,
+        &PermissionDecision::allow_with_input(json!({ "command": "rm -rf build/tmp" })),
+    )
+    .unwrap();
+    assert_eq!(allowed["response"]["response"]["behavior"], "allow");
+    assert_eq!(
+        allowed["response"]["response"]["updatedInput"],
+        json!({ "command": "rm -rf build/tmp" })
+    );
+
+    let denied = build_decision_response(
+        "claude",
+        &request,
+        &PermissionDecision::reject_with_message("Only delete build/tmp."),
+    )
+    .unwrap();
+    assert_eq!(denied["response"]["response"]["behavior"], "deny");
+    assert_eq!(
+        denied["response"]["response"]["message"],
+        "Only delete build/tmp."
+    );
+
+    // Fields that do not fit the decision are dropped
+    let decision = PermissionDecision {
+        message: Some("unused".to_string()),
+        ..PermissionDecision::new("always")
+    };
+    assert_eq!(
+        decision.for_tool("claude"),
+        PermissionDecision::new("always")
+    );
+    assert!(
+        build_decision_response("claude", &request, &PermissionDecision::new("maybe")).is_err()
+    );
+}
+
+#[test]
+fn agent_decisions_degrade_to_the_native_protocol() {
+    // agent cannot run an edited input, so the request is rejected instead
+    let edited = PermissionDecision::allow_with_input(json!({ "command": "ls" }));
+    assert_eq!(edited.for_tool("agent"), PermissionDecision::new("reject"));
+    let denied = PermissionDecision::reject_with_message("no");
+    assert_eq!(denied.for_tool("agent"), PermissionDecision::new("reject"));
+    assert_eq!(
+        PermissionDecision::new("sure").for_tool("agent"),
+        PermissionDecision::new("reject")
+    );
+
+    let mut written: Vec<String> = Vec::new();
+    let handled = {
+        let mut relay = PermissionRelay::new(
+            "agent",
+            |_: &NormalizedPermissionRequest| edited.clone(),
+            |line: &str| written.push(line.to_string()),
+        );
+        relay
+            .handle_message(&agent_permission("p9", "ls -la"))
+            .unwrap()
+    };
+    assert_eq!(handled.1, "reject");
+    let frame: serde_json::Value = serde_json::from_str(&written[0]).unwrap();
+    assert_eq!(
+        frame,
+        json!({ "type": "permission_response", "permissionID": "p9", "response": "reject" })
+    );
+}
+
+#[tokio::test]
+async fn async_relay_records_the_decision_as_sent() {
+    let handler = AsyncPermissionHandler::new(|request: NormalizedPermissionRequest| async move {
+        let command = request.command.unwrap_or_default();
+        PermissionDecision::allow_with_input(json!({ "command": format!("{} --dry-run", command) }))
+    });
+    let (frames, mut written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay = AsyncPermissionRelay::new("claude", handler, None, frames);
+    relay.handle_message(&json!({
+        "type": "control_request",
+        "request_id": "req-d",
+        "request": {
+            "subtype": "can_use_tool",
+            "tool_name": "Bash",
+            "input": { "command": "make deploy" },
+        },
+    }));
+
+    let frame: serde_json::Value = serde_json::from_str(&written.recv().await.unwrap()).unwrap();
+    let answered = relay.finish().await;
+    assert_eq!(
+        answered[0].updated_input,
+        Some(json!({ "command": "make deploy --dry-run" }))
+    );
+    assert_eq!(answered[0].message, None);
+    assert_eq!(
+        frame["response"]["response"]["updatedInput"],
+        json!({ "command": "make deploy --dry-run" })
+    );
+}
+
+fn claude_permission(id: &str, tool_name: &str, input: serde_json::Value) -> serde_json::Value {
+    json!({
+        "type": "control_request",
+        "request_id": id,
+        "request": { "subtype": "can_use_tool", "tool_name": tool_name, "input": input },
+    })
+}
+
+#[test]
+fn relay_remembers_claude_always_for_the_session() {
+    let mut asked: Vec<String> = Vec::new();
+    let mut written: Vec<String> = Vec::new();
+    {
+        let mut relay = PermissionRelay::new(
+            "claude",
+            |request: &NormalizedPermissionRequest| {
+                let command = request.command.clone().unwrap_or_default();
+                asked.push(command.clone());
+                if command == "npm test" || command == "/repo/a.rs" {
+                    "always"
+                } else {
+                    "once"
+                }
+            },
+            |line: &str| written.push(line.to_string()),
+        );
+        let messages = [
+            claude_permission("r1", "Bash", json!({ "command": "npm test" })),
+            // Same command up to whitespace: answered without asking
+            claude_permission("r2", "Bash", json!({ "command": "  npm   test " })),
+            claude_permission("r3", "Bash", json!({ "command": "npm run build" })),
+            // `once` is not remembered
+            claude_permission("r4", "Bash", json!({ "command": "npm run build" })),
+            claude_permission(
+                "r5",
+                "Edit",
+                json!({ "file_path": "/repo/a.rs", "old_string": "a" }),
+            ),
+            claude_permission(
+                "r6",
+                "Edit",
+                json!({ "file_path": "/repo/a.rs", "old_string": "b" }),
+            ),
+            // The same path under another tool is a new request
+            claude_permission("r7", "Write", json!({ "file_path": "/repo/a.rs" })),
+        ];
+        for message in &messages {
+            relay.handle_message(message).unwrap();
+        }
+        let decisions: Vec<&str> = relay
+            .get_handled()
+            .iter()
+            .map(|(_, decision, _)| decision.as_str())
+            .collect();
+        assert_eq!(
+            decisions,
+            vec!["always", "always", "once", "once", "always", "always", "always"]
+        );
+    }
+    assert_eq!(
+        asked,
+        vec![
+            "npm test",
+            "npm run build",
+            "npm run build",
+            "/repo/a.rs",
+            "/repo/a.rs"
+        ]
+    );
+    // A remembered request still runs its own input
+    let frame: serde_json::Value = serde_json::from_str(&written[5]).unwrap();
+    assert_eq!(frame["response"]["request_id"], "r6");
+    assert_eq!(frame["response"]["response"]["behavior"], "allow");
+    assert_eq!(
+        frame["response"]["response"]["updatedInput"]["old_string"],
+        "b"
+    );
+}
+
+#[tokio::test]
+async fn async_relay_marks_requests_answered_from_an_earlier_always() {
+    let handler = AsyncPermissionHandler::new(|request: NormalizedPermissionRequest| async move {
+        if request.command.as_deref() == Some("cargo test") {
+            PermissionDecision::new("always")
+        } else {
+            // An edited input is never remembered
+            PermissionDecision {
+                updated_input: Some(json!({ "command": "cargo fmt --check" })),
+                ..PermissionDecision::new("always")
+            }
+        }
+    });
+    let (frames, mut written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay = AsyncPermissionRelay::new("claude", handler, None, frames);
+    for (id, command) in [
+        ("c1", "cargo test"),
+        ("c2", "cargo fmt"),
+        ("c3", "cargo test"),
+        ("c4", "cargo fmt"),
+    ] {
+        relay.handle_message(&claude_permission(
+            id,
+            "Bash",
+            json!({ "command": command }),
+        ));
+        // Let each decision land before the next request arrives
+        written.recv().await.unwrap();
+    }
+
+    let answered = relay.finish().await;
+    let remembered: Vec<bool> = answered.iter().map(|handled| handled.remembered).collect();
+    assert_eq!(remembered, vec![false, false, true, false]);
+    assert_eq!(answered[2].decision, "always");
+    assert_eq!(
+        answered[3].updated_input,
+        Some(json!({ "command": "cargo fmt --check" }))
+    );
+}
+
+/// Audit sink keeping entries in memory
+#[derive(Clone, Default)]
+struct CollectedAudit(std::sync::Arc<std::sync::Mutex<Vec<PermissionAuditEntry>>>);
+
+impl PermissionAuditSink for CollectedAudit {
+    fn record(&self, entry: &PermissionAuditEntry) -> Result<(), String> {
+        self.0.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+}
+
+#[test]
+fn relay_records_each_decision_and_its_source_in_the_audit_trail() {
+    let audit = CollectedAudit::default();
+    let mut written: Vec<String> = Vec::new();
+    {
+        let mut relay = PermissionRelay::new(
+            "claude",
+            |_: &NormalizedPermissionRequest| PermissionDecision::new("always"),
+            |line: &str| written.push(line.to_string()),
+        )
+        .with_audit(PermissionAudit::new(audit.clone()));
+        for id in ["r1", "r2"] {
+            relay.handle_message(&claude_permission(id, "Bash", json!({ "command": "make" })));
+        }
+    }
+
+    let entries = audit.0.lock().unwrap().clone();
+    let sources: Vec<&str> = entries.iter().map(|entry| entry.source.as_str()).collect();
+    assert_eq!(sources, vec!["handler", "session"]);
+    assert_eq!(entries[1].tool, "claude");
+    assert_eq!(entries[1].id.as_deref(), Some("r2"));
+    assert_eq!(entries[1].tool_name.as_deref(), Some("Bash"));
+    assert_eq!(entries[1].command.as_deref(), Some("make"));
+    assert_eq!(entries[1].decision, "always");
+    assert_eq!(
+        entries[1].response,
+        serde_json::from_str::<serde_json::Value>(&written[1]).unwrap()
+    );
+    assert!(entries[0].timestamp_ms > 0);
+}
+
+#[tokio::test]
+async fn async_relay_audits_timeouts_to_a_jsonl_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    std::fs::write(&path, "{\"earlier\":true}\n").unwrap();
+    let handler = AsyncPermissionHandler::new(|request: NormalizedPermissionRequest| async move {
+        if request.command.as_deref() == Some("slow") {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+        "once"
+    });
+    let (frames, _written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay =
+        AsyncPermissionRelay::new("agent", handler, Some(Duration::from_millis(50)), frames)
+            .with_audit(PermissionAudit::file(&path).unwrap());
+    relay.handle_message(&agent_permission("p1", "fast"));
+    relay.handle_message(&agent_permission("p2", "slow"));
+    let answered = relay.finish().await;
+    assert_eq!(answered[1].source, "timeout");
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], json!({ "earlier": true }));
+    let mut entries = lines[1..].to_vec();
+    entries.sort_by_key(|entry| entry["id"].as_str().unwrap().to_string());
+    assert_eq!(entries[0]["decision"], "once");
+    assert_eq!(entries[0]["source"], "handler");
+    assert_eq!(entries[1]["decision"], "reject");
+    assert_eq!(entries[1]["source"], "timeout");
+    assert_eq!(entries[1]["command"], "slow");
+    assert_eq!(entries[1]["response"], answered[1].response);
+}
@@ -0,0 +1,337 @@
+//! Tests for compiling static permission policies to native tool policies
+
+use agent_commander::{
+    build_agent_launch_spec, AgentCommandOptions, AgentOptions, StaticPermissionPolicy,
+    StaticPermissionRule,
+};
+use serde_json::{json, Value};
+
+fn rule(category: &str, pattern: Option<&str>, action: &str) -> StaticPermissionRule {
+    StaticPermissionRule {
+        category: category.to_string(),
+        pattern: pattern.map(str::to_string),
+        action: action.to_string(),
+    }
+}
+
+const POLICY_TOML: &str = r#"
+default = "allow"
+
+[[rules]]
+category = "bash"
+pattern = "git push*"
+action = "deny"
+
+[[rules]]
+category = "webfetch"
+action = "deny"
+"#;
+
+#[test]
+fn test_static_policy_rejects_unknown_categories_actions_and_repeats() {
+    let error =
+        StaticPermissionPolicy::new(vec![rule("network", None, "deny")], "allow").unwrap_err();
+    assert!(error.contains("category \"network\""), "{}", error);
+
+    let error = StaticPermissionPolicy::new(vec![rule("bash", None, "ask")], "allow").unwrap_err();
+    assert!(error.contains("action \"ask\""), "{}", error);
+
+    let error = StaticPermissionPolicy::new(vec![], "maybe").unwrap_err();
+    assert!(error.contains("default action"), "{}", error);
+
+    let error = StaticPermissionPolicy::new(
+        vec![
+            rule("bash", Some("rm*"), "deny"),
+            rule("bash", Some("rm*"), "allow"),
+        ],
+        "allow",
+    )
+    .unwrap_err();
+    assert!(error.contains("rule 2 repeats"), "{}", error);
+}
+
+#[test]
+fn test_static_policy_compiles_to_opencode_permission_env() {
+    let policy = StaticPermissionPolicy::from_toml_str(POLICY_TOML).unwrap();
+    let native = policy.compile("opencode").unwrap();
+
+    assert!(native.args.is_empty());
+    assert!(native.unsupported.is_empty(), "{:?}", native.unsupported);
+    assert_eq!(native.env.len(), 1);
+    assert_eq!(native.env[0].0, "OPENCODE_PERMISSION");
+    let permission: Value = serde_json::from_str(&native.env[0].1).unwrap();
+    assert_eq!(
+        permission,
+        json!({
+            "edit": "allow",
+            "bash": { "git push*": "deny", "*": "allow" },
+            "webfetch": "deny",
+            "task": "allow",
+        })
+    );
+}
+
+#[test]
+fn test_static_policy_compiles_to_agent_permission_flag() {
+    let policy = StaticPermissionPolicy::new(vec![rule("edit", None, "deny")], "allow").unwrap();
+    let native = policy.compile("agent").unwrap();
+
+    let permission: Value = serde_json::from_str(native.permission.as_deref().unwrap()).unwrap();
+    assert_eq!(permission["edit"], "deny");
+    assert_eq!(permission["bash"], "allow");
+    assert!(native.args.is_empty());
+    assert!(native.env.is_empty());
+}
+
+#[test]
+fn test_static_policy_reports_what_opencode_cannot_express() {
+    let policy = StaticPermissionPolicy::new(
+        vec![
+            rule("edit", Some("/etc/*"), "deny"),
+            rule("read", None, "deny"),
+        ],
+        "allow",
+    )
+    .unwrap();
+    let native = policy.compile("opencode").unwrap();
+
+    assert_eq!(native.unsupported.len(), 2, "{:?}", native.unsupported);
+    assert!(native.unsupported[0].starts_with("edit pattern \"/etc/*\""));
+    assert!(native.unsupported[1].starts_with("read deny"));
+}
+
+#[test]
+fn test_static_policy_keeps_claude_bypass_when_only_patterns_are_denied() {
+    let policy = StaticPermissionPolicy::from_toml_str(
+        r#"
+[[rules]]
+category = "bash"
+pattern = "git push*"
+action = "deny"
+
+[[rules]]
+category = "edit"
+pattern = "/etc/*"
+action = "deny"
+"#,
+    )
+    .unwrap();
+    let native = policy.compile("claude").unwrap();
+
+    assert!(!native.skip_default_safety_flags);
+    assert_eq!(
+        native.args,
+        vec![
+            "--disallowedTools",
+            "Bash(git push:*)",
+            "--disallowedTools",
+            "Edit(//etc/**)",
+        ]
+    );
+    assert!(native.unsupported.is_empty(), "{:?}", native.unsupported);
+}
+
+#[test]
+fn test_static_policy_lists_claude_allowed_tools_under_a_deny_default() {
+    let policy = StaticPermissionPolicy::new(
+        vec![
+            rule("edit", None, "allow"),
+            rule("bash", Some("cargo test*"), "allow"),
+            rule("bash", Some("git status"), "allow"),
+        ],
+        "deny",
+    )
+    .unwrap();
+    let native = policy.compile("claude").unwrap();
+
+    assert!(native.skip_default_safety_flags);
+    assert_eq!(
+        native.args,
+        vec![
+            "--allowedTools",
+            "Bash(cargo test:*)",
+            "--allowedTools",
+            "Bash(git status)",
+            "--allowedTools",
+            "Edit",
+            "--disallowedTools",
+            "Read",
+        ]
+    );
+    assert!(native.unsupported.is_empty(), "{:?}", native.unsupported);
+}
+
+#[test]
+fn test_static_policy_reports_what_claude_cannot_express() {
+    let policy = StaticPermissionPolicy::new(
+        vec![
+            rule("read", None, "deny"),
+            rule("read", Some("/repo/*"), "allow"),
+            rule("bash", Some("rm * -rf"), "deny"),
+            rule("webfetch", Some("https://example.com/*"), "deny"),
+        ],
+        "allow",
+    )
+    .unwrap();
+    let native = policy.compile("claude").unwrap();
+
+    assert_eq!(native.args, vec!["--disallowedTools", "Read"]);
+    assert_eq!(native.unsupported.len(), 3, "{:?}", native.unsupported);
+    assert!(native.unsupported[0].starts_with("bash pattern \"rm * -rf\""));
+    assert!(native.unsupported[1].starts_with("read pattern \"/repo/*\""));
+    assert!(native.unsupported[2].starts_with("webfetch pattern"));
+}
+
+#[test]
+fn test_static_policy_picks_gemini_and_qwen_approval_modes() {
+    let cases = [
+        (vec![], "allow", "gemini", "yolo"),
+        (
+            vec![rule("bash", None, "deny")],
+            "allow",
+            "gemini",
+            "auto_edit",
+        ),
+        (
+            vec![rule("bash", None, "deny")],
+            "allow",
+            "qwen",
+            "auto-edit",
+        ),
+        (vec![], "deny", "qwen", "default"),
+    ];
+    for (rules, default, tool, mode) in cases {
+        let policy = StaticPermissionPolicy::new(rules, default).unwrap();
+        let native = policy.compile(tool).unwrap();
+        assert_eq!(native.args, vec!["--approval-mode", mode], "{}", tool);
+        assert!(native.skip_default_safety_flags);
+    }
+}
+
+#[test]
+fn test_static_policy_reports_what_approval_modes_cannot_express() {
+    let policy = StaticPermissionPolicy::from_toml_str(POLICY_TOML).unwrap();
+    let native = policy.compile("gemini").unwrap();
+
+    assert_eq!(native.args, vec!["--approval-mode", "yolo"]);
+    assert_eq!(
+        native.unsupported,
+        vec![
+            "webfetch deny: the yolo approval mode allows it",
+            "bash pattern \"git push*\": approval modes cannot tell commands or paths apart",
+        ]
+    );
+}
+
+#[test]
+fn test_static_policy_rejects_tools_without_static_policies() {
+    let policy = StaticPermissionPolicy::new(vec![], "allow").unwrap();
+    let error = policy.compile("codex").unwrap_err();
+    assert!(
+        error.contains("\"codex\" has no static permission policy"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_native_policy_applies_to_agent_options() {
+    let policy = StaticPermissionPolicy::new(vec![rule("bash", None, "deny")], "allow").unwrap();
+    let mut options = AgentOptions {
+        tool: "claude".to_string(),
+        extra_args: vec!["--verbose".to_string()],
+        ..Default::default()
+    };
+    policy
+        .compile("claude")
+        .unwrap()
+        .apply(&mut options)
+        .unwrap();
+
+    assert!(options.skip_default_safety_flags);
+    assert_eq!(
+        options.extra_args,
+        vec![
+            "--verbose",
+            "--allowedTools",
+            "Edit",
+            "--allowedTools",
+            "WebFetch",
+            "--allowedTools",
+            "Task"
+        ]
+    );
+
+    let mut options = AgentOptions {
+        tool: "opencode".to_string(),
+        ..Default::default()
+    };
+    policy
+        .compile("opencode")
+        .unwrap()
+        .apply(&mut options)
+        .unwrap();
+    assert_eq!(options.extra_env[0].0, "OPENCODE_PERMISSION");
+}
+
+#[test]
+fn test_native_policy_passes_agent_permission_once() {
+    let policy = StaticPermissionPolicy::new(vec![rule("edit", None, "deny")], "allow").unwrap();
+    let native = policy.compile("agent").unwrap();
+    let mut options = AgentOptions {
+        tool: "agent".to_string(),
+        working_directory: "/tmp".to_string(),
+        ..Default::default()
+    };
+    native.apply(&mut options).unwrap();
+
+    assert_eq!(options.permission, native.permission);
+    assert!(options.extra_args.is_empty());
+    let spec = build_agent_launch_spec(&AgentCommandOptions {
+        tool: "agent".to_string(),
+        working_directory: "/tmp".to_string(),
+        permission: options.permission.clone(),
+        ..Default::default()
+    });
+    let flags = spec.args.iter().filter(|arg| *arg == "--permission");
+    assert_eq!(flags.count(), 1);
+
+    let error = native.apply(&mut options).unwrap_err();
+    assert!(
+        error.contains("already has a permission policy"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_native_policy_is_not_combined_with_read_only_settings() {
+    let policy = StaticPermissionPolicy::new(vec![], "allow").unwrap();
+    for tool in ["gemini", "qwen", "opencode", "agent", "claude"] {
+        let native = policy.compile(tool).unwrap();
+        for plan_only in [false, true] {
+            let mut options = AgentOptions {
+                tool: tool.to_string(),
+                read_only: !plan_only,
+                plan_only,
+                ..Default::default()
+            };
+            let error = native.apply(&mut options).unwrap_err();
+            assert!(error.contains("read_only or plan_only"), "{}", error);
+            assert!(options.extra_args.is_empty());
+            assert!(options.extra_env.is_empty());
+            assert!(options.permission.is_none());
+        }
+    }
+
+    let mut options = AgentOptions {
+        tool: "qwen".to_string(),
+        ..Default::default()
+    };
+    let error = policy
+        .compile("gemini")
+        .unwrap()
+        .apply(&mut options)
+        .unwrap_err();
+    assert!(error.contains("compiled for \"gemini\""), "{}", error);
+}
@@ -0,0 +1,315 @@
+//! Tests for the workspace boundary guard of permission requests
+
+use agent_commander::{
+    normalize_permission_request, AsyncPermissionHandler, AsyncPermissionRelay,
+    NormalizedPermissionRequest, PermissionRelay, WorkspaceGuard,
+};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+fn claude_frame(tool_name: &str, input: Value) -> Value {
+    json!({
+        "type": "control_request",
+        "request_id": "r1",
+        "request": { "subtype": "can_use_tool", "tool_name": tool_name, "input": input },
+    })
+}
+
+fn claude_request(tool_name: &str, input: Value) -> NormalizedPermissionRequest {
+    normalize_permission_request("claude", &claude_frame(tool_name, input)).unwrap()
+}
+
+fn bash(command: &str) -> NormalizedPermissionRequest {
+    claude_request("Bash", json!({ "command": command }))
+}
+
+fn guard(workspace: &Path) -> WorkspaceGuard {
+    WorkspaceGuard::new(workspace, &[] as &[&str])
+}
+
+#[test]
+fn file_paths_must_resolve_inside_the_workspace() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    fs::create_dir_all(workspace.join("src")).unwrap();
+    let guard = guard(&workspace);
+
+    for path in ["src/main.rs", "new/file.txt", "./src/../README.md"] {
+        let request = claude_request("Edit", json!({ "file_path": path }));
+        assert_eq!(guard.check(&request), Ok(()), "{}", path);
+    }
+    let absolute = workspace.join("src/lib.rs");
+    let request = claude_request("Write", json!({ "file_path": absolute }));
+    assert_eq!(guard.check(&request), Ok(()));
+
+    for path in ["/etc/passwd", "../outside.txt", "src/../../outside.txt"] {
+        let request = claude_request("Read", json!({ "file_path": path }));
+        let error = guard.check(&request).unwrap_err();
+        assert!(error.starts_with(path), "{}", error);
+        assert!(error.ends_with("outside the workspace"), "{}", error);
+    }
+}
+
+#[test]
+fn extra_directories_are_allowed() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    let shared = dir.path().join("shared");
+    fs::create_dir_all(&workspace).unwrap();
+    fs::create_dir_all(&shared).unwrap();
+    let guard = WorkspaceGuard::new(&workspace, &["../shared"]);
+
+    let request = claude_request("Read", json!({ "file_path": shared.join("notes.md") }));
+    assert_eq!(guard.check(&request), Ok(()));
+    let request = claude_request("Grep", json!({ "pattern": "x", "path": dir.path() }));
+    assert!(guard.check(&request).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_out_of_the_workspace_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    let outside = dir.path().join("outside");
+    fs::create_dir_all(&workspace).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(&outside, workspace.join("link")).unwrap();
+    let guard = guard(&workspace);
+
+    let request = claude_request("Write", json!({ "file_path": "link/escape.txt" }));
+    let error = guard.check(&request).unwrap_err();
+    assert!(
+        error.contains(&outside.canonicalize().unwrap().display().to_string()),
+        "{}",
+        error
+    );
+
+    let error = guard.check(&bash("echo x > link/escape.txt")).unwrap_err();
+    assert!(
+        error.starts_with("link/escape.txt resolves to"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn shell_cd_and_redirect_targets_are_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    fs::create_dir_all(workspace.join("sub")).unwrap();
+    let guard = guard(&workspace);
+
+    for command in [
+        "ls -la",
+        "cargo test 2>&1 | tee 'test output.log'",
+        "echo hi > notes.txt 2>/dev/null",
+        "cd sub && echo done >> ../log.txt",
+        "cat <<EOF\n/etc/passwd\nEOF",
+        "grep -r '> /etc/hosts' .",
+    ] {
+        assert_eq!(guard.check(&bash(command)), Ok(()), "{}", command);
+    }
+
+    for (command, target) in [
+        ("cd /etc && ls", "/etc"),
+        ("cd sub; cat < ../../secret", "../../secret"),
+        ("echo x>/tmp/out", "/tmp/out"),
+        ("npm test &> ../test.log", "../test.log"),
+        ("pushd ..", ".."),
+        ("cd", "~"),
+    ] {
+        let error = guard.check(&bash(command)).unwrap_err();
+        assert!(error.starts_with(target), "{}: {}", command, error);
+    }
+
+    let error = guard.check(&bash("echo x > \"$OUT\"")).unwrap_err();
+    assert_eq!(error, "$OUT cannot be resolved before the command runs");
+}
+
+#[test]
+fn cd_options_and_command_prefixes_do_not_hide_the_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    fs::create_dir_all(workspace.join("sub")).unwrap();
+    let guard = guard(&workspace);
+
+    for command in [
+        "cd -P sub && echo x > out",
+        "cd -- sub; echo x > out",
+        "builtin cd sub",
+        "command -v cd",
+        "LANG=C cd sub",
+    ] {
+        assert_eq!(guard.check(&bash(command)), Ok(()), "{}", command);
+    }
+
+    for (command, target) in [
+        ("cd -P /etc && echo x > passwd", "/etc"),
+        ("cd -L -e /etc && echo x > passwd", "/etc"),
+        ("cd -- /etc; echo x > passwd", "/etc"),
+        ("builtin cd /etc; echo x > passwd", "/etc"),
+        ("command cd /etc; echo x > passwd", "/etc"),
+        ("VAR=x cd /etc; echo x > passwd", "/etc"),
+        ("OUT=$(pwd) cd /etc", "/etc"),
+        ("{ cd /etc; echo x > passwd; }", "/etc"),
+        ("pushd -n /etc", "/etc"),
+    ] {
+        let error = guard.check(&bash(command)).unwrap_err();
+        assert!(error.starts_with(target), "{}: {}", command, error);
+    }
+
+    for (command, error) in [
+        ("cd /tmp; cd -; echo x > passwd", "cd -"),
+        ("cd -P -; echo x > passwd", "cd -"),
+        ("pushd /tmp; popd; echo x > passwd", "popd"),
+        ("pushd +1", "pushd +1"),
+    ] {
+        assert_eq!(
+            guard.check(&bash(command)),
+            Err(format!(
+                "{} returns to a directory that cannot be resolved before the command runs",
+                error
+            )),
+            "{}",
+            command
+        );
+    }
+}
+
+#[test]
+fn agent_file_and_bash_permissions_are_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let guard = guard(dir.path());
+    let agent = |tool: &str, metadata: Value| {
+        normalize_permission_request(
+            "agent",
+            &json!({ "type": "permission_request", "permissionID": "p1", "tool": tool, "metadata": metadata }),
+        )
+        .unwrap()
+    };
+
+    assert!(guard
+        .check(&agent("edit", json!({ "filePath": "/etc/hosts" })))
+        .is_err());
+    assert!(guard
+        .check(&agent("bash", json!({ "command": "cd / && rm -rf tmp" })))
+        .is_err());
+    assert_eq!(
+        guard.check(&agent("bash", json!({ "command": "make > build.log" }))),
+        Ok(())
+    );
+}
+
+#[test]
+fn codex_requests_resolve_against_their_own_cwd_inside_the_workspace() {
+    let dir = tempfile::tempdir().unwrap();
+    let workspace = dir.path().join("repo");
+    fs::create_dir_all(workspace.join("sub")).unwrap();
+    let guard = guard(&workspace);
+    let codex = |method: &str, params: Value| {
+        normalize_permission_request(
+            "codex",
+            &json!({ "id": 1, "method": method, "params": params }),
+        )
+        .unwrap()
+    };
+    let command = |cwd: &Path, command: &str| {
+        codex(
+            "item/commandExecution/requestApproval",
+            json!({ "threadId": "thr_1", "itemId": "item_1", "cwd": cwd, "command": command }),
+        )
+    };
+    let sub = workspace.join("sub");
+
+    assert_eq!(guard.check(&command(&sub, "echo x > ../notes.txt")), Ok(()));
+    assert_eq!(guard.check(&command(&sub, "cd .. && ls")), Ok(()));
+    let error = guard
+        .check(&command(&sub, "cat < ../../secret"))
+        .unwrap_err();
+    assert!(error.starts_with("../../secret resolves to"), "{}", error);
+    let error = guard.check(&command(&sub, "cd ../.. && ls")).unwrap_err();
+    assert!(error.starts_with("../.. resolves to"), "{}", error);
+
+    // A cwd outside the workspace is rejected before anything else
+    let error = guard.check(&command(dir.path(), "ls")).unwrap_err();
+    assert!(
+        error.starts_with(&format!("{} resolves to", dir.path().display())),
+        "{}",
+        error
+    );
+
+    let change = |path: &str| {
+        codex(
+            "item/fileChange/requestApproval",
+            json!({
+                "threadId": "thr_1",
+                "itemId": "item_2",
+                "cwd": "sub",
+                "changes": [{ "path": path, "kind": { "type": "add" }, "diff": "x\n" }],
+            }),
+        )
+    };
+    assert_eq!(guard.check(&change("../notes.md")), Ok(()));
+    assert!(guard.check(&change("../../notes.md")).is_err());
+}
+
+#[test]
+fn relay_rejects_outside_requests_before_asking_the_handler() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut asked = Vec::new();
+    let mut written = Vec::new();
+    {
+        let mut relay = PermissionRelay::new(
+            "claude",
+            |request: &NormalizedPermissionRequest| {
+                asked.push(request.command.clone().unwrap_or_default());
+                "once"
+            },
+            |frame: &str| written.push(frame.to_string()),
+        )
+        .with_workspace_guard(guard(dir.path()));
+
+        let outside = relay
+            .handle_message(&claude_frame("Read", json!({ "file_path": "/etc/shadow" })))
+            .unwrap();
+        assert_eq!(outside.1, "reject");
+        let inside = relay
+            .handle_message(&claude_frame("Read", json!({ "file_path": "notes.md" })))
+            .unwrap();
+        assert_eq!(inside.1, "once");
+    }
+
+    assert_eq!(asked, vec!["notes.md"]);
+    let rejection: Value = serde_json::from_str(&written[0]).unwrap();
+    let message = rejection["response"]["response"]["message"]
+        .as_str()
+        .unwrap();
+    assert!(
+        message.starts_with("/etc/shadow resolves to"),
+        "{}",
+        message
+    );
+}
+
+#[tokio::test]
+async fn async_relay_records_why_the_guard_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let handler = AsyncPermissionHandler::new(|_| async { "once" });
+    let (frames, _written) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay = AsyncPermissionRelay::new("claude", handler, None, frames)
+        .with_workspace_guard(guard(dir.path()));
+
+    relay.handle_message(&claude_frame("Bash", json!({ "command": "cd /etc" })));
+    let answered = relay.finish().await;
+
+    assert_eq!(answered.len(), 1);
+    assert_eq!(answered[0].decision, "reject");
+    assert!(
+        answered[0]
+            .source
+            .starts_with("workspace guard: /etc resolves to"),
+        "{}",
+        answered[0].source
+    );
+}